@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracing::rt::material_library::MaterialLibrary;
+use sti::arena::Arena;
+
+fuzz_target!(|source: &str| {
+    let arena = Arena::new();
+    let mut library = MaterialLibrary::new();
+    let _ = library.parse(&arena, source);
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracing::options::Options;
+
+// the whole command line, with the arguments separated by spaces
+fuzz_target!(|line: &str| {
+    let args = Vec::from_iter(line.split(' ').map(str::to_string));
+    let _ = Options::parse(args, &["bouncing_spheres", "quads"]);
+});
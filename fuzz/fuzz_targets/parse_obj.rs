@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracing::rt::mesh::Mesh;
+use sti::arena::Arena;
+
+// a bad file has to come back as an error, never a panic
+fuzz_target!(|source: &str| {
+    let arena = Arena::new();
+    let _ = Mesh::parse_obj(&arena, source, true);
+});
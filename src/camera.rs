@@ -1,4 +1,37 @@
-use crate::{math::vec3::{Colour, Point, Vec3}, rt::{camera::RaytracingCamera, hittable::Hittable, materials::Material, texture::Texture}};
+use std::f32::consts::PI;
+
+use crate::{math::{half::Half, interval::Interval, ray::Ray, vec3::{Colour, Point, Vec3}}, rng::Stream, rt::{aperture::Aperture, camera::{Accumulator, DebugView, Precision, RaytracingCamera, Region, RenderSettings, View}, fog::Fog, hittable::{HitRecord, Hittable}, materials::Material, sky::Sky, texture::Texture, units::Units}};
+
+/// the angles in degrees `Camera::frame` looks at the world from
+const FRAMING_PITCH : f32 = -25.0;
+const FRAMING_YAW : f32 = 60.0;
+
+/// how close the camera can get to a surface in metres
+const COLLISION_RADIUS : f32 = 0.3;
+/// how high above the ground the camera is when walking in metres
+const EYE_HEIGHT : f32 = 1.7;
+/// in metres per second squared
+const GRAVITY : f32 = 9.81;
+/// in metres per second, about how fast a person falls
+const TERMINAL_VELOCITY : f32 = 55.0;
+/// how many rays around the middle one collisions are tested with,
+/// they're just inside of `COLLISION_RADIUS` so a camera resting
+/// that far from a surface doesn't start them on it
+const COLLISION_RAYS : usize = 8;
+const COLLISION_RING : f32 = 0.9;
+
+
+///
+/// A saved viewpoint, see `Camera::bookmark`
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bookmark {
+    pub position: Point,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub vfov: f32,
+    pub focus_dist: f32,
+}
 
 
 #[derive(Clone)]
@@ -10,14 +43,46 @@ pub struct Camera<'a> {
     pub yaw: f32,
 
     aspect_ratio: f32,
+    /// how much wider than tall a pixel is shown, see `RaytracingCamera::new`
+    pub pixel_aspect: f32,
+    width: usize,
     vfov: f32,
     vup: Vec3,
     focus_dist: f32,
-    pub rt_cam: RaytracingCamera,
+    pub rt_cam: RaytracingCamera<'a>,
+    /// the shape of the lens, see `set_aperture`
+    aperture: Option<&'a Aperture>,
     
 
     acc_colours: Vec<Colour>, 
+    acc_squares: Vec<Colour>,
+    /// only used with `Precision::F16`, see `set_precision`
+    acc_half: Vec<[Half; 6]>,
+    precision: Precision,
+    /// how many samples each pixel has
+    counts: Vec<u32>,
+    /// in percent of the width and height, see `set_overscan`
+    overscan: f32,
+    overscanned: Vec<u32>,
+    depth: Vec<f32>,
     pub samples: usize,
+    pub fog: Option<Fog>,
+    pub sky: Sky<'a>,
+    pub units: Units,
+    /// how much brighter every emitting material is made
+    pub light_scale: f32,
+    /// in stops, brightens the image without re-rendering it
+    pub exposure: f32,
+    /// shows a debug view in place of the image
+    pub debug: Option<DebugView>,
+    /// stops the camera from moving through the world
+    pub collide: bool,
+    /// keeps the camera at eye height above the ground, it
+    /// falls when there's nothing below it and also collides
+    pub walk: bool,
+    fall_speed: f32,
+    /// what the random numbers of the render are drawn from
+    pub stream: Stream,
     world: Hittable<'a>,
 }
 
@@ -26,7 +91,8 @@ impl<'a> Camera<'a> {
                aspect_ratio: f32, width: usize,
                max_depth: usize, vfov: f32, 
                vup: Vec3, defocus_angle: f32, focus_dist: f32) -> Self {
-        let rc = RaytracingCamera::new(aspect_ratio, width, max_depth, vfov, position, position + direction, vup, defocus_angle, focus_dist);
+        let view = View { look_from: position, look_at: position + direction, vup, vfov, defocus_angle, focus_dist };
+        let rc = RaytracingCamera::new(aspect_ratio, 1.0, width, max_depth, view);
 
         let height = {
             let val = (width as f32 / aspect_ratio) as usize;
@@ -37,14 +103,34 @@ impl<'a> Camera<'a> {
             position,
             direction,
             aspect_ratio,
+            pixel_aspect: 1.0,
+            width,
             vfov,
             vup,
             focus_dist,
             rt_cam: rc,
+            aperture: None,
             acc_colours: Vec::from_iter((0..width * height).map(|_| Colour::ZERO)),
+            acc_squares: Vec::from_iter((0..width * height).map(|_| Colour::ZERO)),
+            acc_half: Vec::new(),
+            precision: Precision::F32,
+            counts: vec![0; width * height],
+            overscan: 0.0,
+            overscanned: Vec::new(),
+            depth: Vec::from_iter((0..width * height).map(|_| f32::INFINITY)),
             pitch: 0.0,
             yaw: 0.0,
             samples: 0,
+            fog: None,
+            sky: Sky::Gradient,
+            units: Units::Metres,
+            light_scale: 1.0,
+            exposure: 0.0,
+            debug: None,
+            collide: false,
+            walk: false,
+            fall_speed: 0.0,
+            stream: Stream::new(0),
             world: Hittable::sphere(Point::ONE, 1.0, Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) }),
         }
     }
@@ -52,39 +138,307 @@ impl<'a> Camera<'a> {
 
     pub fn set_world(&mut self, world: Hittable<'a>) {
         self.world = world;
+        self.samples = 0;
+    }
+
+
+    ///
+    /// Looks down at the whole of `world` from the side so all
+    /// of its bounded objects fit on screen, handy for scenes
+    /// which don't come with a camera position
+    ///
+    pub fn frame(&mut self, world: &Hittable) {
+        let Some(bounds) = world.bounds() else { return };
+
+        let min = bounds.min_corner();
+        let max = bounds.max_corner();
+        let centre = 0.5 * (min + max);
+        let radius = 0.5 * (max - min).length();
+
+        // fit the bounding sphere into the narrower of both fields of view
+        let half_vfov = 0.5 * self.vfov.to_radians();
+        let half_hfov = (half_vfov.tan() * self.aspect_ratio * self.pixel_aspect).atan();
+        let distance = radius / half_vfov.min(half_hfov).sin();
+
+        self.pitch = FRAMING_PITCH;
+        self.yaw = FRAMING_YAW;
+        self.direction = pitch_yaw_direction(self.pitch, self.yaw);
+        self.position = centre - distance * self.direction;
+        self.samples = 0;
+    }
+
+
+    pub fn bookmark(&self) -> Bookmark {
+        Bookmark { position: self.position, pitch: self.pitch, yaw: self.yaw, vfov: self.vfov, focus_dist: self.focus_dist }
+    }
+
+
+    pub fn go_to(&mut self, bookmark: Bookmark) {
+        self.position = bookmark.position;
+        self.pitch = bookmark.pitch;
+        self.yaw = bookmark.yaw;
+        self.vfov = bookmark.vfov;
+        self.focus_dist = bookmark.focus_dist;
+        self.direction = pitch_yaw_direction(self.pitch, self.yaw);
+        self.fall_speed = 0.0;
+        self.samples = 0;
+    }
+
+
+    ///
+    /// Gives the defocus blur the shape of `aperture`
+    /// instead of a disk, or makes it round again
+    ///
+    pub fn set_aperture(&mut self, aperture: Option<&'a Aperture>) {
+        self.aperture = aperture;
+        self.update_render();
+        self.samples = 0;
+    }
+
+
+    ///
+    /// Renders `percent` of the width and height past each edge
+    /// of the image as well, `render` crops it off again
+    ///
+    pub fn set_overscan(&mut self, percent: f32) {
+        self.overscan = percent;
+        self.update_render();
+
+        let pixels = self.rt_cam.image.0 * self.rt_cam.image.1;
+        self.allocate_accumulation();
+        self.depth = vec![f32::INFINITY; pixels];
+        self.overscanned = if self.overscan_border() == (0, 0) { Vec::new() } else { vec![0; pixels] };
+        self.samples = 0;
+    }
+
+
+    ///
+    /// Switches what the samples are accumulated in,
+    /// which starts the accumulation over
+    ///
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+        self.allocate_accumulation();
+        self.samples = 0;
+    }
+
+
+    fn allocate_accumulation(&mut self) {
+        let pixels = self.rt_cam.image.0 * self.rt_cam.image.1;
+        let (f32_pixels, f16_pixels) = match self.precision {
+            Precision::F32 => (pixels, 0),
+            Precision::F16 => (0, pixels),
+        };
+
+        self.acc_colours = vec![Colour::ZERO; f32_pixels];
+        self.acc_squares = vec![Colour::ZERO; f32_pixels];
+        self.acc_half = vec![[Half::ZERO; 6]; f16_pixels];
+        self.counts = vec![0; pixels];
+    }
+
+
+    fn overscan_border(&self) -> (usize, usize) {
+        let height = (self.width as f32 / self.aspect_ratio) as usize;
+        let border = |size: usize| (size as f32 * self.overscan / 100.0).round() as usize;
+        (border(self.width), border(height))
     }
 
 
     pub fn render(&mut self, buff: &mut [u32]) {
+        let height = buff.len() / self.width;
+        self.render_region(buff, Region::full((self.width, height)));
+    }
+
+
+    ///
+    /// Only adds a sample to the pixels in `region`, the rest of
+    /// `buff` is left as it is. Each pixel keeps its own sample
+    /// count so they're all normalised right however often
+    /// they were rendered
+    ///
+    pub fn render_region(&mut self, buff: &mut [u32], region: Region) {
         self.update_render();
         self.samples += 1;
-        unsafe { self.rt_cam.render(&mut self.acc_colours, buff, self.samples, &self.world) };
+
+        let border = self.overscan_border();
+        let region = Region {
+            min: (region.min.0 + border.0, region.min.1 + border.1),
+            max: (region.max.0 + border.0, region.max.1 + border.1),
+        };
+
+        let target = if border == (0, 0) { &mut *buff } else { &mut self.overscanned };
+        match self.debug {
+            Some(view) => self.rt_cam.render_debug(view, target, &self.world),
+            None => {
+                let acc = match self.precision {
+                    Precision::F32 => Accumulator::F32 { colours: &mut self.acc_colours, squares: &mut self.acc_squares },
+                    Precision::F16 => Accumulator::F16(&mut self.acc_half),
+                };
+                unsafe { self.rt_cam.render(acc, &mut self.counts, &mut self.depth, target, region, &self.world) }
+            },
+        }
+
+        if border != (0, 0) { self.crop_overscan(buff) }
+    }
+
+
+    fn crop_overscan(&self, buff: &mut [u32]) {
+        let border = self.overscan_border();
+        let full_width = self.rt_cam.image.0;
+        for (y, row) in buff.chunks_exact_mut(self.width).enumerate() {
+            let start = (y + border.1) * full_width + border.0;
+            row.copy_from_slice(&self.overscanned[start..start + self.width]);
+        }
     }
 
 
     fn update_render(&mut self) {
-        let direction = Vec3::new(
-            self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
-            self.pitch.to_radians().sin(),
-            self.yaw.to_radians().sin() * self.pitch.to_radians().cos()
-        );
-
-        let render = RaytracingCamera::new(self.aspect_ratio, self.rt_cam.image.0,
-                                       self.rt_cam.max_depth,
-                                       self.vfov, self.position, self.position + direction,
-                                       self.vup, self.rt_cam.defocus_angle, self.focus_dist);
-        self.rt_cam = render;
+        let direction = pitch_yaw_direction(self.pitch, self.yaw);
+
+        let view = View { look_from: self.position, look_at: self.position + direction, vup: self.vup,
+                          vfov: self.vfov, defocus_angle: self.rt_cam.defocus_angle, focus_dist: self.focus_dist };
+        let settings = RenderSettings { sky: self.sky, units: self.units, light_scale: self.light_scale, exposure: self.exposure,
+                                        fog: self.fog, stream: self.stream, ..self.rt_cam.settings };
+
+        let render = RaytracingCamera::new(self.aspect_ratio, self.pixel_aspect, self.width, self.rt_cam.max_depth, view);
+        self.rt_cam = render.with_overscan(self.overscan_border()).with_settings(settings).with_aperture(self.aperture);
 
         if self.samples == 0 {
             self.acc_colours.iter_mut()
+                .chain(self.acc_squares.iter_mut())
                 .for_each(|x|{
                     *x = Colour::ZERO;
                 });
+            self.acc_half.fill([Half::ZERO; 6]);
+            self.counts.fill(0);
+        }
+    }
+
+
+    ///
+    /// Estimates how far the pixels are from what they'd converge
+    /// to with infinitely many samples, as the root mean square
+    /// over every pixel and channel of the standard error of its
+    /// mean. The per pixel variances are unbiased estimates.
+    /// Colours are in gamma space from 0 to 1. Pixels with less
+    /// than two samples are left out, and it's infinite if
+    /// that's all of them
+    ///
+    pub fn noise(&self) -> f32 {
+        // the squared standard error summed over the channels
+        let squared_error = |n: u32, mean: Colour, mean_square: Colour| {
+            let n = n as f32;
+            let variance = (n / (n - 1.0)) * (mean_square - mean * mean);
+            // rounding can take it slightly below zero
+            (variance.x.max(0.0) + variance.y.max(0.0) + variance.z.max(0.0)) / n
+        };
+
+        let mut total = 0.0;
+        let mut pixels = 0;
+        for (i, &n) in self.counts.iter().enumerate() {
+            if n < 2 { continue }
+
+            total += match self.precision {
+                Precision::F32 => squared_error(n, self.acc_colours[i] / n as f32, self.acc_squares[i] / n as f32),
+
+                Precision::F16 => {
+                    let m = &self.acc_half[i];
+                    squared_error(n, Colour::new(m[0].to_f32(), m[1].to_f32(), m[2].to_f32()),
+                                     Colour::new(m[3].to_f32(), m[4].to_f32(), m[5].to_f32()))
+                },
+            };
+            pixels += 1;
         }
+
+        if pixels == 0 { return f32::INFINITY }
+        (total / (3.0 * pixels as f32)).sqrt()
+    }
+
+
+    ///
+    /// The mean colour of every visible pixel so far, in gamma
+    /// space like the samples but before the exposure and fog,
+    /// so bright lights go over 1. Pixels without samples are black
+    ///
+    pub fn mean_image(&self) -> Vec<Colour> {
+        Vec::from_iter(self.visible_pixels().map(|i| self.mean(i).unwrap_or(Colour::ZERO)))
+    }
+
+
+    ///
+    /// The root mean square error over every pixel and channel of
+    /// `mean_image` against `reference`, which has to be the same
+    /// size. Pixels without samples are left out
+    ///
+    pub fn rmse(&self, reference: &[Colour]) -> f32 {
+        let mut total = 0.0;
+        let mut pixels = 0;
+        for (i, &expected) in self.visible_pixels().zip(reference) {
+            let Some(mean) = self.mean(i) else { continue };
+            let error = mean - expected;
+            total += error.dot(error);
+            pixels += 1;
+        }
+
+        if pixels == 0 { return f32::INFINITY }
+        (total / (3.0 * pixels as f32)).sqrt()
+    }
+
+
+    ///
+    /// The indices into the accumulation of the pixels inside
+    /// of the overscan border, row by row
+    ///
+    fn visible_pixels(&self) -> impl Iterator<Item = usize> {
+        let border = self.overscan_border();
+        let full_width = self.rt_cam.image.0;
+        let (width, height) = (self.width, self.rt_cam.image.1 - 2 * border.1);
+        (0..height).flat_map(move |y| (0..width).map(move |x| (y + border.1) * full_width + x + border.0))
+    }
+
+
+    fn mean(&self, i: usize) -> Option<Colour> {
+        let n = self.counts[i];
+        if n == 0 { return None }
+
+        Some(match self.precision {
+            Precision::F32 => self.acc_colours[i] / n as f32,
+            Precision::F16 => {
+                let m = &self.acc_half[i];
+                Colour::new(m[0].to_f32(), m[1].to_f32(), m[2].to_f32())
+            },
+        })
+    }
+
+
+    ///
+    /// Multiplies the brightness of the lights by `factor`. With
+    /// `keep_exposure` off the exposure is lowered by as much so
+    /// the lights look the same and only the balance between
+    /// them and the sky changes
+    ///
+    pub fn scale_lights(&mut self, factor: f32, keep_exposure: bool) {
+        self.light_scale *= factor;
+        if !keep_exposure { self.exposure -= factor.log2() }
+        self.samples = 0;
+    }
+
+
+    ///
+    /// Brightens the lights by `light_stops` and the exposure by
+    /// `exposure_stops`. With `linked` on the exposure doesn't
+    /// make up for the lights
+    ///
+    pub fn adjust_lighting(&mut self, light_stops: f32, exposure_stops: f32, linked: bool) {
+        if light_stops != 0.0 { self.scale_lights(light_stops.exp2(), !linked) }
+        self.exposure += exposure_stops;
     }
 
 
     pub fn move_by(&mut self, step: Vec3) {
+        let step = if self.walk { Vec3::new(step.x, 0.0, step.z) } else { step };
+        let step = if self.collide || self.walk { self.slide(step) } else { step };
+
         self.position += step;
         if step != Vec3::ZERO {
             self.samples = 0;
@@ -92,6 +446,121 @@ impl<'a> Camera<'a> {
     }
 
 
+    ///
+    /// Lets the camera fall towards the ground over `dt` seconds
+    /// while walking, it stands `EYE_HEIGHT` above whatever is below
+    ///
+    pub fn fall(&mut self, dt: f32) {
+        if !self.walk { return }
+
+        // standing on the edge of a ledge keeps the camera up
+        let eye_height = self.units.from_metres(EYE_HEIGHT);
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let ground = self.collision_offsets(down).filter_map(|offset| {
+            let mut rec = HitRecord::default();
+            let ray = Ray::new(self.position + offset, down, 0.0);
+            self.world.hit(ray, Interval::new(0.0, f32::INFINITY), &mut rec).then_some(rec.t)
+        }).fold(f32::INFINITY, f32::min);
+
+        // over nothing at all there's nowhere to fall to, it stays put
+        if ground == f32::INFINITY {
+            self.fall_speed = 0.0;
+            return;
+        }
+
+        let terminal_velocity = self.units.from_metres(TERMINAL_VELOCITY);
+        self.fall_speed = (self.fall_speed + self.units.from_metres(GRAVITY) * dt).min(terminal_velocity);
+        let drop = self.fall_speed * dt;
+
+        let before = self.position.y;
+        if ground - drop <= eye_height {
+            // landed, or stepped onto something higher
+            self.position.y += eye_height - ground;
+            self.fall_speed = 0.0;
+        } else {
+            self.position.y -= drop;
+        }
+
+        if self.position.y != before {
+            self.samples = 0;
+        }
+    }
+
+
+    ///
+    /// Shortens `step` so the camera stays `COLLISION_RADIUS` away
+    /// from what's in its way and slides the rest along the surface.
+    /// Only a few rays around its path are tested, so the camera
+    /// can still brush through things thinner than the gaps
+    ///
+    fn slide(&self, step: Vec3) -> Vec3 {
+        let Some((allowed, normal)) = self.blocked(self.position, step) else { return step };
+
+        let remaining = step - allowed;
+        let along_surface = remaining - remaining.dot(normal) * normal;
+
+        // don't slide into a second surface
+        match self.blocked(self.position + allowed, along_surface) {
+            Some((slide, _)) => allowed + slide,
+            None => allowed + along_surface,
+        }
+    }
+
+
+    ///
+    /// Returns how far along `step` the camera can move from `from`
+    /// and the normal of what blocks it, if anything does
+    ///
+    fn blocked(&self, from: Point, step: Vec3) -> Option<(Vec3, Vec3)> {
+        let length = step.length();
+        if length == 0.0 { return None }
+
+        let radius = self.units.from_metres(COLLISION_RADIUS);
+        let direction = step / length;
+        let mut closest = None;
+        let mut t = Interval::new(0.0, length + radius);
+        for offset in self.collision_offsets(direction) {
+            let mut rec = HitRecord::default();
+            if self.world.hit(Ray::new(from + offset, direction, 0.0), t, &mut rec) {
+                t.max = rec.t;
+                closest = Some(rec.normal);
+            }
+        }
+
+        Some(((t.max - radius).max(0.0) * direction, closest?))
+    }
+
+
+    ///
+    /// The middle of the camera and `COLLISION_RAYS` points
+    /// around it, on the plane at a right angle to `direction`
+    ///
+    fn collision_offsets(&self, direction: Vec3) -> impl Iterator<Item = Vec3> {
+        let radius = COLLISION_RING * self.units.from_metres(COLLISION_RADIUS);
+        let side = if direction.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let u = direction.cross(side).unit();
+        let v = direction.cross(u);
+
+        std::iter::once(Vec3::ZERO).chain((0..COLLISION_RAYS).map(move |i| {
+            let angle = i as f32 * 2.0 * PI / COLLISION_RAYS as f32;
+            radius * (angle.cos() * u + angle.sin() * v)
+        }))
+    }
+
+
+    ///
+    /// The point and normal of the surface in the middle of
+    /// the screen, if the camera is looking at anything
+    ///
+    pub fn pick(&self) -> Option<(Point, Vec3)> {
+        let mut rec = HitRecord::default();
+        let ray = Ray::new(self.position, self.direction, 0.0);
+        if !self.world.hit(ray, Interval::new(0.0, f32::INFINITY), &mut rec) { return None }
+
+        Some((rec.point, rec.normal))
+    }
+
+
     pub fn forward(&self) -> Vec3 {
         self.direction
     }
@@ -116,11 +585,7 @@ impl<'a> Camera<'a> {
     pub fn change_pitch_yaw_by(&mut self, delta_pitch: f32, delta_yaw: f32) {
         self.pitch += delta_pitch;
         self.yaw += delta_yaw;
-        self.direction = Vec3::new(
-            self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
-            self.pitch.to_radians().sin(),
-            self.yaw.to_radians().sin() * self.pitch.to_radians().cos()
-        );
+        self.direction = pitch_yaw_direction(self.pitch, self.yaw);
         if delta_pitch != 0.0 || delta_yaw != 0.0 {
             self.samples = 0;
         }
@@ -128,3 +593,10 @@ impl<'a> Camera<'a> {
 }
 
 
+fn pitch_yaw_direction(pitch: f32, yaw: f32) -> Vec3 {
+    Vec3::new(
+        yaw.to_radians().cos() * pitch.to_radians().cos(),
+        pitch.to_radians().sin(),
+        yaw.to_radians().sin() * pitch.to_radians().cos()
+    )
+}
@@ -0,0 +1,153 @@
+use std::{fmt::Write, fs, io};
+
+use sti::arena::Arena;
+
+use crate::{camera::Camera, generator::{GeneratedScene, Generator}, math::{interval::Interval, ray::Ray, vec3::{Colour, Vec3}}, rng::Stream, rt::hittable::{HitRecord, Hittable}, session::{self, Reference}, ASPECT_RATIO, MAX_DEPTH, RENDER_RESOLUTION, RENDER_RESOLUTION_X};
+
+
+///
+/// Renders `count` scenes drawn from `generator` into `out_dir`
+/// for training vision models. Sample `i` is written as
+///
+/// - `rgb/<i>.ppm`, the finished image
+/// - `depth/<i>.pfm`, the distance along the view direction to the
+///   first hit in every channel, 0 where nothing is hit
+/// - `normals/<i>.pfm`, the unit normal facing the camera in
+///   camera space, x to the right, y up and z towards the camera
+/// - `instances/<i>.pgm`, a 16 bit id of the object in every pixel,
+///   0 for the sky, 1 for the ground and the rest from 2 on
+///   in the order of `GeneratedScene::objects`
+///
+/// and `cameras.txt` gets a `<i> <x> <y> <z> <pitch> <yaw> <vfov>`
+/// line. The guides are traced through the middle of each pixel.
+/// The same `seed` gives the same scenes
+///
+pub fn generate(out_dir: &str, generator: &Generator, count: usize, samples: usize, seed: u64) -> io::Result<()> {
+    for dir in ["rgb", "depth", "normals", "instances"] {
+        fs::create_dir_all(format!("{out_dir}/{dir}"))?;
+    }
+
+    let stream = Stream::named("dataset").split(seed);
+    let mut cameras = String::new();
+    let mut buff = vec![0; RENDER_RESOLUTION * RENDER_RESOLUTION_X];
+
+    for i in 0..count {
+        let arena = Arena::new();
+        let GeneratedScene { objects, sky, viewpoint: bookmark } = generator.scene(&arena, stream.split(i as u64));
+
+        let mut camera = Camera::new(bookmark.position, Vec3::new(1.0, 0.0, 0.0), ASPECT_RATIO, RENDER_RESOLUTION_X,
+                                     MAX_DEPTH, bookmark.vfov, Vec3::new(0.0, 1.0, 0.0), 0.0, bookmark.focus_dist);
+        camera.set_world(Hittable::bvh(&arena, objects));
+        camera.sky = sky;
+        camera.go_to(bookmark);
+        camera.stream = stream.split(i as u64);
+
+        for _ in 0..samples { camera.render(&mut buff) }
+        session::write_ppm(&format!("{out_dir}/rgb/{i:05}.ppm"), &buff)?;
+
+        let guides = Guides::trace(&camera, objects);
+        let size = camera.rt_cam.image;
+        Reference { size, pixels: Vec::from_iter(guides.depth.iter().map(|&depth| depth * Colour::ONE)) }
+            .save(&format!("{out_dir}/depth/{i:05}.pfm"))?;
+        Reference { size, pixels: guides.normals }.save(&format!("{out_dir}/normals/{i:05}.pfm"))?;
+        write_pgm16(&format!("{out_dir}/instances/{i:05}.pgm"), size, &guides.instances)?;
+
+        let _ = writeln!(cameras, "{i} {} {} {} {} {} {}", bookmark.position.x, bookmark.position.y, bookmark.position.z,
+                         bookmark.pitch, bookmark.yaw, bookmark.vfov);
+        println!("Rendered sample {}/{count}", i + 1);
+    }
+
+    fs::write(format!("{out_dir}/cameras.txt"), cameras)
+}
+
+
+///
+/// What's under the middle of every pixel, row by row
+///
+struct Guides {
+    depth: Vec<f32>,
+    normals: Vec<Vec3>,
+    instances: Vec<u16>,
+}
+
+
+impl Guides {
+    ///
+    /// `objects` are what the camera's world is made of, their
+    /// index plus one is their id
+    ///
+    fn trace(camera: &Camera, objects: &[Hittable]) -> Self {
+        let rt_cam = &camera.rt_cam;
+        let (width, height) = rt_cam.image;
+
+        // the camera's axes, the pixels go right and down
+        let right = rt_cam.pixel_delta_u.unit();
+        let up = -rt_cam.pixel_delta_v.unit();
+        let back = right.cross(up);
+
+        let mut guides = Guides { depth: Vec::new(), normals: Vec::new(), instances: Vec::new() };
+        for y in 0..height {
+            for x in 0..width {
+                let pixel_centre = rt_cam.pixel00_loc + (x as f32 * rt_cam.pixel_delta_u) + (y as f32 * rt_cam.pixel_delta_v);
+                let ray = Ray::new(rt_cam.centre, pixel_centre - rt_cam.centre, 0.0);
+
+                let mut closest = None;
+                let mut rec = HitRecord::default();
+                let mut ray_t = Interval::new(0.0, f32::INFINITY);
+                for (id, object) in objects.iter().enumerate() {
+                    if !object.hit(ray, ray_t, &mut rec) { continue }
+                    ray_t.max = rec.t;
+                    closest = Some((id, rec.normal));
+                }
+
+                let Some((id, normal)) = closest
+                else {
+                    guides.depth.push(0.0);
+                    guides.normals.push(Vec3::ZERO);
+                    guides.instances.push(0);
+                    continue
+                };
+
+                guides.depth.push((ray_t.max * ray.direction).dot(-back));
+                guides.normals.push(Vec3::new(normal.dot(right), normal.dot(up), normal.dot(back)));
+                guides.instances.push((id + 1).min(u16::MAX as usize) as u16);
+            }
+        }
+
+        guides
+    }
+}
+
+
+///
+/// A 16 bit greyscale portable graymap, which is big endian
+///
+fn write_pgm16(path: &str, size: (usize, usize), values: &[u16]) -> io::Result<()> {
+    let mut bytes = format!("P5\n{} {}\n65535\n", size.0, size.1).into_bytes();
+    for value in values { bytes.extend(value.to_be_bytes()) }
+    fs::write(path, bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{math::vec3::Point, rt::materials::Material};
+
+    use super::*;
+
+    #[test]
+    fn guides_of_a_sphere_ahead() {
+        let objects = [Hittable::sphere(Point::new(10.0, 0.0, 0.0), 1.0, Material::default())];
+        let camera = Camera::new(Point::ZERO, Vec3::new(1.0, 0.0, 0.0), 1.0, 33, MAX_DEPTH, 20.0,
+                                 Vec3::new(0.0, 1.0, 0.0), 0.0, 10.0);
+
+        let guides = Guides::trace(&camera, &objects);
+        let centre = 16 * 33 + 16;
+        assert_eq!(guides.instances[centre], 1);
+        assert!((guides.depth[centre] - 9.0).abs() < 1e-4, "{}", guides.depth[centre]);
+        assert!((guides.normals[centre] - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-4, "{:?}", guides.normals[centre]);
+
+        // the corners see past it
+        assert_eq!((guides.instances[0], guides.depth[0], guides.normals[0]), (0, 0.0, Vec3::ZERO));
+    }
+}
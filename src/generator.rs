@@ -0,0 +1,251 @@
+use sti::arena::Arena;
+
+use crate::{camera::Bookmark, math::vec3::{Colour, Point, Vec3}, rng::{next_f32, Stream}, rt::{hittable::Hittable, materials::Material, sky::Sky, texture::Texture}};
+
+/// where the sun of generated skies is worked out for
+const GENERATOR_LATITUDE : f32 = 45.0;
+
+
+///
+/// Values drawn evenly from `min` to `max`
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Uniform {
+    pub min: f32,
+    pub max: f32,
+}
+
+
+impl Uniform {
+    pub const fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+
+    pub const fn fixed(value: f32) -> Self {
+        Self::new(value, value)
+    }
+
+
+    pub fn sample(self) -> f32 {
+        self.min + (self.max - self.min) * next_f32()
+    }
+
+
+    ///
+    /// Either a single value or `<min>..<max>`
+    ///
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.split_once("..") {
+            Some((min, max)) => Some(Self::new(min.parse().ok()?, max.parse().ok()?)),
+            None => Some(Self::fixed(s.parse().ok()?)),
+        }
+    }
+}
+
+
+///
+/// The distributions random scenes are drawn from. Every scene
+/// is a ground with objects standing on it, lights floating
+/// above them and a camera looking at them from somewhere around
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Generator {
+    /// how many objects there are, rounded down
+    pub objects: Uniform,
+    /// how far from the middle the objects stand along x and z
+    pub spread: f32,
+    /// the radius of spheres and half the width of boxes
+    pub size: Uniform,
+    /// the odds of an object being diffuse, metal or glass
+    pub materials: [f32; 3],
+    /// how many glowing spheres there are, rounded down
+    pub lights: Uniform,
+    /// how bright the lights are, they're white
+    pub light_strength: Uniform,
+    /// the time of day the sky is drawn at, in hours,
+    /// or the plain gradient sky if there's none
+    pub hours: Option<Uniform>,
+    /// how far the camera is from what it looks at
+    pub distance: Uniform,
+    /// how far above the ground the camera looks down from, in degrees
+    pub elevation: Uniform,
+    pub vfov: Uniform,
+}
+
+
+///
+/// A scene drawn from a `Generator`
+///
+pub struct GeneratedScene<'a> {
+    /// the ground first, then the objects and then the lights
+    pub objects: &'a [Hittable<'a>],
+    pub sky: Sky<'a>,
+    pub viewpoint: Bookmark,
+}
+
+
+impl Generator {
+    pub const DEFAULT : Generator = Generator {
+        objects: Uniform::new(3.0, 13.0),
+        spread: 4.0,
+        size: Uniform::new(0.3, 1.3),
+        materials: [2.0, 1.0, 1.0],
+        lights: Uniform::fixed(0.0),
+        light_strength: Uniform::new(2.0, 10.0),
+        hours: None,
+        distance: Uniform::new(8.0, 16.0),
+        elevation: Uniform::new(10.0, 60.0),
+        vfov: Uniform::new(20.0, 50.0),
+    };
+
+
+    ///
+    /// Changes the distributions given as `<name>=<value>` pairs
+    /// separated by commas, with the names of the fields. Ranges
+    /// are `<min>..<max>` and `materials` is `<diffuse>:<metal>:<glass>`
+    ///
+    pub fn parse_overrides(mut self, settings: &str) -> Option<Self> {
+        for setting in settings.split(',') {
+            let (name, value) = setting.split_once('=')?;
+            match name {
+                "objects" => self.objects = Uniform::parse(value)?,
+                "spread" => self.spread = value.parse().ok()?,
+                "size" => self.size = Uniform::parse(value)?,
+                "materials" => {
+                    let mut odds = value.split(':').map(|odds| odds.parse::<f32>().ok());
+                    let (Some(Some(diffuse)), Some(Some(metal)), Some(Some(glass)), None) = (odds.next(), odds.next(), odds.next(), odds.next())
+                    else { return None };
+                    self.materials = [diffuse, metal, glass];
+                },
+                "lights" => self.lights = Uniform::parse(value)?,
+                "light_strength" => self.light_strength = Uniform::parse(value)?,
+                "hours" => self.hours = Some(Uniform::parse(value)?),
+                "distance" => self.distance = Uniform::parse(value)?,
+                "elevation" => self.elevation = Uniform::parse(value)?,
+                "vfov" => self.vfov = Uniform::parse(value)?,
+                _ => return None,
+            }
+        }
+
+        Some(self)
+    }
+
+
+    ///
+    /// Draws a scene, the same `stream` gives the same scene
+    ///
+    pub fn scene<'a>(&self, arena: &'a Arena, stream: Stream) -> GeneratedScene<'a> {
+        stream.enter();
+
+        let mut objects = sti::vec::Vec::new_in(arena);
+        let ground = Material::Lambertian { texture: Texture::SolidColour(random_colour(0.2, 0.8)) };
+        objects.push(Hittable::axis_aligned_box(Point::new(-50.0, -1.0, -50.0), Point::new(50.0, 0.0, 50.0), ground));
+
+        for _ in 0..self.objects.sample().max(0.0) as usize {
+            let size = self.size.sample();
+            let position = Point::new(self.spread * (2.0 * next_f32() - 1.0), 0.0, self.spread * (2.0 * next_f32() - 1.0));
+            let material = self.random_material();
+
+            let object = if next_f32() < 0.5 { Hittable::sphere(position + Vec3::new(0.0, size, 0.0), size, material) }
+                         else {
+                             let half = Vec3::new(size, 0.0, size);
+                             Hittable::axis_aligned_box(position - half, position + half + Vec3::new(0.0, 2.0 * size, 0.0), material)
+                         };
+            objects.push(object);
+        }
+
+        for _ in 0..self.lights.sample().max(0.0) as usize {
+            let position = Point::new(self.spread * (2.0 * next_f32() - 1.0), 3.0 + 2.0 * next_f32(), self.spread * (2.0 * next_f32() - 1.0));
+            let white = arena.alloc_new(Texture::SolidColour(Colour::ONE));
+            let black = arena.alloc_new(Texture::SolidColour(Colour::ZERO));
+            let light = Material::Principled {
+                texture: Texture::SolidColour(Colour::ONE),
+                metallic: black,
+                roughness: white,
+                emission: white,
+                emission_strength: self.light_strength.sample(),
+                two_sided: false,
+                specular: 0.5,
+                sheen: 0.0,
+                clearcoat: 0.0,
+                transmission: 0.0,
+            };
+            objects.push(Hittable::sphere(position, 0.3 + 0.3 * next_f32(), light));
+        }
+
+        let sky = match self.hours {
+            Some(hours) => Sky::at_time_of_day(hours.sample(), GENERATOR_LATITUDE),
+            None => Sky::Gradient,
+        };
+
+        GeneratedScene { objects: objects.leak(), sky, viewpoint: self.random_viewpoint() }
+    }
+
+
+    fn random_material(&self) -> Material<'static> {
+        let [diffuse, metal, glass] = self.materials.map(|odds| odds.max(0.0));
+        let pick = next_f32() * (diffuse + metal + glass);
+
+        if pick < diffuse || diffuse + metal + glass <= 0.0 {
+            Material::Lambertian { texture: Texture::SolidColour(random_colour(0.0, 1.0)) }
+        } else if pick < diffuse + metal {
+            Material::Metal { texture: Texture::SolidColour(random_colour(0.5, 1.0)), fuzz_radius: 0.3 * next_f32() }
+        } else {
+            Material::Dielectric { refraction_index: 1.5, thin_walled: false, absorption: Colour::ZERO, texture: Texture::SolidColour(Colour::ONE) }
+        }
+    }
+
+
+    ///
+    /// Somewhere around the scene, looking at a point near its middle
+    ///
+    fn random_viewpoint(&self) -> Bookmark {
+        let distance = self.distance.sample();
+        let around = 2.0 * std::f32::consts::PI * next_f32();
+        let elevation = self.elevation.sample().to_radians();
+
+        let target = Point::new(next_f32() - 0.5, 0.5 * next_f32(), next_f32() - 0.5);
+        let position = target + distance * Vec3::new(elevation.cos() * around.cos(), elevation.sin(), elevation.cos() * around.sin());
+
+        let direction = (target - position).unit();
+        Bookmark {
+            position,
+            pitch: direction.y.asin().to_degrees(),
+            yaw: direction.z.atan2(direction.x).to_degrees(),
+            vfov: self.vfov.sample(),
+            focus_dist: distance,
+        }
+    }
+}
+
+
+fn random_colour(min: f32, max: f32) -> Colour {
+    let channel = || min + (max - min) * next_f32();
+    Colour::new(channel(), channel(), channel())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenes_follow_the_distributions() {
+        let generator = Generator::DEFAULT.parse_overrides("objects=5,materials=0:1:0,lights=2,hours=12,elevation=30..40").unwrap();
+        assert_eq!(generator.objects, Uniform::fixed(5.0));
+        assert!(Generator::DEFAULT.parse_overrides("objects=many").is_none());
+        assert!(Generator::DEFAULT.parse_overrides("materials=1:2").is_none());
+
+        let arena = Arena::new();
+        let scene = generator.scene(&arena, Stream::new(7));
+        // the ground, five objects and two lights
+        assert_eq!(scene.objects.len(), 8);
+        assert!(matches!(scene.sky, Sky::Daylight { .. }));
+        assert!((-40.0..=-30.0).contains(&scene.viewpoint.pitch), "{}", scene.viewpoint.pitch);
+
+        // the same stream gives the same scene
+        let again = generator.scene(&arena, Stream::new(7));
+        assert_eq!(again.viewpoint.position, scene.viewpoint.position);
+    }
+}
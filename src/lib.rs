@@ -0,0 +1,19 @@
+pub mod math;
+pub mod camera;
+pub mod session;
+pub mod dataset;
+pub mod generator;
+pub mod tuning;
+pub mod options;
+pub mod rng;
+pub mod utils;
+pub mod rt;
+pub mod perlin_noise;
+
+
+pub const RENDER_RESOLUTION : usize = 1080;
+pub const RENDER_RESOLUTION_X : usize = (RENDER_RESOLUTION as f32 * ASPECT_RATIO) as usize;
+pub const MAX_DEPTH : usize = 25;
+pub const ASPECT_RATIO : f32 = 16.0 / 9.0;
+/// in metres per second, the camera converts it to the scene's units
+pub const CAMERA_SPEED : f32 = 5.0;
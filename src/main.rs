@@ -1,60 +1,248 @@
-mod math;
-mod camera;
-pub mod rng;
-pub mod utils;
-pub mod rt;
-pub mod perlin_noise;
+use std::{env, f32::consts::PI, fmt::Display, io, mem::transmute, num::{NonZero, NonZeroU32}, rc::Rc, time::{Duration, Instant}};
 
-use std::{env, fs, mem::transmute, num::{NonZero, NonZeroU32}, rc::Rc, time::Instant};
-
-use perlin_noise::PerlinNoise;
-use sdl2::{event::Event, keyboard::Keycode, pixels::PixelFormatEnum, rect::Rect, render::TextureAccess, sys::SDL_CreateTexture, TimerSubsystem};
+use raytracing::{dataset, perlin_noise::PerlinNoise, rng, session, tuning, utils, ASPECT_RATIO, MAX_DEPTH, RENDER_RESOLUTION, RENDER_RESOLUTION_X};
+use sdl2::{event::Event, keyboard::{Keycode, Mod}, mouse::MouseButton, pixels::PixelFormatEnum, rect::Rect, render::TextureAccess, sys::SDL_CreateTexture, TimerSubsystem};
 use sti::arena::Arena;
 
-use crate::{camera::Camera, math::vec3::{Colour, Point, Vec3}, rt::{hittable::Hittable, materials::Material, texture::Texture}};
+use raytracing::{camera::Camera, options::{Comparison, Mode, Options, SkyOption}, session::{Bookmarks, FrameInput, Quality, Recording, Reference}, math::{aabb::AABB, matrix::Matrix4, vec3::{Colour, Point, Vec3}}, rt::{aperture::Aperture, bake::{self, BakeOptions}, camera::Region, curve::Curve, fog::Fog, fractal, sky::Sky, hittable::{Accelerator, BvhOptions, Hittable, HittableKind}, material_library::MaterialLibrary, materials::Material, medium::{Medium, TemperatureGrid}, mesh::Mesh, scatter::{self, ScatterOptions}, sdf, texture::Texture, validation, voxels::VoxelOctree}};
 
 
-const RENDER_RESOLUTION : usize = 1080;
-const RENDER_RESOLUTION_X : usize = (RENDER_RESOLUTION as f32 * ASPECT_RATIO) as usize;
 const DISPLAY_RESOLUTION : usize = 900;
 const DISPLAY_RESOLUTION_X : usize = (DISPLAY_RESOLUTION as f32 * ASPECT_RATIO) as usize;
-const MAX_DEPTH : usize = 25;
-const ASPECT_RATIO : f32 = 16.0 / 9.0;
 const SENSITIVITY : f32 = 0.05;
-const CAMERA_SPEED : f32 = 5.0;
+/// in stops, how much the light and exposure hotkeys change by
+const EXPOSURE_STEP : f32 = 0.5;
+const TUNING_PROBE_SAMPLES : usize = 4;
+/// the BVH is tuned on renders this many times narrower than the real one
+const TUNING_PROBE_SCALE : usize = 4;
+const TIMELAPSE_LATITUDE : f32 = 45.0;
+const FOREST_LOD_DISTANCE : f32 = 20.0;
+const PREVIEW_FOG : Fog = Fog { colour: Colour::new(0.8, 0.85, 0.9), start: 5.0, end: 60.0 };
+/// the built-in scenes alt and a number switch between,
+/// the name is what their tuning and bookmarks are kept by
+const SCENES : [(&str, Scene); 13] = [
+    ("bouncing_spheres", bouncing_spheres),
+    ("tori", tori),
+    ("sdf_shapes", sdf_shapes),
+    ("quads", quads),
+    ("transforms", transforms),
+    ("forest", forest),
+    ("campfire", campfire),
+    ("pool", pool),
+    ("meshes", meshes),
+    ("fractals", fractals),
+    ("flat_shapes", flat_shapes),
+    ("grass", grass),
+    ("voxel_terrain", voxel_terrain),
+];
+
+
+type Scene = for<'a> fn(&'a Arena, &MaterialLibrary<'a>, Accelerator) -> Hittable<'a>;
 
 
 fn main() {
+    let scene_names = SCENES.map(|(name, _)| name);
+    let options = Options::parse(Vec::from_iter(env::args().skip(1)), &scene_names).unwrap_or_else(|usage| {
+        eprintln!("{usage}");
+        std::process::exit(1)
+    });
+
+    // before anything uses the thread pool
+    if options.pin_threads {
+        if let Err(err) = utils::pin_render_threads() { println!("Couldn't pin the render threads: {err}") }
+    }
+
     println!("Setting up..");
     let time = Instant::now();
 
     // Camera
-    let mut camera = Camera::new(Point::new(-0.0, 7.0, -0.0), Vec3::new(1.0, 0.0, 0.0),
-                             ASPECT_RATIO, RENDER_RESOLUTION_X as usize, MAX_DEPTH, 20.0,
-                             Vec3::new(0.0, 2.0, 0.0), 0.0, 10.0);
-    camera.change_pitch_yaw_by(-90.0, 0.0);
+    let mut camera = default_camera();
 
-    // Rng
-    for _ in 0..RENDER_RESOLUTION {
-        rng::next_f32();
+    // World
+    let accelerator = options.accelerator;
+
+    // how many cutouts of alpha masked materials rays go through,
+    // fewer makes scenes full of fences and leaves faster to render
+    if let Some(depth) = options.transparency_depth { camera.rt_cam.settings.transparency_depth = depth }
+
+    // with `camera` the levels of detail are picked once from where
+    // the camera starts in each scene instead of for every ray
+    let lod_arena = Arena::new();
+    let fixed_lod = options.fixed_lod.then_some(&lod_arena);
+
+    // the built-in scenes look their materials up by name in here
+    // and use their own for the names it doesn't have
+    let library_arena = Arena::new();
+    let mut materials = MaterialLibrary::new();
+    if let Some(path) = &options.materials {
+        or_exit(materials.load(&library_arena, path), path, "usage: --materials <file>");
     }
 
-    // World
-    let arena = Arena::new();
-    let world = bouncing_spheres(&arena);
+    // every scene gets its own arena, switching to another one
+    // keeps those already built around to switch back to
+    let arenas : [Arena; SCENES.len()] = std::array::from_fn(|_| Arena::new());
+    let mut worlds : [Option<Hittable>; SCENES.len()] = std::array::from_fn(|_| None);
+    // alt and a number only reaches the first nine
+    let mut scene = options.scene;
+
+    let quality = options.quality;
+    // an explicit target noise wins over the preset's
+    let target_noise = options.target_noise.or(quality.map(Quality::target_noise));
+
+    if let Some(pixel_aspect) = options.pixel_aspect { camera.pixel_aspect = pixel_aspect }
+
+    // for scenes and meshes modelled in something other than metres,
+    // it scales the camera's speed, height and the bounce offset
+    if let Some(units) = options.units { camera.units = units }
+
+    // lights given in lumens or watts look like a photo taken at it
+    if let Some(ev100) = options.ev100 { camera.exposure = -ev100 }
+
+    let sky_arena = Arena::new();
+    if let Some(sky) = &options.sky {
+        camera.sky = match sky {
+            SkyOption::Sky(sky) => *sky,
+            SkyOption::Daylight { hours } => Sky::at_time_of_day(*hours, TIMELAPSE_LATITUDE),
+            SkyOption::Environment { path } => {
+                const USAGE : &str = "usage: --sky env:<path>";
+                let mut image = or_exit(image::ImageReader::open(path), path, USAGE);
+                image.no_limits();
+                Sky::Environment { image: sky_arena.alloc_new(or_exit(image.decode(), path, USAGE).into_rgb32f()) }
+            },
+        };
+    }
+
+    // a mask the defocus blur takes the shape of
+    let aperture_arena = Arena::new();
+    if let Some(path) = &options.aperture {
+        const USAGE : &str = "usage: --aperture <image>";
+        let mut image = or_exit(image::ImageReader::open(path), path, USAGE);
+        image.no_limits();
+        let image = or_exit(image.decode(), path, USAGE).into_rgb32f();
+        let aperture = Aperture::from_image(&image).expect("the aperture has to let some light through");
+        camera.set_aperture(Some(aperture_arena.alloc_new(aperture)));
+    }
+
+    camera.debug = options.debug;
+
+    // R saves the image here and the viewer prints how far off it is
+    let reference_path = options.reference;
+
+    if let Some(quality) = quality { quality.apply(&mut camera) }
+    if let Some(overscan) = options.overscan { camera.set_overscan(overscan) }
+    if let Some(precision) = options.precision { camera.set_precision(precision) }
+    let samples_or = |default: usize| quality.map_or(default, Quality::samples);
+
+    let mode = options.mode;
+
+    // a replay or a render starts in the scene and
+    // from where the recording was started
+    let replayed = match &mode {
+        Mode::Replay { path } | Mode::Render { path, .. } => {
+            let usage = if matches!(mode, Mode::Replay { .. }) { "usage: replay <file>" } else { "usage: render <file> [samples]" };
+            let recording = or_exit(Recording::load(path), path, usage);
+
+            let unknown = |scene: &dyn Display| -> ! {
+                eprintln!("{path}: there's no scene {scene}\n{usage}");
+                std::process::exit(1)
+            };
+            scene = SCENES.iter().position(|(name, _)| *name == recording.scene())
+                .unwrap_or_else(|| unknown(&format!("called '{}'", recording.scene())));
+            if let Some(index) = recording.frames().iter().find_map(|f| f.switch_scene.filter(|&index| index >= SCENES.len())) {
+                unknown(&index);
+            }
+
+            camera.go_to(recording.start());
+            Some(recording)
+        },
+        _ => None,
+    };
+
+    // benchmarks and datasets build scenes of their own and a bake
+    // only builds one with `--in-scene`, the scene isn't built or
+    // its BVH tuned for them
+    if !matches!(mode, Mode::Bench | Mode::Dataset { .. } | Mode::Bake { .. }) {
+        set_world(&mut camera, fixed_lod, scene_world(&arenas, &mut worlds, scene, &materials, accelerator));
+    }
 
-    camera.set_world(world);
-    
     println!("Set up in {}ms", time.elapsed().as_millis());
 
-    let mut args = env::args();
-    args.next();
+    // the file a session is recorded into and the recording being replayed
+    let mut recording = None;
+    let mut replay = None;
+
+    match mode {
+        Mode::Interactive => (),
+
+        Mode::Image => {
+            render_image(camera, samples_or(50), target_noise);
+            return;
+        },
+
+        Mode::Bench => {
+            benchmark(10);
+            return;
+        },
+
+        Mode::Record { path } => recording = Some((path, Recording::new(SCENES[scene].0, camera.bookmark()))),
+
+        Mode::Replay { .. } => replay = replayed,
+
+        Mode::Render { samples, .. } => {
+            let recording = replayed.expect("the recording is loaded with the mode");
+            recording.render_offline(camera, PREVIEW_FOG, samples.unwrap_or(samples_or(100)), target_noise, "frames", |camera, index| {
+                let world = scene_world(&arenas, &mut worlds, index, &materials, accelerator);
+                // the levels are picked from where it's framed from
+                camera.frame(&world);
+                set_world(camera, fixed_lod, world);
+            }).unwrap();
+            return;
+        },
+
+        Mode::Timelapse { frames, samples } => {
+            session::render_timelapse(camera, TIMELAPSE_LATITUDE, frames, samples.unwrap_or(samples_or(100)), target_noise, "frames").unwrap();
+            return;
+        },
+
+        Mode::Dataset { out_dir, count, samples, seed, generator } => {
+            dataset::generate(&out_dir, &generator, count, samples.unwrap_or(samples_or(32)), seed).unwrap();
+            return;
+        },
+
+        Mode::Bake { mesh_path, out_path, bake, size, samples, padding, in_scene } => {
+            const USAGE : &str = "usage: bake <obj> <out.pfm|.hdr|.exr> [ao|irradiance] [size] [samples] [--padding <texels>] [--in-scene <scene>]";
+            let samples = samples.unwrap_or(samples_or(BakeOptions::DEFAULT.samples));
+            let options = BakeOptions { size: (size, size), samples, padding, transparency_depth: camera.rt_cam.settings.transparency_depth };
+
+            let arena = Arena::new();
+            let mesh = arena.alloc_new(or_exit(Mesh::load_obj(&arena, &mesh_path, true), &mesh_path, USAGE));
+            let mut world = Hittable::mesh(&arena, mesh, Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.8, 0.8, 0.8)) });
+            if let Some(scene) = in_scene {
+                let scene = scene_world(&arenas, &mut worlds, scene, &materials, accelerator);
+                world = Hittable::list(arena.alloc_new([world, scene]));
+            }
+
+            let time = Instant::now();
+            let pixels = bake::bake(mesh, &world, &camera.sky, camera.units, bake, options)
+                .unwrap_or_else(|| panic!("{mesh_path} has no uvs to bake into"));
+            println!("Baked in {}ms", time.elapsed().as_millis());
 
-    if args.next().is_some_and(|x| &x == "image") {
-        render_image(camera, 50);
-        return;
+            save_hdr(&out_path, options.size, pixels).unwrap();
+            return;
+        },
     }
 
+    // the camera of the right half of the window, it gets the same
+    // input and follows the left camera around wherever it goes
+    let compare_arenas : [Arena; SCENES.len()] = std::array::from_fn(|_| Arena::new());
+    let mut compare_worlds : [Option<Hittable>; SCENES.len()] = std::array::from_fn(|_| None);
+    let mut compare = options.compare.map(|comparison| {
+        let mut compare = camera.clone();
+        comparison.apply(&mut compare);
+        compare.set_world(compare_world(comparison, scene, &materials, accelerator, (&arenas, &mut worlds), (&compare_arenas, &mut compare_worlds)));
+        (compare, comparison)
+    });
+
     let sdl_ctx = sdl2::init().unwrap();
     let video_subsystem = sdl_ctx.video().unwrap();
 
@@ -80,47 +268,114 @@ fn main() {
     let mut event_pump = sdl_ctx.event_pump().unwrap();
     let timer = sdl_ctx.timer().unwrap();
 
-    let mut forward = false;
-    let mut backward = false;
-    let mut left = false;
-    let mut right = false;
-    let mut speedboost = false;
+    let mut input = FrameInput::default();
+    let mut replay_frame = 0;
+    // the first point picked with the measuring tool
+    let mut measure_from = None;
+    // viewpoints are kept next to the scene across sessions
+    let mut bookmarks_path = format!("{}.bookmarks", SCENES[scene].0);
+    let mut bookmarks = load_bookmarks(&bookmarks_path);
     let mut last = timer.performance_counter();
 
+    let mut reference = match Reference::load(&reference_path) {
+        Ok(reference) if reference.size == (RENDER_RESOLUTION_X, RENDER_RESOLUTION) => Some(reference),
+        Ok(_) => { println!("Ignoring '{reference_path}', it's not the size of the render"); None },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => { println!("Couldn't load the reference: {err}"); None },
+    };
+
     'main: loop {
         let now = timer.performance_counter();
-        let dt = (now - last) as f32 / timer.performance_frequency() as f32;
+        input.dt = (now - last) as f32 / timer.performance_frequency() as f32;
+        input.delta_pitch = 0.0;
+        input.delta_yaw = 0.0;
+        input.toggle_fog = false;
+        input.toggle_collide = false;
+        input.toggle_walk = false;
+        input.switch_scene = None;
+        input.go_to = None;
+        input.light_stops = 0.0;
+        input.exposure_stops = 0.0;
+        input.linked_lighting = false;
         last = now;
 
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'main,
                 Event::MouseMotion { xrel, yrel, .. } => {
-                    camera.change_pitch_yaw_by(yrel as f32 * SENSITIVITY, xrel as f32 * SENSITIVITY);
+                    input.delta_pitch += yrel as f32 * SENSITIVITY;
+                    input.delta_yaw += xrel as f32 * SENSITIVITY;
                 }
 
-                Event::KeyDown { keycode, .. } => {
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, .. } => measure(&camera, &mut measure_from),
+
+                Event::KeyDown { keycode, keymod, .. } => {
                     let Some(key) = keycode else { continue };
+                    // holding shift keeps the lights as bright on screen
+                    let linked = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+
+                    // ctrl and a number stores a bookmark, the number alone goes back to it
+                    // and alt and a number switches to another scene
+                    if let Some(slot) = bookmark_slot(key) {
+                        if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+                            input.switch_scene = Some(slot as usize - 1);
+                        } else if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+                            bookmarks.set(slot, camera.bookmark());
+                            match bookmarks.save(&bookmarks_path) {
+                                Ok(()) => println!("Stored bookmark {slot}"),
+                                Err(err) => println!("Couldn't save the bookmarks: {err}"),
+                            }
+                        } else if let Some(bookmark) = bookmarks.get(slot) {
+                            input.go_to = Some(bookmark);
+                        }
+
+                        continue;
+                    }
                     
                     match key {
-                        Keycode::W => forward = true,
-                        Keycode::S => backward = true,
-                        Keycode::D => right = true,
-                        Keycode::A => left = true,
-                        Keycode::Space => speedboost = true,
+                        Keycode::W => input.forward = true,
+                        Keycode::S => input.backward = true,
+                        Keycode::D => input.right = true,
+                        Keycode::A => input.left = true,
+                        Keycode::Space => input.speedboost = true,
+                        Keycode::F => input.toggle_fog = true,
+                        Keycode::C => input.toggle_collide = true,
+                        Keycode::G => input.toggle_walk = true,
+                        Keycode::R => {
+                            let image = Reference { size: (RENDER_RESOLUTION_X, RENDER_RESOLUTION), pixels: camera.mean_image() };
+                            match image.save(&reference_path) {
+                                Ok(()) => println!("Saved the reference to '{reference_path}' after {} samples", camera.samples),
+                                Err(err) => println!("Couldn't save the reference: {err}"),
+                            }
+                            reference = Some(image);
+                        },
                         _ => (),
                     };
+
+                    let lighting = match key {
+                        Keycode::L => Some((EXPOSURE_STEP, 0.0, linked)),
+                        Keycode::K => Some((-EXPOSURE_STEP, 0.0, linked)),
+                        Keycode::Equals => Some((0.0, EXPOSURE_STEP, false)),
+                        Keycode::Minus => Some((0.0, -EXPOSURE_STEP, false)),
+                        _ => None,
+                    };
+
+                    if let Some((light_stops, exposure_stops, linked)) = lighting {
+                        input.light_stops += light_stops;
+                        input.exposure_stops += exposure_stops;
+                        input.linked_lighting |= linked;
+                    }
                 }
 
                 Event::KeyUp { keycode, .. } => {
                     let Some(key) = keycode else { continue };
                     
                     match key {
-                        Keycode::W => forward = false,
-                        Keycode::S => backward = false,
-                        Keycode::D => right = false,
-                        Keycode::A => left = false,
-                        Keycode::Space => speedboost = false,
+                        Keycode::W => input.forward = false,
+                        Keycode::S => input.backward = false,
+                        Keycode::D => input.right = false,
+                        Keycode::A => input.left = false,
+                        Keycode::Space => input.speedboost = false,
                         _ => (),
                     };
                 }
@@ -129,17 +384,60 @@ fn main() {
         }
 
 
-        let mut cam_speed = CAMERA_SPEED * dt as f32;
+        // a replay ignores the live input and uses the recorded frames
+        let frame = match &replay {
+            Some(replay) => {
+                let Some(&frame) = replay.frames().get(replay_frame) else { break 'main };
+                replay_frame += 1;
+                frame
+            },
+
+            None => input,
+        };
+
+        if let Some(index) = frame.switch_scene.filter(|&index| index != scene) {
+            scene = index;
+            let world = scene_world(&arenas, &mut worlds, scene, &materials, accelerator);
+            // the levels are picked from where it's framed from
+            camera.frame(&world);
+            set_world(&mut camera, fixed_lod, world);
+
+            if let Some((compare, comparison)) = &mut compare {
+                compare.set_world(compare_world(*comparison, scene, &materials, accelerator, (&arenas, &mut worlds), (&compare_arenas, &mut compare_worlds)));
+            }
+
+            bookmarks_path = format!("{}.bookmarks", SCENES[scene].0);
+            bookmarks = load_bookmarks(&bookmarks_path);
+            println!("Switched to '{}'", SCENES[scene].0);
+        }
+
+        frame.apply(&mut camera, PREVIEW_FOG);
+        if frame.light_stops != 0.0 || frame.exposure_stops != 0.0 {
+            println!("Lights x{}, exposure {:+} stops", camera.light_scale, camera.exposure);
+        }
+
+        if let Some((compare, _)) = &mut compare {
+            frame.apply(compare, PREVIEW_FOG);
+            // a different world can make walking or colliding
+            // take it somewhere else
+            if compare.bookmark() != camera.bookmark() { compare.go_to(camera.bookmark()) }
+        }
 
-        if speedboost { cam_speed *= 5.0 }
-        if forward { camera.move_by(cam_speed * camera.forward()) }
-        if backward { camera.move_by(cam_speed * camera.backward()) }
-        if left { camera.move_by(cam_speed * camera.left()) }
-        if right { camera.move_by(cam_speed * camera.right()) }
+        if let Some((_, recording)) = &mut recording {
+            recording.push(frame);
+        }
 
 
         let render_time = timed(&timer, || {
-            camera.render(pixels.as_mut_slice());
+            let Some((compare, _)) = &mut compare
+            else { return camera.render(pixels.as_mut_slice()) };
+
+            let (width, height) = (RENDER_RESOLUTION_X, RENDER_RESOLUTION);
+            camera.render_region(pixels.as_mut_slice(), Region { min: (0, 0), max: (width / 2, height) });
+            compare.render_region(pixels.as_mut_slice(), Region { min: (width / 2, 0), max: (width, height) });
+
+            // a line between both halves
+            for row in pixels.chunks_exact_mut(width) { row[width / 2] = u32::MAX }
         });
 
         let draw_time = timed(&timer, || {
@@ -154,10 +452,20 @@ fn main() {
         });
 
 
-        println!("Rendered in {render_time}ms, Drawn in {draw_time}ms");
+        match &reference {
+            Some(reference) => println!("Rendered in {render_time}ms, Drawn in {draw_time}ms, RMSE {}", camera.rmse(&reference.pixels)),
+            None => println!("Rendered in {render_time}ms, Drawn in {draw_time}ms"),
+        }
 
     }
 
+    if let Some((path, recording)) = recording {
+        match recording.save(&path) {
+            Ok(()) => println!("Saved {} frames to '{path}'", recording.frames().len()),
+            Err(err) => println!("Couldn't save the recording: {err}"),
+        }
+    }
+
     // else, raylib
     /*
     let mut window = Window::new("Raytracing", RENDER_RESOLUTION_X, RENDER_RESOLUTION, WindowOptions {
@@ -212,7 +520,33 @@ fn main() {
 }
 
 
-fn world_sphere<'a>(arena: &'a Arena) -> Hittable<'a> {
+///
+/// Picks the point in the middle of the screen. Every second
+/// pick prints how far it is from the one before, in the scene's
+/// units and in metres, which helps with placing objects by hand
+///
+fn measure(camera: &Camera, from: &mut Option<(Point, Vec3)>) {
+    let Some((point, normal)) = camera.pick() else {
+        println!("Measure: nothing to pick");
+        return;
+    };
+
+    match from.take() {
+        None => {
+            println!("Measure: from {point} (normal {normal})");
+            *from = Some((point, normal));
+        },
+
+        Some((start, start_normal)) => {
+            let distance = (point - start).length();
+            println!("Measure: from {start} (normal {start_normal}) to {point} (normal {normal})");
+            println!("Measure: distance {distance} ({} m), offset {}", distance * camera.units.metres(), point - start);
+        },
+    }
+}
+
+
+fn world_sphere<'a>(arena: &'a Arena, accelerator: Accelerator) -> Hittable<'a> {
     let mut world = sti::vec::Vec::new_in(arena);
 
     let mut image = image::ImageReader::open("earthmap3.png").unwrap();
@@ -222,31 +556,31 @@ fn world_sphere<'a>(arena: &'a Arena) -> Hittable<'a> {
     let material_ground = Material::Lambertian { texture: Texture::Image { image } };
     world.push(Hittable::sphere(Point::new(0.0, 0.0, 0.0), 2.0, material_ground));
 
-    let world = Hittable::bvh(&arena, world.leak());
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
     world
 }
 
 
-fn checkered_spheres<'a>(arena: &'a Arena) -> Hittable<'a> {
+fn checkered_spheres<'a>(arena: &'a Arena, accelerator: Accelerator) -> Hittable<'a> {
     let mut world = sti::vec::Vec::new_in(arena);
 
     let material_ground = Material::Lambertian { texture: Texture::Checkerboard { inv_scale: 1.0, even: arena.alloc_new(Texture::SolidColour(Colour::ZERO)), odd: arena.alloc_new(Texture::SolidColour(Colour::ONE)) } };
     world.push(Hittable::sphere(Point::new(0.0, -10.0, 0.0), 10.0, material_ground));
     world.push(Hittable::sphere(Point::new(0.0, 10.0, 0.0), 10.0, material_ground));
 
-    let world = Hittable::bvh(&arena, world.leak());
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
     world
 }
 
 
-fn test<'a>(arena: &'a Arena) -> Hittable<'a> {
+fn test<'a>(arena: &'a Arena, accelerator: Accelerator) -> Hittable<'a> {
     let mut world = sti::vec::Vec::new_in(arena);
 
     let material_ground = Material::Lambertian { texture: Texture::NoiseTexture(PerlinNoise::new(arena, 256*16), 0.1) };
     world.push(Hittable::sphere(Point::new(0.0, -1000.0, 0.0), 1000.0, material_ground));
 
    
-    let mat = Material::Dielectric { refraction_index: 1.5, texture: Texture::SolidColour(Colour::ONE)};
+    let mat = Material::Dielectric { refraction_index: 1.5, thin_walled: false, absorption: Colour::ZERO, texture: Texture::SolidColour(Colour::ONE)};
     world.push(Hittable::sphere(Point::new(0.0, 1.0, 0.0), 1.0, mat));
 
     let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.4, 0.2, 0.1)) };
@@ -254,18 +588,357 @@ fn test<'a>(arena: &'a Arena) -> Hittable<'a> {
 
     let mat = Material::Metal { texture: Texture::SolidColour(Colour::new(0.7, 0.6, 0.5)), fuzz_radius: 0.0 };
     world.push(Hittable::sphere(Point::new(4.0, 1.0, 0.0), 1.0, mat));
-    let world = Hittable::bvh(&arena, world.leak());
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
     world
 }
 
 
 
 
-fn bouncing_spheres<'a>(arena: &'a Arena) -> Hittable<'a> {
+fn tori<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
     let mut world = sti::vec::Vec::new_in(arena);
 
-    let material_ground = Material::Lambertian { texture: Texture::Checkerboard { inv_scale: 0.64, even: arena.alloc_new(Texture::SolidColour(Colour::ZERO)), odd: arena.alloc_new(Texture::SolidColour(Colour::ONE)) } };
-    world.push(Hittable::sphere(Point::new(0.0, -1000.0, 0.0), 1000.0, material_ground));
+    let material_ground = materials.material_or("checkerboard", Material::Lambertian { texture: Texture::Checkerboard { inv_scale: 0.64, even: arena.alloc_new(Texture::SolidColour(Colour::ZERO)), odd: arena.alloc_new(Texture::SolidColour(Colour::ONE)) } });
+    world.push(Hittable::plane(Point::ZERO, Vec3::new(0.0, 1.0, 0.0), material_ground));
+
+    let mat = materials.material_or("gold", Material::Metal { texture: Texture::SolidColour(Colour::new(0.8, 0.6, 0.2)), fuzz_radius: 0.1 });
+    world.push(Hittable::torus(Point::new(0.0, 0.5, 0.0), 2.0, 0.5, mat));
+
+    let mat = materials.material_or("glass", Material::Dielectric { refraction_index: 1.5, thin_walled: false, absorption: Colour::ZERO, texture: Texture::SolidColour(Colour::ONE)});
+    world.push(Hittable::torus(Point::new(-5.0, 0.3, 0.0), 1.0, 0.3, mat));
+
+    let mat = materials.material_or("blue_paint", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.2, 0.3, 0.7)) });
+    world.push(Hittable::torus(Point::new(5.0, 0.3, 0.0), 1.0, 0.3, mat));
+
+    let frosted = materials.material_or("frosted_glass", Material::RoughDielectric { refraction_index: 1.5, reflection_roughness: 0.05, transmission_roughness: 0.4, absorption: Colour::ZERO, texture: Texture::SolidColour(Colour::ONE) });
+    world.push(Hittable::torus(Point::new(0.0, 0.3, 4.0), 1.0, 0.3, frosted));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn voxel_terrain<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    const DEPTH : u32 = 7;
+    const SIZE : usize = 1 << DEPTH;
+
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let noise = PerlinNoise::new(arena, 256);
+    let materials = arena.alloc_new([
+        materials.material_or("stone", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.4, 0.4, 0.4)) }),
+        materials.material_or("grass", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.3, 0.6, 0.2)) }),
+    ]);
+
+    let octree = VoxelOctree::new(arena, Point::new(-(SIZE as f32) * 0.25, -8.0, -(SIZE as f32) * 0.25), 0.5, DEPTH, materials,
+    |x, y, z| {
+        let p = 0.05 * Point::new(x as f32, 0.0, z as f32);
+        let height = (16.0 + 24.0 * noise.turbulance(p, 4)) as usize;
+        if y > height { None }
+        else if y == height { Some(1) }
+        else { Some(0) }
+    });
+
+    world.push(Hittable::voxels(arena.alloc_new(octree)));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn sdf_shapes<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let material_ground = materials.material_or("ground", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.5, 0.5, 0.5)) });
+    world.push(Hittable::plane(Point::ZERO, Vec3::new(0.0, 1.0, 0.0), material_ground));
+
+    let blob = arena.alloc_new(|p: Point| {
+        let sphere = sdf::sphere(p - Point::new(0.0, 1.6, 0.0), 0.8);
+        let cube = sdf::round_box(p - Point::new(0.0, 0.7, 0.0), Vec3::new(0.6, 0.6, 0.6), 0.1);
+        sdf::smooth_min(sphere, cube, 0.4)
+    });
+    let mat = materials.material_or("steel", Material::Metal { texture: Texture::SolidColour(Colour::new(0.8, 0.8, 0.9)), fuzz_radius: 0.05 });
+    world.push(Hittable::sdf(AABB::from_points(Point::new(-1.0, 0.0, -1.0), Point::new(1.0, 2.5, 1.0)), blob, mat));
+
+    let centre = Point::new(3.0, 1.2, 0.0);
+    let bulb = arena.alloc_new(move |p: Point| sdf::mandelbulb((p - centre) / 1.2, 8.0, 12) * 1.2);
+    let mat = materials.material_or("clay", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.8, 0.3, 0.2)) });
+    world.push(Hittable::sdf(AABB::from_points(centre - Vec3::new(1.4, 1.4, 1.4), centre + Vec3::new(1.4, 1.4, 1.4)), bulb, mat));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn quads<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let left_red     = Material::Lambertian { texture: Texture::SolidColour(Colour::new(1.0, 0.2, 0.2)) };
+    let back_green   = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.2, 1.0, 0.2)) };
+    let right_blue   = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.2, 0.2, 1.0)) };
+    let upper_orange = Material::Lambertian { texture: Texture::SolidColour(Colour::new(1.0, 0.5, 0.0)) };
+    let lower_teal   = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.2, 0.8, 0.8)) };
+
+    // the walls all face inwards, so the box can be looked into from any side
+    world.push(Hittable::quad(Point::new(-3.0, -2.0, 5.0), Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 4.0, 0.0), left_red).one_sided(arena));
+    world.push(Hittable::quad(Point::new(-2.0, -2.0, 0.0), Vec3::new(4.0, 0.0,  0.0), Vec3::new(0.0, 4.0, 0.0), back_green).one_sided(arena));
+    world.push(Hittable::quad(Point::new( 3.0, -2.0, 1.0), Vec3::new(0.0, 0.0,  4.0), Vec3::new(0.0, 4.0, 0.0), right_blue).one_sided(arena));
+    world.push(Hittable::quad(Point::new(-2.0,  3.0, 1.0), Vec3::new(4.0, 0.0,  0.0), Vec3::new(0.0, 0.0, 4.0), upper_orange).one_sided(arena));
+    world.push(Hittable::quad(Point::new(-2.0, -3.0, 5.0), Vec3::new(4.0, 0.0,  0.0), Vec3::new(0.0, 0.0,-4.0), lower_teal).one_sided(arena));
+
+    // a single pane of glass in front of the back wall
+    let glass = materials.material_or("pane", Material::Dielectric { refraction_index: 1.5, thin_walled: true, absorption: Colour::ZERO, texture: Texture::SolidColour(Colour::ONE) });
+    world.push(Hittable::quad(Point::new(-1.0, -1.0, 2.0), Vec3::new(2.0, 0.0,  0.0), Vec3::new(0.0, 2.0, 0.0), glass));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn flat_shapes<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let red    = Material::Lambertian { texture: Texture::SolidColour(Colour::new(1.0, 0.2, 0.2)) };
+    let green  = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.2, 1.0, 0.2)) };
+    let blue   = materials.material_or("blue_paint", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.2, 0.2, 1.0)) });
+
+    world.push(Hittable::planar_triangle(Point::new(-3.0, -1.0, 1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), red));
+    world.push(Hittable::ellipse(Point::new(0.0, 0.0, 1.0), Vec3::new(0.8, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), green));
+
+    let hexagon = Vec::from_iter((0..6).map(|i| {
+        let angle = i as f32 * PI / 3.0;
+        Point::new(2.0 + angle.cos(), angle.sin(), 1.0)
+    }));
+    world.push(Hittable::polygon(arena, &hexagon, blue));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn transforms<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let material_ground = materials.material_or("ground", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.5, 0.5, 0.5)) });
+    world.push(Hittable::plane(Point::ZERO, Vec3::new(0.0, 1.0, 0.0), material_ground));
+
+    // a unit cube around the origin spun around each axis
+    let cube = |i: usize| {
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.2 + 0.2 * i as f32, 0.4, 0.8 - 0.2 * i as f32)) };
+        Hittable::axis_aligned_box(Point::new(-0.5, -0.5, -0.5), Point::new(0.5, 0.5, 0.5), mat)
+    };
+
+    let cubes = [
+        cube(0).rotate_x_by(arena, 30.0),
+        cube(1).rotate_y_by(arena, 30.0),
+        cube(2).rotate_z_by(arena, 30.0),
+        cube(3).rotate_axis_by(arena, Vec3::ONE, 30.0),
+    ];
+
+    for (i, cube) in cubes.into_iter().enumerate() {
+        world.push(cube.translate_by(arena, Vec3::new(2.0 * i as f32 - 3.0, 1.0, 0.0)));
+    }
+
+    let mat = materials.material_or("steel", Material::Metal { texture: Texture::SolidColour(Colour::new(0.8, 0.8, 0.9)), fuzz_radius: 0.05 });
+    let ellipsoid = Hittable::sphere(Point::ZERO, 1.0, mat)
+        .scale_by(arena, Vec3::new(2.0, 0.5, 1.0))
+        .rotate_y_by(arena, 45.0)
+        .translate_by(arena, Vec3::new(0.0, 0.5, 3.0));
+    world.push(ellipsoid);
+
+    // a motion blurred cube spinning a little while it slides along
+    let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.9, 0.9, 0.2)) };
+    let cube = Hittable::axis_aligned_box(Point::new(-0.5, -0.5, -0.5), Point::new(0.5, 0.5, 0.5), mat);
+    let start = Matrix4::translation(Vec3::new(-2.0, 0.5, -3.0));
+    let end = Matrix4::translation(Vec3::new(-1.0, 0.5, -3.0)) * Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), 0.3);
+    world.push(cube.moving(arena, start, end));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn forest<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let material_ground = materials.material_or("grass", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.3, 0.5, 0.2)) });
+    world.push(Hittable::plane(Point::ZERO, Vec3::new(0.0, 1.0, 0.0), material_ground));
+
+    // one tree which every placement below shares
+    let mut tree = sti::vec::Vec::new_in(arena);
+    let bark = materials.material_or("bark", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.4, 0.25, 0.1)) });
+    let leaves = materials.material_or("leaves", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.1, 0.45, 0.1)) });
+    tree.push(Hittable::axis_aligned_box(Point::new(-0.1, 0.0, -0.1), Point::new(0.1, 1.0, 0.1), bark));
+    tree.push(Hittable::sphere(Point::new(0.0, 1.3, 0.0), 0.5, leaves));
+    tree.push(Hittable::sphere(Point::new(0.0, 1.8, 0.0), 0.35, leaves));
+    let tree = Hittable::bvh(arena, tree.leak());
+
+    // far away the crown is a single sphere and the trunk is dropped
+    let far = Hittable::sphere(Point::new(0.0, 1.5, 0.0), 0.6, leaves);
+    let tree = arena.alloc_new(Hittable::lod(arena, arena.alloc_new([tree, far]), arena.alloc_new([FOREST_LOD_DISTANCE])));
+
+    for _ in 0..1000 {
+        let position = Vec3::new(rng::next_f32() * 60.0 - 30.0, 0.0, rng::next_f32() * 60.0 - 30.0);
+        let matrix = Matrix4::translation(position)
+                   * Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), rng::next_f32() * 2.0 * PI)
+                   * Matrix4::scaling((0.7 + 0.6 * rng::next_f32()) * Vec3::ONE);
+
+        world.push(Hittable::instance(arena, tree, matrix));
+    }
+
+    // half buried rocks in clumps between the trees
+    let stone = materials.material_or("stone", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.45, 0.43, 0.4)) });
+    let rock = Hittable::mesh(arena, arena.alloc_new(Mesh::uv_sphere(arena, 6, 4, false)), stone);
+    let rock = arena.alloc_new(rock.transformed(arena, Matrix4::scaling(Vec3::new(1.0, 0.6, 0.8))));
+
+    let corners = [Point::new(-30.0, 0.0, -30.0), Point::new(30.0, 0.0, -30.0),
+                   Point::new(30.0, 0.0, 30.0), Point::new(-30.0, 0.0, 30.0)];
+    let ground = Mesh::new(arena, &corners, &[], &[[0, 2, 1], [0, 3, 2]], false);
+    let clumps = Texture::NoiseTexture(PerlinNoise::new(arena, 256), 0.3);
+    let options = ScatterOptions { attempts: 3000, scale: (0.1, 0.35), tilt: 0.4, ..ScatterOptions::DEFAULT };
+    world.push(scatter::scatter(arena, rock, &ground, &clumps, options));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn campfire<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let material_ground = materials.material_or("dirt", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.3, 0.25, 0.2)) });
+    world.push(Hittable::plane(Point::ZERO, Vec3::new(0.0, 1.0, 0.0), material_ground));
+
+    let bark = materials.material_or("charred_bark", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.25, 0.15, 0.08)) });
+    for angle in [0.0, 60.0, 120.0] {
+        let log = Hittable::axis_aligned_box(Point::new(-1.0, 0.0, -0.1), Point::new(1.0, 0.2, 0.1), bark);
+        world.push(log.rotate_y_by(arena, angle));
+    }
+
+    // a flame which is hottest at the bottom centre, narrows as
+    // it goes up and flickers with some turbulence
+    let noise = PerlinNoise::new(arena, 256);
+    let bounds = AABB::from_points(Point::new(-1.0, 0.1, -1.0), Point::new(1.0, 3.1, 1.0));
+    let temperatures = TemperatureGrid::new(arena, bounds, [32, 48, 32], |p| {
+        let height = (p.y - 0.1) / 3.0;
+        let radius = (p.x * p.x + p.z * p.z).sqrt() / (0.9 * (1.0 - height));
+        let falloff = (1.0 - radius).max(0.0) * (1.0 - height);
+        let flicker = 0.6 + 0.8 * noise.turbulance(3.0 * p, 5);
+        1900.0 * falloff * flicker
+    });
+
+    let fire = Medium::fire(arena, arena.alloc_new(temperatures), 4.0);
+    world.push(Hittable::medium(arena.alloc_new(fire)));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn pool<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let tiles = materials.material_or("tiles", Material::Lambertian { texture: Texture::Checkerboard { inv_scale: 2.0, even: arena.alloc_new(Texture::SolidColour(Colour::new(0.9, 0.9, 0.9))), odd: arena.alloc_new(Texture::SolidColour(Colour::new(0.2, 0.5, 0.8))) } });
+    let stone = materials.material_or("stone", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.7, 0.65, 0.6)) });
+
+    // the deck around a 8x6 pool which is 2 deep
+    world.push(Hittable::axis_aligned_box(Point::new(-10.0, -2.5, -10.0), Point::new(10.0, -2.0, 10.0), tiles));
+    world.push(Hittable::axis_aligned_box(Point::new(-10.0, -2.0, -10.0), Point::new(-4.0, 0.0, 10.0), stone));
+    world.push(Hittable::axis_aligned_box(Point::new(  4.0, -2.0, -10.0), Point::new(10.0, 0.0, 10.0), stone));
+    world.push(Hittable::axis_aligned_box(Point::new(-4.0, -2.0, -10.0), Point::new(4.0, 0.0, -3.0), stone));
+    world.push(Hittable::axis_aligned_box(Point::new(-4.0, -2.0,   3.0), Point::new(4.0, 0.0, 10.0), stone));
+
+    // the water overlaps the walls and the floor a little so
+    // everything under the surface is inside of it
+    let water = materials.material_or("water", Material::Water { absorption: Colour::new(0.45, 0.09, 0.06), wave_height: 0.02, time: 0.0 });
+    world.push(Hittable::axis_aligned_box(Point::new(-4.1, -2.1, -3.1), Point::new(4.1, -0.2, 3.1), water));
+
+    let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.9, 0.3, 0.1)) };
+    world.push(Hittable::sphere(Point::new(1.0, -1.5, 0.5), 0.5, mat));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn meshes<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let material_ground = materials.material_or("ground", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.5, 0.5, 0.5)) });
+    world.push(Hittable::plane(Point::ZERO, Vec3::new(0.0, 1.0, 0.0), material_ground));
+
+    // the same low poly sphere shaded flat and smooth
+    let mat = materials.material_or("gold", Material::Metal { texture: Texture::SolidColour(Colour::new(0.8, 0.6, 0.2)), fuzz_radius: 0.2 });
+    let flat = arena.alloc_new(Mesh::uv_sphere(arena, 12, 6, false));
+    let smooth = arena.alloc_new(Mesh::uv_sphere(arena, 12, 6, true));
+    let smooth = arena.alloc_new(Hittable::mesh(arena, smooth, mat));
+    world.push(Hittable::mesh(arena, flat, mat).translate_by(arena, Vec3::new(-1.5, 1.0, 0.0)));
+    world.push(Hittable::instance(arena, smooth, Matrix4::translation(Vec3::new(1.5, 1.0, 0.0))));
+
+    // a coarse cage smoothed by subdivision
+    let cage = Mesh::uv_sphere(arena, 6, 3, true);
+    let subdivided = arena.alloc_new(cage.subdivide(arena, 3));
+    world.push(Hittable::mesh(arena, subdivided, mat).translate_by(arena, Vec3::new(0.0, 1.0, 2.5)));
+
+    // the smooth sphere's geometry again with another material
+    let glass = materials.material_or("glass", Material::Dielectric { refraction_index: 1.5, thin_walled: false, absorption: Colour::ZERO, texture: Texture::SolidColour(Colour::ONE) });
+    world.push(Hittable::instance(arena, smooth, Matrix4::translation(Vec3::new(0.0, 1.0, -2.5))).with_material(arena, glass));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn fractals<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let material_ground = materials.material_or("ground", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.5, 0.5, 0.5)) });
+    world.push(Hittable::plane(Point::ZERO, Vec3::new(0.0, 1.0, 0.0), material_ground));
+
+    let mat = materials.material_or("clay", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.7, 0.3, 0.2)) });
+    world.push(fractal::menger_sponge(arena, 4, mat).translate_by(arena, Vec3::new(-1.5, 1.0, 0.0)));
+
+    let mat = materials.material_or("steel", Material::Metal { texture: Texture::SolidColour(Colour::new(0.8, 0.8, 0.9)), fuzz_radius: 0.1 });
+    world.push(fractal::sierpinski_tetrahedron(arena, 6, mat).translate_by(arena, Vec3::new(1.5, 1.0, 0.0)));
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn grass<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let material_ground = materials.material_or("dirt", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.3, 0.2, 0.1)) });
+    world.push(Hittable::plane(Point::ZERO, Vec3::new(0.0, 1.0, 0.0), material_ground));
+
+    let blade = materials.material_or("grass", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.2, 0.6, 0.1)) });
+    for _ in 0..5000 {
+        let root = Point::new(rng::next_f32() * 10.0 - 5.0, 0.0, rng::next_f32() * 10.0 - 5.0);
+        let height = 0.3 + 0.4 * rng::next_f32();
+        // every blade leans over a bit further towards its tip
+        let lean = height * Vec3::new(rng::next_f32() - 0.5, 0.0, rng::next_f32() - 0.5);
+        let control = [
+            root,
+            root + Vec3::new(0.0, height / 3.0, 0.0),
+            root + 0.4 * lean + Vec3::new(0.0, 2.0 * height / 3.0, 0.0),
+            root + lean + Vec3::new(0.0, 0.9 * height, 0.0),
+        ];
+
+        let curve = arena.alloc_new(Curve::bezier(arena, control, 0.02, 0.002, 8));
+        world.push(Hittable::curve(arena, curve, blade));
+    }
+
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
+    world
+}
+
+
+fn bouncing_spheres<'a>(arena: &'a Arena, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let mut world = sti::vec::Vec::new_in(arena);
+
+    let material_ground = materials.material_or("checkerboard", Material::Lambertian { texture: Texture::Checkerboard { inv_scale: 0.64, even: arena.alloc_new(Texture::SolidColour(Colour::ZERO)), odd: arena.alloc_new(Texture::SolidColour(Colour::ONE)) } });
+    world.push(Hittable::plane(Point::ZERO, Vec3::new(0.0, 1.0, 0.0), material_ground));
 
     
     /*
@@ -287,48 +960,218 @@ fn bouncing_spheres<'a>(arena: &'a Arena) -> Hittable<'a> {
                 let fuzz = next_f32_range(Interval::new(0.0, 0.5));
                 mat = Material::Metal { texture: Texture::SolidColour(albedo), fuzz_radius: fuzz };
             } else {
-                mat = Material::Dielectric { refraction_index: 1.5, texture: Texture::SolidColour(Colour::ONE) }
+                mat = Material::Dielectric { refraction_index: 1.5, thin_walled: false, absorption: Colour::ZERO, texture: Texture::SolidColour(Colour::ONE) }
             }
 
-            world.push(Hittable::moving_sphere(centre, centre_2, 0.2, mat ));
+            world.push(Hittable::moving_sphere(arena, centre, centre_2, 0.2, mat ));
         }
     }*/
 
-    let mat = Material::Dielectric { refraction_index: 1.5, texture: Texture::SolidColour(Colour::ONE)};
+    let mat = materials.material_or("glass", Material::Dielectric { refraction_index: 1.5, thin_walled: false, absorption: Colour::ZERO, texture: Texture::SolidColour(Colour::ONE)});
     world.push(Hittable::sphere(Point::new(0.0, 1.0, 0.0), 1.0, mat));
 
-    let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.4, 0.2, 0.1)) };
+    let mat = materials.material_or("clay", Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.4, 0.2, 0.1)) });
     world.push(Hittable::sphere(Point::new(-4.0, 1.0, 0.0), 1.0, mat));
 
-    let mat = Material::Metal { texture: Texture::SolidColour(Colour::new(0.7, 0.6, 0.5)), fuzz_radius: 0.0 };
+    let mat = materials.material_or("steel", Material::Metal { texture: Texture::SolidColour(Colour::new(0.7, 0.6, 0.5)), fuzz_radius: 0.0 });
     world.push(Hittable::sphere(Point::new(4.0, 1.0, 0.0), 1.0, mat));
 
-    let world = Hittable::bvh(&arena, world.leak());
+    let world = Hittable::accelerate(&arena, world.leak(), accelerator);
     world
 }
 
 
-fn render_image(mut camera: Camera, samples: usize) {
+///
+/// The world of the scene at `index`, built in its arena the first time
+///
+fn scene_world<'a>(arenas: &'a [Arena; SCENES.len()], worlds: &mut [Option<Hittable<'a>>; SCENES.len()],
+                   index: usize, materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    worlds[index].get_or_insert_with(|| build_scene(&arenas[index], SCENES[index], materials, accelerator)).clone()
+}
+
+
+///
+/// Gives `camera` `world`, with the levels of detail seen from
+/// where it is if they're picked in `lod_arena` rather than
+/// for every ray
+///
+fn set_world<'a>(camera: &mut Camera<'a>, lod_arena: Option<&'a Arena>, world: Hittable<'a>) {
+    let Some(arena) = lod_arena else { return camera.set_world(world) };
+
     let time = Instant::now();
-    let mut buff = vec![0; (RENDER_RESOLUTION * RENDER_RESOLUTION_X) as usize];
-    for _ in 0..(samples-1) { camera.render(&mut buff); }
+    camera.set_world(world.select_lods(arena, camera.position));
+    println!("Picked the levels of detail in {}ms", time.elapsed().as_millis());
+}
+
+
+///
+/// The world to compare against the scene at `scene` of the
+/// left half. A different accelerator means building the
+/// scene again in `own`, otherwise it's shared with `shared`
+///
+fn compare_world<'a>(comparison: Comparison, scene: usize, materials: &MaterialLibrary<'a>, accelerator: Accelerator,
+                     shared: (&'a [Arena; SCENES.len()], &mut [Option<Hittable<'a>>; SCENES.len()]),
+                     own: (&'a [Arena; SCENES.len()], &mut [Option<Hittable<'a>>; SCENES.len()])) -> Hittable<'a> {
+    let scene = comparison.scene.unwrap_or(scene);
+    match comparison.accelerator {
+        Some(accelerator) => scene_world(own.0, own.1, scene, materials, accelerator),
+        None => scene_world(shared.0, shared.1, scene, materials, accelerator),
+    }
+}
+
+
+///
+/// Builds `scene` with `accelerator`, a BVH is tuned for the
+/// scene first, and prints whatever's wrong with it
+///
+fn build_scene<'a>(arena: &'a Arena, (name, scene): (&str, Scene), materials: &MaterialLibrary<'a>, accelerator: Accelerator) -> Hittable<'a> {
+    let accelerator = match accelerator {
+        Accelerator::BVH(requested) => {
+            Accelerator::BVH(tuning::tuned_bvh_options(name, requested, |options| probe_bvh(options, (name, scene), materials)))
+        },
+        _ => accelerator,
+    };
+
+    // scenes placing things at random look the same every time
+    rng::Stream::named(name).enter();
+    let world = scene(arena, materials, accelerator);
+    for warning in validation::validate(&world, None) {
+        println!("Warning: {warning}");
+    }
+
+    world
+}
+
+
+fn default_camera<'a>() -> Camera<'a> {
+    let mut camera = Camera::new(Point::new(-0.0, 7.0, -0.0), Vec3::new(1.0, 0.0, 0.0),
+                             ASPECT_RATIO, RENDER_RESOLUTION_X as usize, MAX_DEPTH, 20.0,
+                             Vec3::new(0.0, 2.0, 0.0), 0.0, 10.0);
+    camera.change_pitch_yaw_by(-90.0, 0.0);
+    camera
+}
+
+
+///
+/// Renders the default scene with every acceleration structure
+/// and prints how long `samples` samples took with each
+///
+fn benchmark(samples: usize) {
+    let mut buff = vec![0; RENDER_RESOLUTION * RENDER_RESOLUTION_X];
+
+    for accelerator in Accelerator::ALL {
+        let arena = Arena::new();
+        let mut camera = default_camera();
+
+        let time = Instant::now();
+        let world = bouncing_spheres(&arena, &MaterialLibrary::new(), accelerator);
+        let build_time = time.elapsed().as_millis();
+
+        if let HittableKind::KdTree(tree) = world.kind() {
+            let stats = tree.stats();
+            println!("{accelerator:?}: {} interior nodes, {} leaves ({} empty), depth {}, {} references to {} hittables",
+                     stats.interior_nodes, stats.leaves, stats.empty_leaves, stats.depth,
+                     stats.references, tree.hittables().len());
+        }
+        camera.set_world(world);
+
+        let time = Instant::now();
+        for _ in 0..samples { camera.render(&mut buff); }
+        let render_time = time.elapsed().as_millis();
+
+        println!("{accelerator:?}: built in {build_time}ms, rendered {samples} samples in {render_time}ms");
+    }
+}
+
+
+///
+/// Renders a few samples of `scene` with the given BVH options at
+/// a fraction of the resolution, framed like switching to it would,
+/// and returns how long they took
+///
+fn probe_bvh(options: BvhOptions, (name, scene): (&str, Scene), materials: &MaterialLibrary) -> Duration {
+    let arena = Arena::new();
+    let width = RENDER_RESOLUTION_X / TUNING_PROBE_SCALE;
+    let mut camera = Camera::new(Point::ZERO, Vec3::new(1.0, 0.0, 0.0), ASPECT_RATIO, width, MAX_DEPTH, 20.0,
+                                 Vec3::new(0.0, 2.0, 0.0), 0.0, 10.0);
+
+    // built from the same random numbers as the scene itself
+    rng::Stream::named(name).enter();
+    let world = scene(&arena, materials, Accelerator::BVH(options));
+    camera.frame(&world);
+    camera.set_world(world);
+
+    let mut buff = vec![0; width * (width as f32 / ASPECT_RATIO) as usize];
+    let time = Instant::now();
+    for _ in 0..TUNING_PROBE_SAMPLES { camera.render(&mut buff); }
+    time.elapsed()
+}
 
-    let data = camera.render(&mut buff);
+
+fn render_image(mut camera: Camera, samples: usize, target_noise: Option<f32>) {
+    let time = Instant::now();
+    let mut buff = vec![0; (RENDER_RESOLUTION * RENDER_RESOLUTION_X) as usize];
+    session::accumulate(&mut camera, &mut buff, samples, target_noise);
     println!("Rendered in {}ms", time.elapsed().as_millis());
 
-    let mut string = String::new();
-    string.push_str("P3\n");
-    string.push_str(format!("{} {}\n", RENDER_RESOLUTION_X, RENDER_RESOLUTION).as_str());
-    string.push_str("255\n");
+    session::write_ppm("out.ppm", &buff).unwrap();
+}
+
+
+///
+/// Writes linear colours to `path` as a PFM, or in whichever
+/// other float format its extension says, like hdr or exr
+///
+fn save_hdr(path: &str, size: (usize, usize), pixels: Vec<Colour>) -> io::Result<()> {
+    if path.ends_with(".pfm") { return Reference { size, pixels }.save(path) }
+
+    let image = image::Rgb32FImage::from_fn(size.0 as u32, size.1 as u32, |x, y| {
+        let colour = pixels[y as usize * size.0 + x as usize];
+        image::Rgb([colour.x, colour.y, colour.z])
+    });
+    image.save(path).map_err(io::Error::other)
+}
+
+
+fn bookmark_slot(key: Keycode) -> Option<u8> {
+    let slot = match key {
+        Keycode::Num1 => 1,
+        Keycode::Num2 => 2,
+        Keycode::Num3 => 3,
+        Keycode::Num4 => 4,
+        Keycode::Num5 => 5,
+        Keycode::Num6 => 6,
+        Keycode::Num7 => 7,
+        Keycode::Num8 => 8,
+        Keycode::Num9 => 9,
+        _ => return None,
+    };
+
+    Some(slot)
+}
+
+
+///
+/// A scene's bookmarks, a file which can't be read
+/// is reported and the scene starts without any
+///
+fn load_bookmarks(path: &str) -> Bookmarks {
+    Bookmarks::load(path).unwrap_or_else(|err| {
+        println!("Couldn't load the bookmarks from '{path}': {err}");
+        Bookmarks::default()
+    })
+}
 
-    //for d in data {
-    //    let r = (d.x * 255.999) as u8;
-    //    let g = (d.y * 255.999) as u8;
-    //    let b = (d.z * 255.999) as u8;
-    //    string.push_str(&format!("{} {} {} ", r, g, b));
-    //}
 
-    fs::write("out.ppm", &string).unwrap();
+///
+/// Unwraps what was read from `path` or says why it couldn't
+/// be read and stops, bad input files aren't worth a panic
+///
+fn or_exit<T, E: Display>(result: Result<T, E>, path: &str, usage: &str) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("{path}: {err}\n{usage}");
+        std::process::exit(1)
+    })
 }
 
 
@@ -3,3 +3,6 @@ pub mod ray;
 pub mod interval;
 pub mod matrix;
 pub mod aabb;
+pub mod polynomial;
+pub mod half;
+pub mod quaternion;
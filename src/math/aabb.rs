@@ -1,4 +1,39 @@
-use super::{interval::Interval, ray::Ray, vec3::Point};
+use super::{interval::Interval, ray::Ray, vec3::{Point, Vec3}};
+
+///
+/// Where a ray enters and exits a box and
+/// the axes of the faces it goes through
+///
+pub struct Slab {
+    pub entry: f32,
+    pub exit: f32,
+    pub entry_axis: usize,
+    pub exit_axis: usize,
+}
+
+
+///
+/// A ray with `1 / direction` and the direction's signs worked
+/// out up front, for testing it against many boxes in a row
+///
+#[derive(Clone, Copy)]
+pub struct InverseRay {
+    pub origin: Point,
+    pub inv_direction: Vec3,
+    /// whether the ray goes towards negative x, y and z
+    pub negative: [bool; 3],
+}
+
+
+impl InverseRay {
+    pub fn new(ray: Ray) -> Self {
+        let inv_direction = Vec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        // `1 / -0.0` is negative infinity, so it counts as negative
+        let negative = [inv_direction.x < 0.0, inv_direction.y < 0.0, inv_direction.z < 0.0];
+        Self { origin: ray.origin, inv_direction, negative }
+    }
+}
+
 
 #[derive(Clone)]
 pub struct AABB {
@@ -25,6 +60,16 @@ impl AABB {
     }
 
 
+    ///
+    /// Grows any side which is narrower than `delta` to `delta`
+    /// so flat objects still have a box the slab test can hit
+    ///
+    pub fn pad_to_minimums(self, delta: f32) -> Self {
+        let pad = |i: Interval| if i.size() < delta { i.expand(delta) } else { i };
+        Self::new(pad(self.x), pad(self.y), pad(self.z))
+    }
+
+
     pub fn from_aabbs(box1: &AABB, box2: &AABB) -> AABB {
         Self::new(
             Interval::from_intervals(box1.x, box2.x),
@@ -36,6 +81,16 @@ impl AABB {
 
 
 
+    pub fn min_corner(&self) -> Point {
+        Point::new(self.x.min, self.y.min, self.z.min)
+    }
+
+
+    pub fn max_corner(&self) -> Point {
+        Point::new(self.x.max, self.y.max, self.z.max)
+    }
+
+
     pub fn axis_interval(&self, n: usize) -> Interval {
         if n == 1 { return self.y }
         if n == 2 { return self.z }
@@ -43,7 +98,29 @@ impl AABB {
     }
 
 
-    pub fn hit(&self, ray: Ray, mut ray_t: Interval) -> bool {
+    ///
+    /// Splits the box in two at `at` along `axis`
+    ///
+    pub fn split(&self, axis: usize, at: f32) -> (AABB, AABB) {
+        let mut below = self.clone();
+        let mut above = self.clone();
+        let interval = self.axis_interval(axis);
+        let (below_axis, above_axis) = match axis {
+            1 => (&mut below.y, &mut above.y),
+            2 => (&mut below.z, &mut above.z),
+            _ => (&mut below.x, &mut above.x),
+        };
+
+        *below_axis = Interval::new(interval.min, at);
+        *above_axis = Interval::new(at, interval.max);
+        (below, above)
+    }
+
+
+    ///
+    /// Same as `hit` but returns the part of `ray_t` inside of the box
+    ///
+    pub fn hit_interval(&self, ray: Ray, mut ray_t: Interval) -> Option<Interval> {
         let ray_origin = ray.origin;
         let ray_dir = ray.direction;
 
@@ -62,12 +139,80 @@ impl AABB {
                 if t0 < ray_t.max { ray_t.max = t0; }
             }
 
-            if ray_t.max <= ray_t.min { return false }
+            if ray_t.max <= ray_t.min { return None }
+        }
+
+        Some(ray_t)
+    }
+
+
+    ///
+    /// Intersects the ray with all three slabs of the box.
+    /// The ray misses if `exit <= entry`
+    ///
+    #[inline(always)]
+    pub fn slab(&self, ray: Ray) -> Slab {
+        let mut slab = Slab { entry: -f32::INFINITY, exit: f32::INFINITY, entry_axis: 0, exit_axis: 0 };
+
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let adinv = 1.0 / ray.direction[axis];
+            let t0 = (ax.min - ray.origin[axis]) * adinv;
+            let t1 = (ax.max - ray.origin[axis]) * adinv;
+            let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            if near > slab.entry { slab.entry = near; slab.entry_axis = axis; }
+            if far < slab.exit { slab.exit = far; slab.exit_axis = axis; }
+        }
+
+        slab
+    }
+
+
+    pub fn hit(&self, ray: Ray, ray_t: Interval) -> bool {
+        self.hit_inverse(&InverseRay::new(ray), ray_t)
+    }
+
+
+    ///
+    /// Same as `hit` without a division per axis, the sign
+    /// says which side of each slab the ray comes in through
+    ///
+    #[inline(always)]
+    pub fn hit_inverse(&self, ray: &InverseRay, ray_t: Interval) -> bool {
+        self.entry_inverse(ray, ray_t).is_some()
+    }
+
+
+    ///
+    /// Where along `ray` it enters the box, clamped to `ray_t`,
+    /// or `None` if it misses the box within `ray_t`
+    ///
+    #[inline(always)]
+    pub fn entry_inverse(&self, ray: &InverseRay, mut ray_t: Interval) -> Option<f32> {
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let (near, far) = if ray.negative[axis] { (ax.max, ax.min) } else { (ax.min, ax.max) };
+
+            // a NaN from a ray in the plane of a face fails both
+            // comparisons and leaves the interval as it is
+            let t0 = (near - ray.origin[axis]) * ray.inv_direction[axis];
+            let t1 = (far - ray.origin[axis]) * ray.inv_direction[axis];
+            if t0 > ray_t.min { ray_t.min = t0; }
+            if t1 < ray_t.max { ray_t.max = t1; }
+
+            if ray_t.max <= ray_t.min { return None }
         }
 
-        true
+        Some(ray_t.min)
     }
 
+    pub fn surface_area(&self) -> f32 {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        2.0 * (x*y + y*z + z*x)
+    }
+
+
     pub fn longest_axis(&self) -> usize {
         if self.x.size() > self.y.size() { if self.x.size() > self.z.size() { 0 } else { 2 } }
         else { if self.y.size() > self.z.size() { 1 } else { 2 } }
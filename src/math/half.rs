@@ -0,0 +1,144 @@
+///
+/// An IEEE 754 half precision float, only meant for storing
+/// values. Everything is done in `f32` and converted back
+///
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Half(pub u16);
+
+
+impl Half {
+    pub const ZERO : Half = Half(0);
+
+
+    pub fn to_f32(self) -> f32 {
+        let sign = ((self.0 & 0x8000) as u32) << 16;
+        let exponent = ((self.0 >> 10) & 0x1f) as u32;
+        let mantissa = (self.0 & 0x3ff) as u32;
+
+        match exponent {
+            // subnormal, `mantissa * 2^-24`
+            0 => {
+                let value = mantissa as f32 * (1.0 / (1 << 24) as f32);
+                if sign != 0 { -value } else { value }
+            },
+
+            0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+
+            _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+        }
+    }
+
+
+    ///
+    /// Rounds `value` up or down to one of the two halves next to it,
+    /// with a probability of how close it is to each, so the rounding
+    /// is right on average and a running mean doesn't get stuck once
+    /// the steps get smaller than the precision.
+    /// `random` has to be uniform in `[0, 1)`
+    ///
+    pub fn from_f32_stochastic(value: f32, random: f32) -> Half {
+        let down = Half::from_f32_truncated(value);
+        if value.is_nan() || value.is_infinite() { return down }
+
+        // one step away from zero, overflows into infinity
+        let up = Half(down.0 + 1);
+
+        let (low, high) = (down.to_f32().abs(), up.to_f32().abs());
+        if random * (high - low) < value.abs() - low { up } else { down }
+    }
+
+
+    ///
+    /// Rounds `value` towards zero
+    ///
+    pub fn from_f32_truncated(value: f32) -> Half {
+        let bits = value.to_bits();
+        let sign = ((bits >> 16) & 0x8000) as u16;
+        let exponent = ((bits >> 23) & 0xff) as i32;
+        let mantissa = bits & 0x7f_ffff;
+
+        if exponent == 0xff {
+            let nan = if mantissa != 0 { 0x200 } else { 0 };
+            return Half(sign | 0x7c00 | nan);
+        }
+
+        let exponent = exponent - 127 + 15;
+
+        // the largest finite half
+        if exponent >= 0x1f { return Half(sign | 0x7bff) }
+
+        if exponent <= 0 {
+            if exponent < -10 { return Half(sign) }
+
+            let mantissa = (mantissa | 0x80_0000) >> (14 - exponent);
+            return Half(sign | mantissa as u16);
+        }
+
+        Half(sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::rng::next_f32;
+
+    use super::*;
+
+    #[test]
+    fn exact_values_round_trip() {
+        for value in [0.0, -0.0, 1.0, -2.5, 0.333984375, 65504.0, 1.0 / (1 << 24) as f32] {
+            assert_eq!(Half::from_f32_truncated(value).to_f32(), value);
+            assert_eq!(Half::from_f32_stochastic(value, 0.999).to_f32(), value);
+        }
+
+        assert_eq!(Half::from_f32_truncated(f32::INFINITY).to_f32(), f32::INFINITY);
+        assert!(Half::from_f32_truncated(f32::NAN).to_f32().is_nan());
+        assert_eq!(Half::from_f32_truncated(1e6).to_f32(), 65504.0);
+    }
+
+
+    #[test]
+    fn stochastic_rounding_is_right_on_average() {
+        // a quarter of the way between 1.0 and the next half
+        let value = 1.0 + 0.25 / 1024.0;
+
+        let count = 100_000;
+        let sum : f64 = (0..count)
+            .map(|_| Half::from_f32_stochastic(value, next_f32()).to_f32() as f64)
+            .sum();
+
+        assert!((sum / count as f64 - value as f64).abs() < 1e-5);
+    }
+
+
+    #[test]
+    fn running_mean_is_close_to_f32() {
+        // a pixel of noisy gamma space samples, kept as a running mean
+        // in halves the way `RaytracingCamera::render` does
+        let samples = 1000;
+        let pixels = 64;
+
+        let mut squared_error = 0.0;
+        for _ in 0..pixels {
+            let mut sum = 0.0f32;
+            let mut mean = Half::ZERO;
+
+            for n in 1..=samples {
+                let sample = 0.3 + 0.2 * next_f32();
+                sum += sample;
+
+                let m = mean.to_f32();
+                mean = Half::from_f32_stochastic(m + (sample - m) / n as f32, next_f32());
+            }
+
+            let error = mean.to_f32() - sum / samples as f32;
+            squared_error += error * error;
+        }
+
+        // within one step of an 8 bit channel, each rounding error
+        // shrinks as more samples come in but they add up to ~0.002
+        let rms = (squared_error / pixels as f32).sqrt();
+        assert!(rms < 1.0 / 255.0, "{rms}");
+    }
+}
@@ -40,6 +40,13 @@ impl Interval {
     }
 
 
+    #[inline(always)]
+    pub fn expand(self, delta: f32) -> Interval {
+        let padding = delta / 2.0;
+        Self::new(self.min - padding, self.max + padding)
+    }
+
+
     pub fn size(self) -> f32 {
         (self.max - self.min).abs()
     }
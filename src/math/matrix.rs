@@ -1,5 +1,9 @@
 use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Sub};
 
+use super::vec3::{Point, Vec3};
+
+pub type Matrix4 = Matrix<4, 4, f32>;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Matrix<const ROW: usize, const COLUMN: usize, T> {
     rows: [[T; COLUMN]; ROW]
@@ -36,6 +40,113 @@ impl<const ROW: usize, const COLUMN: usize, T: Copy> Matrix<ROW, COLUMN, T> {
 }
 
 
+impl<const ROW: usize, const COLUMN: usize, T: Copy> Matrix<ROW, COLUMN, T> {
+    pub fn transpose(self) -> Matrix<COLUMN, ROW, T> {
+        let arr = std::array::from_fn::<[T; ROW], COLUMN, _>(|i| {
+            std::array::from_fn::<T, ROW, _>(|j| self.rows[j][i])
+        });
+
+        Matrix::new(arr)
+    }
+}
+
+
+impl Matrix<4, 4, f32> {
+    pub fn identity() -> Self {
+        Self::translation(Vec3::ZERO)
+    }
+
+
+    pub fn translation(offset: Vec3) -> Self {
+        Self::new([[1.0, 0.0, 0.0, offset.x],
+                   [0.0, 1.0, 0.0, offset.y],
+                   [0.0, 0.0, 1.0, offset.z],
+                   [0.0, 0.0, 0.0, 1.0     ]])
+    }
+
+
+    pub fn scaling(factor: Vec3) -> Self {
+        Self::new([[factor.x, 0.0,      0.0,      0.0],
+                   [0.0,      factor.y, 0.0,      0.0],
+                   [0.0,      0.0,      factor.z, 0.0],
+                   [0.0,      0.0,      0.0,      1.0]])
+    }
+
+
+    ///
+    /// A counter-clockwise rotation of `angle`
+    /// radians around `axis` through the origin
+    ///
+    pub fn rotation(axis: Vec3, angle: f32) -> Self {
+        let Vec3 { x, y, z } = axis.unit();
+        let (sin, cos) = angle.sin_cos();
+        let c = 1.0 - cos;
+
+        Self::new([[cos + x*x*c,   x*y*c - z*sin, x*z*c + y*sin, 0.0],
+                   [y*x*c + z*sin, cos + y*y*c,   y*z*c - x*sin, 0.0],
+                   [z*x*c - y*sin, z*y*c + x*sin, cos + z*z*c,   0.0],
+                   [0.0,           0.0,           0.0,           1.0]])
+    }
+
+
+    ///
+    /// Inverts the matrix with Gauss-Jordan elimination,
+    /// returns `None` if it's singular
+    ///
+    pub fn inverse(self) -> Option<Self> {
+        let mut m = self.rows;
+        let mut inv = Self::identity().rows;
+
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+            if m[pivot][col].abs() < 1e-12 { return None }
+
+            m.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / m[col][col];
+            for j in 0..4 {
+                m[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == col { continue }
+
+                let factor = m[row][col];
+                for j in 0..4 {
+                    m[row][j] -= factor * m[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+
+        Some(Self::new(inv))
+    }
+
+
+    #[inline(always)]
+    pub fn transform_point(&self, p: Point) -> Point {
+        let r = &self.rows;
+        Point::new(r[0][0] * p.x + r[0][1] * p.y + r[0][2] * p.z + r[0][3],
+                   r[1][0] * p.x + r[1][1] * p.y + r[1][2] * p.z + r[1][3],
+                   r[2][0] * p.x + r[2][1] * p.y + r[2][2] * p.z + r[2][3])
+    }
+
+
+    ///
+    /// Same as `transform_point` but ignores the translation
+    ///
+    #[inline(always)]
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let r = &self.rows;
+        Vec3::new(r[0][0] * v.x + r[0][1] * v.y + r[0][2] * v.z,
+                  r[1][0] * v.x + r[1][1] * v.y + r[1][2] * v.z,
+                  r[2][0] * v.x + r[2][1] * v.y + r[2][2] * v.z)
+    }
+}
+
+
 impl<const ROW: usize, const COLUMN: usize, V, T: Add<Output=V> + Copy> Add for Matrix<ROW, COLUMN, T> {
     type Output = Matrix<ROW, COLUMN, V>;
 
@@ -216,4 +327,39 @@ mod tests {
     }
 
 
+    #[test]
+    fn matrix_transpose() {
+        let m1 = Matrix::new([
+            [1, 2, 3],
+            [4, 5, 6],
+        ]);
+
+        let m2 = Matrix::new([
+            [1, 4],
+            [2, 5],
+            [3, 6],
+        ]);
+
+        assert_eq!(m1.transpose(), m2);
+    }
+
+
+    #[test]
+    fn matrix_inverse() {
+        let m = Matrix4::translation(Vec3::new(1.0, -2.0, 3.0))
+              * Matrix4::rotation(Vec3::new(1.0, 1.0, 0.0), 0.7)
+              * Matrix4::scaling(Vec3::new(2.0, 0.5, 3.0));
+
+        let product = m * m.inverse().unwrap();
+        let identity = Matrix4::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((product[i][j] - identity[i][j]).abs() < 1e-5);
+            }
+        }
+
+        assert_eq!(Matrix4::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse(), None);
+    }
+
+
 }
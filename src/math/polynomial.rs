@@ -0,0 +1,180 @@
+///
+/// Solves `a*x^2 + b*x + c = 0`
+/// Returns the real roots in ascending order and their count
+///
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> ([f64; 2], usize) {
+    if a == 0.0 {
+        if b == 0.0 { return ([0.0; 2], 0) }
+        return ([-c / b, 0.0], 1);
+    }
+
+    let discriminant = b*b - 4.0*a*c;
+    if discriminant < 0.0 { return ([0.0; 2], 0) }
+
+    // avoid the catastrophic cancellation of the textbook formula
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (x0, x1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+
+    if x0 < x1 { ([x0, x1], 2) } else { ([x1, x0], 2) }
+}
+
+
+///
+/// Solves `a*x^3 + b*x^2 + c*x + d = 0`
+/// Returns the real roots in ascending order and their count
+///
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> ([f64; 3], usize) {
+    if a == 0.0 {
+        let (roots, count) = solve_quadratic(b, c, d);
+        return ([roots[0], roots[1], 0.0], count);
+    }
+
+    // depressed cubic `y^3 + p*y + q = 0` with `x = y - b/3a`
+    let (b, c, d) = (b / a, c / a, d / a);
+    let shift = b / 3.0;
+    let p = c - b*b / 3.0;
+    let q = 2.0*b*b*b / 27.0 - b*c / 3.0 + d;
+
+    let discriminant = q*q / 4.0 + p*p*p / 27.0;
+
+    if discriminant > 0.0 {
+        // one real root
+        let sqrt = discriminant.sqrt();
+        let u = (-q / 2.0 + sqrt).cbrt();
+        let v = (-q / 2.0 - sqrt).cbrt();
+        return ([u + v - shift, 0.0, 0.0], 1);
+    }
+
+    if p == 0.0 {
+        return ([-shift, 0.0, 0.0], 1);
+    }
+
+    // three real roots, use the trigonometric form
+    let m = 2.0 * (-p / 3.0).sqrt();
+    let theta = (3.0 * q / (p * m)).clamp(-1.0, 1.0).acos() / 3.0;
+    let mut roots = [0.0; 3];
+    for (k, root) in roots.iter_mut().enumerate() {
+        *root = m * (theta - 2.0 * std::f64::consts::PI * k as f64 / 3.0).cos() - shift;
+    }
+
+    roots.sort_by(|a, b| a.total_cmp(b));
+    (roots, 3)
+}
+
+
+///
+/// Solves `a*x^4 + b*x^3 + c*x^2 + d*x + e = 0` using Ferrari's method
+/// followed by a few newton iterations to polish the roots.
+/// Returns the real roots in ascending order and their count
+///
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> ([f64; 4], usize) {
+    if a == 0.0 {
+        let (roots, count) = solve_cubic(b, c, d, e);
+        return ([roots[0], roots[1], roots[2], 0.0], count);
+    }
+
+    // depressed quartic `y^4 + p*y^2 + q*y + r = 0` with `x = y - b/4a`
+    let (b, c, d, e) = (b / a, c / a, d / a, e / a);
+    let shift = b / 4.0;
+    let bb = b*b;
+    let p = c - 3.0*bb / 8.0;
+    let q = d - b*c / 2.0 + bb*b / 8.0;
+    let r = e - b*d / 4.0 + bb*c / 16.0 - 3.0*bb*bb / 256.0;
+
+    let mut roots = [0.0; 4];
+    let mut count = 0;
+    let mut push_quadratic = |b: f64, c: f64| {
+        let (rs, n) = solve_quadratic(1.0, b, c);
+        for root in &rs[..n] {
+            roots[count] = root - shift;
+            count += 1;
+        }
+    };
+
+    if q.abs() < 1e-12 {
+        // biquadratic `z^2 + p*z + r = 0` with `z = y^2`
+        let (zs, n) = solve_quadratic(1.0, p, r);
+        for &z in &zs[..n] {
+            if z < 0.0 { continue }
+            let y = z.sqrt();
+            push_quadratic(0.0, -y*y);
+        }
+    } else {
+        // the resolvent cubic always has a positive root when `q != 0`
+        let (ms, n) = solve_cubic(8.0, 8.0*p, 2.0*p*p - 8.0*r, -q*q);
+        let m = ms[n-1];
+        if m <= 0.0 { return (roots, 0) }
+
+        let s = (2.0 * m).sqrt();
+        push_quadratic(-s, p / 2.0 + m + q / (2.0 * s));
+        push_quadratic( s, p / 2.0 + m - q / (2.0 * s));
+    }
+
+    for root in &mut roots[..count] {
+        for _ in 0..2 {
+            let x = *root;
+            let f  = (((x + b)*x + c)*x + d)*x + e;
+            let df = ((4.0*x + 3.0*b)*x + 2.0*c)*x + d;
+            if df == 0.0 { break }
+            *root = x - f / df;
+        }
+    }
+
+    roots[..count].sort_by(|a, b| a.total_cmp(b));
+    (roots, count)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(found: &[f64], expected: &[f64]) {
+        assert_eq!(found.len(), expected.len(), "{found:?} != {expected:?}");
+        for (f, e) in found.iter().zip(expected) {
+            assert!((f - e).abs() < 1e-6, "{found:?} != {expected:?}");
+        }
+    }
+
+
+    #[test]
+    fn quadratic() {
+        let (roots, n) = solve_quadratic(1.0, -3.0, 2.0);
+        assert_roots(&roots[..n], &[1.0, 2.0]);
+
+        let (_, n) = solve_quadratic(1.0, 0.0, 1.0);
+        assert_eq!(n, 0);
+    }
+
+
+    #[test]
+    fn cubic() {
+        // (x-1)(x-2)(x-3)
+        let (roots, n) = solve_cubic(1.0, -6.0, 11.0, -6.0);
+        assert_roots(&roots[..n], &[1.0, 2.0, 3.0]);
+
+        // (x-2)(x^2+1)
+        let (roots, n) = solve_cubic(1.0, -2.0, 1.0, -2.0);
+        assert_roots(&roots[..n], &[2.0]);
+    }
+
+
+    #[test]
+    fn quartic() {
+        // (x-1)(x-2)(x-3)(x-4)
+        let (roots, n) = solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0);
+        assert_roots(&roots[..n], &[1.0, 2.0, 3.0, 4.0]);
+
+        // (x^2-4)(x^2-9)
+        let (roots, n) = solve_quartic(1.0, 0.0, -13.0, 0.0, 36.0);
+        assert_roots(&roots[..n], &[-3.0, -2.0, 2.0, 3.0]);
+
+        // (x-0.5)(x+1.5)(x^2+1)
+        let (roots, n) = solve_quartic(1.0, 1.0, 0.25, 1.0, -0.75);
+        assert_roots(&roots[..n], &[-1.5, 0.5]);
+
+        // x^4 + 1
+        let (_, n) = solve_quartic(1.0, 0.0, 0.0, 0.0, 1.0);
+        assert_eq!(n, 0);
+    }
+}
@@ -0,0 +1,118 @@
+use super::{matrix::Matrix4, vec3::Vec3};
+
+
+///
+/// A unit quaternion `w + xi + yj + zk` standing for a rotation,
+/// only used to interpolate between rotations without going
+/// through the singular matrices a straight blend can hit
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub v: Vec3,
+}
+
+
+impl Quaternion {
+    pub const IDENTITY : Quaternion = Quaternion { w: 1.0, v: Vec3::ZERO };
+
+
+    ///
+    /// The rotation in the upper 3x3 of `m`, which
+    /// has to be orthonormal with a determinant of 1
+    ///
+    pub fn from_matrix(m: Matrix4) -> Quaternion {
+        // Shepperd's method, starting from the largest
+        // component so nothing is divided by almost 0
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Quaternion { w: 0.25 * s, v: Vec3::new(m[2][1] - m[1][2], m[0][2] - m[2][0], m[1][0] - m[0][1]) / s }
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Quaternion { w: (m[2][1] - m[1][2]) / s, v: Vec3::new(0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s) }
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Quaternion { w: (m[0][2] - m[2][0]) / s, v: Vec3::new((m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s) }
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Quaternion { w: (m[1][0] - m[0][1]) / s, v: Vec3::new((m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s) }
+        };
+
+        q.normalised()
+    }
+
+
+    pub fn to_matrix(self) -> Matrix4 {
+        let Quaternion { w, v: Vec3 { x, y, z } } = self;
+
+        Matrix4::new([[1.0 - 2.0*(y*y + z*z), 2.0*(x*y - w*z),       2.0*(x*z + w*y),       0.0],
+                      [2.0*(x*y + w*z),       1.0 - 2.0*(x*x + z*z), 2.0*(y*z - w*x),       0.0],
+                      [2.0*(x*z - w*y),       2.0*(y*z + w*x),       1.0 - 2.0*(x*x + y*y), 0.0],
+                      [0.0,                   0.0,                   0.0,                   1.0]])
+    }
+
+
+    pub fn dot(self, rhs: Quaternion) -> f32 {
+        self.w * rhs.w + self.v.dot(rhs.v)
+    }
+
+
+    ///
+    /// The angle in radians of the shortest rotation from `self` to `rhs`
+    ///
+    pub fn angle_to(self, rhs: Quaternion) -> f32 {
+        2.0 * self.dot(rhs).abs().min(1.0).acos()
+    }
+
+
+    ///
+    /// Spherical interpolation along the shortest way from `self`
+    /// at 0 to `rhs` at 1, turning at a constant speed
+    ///
+    pub fn slerp(self, rhs: Quaternion, t: f32) -> Quaternion {
+        // `q` and `-q` are the same rotation, the closer one is the shorter way
+        let mut cos = self.dot(rhs);
+        let rhs = if cos < 0.0 { cos = -cos; Quaternion { w: -rhs.w, v: -rhs.v } } else { rhs };
+
+        // too close for the sine to be divided by, a blend is just as good
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+
+        Quaternion { w: a * self.w + b * rhs.w, v: a * self.v + b * rhs.v }.normalised()
+    }
+
+
+    fn normalised(self) -> Quaternion {
+        let length = (self.w * self.w + self.v.length_squared()).sqrt();
+        if length == 0.0 { return Quaternion::IDENTITY }
+        Quaternion { w: self.w / length, v: self.v / length }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slerp_turns_halfway_around_almost_a_half_turn() {
+        // a whole half turn could go either way
+        let axis = Vec3::new(1.0, 2.0, 0.5).unit();
+        let start = Quaternion::from_matrix(Matrix4::identity());
+        let end = Quaternion::from_matrix(Matrix4::rotation(axis, 178f32.to_radians()));
+
+        let half = start.slerp(end, 0.5).to_matrix();
+        let expected = Matrix4::rotation(axis, 89f32.to_radians());
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((half[i][j] - expected[i][j]).abs() < 1e-5, "{half:?} != {expected:?}");
+            }
+        }
+    }
+}
@@ -1,7 +1,12 @@
-use crate::rt::hittable::{HitRecord, Hittable};
+use crate::{rng::Stream, rt::{camera::RenderSettings, hittable::{HitRecord, Hittable}, materials::{BsdfSample, Interior}, sky::Sky, units::Units}};
 
 use super::{vec3::{Point, Vec3, Colour}, interval::Interval};
 
+/// how many cutouts of alpha masked materials a ray goes through
+/// before whatever it hits next counts as solid, fences seen
+/// through fences would make for endless traversals otherwise
+pub const TRANSPARENCY_DEPTH : usize = 16;
+
 #[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Point,
@@ -21,20 +26,156 @@ impl Ray {
 
 
     #[inline(always)]
-    pub fn colour(self, world: &Hittable, depth: usize) -> Colour {
-        if depth == 0 { return Colour::ZERO }
+    pub fn colour(self, world: &Hittable, sky: &Sky, units: Units, depth: usize, transparency_depth: usize, stream: Stream) -> Colour {
+        let settings = RenderSettings { sky: *sky, units, transparency_depth, stream, ..RenderSettings::DEFAULT };
+        self.colour_and_distance(world, &settings, depth).0
+    }
+
+
+    ///
+    /// Whether anything in `world` is between the origin and
+    /// `tmax` along the ray, for shadow rays. Hits right at the
+    /// origin don't count, so rays leaving a surface should start
+    /// a little off of it. It goes through the cutouts of alpha
+    /// masked materials, up to `transparency_depth` of them, as
+    /// they're found by `Hittable::any_hit`. With 0 the cutouts
+    /// block it like the rest of the surface. Either way it's
+    /// cheaper than `Hittable::hit` since it stops at the first
+    /// solid thing found
+    ///
+    pub fn hit_any(&self, tmax: f32, world: &Hittable, units: Units, transparency_depth: usize) -> bool {
+        let mut cutouts = transparency_depth;
+        world.any_hit(*self, Interval::new(0.0, tmax), units, &mut cutouts)
+    }
+
+
+    ///
+    /// Finds the closest hit in `ray_t` like `Hittable::hit` but
+    /// skips over the cutouts of alpha masked materials. Soft
+    /// alphas are hit or gone through at random, so over many
+    /// samples they let through as much light as they're clear.
+    /// After `transparency_depth` cutouts the next hit is taken
+    /// whatever its alpha
+    ///
+    fn hit_through_cutouts<'a>(self, world: &Hittable<'a>, mut ray_t: Interval, units: Units,
+                               transparency_depth: usize, rec: &mut HitRecord<'a>) -> bool {
+        for _ in 0..transparency_depth {
+            if !world.hit(self, ray_t, rec) { return false }
+            if rec.material.is_opaque_at(rec) { return true }
+
+            // through a cutout, the same bounce goes on past it
+            ray_t.min = rec.t + units.hit_epsilon();
+        }
+
+        world.hit(self, ray_t, rec)
+    }
+
+
+    ///
+    /// Same as `colour` but also returns the distance to
+    /// the first hit, or `f32::INFINITY` if the ray escapes, lit
+    /// with the `settings`. Their exposure and fog are left to the
+    /// camera. Each bounce draws from its own split of their stream
+    ///
+    #[inline(always)]
+    pub fn colour_and_distance(self, world: &Hittable, settings: &RenderSettings, depth: usize) -> (Colour, f32) {
+        self.colour_through(world, settings, depth, Colour::ZERO, None)
+    }
+
+
+    ///
+    /// `absorption` is the Beer-Lambert coefficient per metre of
+    /// whatever the ray is travelling through. Refracting through
+    /// a material with an absorbing inside enters or leaves it,
+    /// the same goes for the `interior` of subsurface materials
+    /// which the ray walks through scattering off it as it goes
+    ///
+    fn colour_through(self, world: &Hittable, settings: &RenderSettings, depth: usize,
+                      absorption: Colour, interior: Option<Interior>) -> (Colour, f32) {
+        let RenderSettings { sky, units, light_scale, transparency_depth, stream, .. } = *settings;
+        if depth == 0 { return (Colour::ZERO, f32::INFINITY) }
+        // the depth is different for every bounce of a path
+        stream.split(depth as u64).enter();
+
         let mut rec = HitRecord::default();
-        if world.hit(self, Interval::new(0.001, f32::INFINITY), &mut rec) {
-            if let Some((scattered, attenuation)) = rec.material.scatter(self, &rec) {
-                return attenuation * scattered.colour(world, depth - 1);
+        let ray_t = Interval::new(units.hit_epsilon(), f32::INFINITY);
+        let hit = self.hit_through_cutouts(world, ray_t, units, transparency_depth, &mut rec);
+
+        // the walk can scatter before it gets to the surface
+        let mut walk_weight = Colour::ONE;
+        if let Some(interior) = interior {
+            let length = self.direction.length() * units.metres();
+            let limit = if hit { rec.t * length } else { f32::INFINITY };
+            let (metres, weight) = interior.step(limit);
+            walk_weight = weight;
+
+            if metres < limit {
+                let scattered = Ray::new(self.at(metres / length), Vec3::random_unit(), self.time);
+                let colour = scattered.colour_through(world, settings, depth - 1, absorption, Some(interior)).0;
+                return (weight * colour, metres / units.metres())
             }
+        }
+
+        if hit {
+            let distance = rec.t * self.direction.length();
+            let metres = distance * units.metres();
+            let transmittance = Colour::new((-absorption.x * metres).exp(),
+                                            (-absorption.y * metres).exp(),
+                                            (-absorption.z * metres).exp());
 
-            return Colour::new(0.0, 0.0, 0.0)
+            let emitted = light_scale * rec.material.emitted(&rec);
+            if let Some(BsdfSample { ray: scattered, weight: attenuation, .. }) = rec.material.sample(self, &rec) {
+                let refracted = scattered.direction.dot(rec.normal) < 0.0;
+                // rays bouncing off of smooth shaded meshes start from the
+                // smooth surface so they aren't shadowed at the terminator
+                let scattered = if refracted { scattered }
+                                else { Ray::new(scattered.origin + rec.terminator_offset, scattered.direction, scattered.time) };
+                let absorption = match rec.material.absorption() {
+                    Some(inside) if refracted => if rec.front_face { inside } else { Colour::ZERO },
+                    _ => absorption,
+                };
+                let interior = match rec.material.interior(&rec) {
+                    Some(inside) if refracted => if rec.front_face { Some(inside) } else { None },
+                    _ => interior,
+                };
+
+                let colour = emitted + attenuation * scattered.colour_through(world, settings, depth - 1, absorption, interior).0;
+                return (walk_weight * transmittance * colour, distance);
+            }
+
+            return (walk_weight * transmittance * emitted, distance)
         }
 
-        let unit_dir = self.direction.unit();
-        let a = 0.5 * (unit_dir.y + 1.0);
-        return (1.0 - a) * Colour::new(1.0, 1.0, 1.0) + a * Colour::new(0.5, 0.7, 1.0);
+        (sky.colour(self.direction), f32::INFINITY)
     }
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use sti::arena::Arena;
+
+    use crate::rt::{materials::Material, texture::Texture};
+
+    use super::*;
+
+    #[test]
+    fn shadows_through_cutouts() {
+        let arena = Arena::new();
+        let clear = arena.alloc_new(Texture::SolidColour(Colour::ZERO));
+        let fence = Material::AlphaMasked { material: arena.alloc_new(Material::default()), alpha: clear, cutoff: Some(0.5) };
+
+        // three fences one after another across the ray
+        let fences = arena.alloc_new([1.0, 2.0, 3.0].map(|z| {
+            Hittable::quad(Point::new(-1.0, -1.0, z), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), fence)
+        }));
+        let world = Hittable::list(fences);
+        let ray = Ray::new(Point::ZERO, Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        assert!(ray.hit_any(10.0, &world, Units::Metres, 0));
+        assert!(!ray.hit_any(10.0, &world, Units::Metres, 3));
+        // past the limit the last fence counts as solid
+        assert!(ray.hit_any(10.0, &world, Units::Metres, 2));
+    }
+}
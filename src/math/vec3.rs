@@ -1,4 +1,4 @@
-use std::{fmt::Display, ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub}};
+use std::{fmt::Display, ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub}};
 
 use crate::rng::{next_f32, next_f32_range};
 
@@ -226,3 +226,13 @@ impl Index<usize> for Vec3 {
         unreachable!()
     }
 }
+
+
+impl IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        if index == 0 { return &mut self.x }
+        if index == 1 { return &mut self.y }
+        if index == 2 { return &mut self.z }
+        unreachable!()
+    }
+}
@@ -0,0 +1,377 @@
+use crate::{camera::Camera, generator::Generator, math::vec3::Colour, rt::{bake::{Bake, BakeOptions}, camera::{DebugView, Precision}, hittable::{Accelerator, BvhOptions}, sky::Sky, units::Units}, session::Quality, MAX_DEPTH};
+
+/// in the mesh's units, anything further away doesn't darken the bake
+pub const BAKE_OCCLUSION_DISTANCE : f32 = 1.0;
+
+
+///
+/// Everything given on the command line. The options can go
+/// anywhere, the first argument left after them picks the mode
+/// and the rest are the mode's own arguments
+///
+pub struct Options {
+    /// pins the render threads to a core each
+    pub pin_threads: bool,
+    pub accelerator: Accelerator,
+    /// how many cutouts of alpha masked materials rays go through
+    pub transparency_depth: Option<usize>,
+    /// the levels of detail are picked once from where the
+    /// camera starts in each scene instead of for every ray
+    pub fixed_lod: bool,
+    /// a material library file the built-in scenes look their materials up in
+    pub materials: Option<String>,
+    /// the index into the scene names given to `parse`
+    pub scene: usize,
+    pub quality: Option<Quality>,
+    pub target_noise: Option<f32>,
+    /// in percent, from 0 to 100
+    pub overscan: Option<f32>,
+    pub pixel_aspect: Option<f32>,
+    pub units: Option<Units>,
+    /// the exposure value at ISO 100
+    pub ev100: Option<f32>,
+    pub sky: Option<SkyOption>,
+    /// an image the defocus blur takes the shape of
+    pub aperture: Option<String>,
+    pub debug: Option<DebugView>,
+    pub precision: Option<Precision>,
+    /// where R saves the image and the viewer compares against
+    pub reference: String,
+    /// what the right half of the window is rendered with instead
+    pub compare: Option<Comparison>,
+    pub mode: Mode,
+}
+
+
+///
+/// What to do after setting up, with the arguments of it
+/// which have a default left out for the caller to fill in.
+/// Sample counts are left out so `--quality` can pick them
+///
+pub enum Mode {
+    /// a window to look around the scene in
+    Interactive,
+    /// renders the scene into `out.ppm`
+    Image,
+    /// times every acceleration structure
+    Bench,
+    /// a window which records the input into `path`
+    Record { path: String },
+    /// a window which replays the recording in `path`
+    Replay { path: String },
+    /// renders every frame of the recording in `path`
+    Render { path: String, samples: Option<usize> },
+    /// renders a day of sunlight
+    Timelapse { frames: usize, samples: Option<usize> },
+    /// renders random scenes into `out_dir`
+    Dataset { out_dir: String, count: usize, samples: Option<usize>, seed: u64, generator: Generator },
+    /// bakes the lighting of the mesh at `mesh_path` into an image
+    Bake { mesh_path: String, out_path: String, bake: Bake, size: usize, samples: Option<usize>, padding: usize,
+           /// the index of the scene it's lit and shadowed by
+           in_scene: Option<usize> },
+}
+
+
+///
+/// The sky `--sky` asks for, the caller picks the latitude
+/// of a daylight sky and loads an environment map
+///
+#[derive(Clone)]
+pub enum SkyOption {
+    Sky(Sky<'static>),
+    Daylight { hours: f32 },
+    Environment { path: String },
+}
+
+
+///
+/// What the right half of the window is rendered with
+/// differently from the left half, see `--compare`
+///
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Comparison {
+    /// the index of the scene, follows the left half if not set
+    pub scene: Option<usize>,
+    pub accelerator: Option<Accelerator>,
+    pub precision: Option<Precision>,
+    pub exposure: Option<f32>,
+    pub max_depth: Option<usize>,
+}
+
+
+impl Options {
+    ///
+    /// Parses the arguments after the program's name, `scenes`
+    /// are the names of the scenes to pick from. The error is
+    /// the usage of whatever was wrong
+    ///
+    pub fn parse(mut args: Vec<String>, scenes: &[&str]) -> Result<Self, String> {
+        let scene_index = |name: &str| scenes.iter().position(|scene| *scene == name);
+
+        let pin_threads = take_flag(&mut args, "--pin-threads");
+
+        let accelerator = match take_option(&mut args, "--accelerator")? {
+            Some(name) => Accelerator::parse(&name).ok_or("usage: --accelerator bvh|quantized-bvh|median-bvh|sbvh|kdtree|grid")?,
+            None => Accelerator::BVH(BvhOptions::DEFAULT),
+        };
+
+        let transparency_depth = take_option(&mut args, "--transparency-depth")?
+            .map(|depth| depth.parse().map_err(|_| "usage: --transparency-depth <cutouts>")).transpose()?;
+
+        let fixed_lod = match take_option(&mut args, "--lod")?.as_deref() {
+            Some("camera") => true,
+            Some("ray") | None => false,
+            Some(_) => return Err("usage: --lod ray|camera".to_string()),
+        };
+
+        let materials = take_option(&mut args, "--materials")?;
+
+        let scene = match take_option(&mut args, "--scene")? {
+            Some(name) => scene_index(&name).ok_or("usage: --scene <name of a built-in scene>")?,
+            None => 0,
+        };
+
+        let quality = take_option(&mut args, "--quality")?
+            .map(|name| Quality::parse(&name).ok_or("usage: --quality draft|medium|final")).transpose()?;
+        let target_noise = take_option(&mut args, "--target-noise")?
+            .map(|noise| noise.parse::<f32>().map_err(|_| "usage: --target-noise <noise>")).transpose()?;
+
+        // anything past 100% would allocate buffers many times the size of the image
+        let overscan = take_option(&mut args, "--overscan")?
+            .map(|percent| percent.parse::<f32>().ok().filter(|p| (0.0..=100.0).contains(p))
+                                  .ok_or("usage: --overscan <percent from 0 to 100>")).transpose()?;
+
+        let pixel_aspect = take_option(&mut args, "--pixel-aspect")?
+            .map(|ratio| ratio.parse::<f32>().ok().filter(|ratio| *ratio > 0.0 && ratio.is_finite())
+                              .ok_or("usage: --pixel-aspect <positive ratio>")).transpose()?;
+
+        let units = take_option(&mut args, "--units")?
+            .map(|units| Units::parse(&units).ok_or("usage: --units metres|centimetres|millimetres")).transpose()?;
+
+        let ev100 = take_option(&mut args, "--ev100")?
+            .map(|ev100| ev100.parse::<f32>().ok().filter(|ev| ev.is_finite())
+                              .ok_or("usage: --ev100 <exposure value at ISO 100>")).transpose()?;
+
+        let sky = take_option(&mut args, "--sky")?
+            .map(|sky| SkyOption::parse(&sky)
+                .ok_or("usage: --sky gradient|solid:<r>,<g>,<b>|gradient:<r>,<g>,<b>:<r>,<g>,<b>|daylight:<hours>|env:<path>")).transpose()?;
+
+        let aperture = take_option(&mut args, "--aperture")?;
+
+        let debug = match take_option(&mut args, "--debug")?.as_deref() {
+            Some("bvh") => Some(DebugView::Bvh),
+            Some(_) => return Err("usage: --debug bvh".to_string()),
+            None => None,
+        };
+
+        let precision = take_option(&mut args, "--precision")?
+            .map(|precision| Precision::parse(&precision).ok_or("usage: --precision f32|f16")).transpose()?;
+
+        let reference = take_option(&mut args, "--reference")?.unwrap_or("reference.pfm".to_string());
+
+        let compare = take_option(&mut args, "--compare")?
+            .map(|settings| Comparison::parse(&settings, scenes)
+                .ok_or("usage: --compare <setting>=<value>[,..] with scene, accelerator, precision, exposure or depth")).transpose()?;
+
+        let mode = Mode::parse(args, scenes)?;
+
+        Ok(Self {
+            pin_threads, accelerator, transparency_depth, fixed_lod, materials, scene, quality, target_noise,
+            overscan, pixel_aspect, units, ev100, sky, aperture, debug, precision,
+            reference, compare, mode,
+        })
+    }
+}
+
+
+impl Mode {
+    fn parse(args: Vec<String>, scenes: &[&str]) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let mode = args.next();
+
+        let mode = match mode.as_deref() {
+            None => Mode::Interactive,
+            Some("image") => Mode::Image,
+            Some("bench") => Mode::Bench,
+
+            Some("record") => Mode::Record { path: args.next().ok_or("usage: record <file>")? },
+            Some("replay") => Mode::Replay { path: args.next().ok_or("usage: replay <file>")? },
+
+            Some("render") => {
+                const USAGE : &str = "usage: render <file> [samples]";
+                let path = args.next().ok_or(USAGE)?;
+                Mode::Render { path, samples: parse_next(&mut args, USAGE)? }
+            },
+
+            Some("timelapse") => {
+                const USAGE : &str = "usage: timelapse [frames] [samples]";
+                let frames = parse_next(&mut args, USAGE)?.unwrap_or(48);
+                Mode::Timelapse { frames, samples: parse_next(&mut args, USAGE)? }
+            },
+
+            Some("dataset") => {
+                const USAGE : &str = "usage: dataset <dir> [count] [samples] [--seed <seed>] [--randomise <field>=<value>[,..]]";
+                let mut rest = Vec::from_iter(args.by_ref());
+                let seed = take_option(&mut rest, "--seed")?.map(|seed| seed.parse().map_err(|_| USAGE)).transpose()?.unwrap_or(0);
+                let generator = match take_option(&mut rest, "--randomise")? {
+                    Some(settings) => Generator::DEFAULT.parse_overrides(&settings).ok_or(USAGE)?,
+                    None => Generator::DEFAULT,
+                };
+
+                args = rest.into_iter();
+                let out_dir = args.next().ok_or(USAGE)?;
+                let count = parse_next(&mut args, USAGE)?.unwrap_or(100);
+                Mode::Dataset { out_dir, count, samples: parse_next(&mut args, USAGE)?, seed, generator }
+            },
+
+            Some("bake") => {
+                const USAGE : &str = "usage: bake <obj> <out.pfm|.hdr|.exr> [ao|irradiance] [size] [samples] [--padding <texels>] [--in-scene <scene>]";
+                let mut rest = Vec::from_iter(args.by_ref());
+                let padding = take_option(&mut rest, "--padding")?
+                    .map(|padding| padding.parse().map_err(|_| USAGE)).transpose()?.unwrap_or(BakeOptions::DEFAULT.padding);
+                // lit by and shadowed by one of the built-in scenes
+                // rather than just the mesh itself
+                let in_scene = take_option(&mut rest, "--in-scene")?
+                    .map(|name| scenes.iter().position(|scene| *scene == name).ok_or(USAGE)).transpose()?;
+
+                args = rest.into_iter();
+                let mesh_path = args.next().ok_or(USAGE)?;
+                let out_path = args.next().ok_or(USAGE)?;
+                let bake = match args.next().as_deref() {
+                    None | Some("ao") => Bake::AmbientOcclusion { distance: BAKE_OCCLUSION_DISTANCE },
+                    Some("irradiance") => Bake::Irradiance { depth: MAX_DEPTH },
+                    Some(_) => return Err(USAGE.to_string()),
+                };
+                let size = parse_next(&mut args, USAGE)?.unwrap_or(BakeOptions::DEFAULT.size.0);
+                let samples = parse_next(&mut args, USAGE)?;
+                Mode::Bake { mesh_path, out_path, bake, size, samples, padding, in_scene }
+            },
+
+            Some(mode) => return Err(format!("unknown mode '{mode}', usage: [image|bench|record|replay|render|timelapse|dataset|bake] <arguments of the mode>")),
+        };
+
+        match args.next() {
+            Some(arg) => Err(format!("unexpected argument '{arg}'")),
+            None => Ok(mode),
+        }
+    }
+}
+
+
+impl SkyOption {
+    ///
+    /// Parses `gradient`, `solid:<r>,<g>,<b>`, `gradient:<r>,<g>,<b>:<r>,<g>,<b>`,
+    /// `daylight:<hours>` or `env:<path>`
+    ///
+    pub fn parse(spec: &str) -> Option<Self> {
+        let colour = |rgb: &str| {
+            let rgb = Vec::from_iter(rgb.split(',').map(|c| c.parse::<f32>().ok()));
+            let &[Some(r), Some(g), Some(b)] = rgb.as_slice() else { return None };
+            Some(Colour::new(r, g, b))
+        };
+
+        let (kind, rest) = spec.split_once(':').unwrap_or((spec, ""));
+        let sky = match (kind, rest) {
+            ("gradient", "") => SkyOption::Sky(Sky::Gradient),
+            ("gradient", colours) => {
+                let (bottom, top) = colours.split_once(':')?;
+                SkyOption::Sky(Sky::VerticalGradient { bottom: colour(bottom)?, top: colour(top)? })
+            },
+            ("solid", rgb) => SkyOption::Sky(Sky::Solid(colour(rgb)?)),
+            ("daylight", hours) => SkyOption::Daylight { hours: hours.parse().ok().filter(|hours: &f32| hours.is_finite())? },
+            ("env", path) => SkyOption::Environment { path: path.to_string() },
+            _ => return None,
+        };
+
+        Some(sky)
+    }
+}
+
+
+impl Comparison {
+    ///
+    /// Parses `setting=value` pairs separated by commas,
+    /// `scenes` are the names of the scenes to pick from
+    ///
+    pub fn parse(settings: &str, scenes: &[&str]) -> Option<Self> {
+        let mut comparison = Comparison::default();
+        for setting in settings.split(',') {
+            let (name, value) = setting.split_once('=')?;
+            match name {
+                "scene" => comparison.scene = Some(scenes.iter().position(|scene| *scene == value)?),
+                "accelerator" => comparison.accelerator = Some(Accelerator::parse(value)?),
+                "precision" => comparison.precision = Some(Precision::parse(value)?),
+                "exposure" => comparison.exposure = Some(value.parse().ok()?),
+                "depth" => comparison.max_depth = Some(value.parse().ok()?),
+                _ => return None,
+            }
+        }
+
+        Some(comparison)
+    }
+
+
+    pub fn apply(&self, camera: &mut Camera) {
+        if let Some(precision) = self.precision { camera.set_precision(precision) }
+        if let Some(exposure) = self.exposure { camera.exposure = exposure }
+        if let Some(max_depth) = self.max_depth { camera.rt_cam.max_depth = max_depth }
+    }
+}
+
+
+///
+/// Removes `--name <value>` from the arguments and returns
+/// the value, it's an error for it to be missing
+///
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let Some(i) = args.iter().position(|arg| arg == name) else { return Ok(None) };
+    let value = args.get(i + 1).ok_or_else(|| format!("usage: {name} needs a value"))?.clone();
+    args.drain(i..i + 2);
+    Ok(Some(value))
+}
+
+
+///
+/// Removes `--name` from the arguments and returns whether it was there
+///
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let Some(i) = args.iter().position(|arg| arg == name) else { return false };
+    args.remove(i);
+    true
+}
+
+
+///
+/// Parses the next argument if there is one
+///
+fn parse_next<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, usage: &str) -> Result<Option<T>, String> {
+    args.next().map(|arg| arg.parse().map_err(|_| usage.to_string())).transpose()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Options, String> {
+        Options::parse(Vec::from_iter(line.split_whitespace().map(str::to_string)), &["spheres", "quads"])
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        let options = parse("--scene quads render path.txt 4 --quality draft").unwrap();
+        assert_eq!(options.scene, 1);
+        assert_eq!(options.quality, Some(Quality::Draft));
+        assert!(matches!(options.mode, Mode::Render { path, samples: Some(4) } if path == "path.txt"));
+
+        assert!(parse("--scene").is_err());
+        assert!(parse("--scene cubes").is_err());
+        assert!(parse("--sky solid:1,2").is_err());
+        assert!(parse("--compare depth=deep").is_err());
+        assert!(parse("render").is_err());
+        assert!(parse("render path.txt many").is_err());
+        assert!(parse("bake mesh.obj out.pfm --padding").is_err());
+        assert!(parse("record path.txt extra").is_err());
+        assert!(parse("explode").is_err());
+    }
+}
@@ -1,4 +1,25 @@
+pub mod aperture;
+pub mod bake;
+pub mod blackbody;
 pub mod camera;
+pub mod curve;
+pub mod fog;
+pub mod fractal;
+pub mod grid;
 pub mod hittable;
+pub mod kdtree;
+pub mod lod;
+pub mod material_library;
 pub mod materials;
+pub mod medium;
+pub mod mesh;
+pub mod quad_batch;
+pub mod quantized_bvh;
+pub mod scatter;
+pub mod sdf;
+pub mod sky;
 pub mod texture;
+pub mod transform;
+pub mod units;
+pub mod validation;
+pub mod voxels;
@@ -0,0 +1,100 @@
+use image::Rgb32FImage;
+
+use crate::{math::vec3::Vec3, rng::next_f32};
+
+
+///
+/// The shape of the lens opening for depth of field, from a
+/// greyscale mask where black blocks the light. Points on it are
+/// drawn with a chance proportional to how bright the mask is
+/// there, so no samples go to the blocked parts and every one
+/// of them counts the same
+///
+pub struct Aperture {
+    width: usize,
+    height: usize,
+    /// the running sum of the brightness of each pixel, row by row
+    cdf: Vec<f32>,
+    /// the first pixel the sum reaches its total at, nothing
+    /// past it lets any light through
+    last_lit: usize,
+}
+
+
+impl Aperture {
+    ///
+    /// `brightness` is how much light gets through at each pixel,
+    /// it's `None` if none gets through anywhere
+    ///
+    pub fn new(width: usize, height: usize, brightness: impl Fn(usize, usize) -> f32) -> Option<Self> {
+        let mut cdf = Vec::with_capacity(width * height);
+        let mut total = 0.0;
+        for y in 0..height {
+            for x in 0..width {
+                total += brightness(x, y).max(0.0);
+                cdf.push(total);
+            }
+        }
+
+        if total <= 0.0 || !total.is_finite() { return None }
+
+        let last_lit = cdf.partition_point(|&sum| sum < total);
+        Some(Self { width, height, cdf, last_lit })
+    }
+
+
+    ///
+    /// The mean of the channels is the brightness
+    ///
+    pub fn from_image(image: &Rgb32FImage) -> Option<Self> {
+        Self::new(image.width() as usize, image.height() as usize, |x, y| {
+            let pixel = image.get_pixel(x as u32, y as u32);
+            (pixel[0] + pixel[1] + pixel[2]) / 3.0
+        })
+    }
+
+
+    ///
+    /// A point on the aperture with x and y from -1 to 1 along
+    /// its longer side, centred on the middle of the mask and
+    /// with y going up. It takes the place of a point in the
+    /// unit disk for round apertures
+    ///
+    pub fn sample(&self) -> Vec3 {
+        let target = next_f32() * self.cdf[self.last_lit];
+        // blocked pixels have the same sum as the one before
+        // them so the search never stops on one
+        let index = self.cdf.partition_point(|&sum| sum <= target).min(self.last_lit);
+        let (x, y) = (index % self.width, index / self.width);
+
+        let scale = 2.0 / self.width.max(self.height) as f32;
+        Vec3::new((x as f32 + next_f32() - 0.5 * self.width as f32) * scale,
+                  (0.5 * self.height as f32 - y as f32 - next_f32()) * scale,
+                  0.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_follow_the_brightness() {
+        let brightness = [0.0, 1.0, 0.0, 3.0];
+        let aperture = Aperture::new(4, 1, |x, _| brightness[x]).unwrap();
+
+        let mut counts = [0; 4];
+        for _ in 0..10_000 {
+            let point = aperture.sample();
+            assert!((-1.0..=1.0).contains(&point.x));
+            counts[((point.x + 1.0) * 2.0).min(3.0) as usize] += 1;
+        }
+
+        assert_eq!((counts[0], counts[2]), (0, 0));
+        let ratio = counts[3] as f32 / counts[1] as f32;
+        assert!((2.5..3.5).contains(&ratio), "{ratio}");
+
+        assert!(Aperture::new(2, 2, |_, _| 0.0).is_none());
+    }
+}
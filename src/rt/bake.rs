@@ -0,0 +1,232 @@
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use crate::{math::{ray::{Ray, TRANSPARENCY_DEPTH}, vec3::{Colour, Vec3}}, rng::Stream};
+
+use super::{hittable::Hittable, mesh::Mesh, sky::Sky, units::Units};
+
+
+///
+/// What `bake` writes into each texel
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bake {
+    /// how much of the hemisphere above the surface is open
+    /// within `distance`, from 0 for fully covered to 1
+    AmbientOcclusion { distance: f32 },
+    /// the light a white diffuse surface there would reflect,
+    /// with paths up to `depth` bounces long
+    Irradiance { depth: usize },
+}
+
+
+///
+/// How big a texture `bake` makes and how much work goes into it
+///
+#[derive(Clone, Copy, Debug)]
+pub struct BakeOptions {
+    /// width and height in texels
+    pub size: (usize, usize),
+    /// rays per texel
+    pub samples: usize,
+    /// how many texels the islands are grown by so
+    /// filtering at their edges doesn't bleed in black
+    pub padding: usize,
+    /// how many cutouts of alpha masked materials a ray goes
+    /// through, with 0 they shadow like the rest of the surface
+    pub transparency_depth: usize,
+}
+
+
+impl BakeOptions {
+    pub const DEFAULT : BakeOptions = BakeOptions {
+        size: (512, 512),
+        samples: 64,
+        padding: 2,
+        transparency_depth: TRANSPARENCY_DEPTH,
+    };
+}
+
+
+///
+/// Bakes `mesh` into a texture laid out by its uvs, with rays
+/// going into `world` which the mesh should be a part of, so
+/// everything else in it shadows and lights the mesh as well.
+/// Rows go from the top of the texture, where `v` is 1, down.
+/// Texels outside of every triangle are black except for the
+/// padding around the islands. `None` if the mesh has no uvs
+///
+pub fn bake(mesh: &Mesh, world: &Hittable, sky: &Sky, units: Units, bake: Bake, options: BakeOptions) -> Option<Vec<Colour>> {
+    if !mesh.has_uvs() { return None }
+
+    let BakeOptions { size, samples, padding, transparency_depth } = options;
+
+    let coverage = texel_coverage(mesh, size);
+    let mut texels = vec![Colour::ZERO; size.0 * size.1];
+    let stream = Stream::named("bake");
+
+    texels.chunks_mut(size.0).enumerate().par_bridge().for_each(|(y, row)| {
+        for (x, texel) in row.iter_mut().enumerate() {
+            let index = y * size.0 + x;
+            let Some((triangle, b1, b2)) = coverage[index] else { continue };
+            let texel_stream = stream.split(index as u64);
+            texel_stream.enter();
+
+            // from the smooth surface so low poly meshes aren't
+            // shadowed at the terminator, off of the side the normal is on
+            let point = mesh.terminator_point(triangle, b1, b2);
+            let normal = mesh.shading_normal(triangle, b1, b2);
+            let geometric = mesh.geometric_normal(triangle);
+            let side = if geometric.dot(normal) < 0.0 { -geometric } else { geometric };
+            let origin = point + units.hit_epsilon() * side;
+
+            let mut sum = Colour::ZERO;
+            for sample in 0..samples {
+                let mut direction = normal + Vec3::random_unit();
+                if direction.near_zero() { direction = normal }
+                let ray = Ray::new(origin, direction.unit(), 0.0);
+
+                sum += match bake {
+                    Bake::AmbientOcclusion { distance } => if ray.hit_any(distance, world, units, transparency_depth) { Colour::ZERO } else { Colour::ONE },
+                    Bake::Irradiance { depth } => ray.colour(world, sky, units, depth, transparency_depth, texel_stream.split(sample as u64)),
+                };
+            }
+
+            *texel = sum / samples.max(1) as f32;
+        }
+    });
+
+    let mut covered = Vec::from_iter(coverage.iter().map(Option::is_some));
+    for _ in 0..padding {
+        dilate(&mut texels, &mut covered, size);
+    }
+
+    Some(texels)
+}
+
+
+///
+/// The triangle and barycentric weights at the centre of every
+/// texel, if any triangle covers it in uv space. Where triangles
+/// overlap the last one wins
+///
+fn texel_coverage(mesh: &Mesh, (width, height): (usize, usize)) -> Vec<Option<(u32, f32, f32)>> {
+    let mut coverage = vec![None; width * height];
+    // texel centres are at half texels, `v` goes up the texture
+    let to_texels = |(u, v): (f32, f32)| (u * width as f32 - 0.5, (1.0 - v) * height as f32 - 0.5);
+
+    for triangle in 0..mesh.triangle_count() as u32 {
+        let [a, b, c] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)].map(|(b1, b2)| to_texels(mesh.uv(triangle, b1, b2)));
+
+        let (e1, e2) = ((b.0 - a.0, b.1 - a.1), (c.0 - a.0, c.1 - a.1));
+        let det = e1.0 * e2.1 - e2.0 * e1.1;
+        if det.abs() < f32::EPSILON { continue }
+
+        let min_x = a.0.min(b.0).min(c.0).floor().max(0.0) as usize;
+        let min_y = a.1.min(b.1).min(c.1).floor().max(0.0) as usize;
+        let max_x = (a.0.max(b.0).max(c.0).ceil().max(0.0) as usize).min(width - 1);
+        let max_y = (a.1.max(b.1).max(c.1).ceil().max(0.0) as usize).min(height - 1);
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let (dx, dy) = (x as f32 - a.0, y as f32 - a.1);
+                let b1 = (dx * e2.1 - e2.0 * dy) / det;
+                let b2 = (e1.0 * dy - dx * e1.1) / det;
+
+                // a little slack so texels on shared edges aren't lost
+                let inside = b1 >= -1e-4 && b2 >= -1e-4 && b1 + b2 <= 1.0 + 1e-4;
+                if inside { coverage[y * width + x] = Some((triangle, b1, b2)) }
+            }
+        }
+    }
+
+    coverage
+}
+
+
+///
+/// Fills every texel next to a covered one with the
+/// mean of its covered neighbours and marks it covered
+///
+fn dilate(texels: &mut [Colour], covered: &mut [bool], (width, height): (usize, usize)) {
+    let before = covered.to_vec();
+    for y in 0..height {
+        for x in 0..width {
+            if before[y * width + x] { continue }
+
+            let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+            let (mut sum, mut count) = (Colour::ZERO, 0);
+            for (nx, ny) in neighbours {
+                if nx >= width || ny >= height || !before[ny * width + nx] { continue }
+                sum += texels[ny * width + nx];
+                count += 1;
+            }
+
+            if count == 0 { continue }
+            texels[y * width + x] = sum / count as f32;
+            covered[y * width + x] = true;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sti::arena::Arena;
+
+    use crate::{math::vec3::Point, rt::materials::Material};
+
+    use super::*;
+
+    #[test]
+    fn open_and_covered_quads() {
+        let arena = Arena::new();
+        let positions = [Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0),
+                         Point::new(1.0, 0.0, 1.0), Point::new(0.0, 0.0, 1.0)];
+        let uvs = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let quad = arena.alloc_new(Mesh::new(&arena, &positions, &uvs, &[[0, 2, 1], [0, 3, 2]], true));
+        let world = Hittable::mesh(&arena, quad, Material::default());
+
+        // nothing above it and a white sky all around
+        let sky = Sky::Solid(Colour::ONE);
+        let options = BakeOptions { size: (8, 8), samples: 4, ..BakeOptions::DEFAULT };
+        let occlusion = bake(quad, &world, &sky, Units::Metres, Bake::AmbientOcclusion { distance: 10.0 }, options).unwrap();
+        assert!(occlusion.iter().all(|&texel| texel == Colour::ONE));
+
+        let irradiance = bake(quad, &world, &sky, Units::Metres, Bake::Irradiance { depth: 4 }, options).unwrap();
+        assert!(irradiance.iter().all(|texel| (texel.x - 1.0).abs() < 1e-5));
+
+        // slabs on both sides close everything off
+        let roof = Hittable::axis_aligned_box(Point::new(-100.0, 0.5, -100.0), Point::new(100.0, 0.6, 100.0), Material::default());
+        let floor = Hittable::axis_aligned_box(Point::new(-100.0, -0.6, -100.0), Point::new(100.0, -0.5, 100.0), Material::default());
+        let world = Hittable::list(arena.alloc_new([world, roof, floor]));
+        let occlusion = bake(quad, &world, &sky, Units::Metres, Bake::AmbientOcclusion { distance: 10.0 }, options).unwrap();
+        // only rays skimming along the quad get out sideways
+        let open = occlusion.iter().map(|texel| texel.x).sum::<f32>() / occlusion.len() as f32;
+        assert!(open < 0.05, "{open}");
+
+        let flat = Mesh::new(&arena, &positions, &[], &[[0, 2, 1]], false);
+        assert!(bake(&flat, &world, &sky, Units::Metres, Bake::AmbientOcclusion { distance: 1.0 }, options).is_none());
+    }
+
+
+    #[test]
+    fn padding_grows_islands() {
+        let arena = Arena::new();
+        // a small island in the middle of the texture
+        let positions = [Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(0.0, 0.0, 1.0)];
+        let uvs = [(0.4, 0.4), (0.6, 0.4), (0.4, 0.6)];
+        let island = arena.alloc_new(Mesh::new(&arena, &positions, &uvs, &[[0, 2, 1]], false));
+        let world = Hittable::mesh(&arena, island, Material::default());
+        let sky = Sky::Solid(Colour::ONE);
+
+        let lit = |padding| {
+            let options = BakeOptions { size: (16, 16), samples: 1, padding, ..BakeOptions::DEFAULT };
+            let texels = bake(island, &world, &sky, Units::Metres, Bake::AmbientOcclusion { distance: 1.0 }, options).unwrap();
+            texels.iter().filter(|&&texel| texel == Colour::ONE).count()
+        };
+
+        assert!(lit(0) > 0);
+        assert!(lit(2) > lit(0));
+        assert_eq!(lit(32), 16 * 16);
+    }
+}
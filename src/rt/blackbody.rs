@@ -0,0 +1,61 @@
+use crate::math::vec3::{Colour, Vec3};
+
+const WAVELENGTH_MIN : f32 = 380.0;
+const WAVELENGTH_MAX : f32 = 780.0;
+const WAVELENGTH_STEP : f32 = 5.0;
+
+
+///
+/// The linear sRGB colour of a blackbody at `kelvin`.
+/// Planck's law is integrated against the CIE 1931 colour
+/// matching functions over the visible spectrum, the result
+/// is normalised to a luminance of 1 so only the hue changes
+///
+pub fn blackbody(kelvin: f32) -> Colour {
+    let mut xyz = Vec3::ZERO;
+    let mut wavelength = WAVELENGTH_MIN;
+    while wavelength <= WAVELENGTH_MAX {
+        xyz += planck(wavelength, kelvin) * cie_xyz(wavelength);
+        wavelength += WAVELENGTH_STEP;
+    }
+
+    if xyz.y <= 0.0 { return Colour::ZERO }
+    let xyz = xyz / xyz.y;
+
+    let r =  3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z;
+    let g = -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z;
+    let b =  0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z;
+
+    // deep reds are outside of the sRGB gamut
+    Colour::new(r.max(0.0), g.max(0.0), b.max(0.0))
+}
+
+
+///
+/// Spectral radiance at `wavelength` nanometres, up to a constant factor
+///
+fn planck(wavelength: f32, kelvin: f32) -> f32 {
+    // the second radiation constant in nm·K
+    const C2 : f64 = 1.4387769e7;
+
+    let wavelength = wavelength as f64;
+    let x = C2 / (wavelength * kelvin as f64);
+    (1e15 / (wavelength.powi(5) * x.exp_m1())) as f32
+}
+
+
+///
+/// The multi-lobe gaussian fit of the CIE 1931 colour
+/// matching functions by Wyman, Sloan and Shirley
+///
+fn cie_xyz(wavelength: f32) -> Vec3 {
+    let lobe = |centre: f32, below: f32, above: f32| {
+        let t = (wavelength - centre) * if wavelength < centre { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+
+    let x = 1.056 * lobe(599.8, 0.0264, 0.0323) + 0.362 * lobe(442.0, 0.0624, 0.0374) - 0.065 * lobe(501.1, 0.0490, 0.0382);
+    let y = 0.821 * lobe(568.8, 0.0213, 0.0247) + 0.286 * lobe(530.9, 0.0613, 0.0322);
+    let z = 1.217 * lobe(437.0, 0.0845, 0.0278) + 0.681 * lobe(459.0, 0.0385, 0.0725);
+    Vec3::new(x, y, z)
+}
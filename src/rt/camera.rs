@@ -2,12 +2,196 @@ use std::{f32::consts::E, sync::atomic::AtomicUsize};
 
 use rayon::iter::{ParallelBridge, ParallelIterator};
 
-use crate::{math::{ray::Ray, vec3::{Colour, Point, Vec3}}, rng::next_f32, utils::SendPtr, RENDER_RESOLUTION};
+use crate::{math::{half::Half, interval::Interval, ray::{Ray, TRANSPARENCY_DEPTH}, vec3::{Colour, Point, Vec3}}, rng::{next_f32, Stream}, utils::SendPtr, RENDER_RESOLUTION};
+
+use super::{aperture::Aperture, fog::Fog, hittable::{Hittable, TraversalCost}, sky::Sky, units::Units};
+
+/// How many box and primitive tests make a pixel of the heat map white
+const HEAT_MAP_TESTS : f32 = 256.0;
+/// How many primary rays `get_rays` sets up at once,
+/// as many `f32`s as fit in an AVX register
+const RAY_LANES : usize = 8;
+
+
+///
+/// Views which show how the image is rendered instead of the image
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    /// How many bounding boxes (red) and primitives (green)
+    /// were tested for the ray through the middle of each pixel
+    Bvh,
+}
+
+
+///
+/// How the samples of each pixel are accumulated
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    /// sums of the colours and of their squares in `f32`
+    F32,
+    /// running means of the colours and of their squares in
+    /// halves, half as much memory as `F32` and stochastically
+    /// rounded so the means don't drift, see `Half::from_f32_stochastic`
+    F16,
+}
+
+
+impl Precision {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "f32" => Some(Precision::F32),
+            "f16" => Some(Precision::F16),
+            _ => None,
+        }
+    }
+}
+
+
+///
+/// A rectangle of pixels from `min` up to but not including `max`
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub min: (usize, usize),
+    pub max: (usize, usize),
+}
+
+
+impl Region {
+    pub fn full(image: (usize, usize)) -> Self {
+        Self { min: (0, 0), max: image }
+    }
+}
+
+
+///
+/// The buffers a `RaytracingCamera` accumulates samples into
+///
+pub enum Accumulator<'b> {
+    F32 { colours: &'b mut [Colour], squares: &'b mut [Colour] },
+    /// the mean colour followed by the mean of its square
+    F16(&'b mut [[Half; 6]]),
+}
+
+
+impl Accumulator<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            Accumulator::F32 { colours, .. } => colours.len(),
+            Accumulator::F16(means) => means.len(),
+        }
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+
+#[derive(Clone, Copy)]
+enum AccumulatorPtr {
+    F32(SendPtr<Colour>, SendPtr<Colour>),
+    F16(SendPtr<[Half; 6]>),
+}
+
+
+impl AccumulatorPtr {
+    unsafe fn add(self, count: usize) -> Self {
+        match self {
+            AccumulatorPtr::F32(colours, squares) => AccumulatorPtr::F32(SendPtr(colours.0.add(count)), SendPtr(squares.0.add(count))),
+            AccumulatorPtr::F16(means) => AccumulatorPtr::F16(SendPtr(means.0.add(count))),
+        }
+    }
+
+
+    ///
+    /// Adds `colour` as sample number `sample_count`
+    /// and returns the mean of the pixel
+    ///
+    unsafe fn accumulate(self, colour: Colour, sample_count: f32) -> Colour {
+        match self {
+            AccumulatorPtr::F32(colours, squares) => {
+                let sum = colours.0.read() + colour;
+                colours.0.write(sum);
+                squares.0.write(squares.0.read() + colour * colour);
+                sum / sample_count
+            },
+
+            AccumulatorPtr::F16(means) => {
+                let mut values = means.0.read();
+                let samples = [colour.x, colour.y, colour.z, colour.x * colour.x, colour.y * colour.y, colour.z * colour.z];
+                for (mean, sample) in values.iter_mut().zip(samples) {
+                    let m = mean.to_f32();
+                    *mean = Half::from_f32_stochastic(m + (sample - m) / sample_count, next_f32());
+                }
+
+                means.0.write(values);
+                Colour::new(values[0].to_f32(), values[1].to_f32(), values[2].to_f32())
+            },
+        }
+    }
+}
+
+
+///
+/// Where a `RaytracingCamera` looks from and how its lens is set
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub look_from: Point,
+    pub look_at: Point,
+    pub vup: Vec3,
+    /// the vertical field of view in degrees
+    pub vfov: f32,
+    /// the angle in degrees of the cone of rays through each
+    /// pixel, 0 keeps everything in focus
+    pub defocus_angle: f32,
+    pub focus_dist: f32,
+}
+
+
+///
+/// How a `RaytracingCamera` lights the scene
+/// and turns its samples into an image
+///
+#[derive(Clone, Copy)]
+pub struct RenderSettings<'a> {
+    /// what rays which escape the scene see
+    pub sky: Sky<'a>,
+    /// the units of the scene's coordinates
+    pub units: Units,
+    /// brightens every emitting material, the sky isn't
+    pub light_scale: f32,
+    /// in stops, only brightens the finished image so it
+    /// can change without throwing away the samples
+    pub exposure: f32,
+    /// the post-process fog, if there's any
+    pub fog: Option<Fog>,
+    /// how many cutouts a ray goes through on every bounce
+    pub transparency_depth: usize,
+    /// every sample draws its random numbers from below this
+    pub stream: Stream,
+}
+
+
+impl RenderSettings<'_> {
+    pub const DEFAULT : RenderSettings<'static> = RenderSettings {
+        sky: Sky::Gradient,
+        units: Units::Metres,
+        light_scale: 1.0,
+        exposure: 0.0,
+        fog: None,
+        transparency_depth: TRANSPARENCY_DEPTH,
+        stream: Stream::new(0),
+    };
+}
 
-use super::hittable::Hittable;
 
 #[derive(Clone)]
-pub struct RaytracingCamera {
+pub struct RaytracingCamera<'a> {
     pub image: (usize, usize),
     pub centre: Point,
     pub pixel00_loc: Vec3,
@@ -17,12 +201,19 @@ pub struct RaytracingCamera {
     pub defocus_angle: f32,
     pub defocus_disk_u: Vec3,
     pub defocus_disk_v: Vec3,
+    /// the shape of the lens, round if there's none
+    pub aperture: Option<&'a Aperture>,
+    pub settings: RenderSettings<'a>,
 }
 
-impl RaytracingCamera {
-    pub fn new(aspect_ratio: f32, width: usize,
-               max_depth: usize, vfov: f32, look_from: Vec3, look_at: Vec3,
-               vup: Vec3, defocus_angle: f32, focus_dist: f32) -> Self {
+impl<'a> RaytracingCamera<'a> {
+    ///
+    /// `pixel_aspect` is how much wider than tall a pixel is when
+    /// it's shown, e.g. 2.0 for an anamorphic lens which gets
+    /// stretched out to twice the width afterwards
+    ///
+    pub fn new(aspect_ratio: f32, pixel_aspect: f32, width: usize, max_depth: usize, view: View) -> Self {
+        let View { look_from, look_at, vup, vfov, defocus_angle, focus_dist } = view;
 
         let height = {
             let val = (width as f32 / aspect_ratio) as usize;
@@ -35,7 +226,7 @@ impl RaytracingCamera {
         let theta = vfov.to_radians();
         let h = (theta/2.0).tan();
         let viewport_height = 2.0 * h * focus_dist;
-        let viewport_width = viewport_height * (width as f32 / height as f32);
+        let viewport_width = viewport_height * (width as f32 * pixel_aspect / height as f32);
 
         let w = (look_from - look_at).unit();
         let u = vup.cross(w).unit();
@@ -68,41 +259,129 @@ impl RaytracingCamera {
             defocus_angle,
             defocus_disk_u,
             defocus_disk_v,
+            aperture: None,
+            settings: RenderSettings::DEFAULT,
         }
     }
 
 
+    pub fn with_settings(mut self, settings: RenderSettings<'a>) -> Self {
+        self.settings = settings;
+        self
+    }
+
+
+    ///
+    /// Defocus blur takes the shape of `aperture`
+    ///
+    pub fn with_aperture(mut self, aperture: Option<&'a Aperture>) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+
+    ///
+    /// Grows the image by `border` pixels on every side without
+    /// changing the pixel size, so more of the scene is seen
+    ///
+    pub fn with_overscan(mut self, border: (usize, usize)) -> Self {
+        self.pixel00_loc = self.pixel00_loc - border.0 as f32 * self.pixel_delta_u - border.1 as f32 * self.pixel_delta_v;
+        self.image = (self.image.0 + 2 * border.0, self.image.1 + 2 * border.1);
+        self
+    }
+
+
+    /// Only the pixels in `region` get a sample, `counts` is how
+    /// many samples each pixel has had so far and normalises it.
+    /// `acc` also keeps the square of every sample so
+    /// the noise left in the image can be estimated.
+    /// `depth` holds the mean distance to the first hit of each pixel
+    /// and is used for the post-process fog of the `settings`
+    ///
     /// # Undefined Behaviour
-    /// - If `colours.len()` != image.x * image.y
-    pub unsafe fn render(&self, acc_colours: &mut [Colour], final_colours: &mut [u32], samples: usize, world: &Hittable) {
-        debug_assert_eq!(acc_colours.len(), self.image.0 * self.image.1);
+    /// - If `acc.len()` != image.x * image.y
+    /// - If `counts.len()` != image.x * image.y
+    /// - If `region` isn't inside the image
+    /// - If `depth.len()` != image.x * image.y
+    pub unsafe fn render(&self, acc: Accumulator, counts: &mut [u32], depth: &mut [f32], final_colours: &mut [u32],
+                         region: Region, world: &Hittable) {
+        debug_assert_eq!(acc.len(), self.image.0 * self.image.1);
+        debug_assert_eq!(counts.len(), self.image.0 * self.image.1);
+        debug_assert!(region.max.0 <= self.image.0 && region.max.1 <= self.image.1);
+        debug_assert_eq!(depth.len(), self.image.0 * self.image.1);
         debug_assert_eq!(final_colours.len(), self.image.0 * self.image.1);
 
         {
-            let acc_ptr = SendPtr(acc_colours.as_mut_ptr());
+            let acc_ptr = match acc {
+                Accumulator::F32 { colours, squares } => AccumulatorPtr::F32(SendPtr(colours.as_mut_ptr()), SendPtr(squares.as_mut_ptr())),
+                Accumulator::F16(means) => AccumulatorPtr::F16(SendPtr(means.as_mut_ptr())),
+            };
+            let counts_ptr = SendPtr(counts.as_mut_ptr());
+            let depth_ptr = SendPtr(depth.as_mut_ptr());
 
             let final_ptr = SendPtr(final_colours.as_mut_ptr());
 
-            let samples = 1.0 / samples as f32;
+            // the accumulated colours are already gamma corrected
+            let exposure = linear_to_gamma(self.settings.exposure.exp2());
+            let fog = self.settings.fog;
             // i have never cared less about UB as i have here
-            (0..self.image.1).par_bridge()
+            (region.min.1..region.max.1).par_bridge()
                 .for_each(move |y| {
                     let acc_ptr = acc_ptr;
+                    let counts_ptr = counts_ptr;
+                    let depth_ptr = depth_ptr;
                     let final_ptr = final_ptr;
 
-                    let mut acc_ptr = unsafe { acc_ptr.0.offset((y*self.image.0) as isize) };
-                    let mut final_ptr = unsafe { final_ptr.0.offset((y*self.image.0) as isize) };
+                    let start = y*self.image.0 + region.min.0;
+                    let mut acc_ptr = unsafe { acc_ptr.add(start) };
+                    let mut counts_ptr = unsafe { counts_ptr.0.add(start) };
+                    let mut depth_ptr = unsafe { depth_ptr.0.add(start) };
+                    let mut final_ptr = unsafe { final_ptr.0.add(start) };
+
+                    // every sample of every pixel has its own stream, the
+                    // bounces use their depth below it so 0 is left free
+                    let sample_stream = |x: usize, sample: u32| self.settings.stream.split((y * self.image.0 + x) as u64).split(sample as u64);
+
+                    let mut rays = [Ray::new(Point::ZERO, Vec3::ZERO, 0.0); RAY_LANES];
+                    for x in region.min.0..region.max.0 {
+                        let sample = unsafe { counts_ptr.read() };
+                        let lane = (x - region.min.0) % RAY_LANES;
+                        if lane == 0 {
+                            // every ray of a batch draws from its own pixel's stream,
+                            // the lanes past the region are never used
+                            let streams = std::array::from_fn(|lane| {
+                                let sample = if x + lane < region.max.0 { unsafe { counts_ptr.add(lane).read() } } else { 0 };
+                                sample_stream(x + lane, sample).split(0)
+                            });
+                            rays = self.get_rays(x, y, streams);
+                        }
+
+                        let (colour, distance) = self.colour_of(world, rays[lane], sample_stream(x, sample));
+
+                        let count = sample + 1;
+                        unsafe { counts_ptr.write(count) };
+                        let sample_count = count as f32;
 
-                    for x in 0..self.image.0 {
-                        let colour = self.colour_of(world, x, y);
+                        let mean = unsafe { acc_ptr.accumulate(colour, sample_count) };
 
-                        unsafe { acc_ptr.write(acc_ptr.read() + colour) };
+                        // running mean, escaped rays make the pixel infinitely far
+                        let mean_depth = unsafe { depth_ptr.read() };
+                        let mean_depth = if sample_count <= 1.0 { distance }
+                                         else { mean_depth + (distance - mean_depth) / sample_count };
+                        let mean_depth = if mean_depth.is_nan() { f32::INFINITY } else { mean_depth };
+                        unsafe { depth_ptr.write(mean_depth) };
                         
                         {
-                            let colour = samples * unsafe { acc_ptr.read() };
-                            let r = (colour.x * 255.999) as u32;
-                            let g = (colour.y * 255.999) as u32;
-                            let b = (colour.z * 255.999) as u32;
+                            let colour = exposure * mean;
+                            let colour = match fog {
+                                Some(fog) => fog.apply(colour, mean_depth),
+                                None => colour,
+                            };
+                            // bright lights like the sun can go over 1
+                            let unit = Interval::new(0.0, 1.0);
+                            let r = (unit.clamp(colour.x) * 255.999) as u32;
+                            let g = (unit.clamp(colour.y) * 255.999) as u32;
+                            let b = (unit.clamp(colour.z) * 255.999) as u32;
 
                             let val = (r << 16) | (g << 8) | (b);
                             unsafe { final_ptr.write(val) };
@@ -111,6 +390,8 @@ impl RaytracingCamera {
 
                         //unsafe { final_ptr.write(acc_ptr.read() / samples as f32) };
                         acc_ptr = unsafe { acc_ptr.add(1) };
+                        counts_ptr = unsafe { counts_ptr.add(1) };
+                        depth_ptr = unsafe { depth_ptr.add(1) };
                         final_ptr = unsafe { final_ptr.add(1) };
                     }
 
@@ -119,47 +400,91 @@ impl RaytracingCamera {
         }
     }
 
+
+    ///
+    /// Renders `view` into `final_colours` in one pass
+    ///
+    pub fn render_debug(&self, view: DebugView, final_colours: &mut [u32], world: &Hittable) {
+        debug_assert_eq!(final_colours.len(), self.image.0 * self.image.1);
+
+        match view {
+            DebugView::Bvh => {
+                final_colours.chunks_mut(self.image.0).enumerate().par_bridge().for_each(|(y, row)| {
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        let pixel_centre = self.pixel00_loc + (x as f32 * self.pixel_delta_u) + (y as f32 * self.pixel_delta_v);
+                        let ray = Ray::new(self.centre, pixel_centre - self.centre, 0.0);
+
+                        let mut cost = TraversalCost::default();
+                        world.traversal_cost(ray, Interval::new(0.0, f32::INFINITY), &mut cost);
+
+                        let unit = Interval::new(0.0, 1.0);
+                        let r = (unit.clamp(cost.box_tests as f32 / HEAT_MAP_TESTS) * 255.999) as u32;
+                        let g = (unit.clamp(cost.primitive_tests as f32 / HEAT_MAP_TESTS) * 255.999) as u32;
+                        *pixel = (r << 16) | (g << 8);
+                    }
+                });
+            },
+        }
+    }
+
     
-    fn colour_of(&self, world: &Hittable, x: usize, y: usize) -> Colour {
+    fn colour_of(&self, world: &Hittable, ray: Ray, stream: Stream) -> (Colour, f32) {
         // calculate the colour
-        let ray = self.get_ray(x, y);
-        let mut colour = ray.colour(&world, self.max_depth);
+        let (mut colour, distance) = ray.colour_and_distance(world, &RenderSettings { stream, ..self.settings }, self.max_depth);
         
         // Linear -> Gamma
         colour.x = linear_to_gamma(colour.x);
         colour.y = linear_to_gamma(colour.y);
         colour.z = linear_to_gamma(colour.z);
 
-        colour
+        (colour, distance)
     }
 
 
+    ///
+    /// The rays through `RAY_LANES` pixels of row `y` starting at
+    /// `x`, which may go past the image. They're set up as lanes
+    /// like in `QuadBatch` so the compiler turns it into SIMD,
+    /// only the random numbers are drawn one at a time, each
+    /// lane's from its stream in `streams`
+    ///
+    fn get_rays(&self, x: usize, y: usize, streams: [Stream; RAY_LANES]) -> [Ray; RAY_LANES] {
+        // where in the pixel each sample is, in pixels
+        let mut offset_u = [0.0; RAY_LANES];
+        let mut offset_v = [0.0; RAY_LANES];
+        let mut time = [0.0; RAY_LANES];
+        let mut origin = [[0.0; RAY_LANES]; 3];
+        for (lane, stream) in streams.into_iter().enumerate() {
+            stream.enter();
+            offset_u[lane] = (x + lane) as f32 - 0.5 + next_f32();
+            offset_v[lane] = y as f32 - 0.5 + next_f32();
+            time[lane] = next_f32();
+
+            let o = if self.defocus_angle <= 0.0 { self.centre } else { self.defocus_disk_sample() };
+            for (axis, origin) in origin.iter_mut().enumerate() { origin[lane] = o[axis] }
+        }
 
-    fn get_ray(&self, x: usize, y: usize) -> Ray {
-        let pixel_centre = self.pixel00_loc + (x as f32 * self.pixel_delta_u) + (y as f32 * self.pixel_delta_v);
-        let pixel_sample = pixel_centre + self.pixel_sample_square();
-
-        let ray_origin = if self.defocus_angle <= 0.0 { self.centre } else { self.defocus_disk_sample() };
-        let ray_direction = pixel_sample - ray_origin;
-        let ray_time = next_f32();
-
-        Ray::new(ray_origin, ray_direction, ray_time)
+        let mut direction = [[0.0; RAY_LANES]; 3];
+        for axis in 0..3 {
+            for lane in 0..RAY_LANES {
+                let sample = self.pixel00_loc[axis] + offset_u[lane] * self.pixel_delta_u[axis] + offset_v[lane] * self.pixel_delta_v[axis];
+                direction[axis][lane] = sample - origin[axis][lane];
+            }
+        }
 
+        std::array::from_fn(|lane| Ray::new(Point::new(origin[0][lane], origin[1][lane], origin[2][lane]),
+                                            Vec3::new(direction[0][lane], direction[1][lane], direction[2][lane]),
+                                            time[lane]))
     }
 
     
     fn defocus_disk_sample(&self) -> Point {
-        let p = Vec3::random_in_unit_disk();
+        let p = match self.aperture {
+            Some(aperture) => aperture.sample(),
+            None => Vec3::random_in_unit_disk(),
+        };
         self.centre + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
     }
-
-
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + next_f32();
-        let py = -0.5 + next_f32();
-
-        px * self.pixel_delta_u + py * self.pixel_delta_v
-    }
 }
 
 
@@ -0,0 +1,112 @@
+use sti::arena::Arena;
+
+use crate::math::{aabb::AABB, interval::Interval, ray::Ray, vec3::{Point, Vec3}};
+
+
+///
+/// A thin round strand for hair, grass and wires. The strand is
+/// stored as a polyline with a radius at every point and each of
+/// its segments is hit like a cylinder lying across the ray,
+/// which is close enough as long as the strand is thin
+///
+pub struct Curve<'a> {
+    points: &'a [Point],
+    radii: &'a [f32],
+}
+
+
+///
+/// Where a ray hits a curve segment, `s` goes from 0 at the
+/// start of the segment to 1 at its end and `axis` is the
+/// closest point on the centre of the strand
+///
+#[derive(Clone, Copy)]
+pub struct CurveHit {
+    pub t: f32,
+    pub s: f32,
+    pub axis: Point,
+}
+
+
+impl<'a> Curve<'a> {
+    ///
+    /// A cubic Bézier curve through `control` split into `segments`
+    /// straight pieces. The width goes linearly from `start_width`
+    /// to `end_width`, so a strand of grass can end in a tip
+    ///
+    pub fn bezier(arena: &'a Arena, control: [Point; 4], start_width: f32, end_width: f32, segments: u32) -> Self {
+        let mut points = sti::vec::Vec::with_cap_in(arena, segments as usize + 1);
+        let mut radii = sti::vec::Vec::with_cap_in(arena, segments as usize + 1);
+
+        for i in 0..=segments {
+            let t = i as f32 / segments as f32;
+            let u = 1.0 - t;
+            let p = (u*u*u) * control[0] + (3.0*u*u*t) * control[1] + (3.0*u*t*t) * control[2] + (t*t*t) * control[3];
+
+            points.push(p);
+            radii.push(0.5 * (start_width + (end_width - start_width) * t));
+        }
+
+        Self { points: points.leak(), radii: radii.leak() }
+    }
+
+
+    pub fn segment_count(&self) -> usize {
+        self.points.len() - 1
+    }
+
+
+    ///
+    /// The box around a segment and its width
+    ///
+    pub fn segment_bounds(&self, segment: u32) -> AABB {
+        let i = segment as usize;
+        let radius = self.radii[i].max(self.radii[i + 1]);
+        let pad = Vec3::new(radius, radius, radius);
+
+        AABB::from_aabbs(&AABB::from_points(self.points[i] - pad, self.points[i] + pad),
+                         &AABB::from_points(self.points[i + 1] - pad, self.points[i + 1] + pad))
+    }
+
+
+    pub fn hit_segment(&self, segment: u32, ray: Ray, t: Interval) -> Option<CurveHit> {
+        let i = segment as usize;
+        let p0 = self.points[i];
+        let edge = self.points[i + 1] - p0;
+
+        // the closest points between the ray and the segment's line
+        let w = ray.origin - p0;
+        let a = ray.direction.dot(ray.direction);
+        let b = ray.direction.dot(edge);
+        let c = edge.dot(edge);
+        let denom = a*c - b*b;
+        let s = if denom.abs() < 1e-12 { 0.0 }
+                else { ((a * edge.dot(w) - b * ray.direction.dot(w)) / denom).clamp(0.0, 1.0) };
+
+        let axis = p0 + s * edge;
+        let closest = ray.direction.dot(axis - ray.origin) / a;
+        let distance_squared = (ray.at(closest) - axis).length_squared();
+
+        let radius = self.radii[i] + (self.radii[i + 1] - self.radii[i]) * s;
+        if distance_squared > radius*radius { return None }
+
+        // step back from the closest point onto the front of the strand
+        let root = closest - (radius*radius - distance_squared).sqrt() / a.sqrt();
+        if !t.surrounds(root) { return None }
+
+        Some(CurveHit { t: root, s, axis })
+    }
+
+
+    ///
+    /// How far along the whole curve `s` on `segment` is, from 0 to 1
+    ///
+    pub fn u(&self, segment: u32, s: f32) -> f32 {
+        (segment as f32 + s) / self.segment_count() as f32
+    }
+
+
+    pub fn points(&self) -> &'a [Point] {
+        self.points
+    }
+}
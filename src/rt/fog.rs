@@ -0,0 +1,29 @@
+use crate::math::{interval::Interval, vec3::Colour};
+
+///
+/// A cheap post-process fog which blends the
+/// rendered colour towards `colour` by hit distance.
+/// Everything closer than `start` is clear and
+/// everything further than `end` is fully fogged
+///
+#[derive(Clone, Copy)]
+pub struct Fog {
+    pub colour: Colour,
+    pub start: f32,
+    pub end: f32,
+}
+
+
+impl Fog {
+    pub fn new(colour: Colour, start: f32, end: f32) -> Self {
+        Self { colour, start, end }
+    }
+
+
+    #[inline(always)]
+    pub fn apply(self, colour: Colour, distance: f32) -> Colour {
+        let range = (self.end - self.start).max(f32::EPSILON);
+        let a = Interval::new(0.0, 1.0).clamp((distance - self.start) / range);
+        (1.0 - a) * colour + a * self.colour
+    }
+}
@@ -0,0 +1,73 @@
+use sti::arena::Arena;
+
+use crate::math::{matrix::Matrix4, vec3::{Point, Vec3}};
+
+use super::{hittable::Hittable, materials::Material, mesh::Mesh};
+
+
+///
+/// A Menger sponge filling the box from -1 to 1 on every axis.
+/// Each level is a BVH of 20 instances of the level below,
+/// so the geometry stays a single box no matter the level
+/// while rays go through `level` nested BVHs
+///
+pub fn menger_sponge<'a>(arena: &'a Arena, level: u32, mat: Material<'a>) -> Hittable<'a> {
+    let mut offsets = Vec::new();
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                // the centre and the middle of every face are removed
+                let centred = (x == 0) as u32 + (y == 0) as u32 + (z == 0) as u32;
+                if centred > 1 { continue }
+
+                offsets.push((2.0 / 3.0) * Vec3::new(x as f32, y as f32, z as f32));
+            }
+        }
+    }
+
+    let base = Hittable::axis_aligned_box(Point::new(-1.0, -1.0, -1.0), Point::ONE, mat);
+    self_similar(arena, base, &offsets, 1.0 / 3.0, level)
+}
+
+
+///
+/// A Sierpinski tetrahedron with its corners on alternating
+/// corners of the box from -1 to 1 on every axis.
+/// Each level is a BVH of 4 instances of the level below
+///
+pub fn sierpinski_tetrahedron<'a>(arena: &'a Arena, level: u32, mat: Material<'a>) -> Hittable<'a> {
+    let corners = [
+        Point::new( 1.0,  1.0,  1.0),
+        Point::new( 1.0, -1.0, -1.0),
+        Point::new(-1.0,  1.0, -1.0),
+        Point::new(-1.0, -1.0,  1.0),
+    ];
+
+    let triangles = [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]];
+    let tetrahedron = arena.alloc_new(Mesh::new(arena, &corners, &[], &triangles, false));
+
+    let offsets = corners.map(|corner| 0.5 * corner);
+    self_similar(arena, Hittable::mesh(arena, tetrahedron, mat), &offsets, 0.5, level)
+}
+
+
+///
+/// Replaces `base` `level` times with copies of itself
+/// shrunk by `scale` and moved to each of `offsets`
+///
+fn self_similar<'a>(arena: &'a Arena, base: Hittable<'a>, offsets: &[Vec3], scale: f32, level: u32) -> Hittable<'a> {
+    let mut shape = base;
+    for _ in 0..level {
+        let child = arena.alloc_new(shape);
+
+        let mut copies = sti::vec::Vec::with_cap_in(arena, offsets.len());
+        for &offset in offsets {
+            let matrix = Matrix4::translation(offset) * Matrix4::scaling(Vec3::new(scale, scale, scale));
+            copies.push(Hittable::instance(arena, child, matrix));
+        }
+
+        shape = Hittable::bvh(arena, copies.leak());
+    }
+
+    shape
+}
@@ -0,0 +1,191 @@
+use sti::arena::Arena;
+
+use crate::math::{aabb::AABB, interval::Interval, ray::Ray, vec3::Vec3};
+
+use super::hittable::{HitCandidate, Hittable};
+
+const MAX_RESOLUTION : usize = 64;
+
+
+///
+/// A uniform grid over a list of hittables.
+/// Works best for many small hittables spread evenly
+/// where a tree would spend most of its time in inner nodes
+///
+pub struct UniformGrid<'a> {
+    hittables: &'a [Hittable<'a>],
+    // `(start, len)` of every cell into `indices`
+    cells: &'a [(u32, u32)],
+    indices: &'a [u32],
+    // hittables without finite bounds are tested on every ray
+    unbounded: &'a [u32],
+    resolution: [usize; 3],
+    bounds: AABB,
+    cell_size: Vec3,
+}
+
+
+impl<'a> UniformGrid<'a> {
+    pub fn new(arena: &'a Arena, hittables: &'a [Hittable<'a>]) -> Self {
+        let is_bounded = |h: &Hittable| (0..3).all(|axis| {
+            let interval = h.bounding_box().axis_interval(axis);
+            interval.min.is_finite() && interval.max.is_finite()
+        });
+
+        let mut bounds = AABB::new(Interval::EMPTY, Interval::EMPTY, Interval::EMPTY);
+        let mut unbounded = sti::vec::Vec::new_in(arena);
+        let mut bounded_count = 0;
+        for (i, h) in hittables.iter().enumerate() {
+            if is_bounded(h) {
+                bounds = AABB::from_aabbs(&bounds, h.bounding_box());
+                bounded_count += 1;
+            } else {
+                unbounded.push(i as u32);
+            }
+        }
+
+        // aim for roughly 3 hittables per cell along the longest axis
+        let extent = Vec3::new(bounds.axis_interval(0).size(), bounds.axis_interval(1).size(), bounds.axis_interval(2).size());
+        let max_extent = extent.x.max(extent.y).max(extent.z).max(f32::EPSILON);
+        let cells_per_unit = (3.0 * bounded_count as f32).cbrt() / max_extent;
+        let resolution : [usize; 3] = std::array::from_fn(|axis| {
+            ((extent[axis] * cells_per_unit).ceil() as usize).clamp(1, MAX_RESOLUTION)
+        });
+        let cell_size = Vec3::new(extent.x / resolution[0] as f32,
+                                  extent.y / resolution[1] as f32,
+                                  extent.z / resolution[2] as f32);
+
+        let mut grid = Self {
+            hittables,
+            cells: &[],
+            indices: &[],
+            unbounded: unbounded.leak(),
+            resolution,
+            bounds,
+            cell_size,
+        };
+
+        // count the hittables in each cell first so
+        // every cell can be a slice of a single array
+        let cell_count = resolution[0] * resolution[1] * resolution[2];
+        let mut counts = vec![0u32; cell_count];
+        grid.for_each_overlap(|cell, _| counts[cell] += 1);
+
+        let mut cells = sti::vec::Vec::with_cap_in(arena, cell_count);
+        let mut start = 0;
+        for &count in &counts {
+            cells.push((start, 0));
+            start += count;
+        }
+
+        let mut indices = sti::vec::Vec::with_cap_in(arena, start as usize);
+        for _ in 0..start { indices.push(0) }
+
+        grid.for_each_overlap(|cell, i| {
+            let (start, len) = &mut cells[cell];
+            indices[(*start + *len) as usize] = i;
+            *len += 1;
+        });
+
+        grid.cells = cells.leak();
+        grid.indices = indices.leak();
+        grid
+    }
+
+
+    pub fn hittables(&self) -> &'a [Hittable<'a>] {
+        self.hittables
+    }
+
+
+    pub fn closest_hit(&self, ray: Ray, t: Interval) -> Option<HitCandidate<'a, 'a>> {
+        let mut t = t;
+        let mut closest = None;
+
+        for &i in self.unbounded {
+            let Some(candidate) = self.hittables[i as usize].closest_hit(ray, t) else { continue };
+            t.max = candidate.t;
+            closest = Some(candidate);
+        }
+
+        let Some(span) = self.bounds.hit_interval(ray, t) else { return closest };
+
+        // 3D-DDA through the cells starting from where the ray enters the grid
+        let entry = ray.at(span.min);
+        let mut cell = [0isize; 3];
+        let mut step = [0isize; 3];
+        let mut next_t = [f32::INFINITY; 3];
+        let mut delta_t = [f32::INFINITY; 3];
+
+        for axis in 0..3 {
+            let min = self.bounds.axis_interval(axis).min;
+            let res = self.resolution[axis] as isize;
+            let offset = (entry[axis] - min) / self.cell_size[axis];
+            cell[axis] = (offset as isize).clamp(0, res - 1);
+
+            let dir = ray.direction[axis];
+            if dir == 0.0 { continue }
+
+            step[axis] = if dir > 0.0 { 1 } else { -1 };
+            let boundary = min + (cell[axis] + (dir > 0.0) as isize) as f32 * self.cell_size[axis];
+            next_t[axis] = (boundary - ray.origin[axis]) / dir;
+            delta_t[axis] = self.cell_size[axis] / dir.abs();
+        }
+
+        loop {
+            let index = self.cell_index(cell[0] as usize, cell[1] as usize, cell[2] as usize);
+            let (start, len) = self.cells[index];
+            for &i in &self.indices[start as usize..(start + len) as usize] {
+                let Some(candidate) = self.hittables[i as usize].closest_hit(ray, t) else { continue };
+                t.max = candidate.t;
+                closest = Some(candidate);
+            }
+
+            let axis = if next_t[0] < next_t[1] { if next_t[0] < next_t[2] { 0 } else { 2 } }
+                       else if next_t[1] < next_t[2] { 1 } else { 2 };
+
+            // anything in later cells is further away than a hit in this one
+            let cell_exit = next_t[axis];
+            if cell_exit >= t.max || cell_exit >= span.max { break }
+
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= self.resolution[axis] as isize { break }
+            next_t[axis] += delta_t[axis];
+        }
+
+        closest
+    }
+
+
+    fn cell_index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + self.resolution[0] * (y + self.resolution[1] * z)
+    }
+
+
+    ///
+    /// Calls `f` with the cell index and hittable index for every
+    /// cell each bounded hittable's bounding box overlaps
+    ///
+    fn for_each_overlap<F: FnMut(usize, u32)>(&self, mut f: F) {
+        for (i, h) in self.hittables.iter().enumerate() {
+            if self.unbounded.contains(&(i as u32)) { continue }
+
+            let range : [(usize, usize); 3] = std::array::from_fn(|axis| {
+                let interval = h.bounding_box().axis_interval(axis);
+                let min = self.bounds.axis_interval(axis).min;
+                let last = self.resolution[axis] - 1;
+                let lo = ((interval.min - min) / self.cell_size[axis]).max(0.0) as usize;
+                let hi = ((interval.max - min) / self.cell_size[axis]).max(0.0) as usize;
+                (lo.min(last), hi.min(last))
+            });
+
+            for z in range[2].0..=range[2].1 {
+                for y in range[1].0..=range[1].1 {
+                    for x in range[0].0..=range[0].1 {
+                        f(self.cell_index(x, y, z), i as u32);
+                    }
+                }
+            }
+        }
+    }
+}
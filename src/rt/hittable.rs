@@ -1,8 +1,28 @@
 use std::{cmp::Ordering, f32::consts::PI};
 
+use rayon::{iter::{IntoParallelRefIterator, ParallelIterator}, slice::ParallelSliceMut};
 use sti::{arena::Arena, traits::FromIn};
 
-use crate::{math::{aabb::AABB, interval::Interval, ray::Ray, vec3::{Point, Vec3}}, rng::next, rt::materials::Material};
+use crate::{math::{aabb::{InverseRay, AABB}, interval::Interval, matrix::Matrix4, polynomial::solve_quartic, ray::Ray, vec3::{Point, Vec3}}, rng::next, utils::Stack, rt::{curve::{Curve, CurveHit}, grid::UniformGrid, kdtree::KdTree, lod::Lod, materials::Material, medium::Medium, mesh::{Mesh, TriangleHit}, quad_batch::{self, QuadBatch}, quantized_bvh::QuantizedBvh, sdf::{self, DistanceFn}, transform::{MotionTransform, Transform}, units::Units, voxels::{VoxelHit, VoxelOctree}}};
+
+///
+/// BVHs are never built deeper than this, anything
+/// below is flattened into a list instead.
+/// This keeps the traversal stack within `BVH_STACK_SIZE`
+///
+const MAX_BVH_DEPTH : usize = 48;
+pub(crate) const BVH_STACK_SIZE : usize = 64;
+/// BVH nodes over at least this many hittables are built in parallel
+const PARALLEL_BVH_BUILD : usize = 4096;
+/// Spatial splits are only tried where the children's overlap is
+/// at least this much of the root's surface area
+const SBVH_OVERLAP : f32 = 1e-5;
+/// How many buckets the centroids are sorted into to find the SAH split
+const SAH_BINS : usize = 12;
+/// How far off their plane the corners of a polygon
+/// may be, as a fraction of the polygon's size
+const POLYGON_TOLERANCE : f32 = 1e-4;
+
 
 #[derive(Clone, Default)]
 pub struct HitRecord<'a> {
@@ -13,6 +33,20 @@ pub struct HitRecord<'a> {
     pub material: Material<'a>,
     pub u: f32,
     pub v: f32,
+    /// Which way `u` grows along the surface, not normalised and
+    /// only roughly at a right angle to the normal. Zero where
+    /// a primitive has no natural direction
+    pub tangent: Vec3,
+    /// Which way `v` grows, only set where it can go either way
+    /// around the normal like on meshes with mirrored uvs
+    pub bitangent: Vec3,
+    /// The texture coordinates of the second uv set
+    /// on meshes which have one, see `Mesh::second_uv`
+    pub second_uv: Option<(f32, f32)>,
+    /// How far rays going out on the side of the normal should
+    /// start off of `point`, see `Mesh::terminator_point`.
+    /// Zero everywhere but on the front of smooth shaded meshes
+    pub terminator_offset: Vec3,
 }
 
 
@@ -26,9 +60,454 @@ pub struct Hittable<'a> {
 #[derive(Clone)]
 pub enum HittableKind<'a> {
     List(&'a [Hittable<'a>]),
-    Sphere { centre: Point, radius: f32, mat: Material<'a> },
-    MovingSphere { centre: Ray, radius: f32, mat: Material<'a> },
-    BVH { left: &'a Hittable<'a>, right: &'a Hittable<'a> }
+    Sphere { centre: Point, radius: f32, mat: Material<'a>, clip: Option<SphereClip> },
+    Torus { centre: Point, major_radius: f32, minor_radius: f32, mat: Material<'a> },
+    Plane { point: Point, normal: Vec3, mat: Material<'a> },
+    Box { mat: Material<'a> },
+    Quad { q: Point, u: Vec3, v: Vec3, w: Vec3, normal: Vec3, d: f32, mat: Material<'a> },
+    QuadBatch(&'a QuadBatch<'a>),
+    Planar { q: Point, u: Vec3, v: Vec3, w: Vec3, normal: Vec3, d: f32, shape: PlanarShape<'a>, mat: Material<'a> },
+    Triangle { mesh: &'a Mesh<'a>, index: u32, mat: Material<'a> },
+    CurveSegment { curve: &'a Curve<'a>, index: u32, mat: Material<'a> },
+    Sdf { distance: DistanceFn<'a>, mat: Material<'a> },
+    Voxels(&'a VoxelOctree<'a>),
+    Medium(&'a Medium<'a>),
+    Transform(&'a Transform<'a>),
+    MotionTransform(&'a MotionTransform<'a>),
+    OneSided(&'a Hittable<'a>),
+    Lod(&'a Lod<'a>),
+    // `left` holds the hittables lower along `axis`
+    BVH { left: &'a Hittable<'a>, right: &'a Hittable<'a>, axis: u8, ordered: bool },
+    QuantizedBvh(&'a QuantizedBvh<'a>),
+    KdTree(&'a KdTree<'a>),
+    Grid(&'a UniformGrid<'a>),
+}
+
+
+///
+/// The acceleration structure a scene is built with
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Accelerator {
+    BVH(BvhOptions),
+    KdTree,
+    Grid,
+}
+
+
+impl Accelerator {
+    pub const ALL : [Accelerator; 3] = [Accelerator::BVH(BvhOptions::DEFAULT), Accelerator::KdTree, Accelerator::Grid];
+
+
+    ///
+    /// Parses `bvh`, `quantized-bvh`, `median-bvh`, `sbvh`, `kdtree`
+    /// or `grid`, a BVH gets the default options
+    ///
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "bvh" => Some(Accelerator::BVH(BvhOptions::DEFAULT)),
+            "quantized-bvh" => Some(Accelerator::BVH(BvhOptions { quantized: true, ..BvhOptions::DEFAULT })),
+            "median-bvh" => Some(Accelerator::BVH(BvhOptions { split: BvhSplit::Median, ..BvhOptions::DEFAULT })),
+            "sbvh" => Some(Accelerator::BVH(BvhOptions { split: BvhSplit::Spatial, ..BvhOptions::DEFAULT })),
+            "kdtree" => Some(Accelerator::KdTree),
+            "grid" => Some(Accelerator::Grid),
+            _ => None,
+        }
+    }
+
+
+    ///
+    /// The name `parse` takes, a BVH with options it
+    /// can't give is named after the closest one
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            Accelerator::BVH(BvhOptions { quantized: true, .. }) => "quantized-bvh",
+            Accelerator::BVH(BvhOptions { split: BvhSplit::Sah, .. }) => "bvh",
+            Accelerator::BVH(BvhOptions { split: BvhSplit::Median, .. }) => "median-bvh",
+            Accelerator::BVH(BvhOptions { split: BvhSplit::Spatial, .. }) => "sbvh",
+            Accelerator::KdTree => "kdtree",
+            Accelerator::Grid => "grid",
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BvhOptions {
+    /// Groups of at most this many hittables become a single list leaf
+    pub leaf_size: usize,
+    pub traversal: BvhTraversal,
+    pub split: BvhSplit,
+    /// Store the boxes of the nodes in 8 bits, see `QuantizedBvh`
+    pub quantized: bool,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BvhTraversal {
+    /// Always visit the left child first
+    LeftFirst,
+    /// Visit the child whose box the ray enters first
+    NearestFirst,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BvhSplit {
+    /// Halve the hittables sorted along the longest axis
+    Median,
+    /// Pick the split with the lowest surface area heuristic
+    /// cost from a few binned candidates on every axis
+    Sah,
+    /// Like `Sah` but space can be split as well, which puts
+    /// hittables crossing the split into both children. Large
+    /// hittables next to small ones then don't make the nodes
+    /// overlap as much, at the cost of more memory
+    Spatial,
+}
+
+
+impl BvhOptions {
+    pub const DEFAULT : BvhOptions = BvhOptions { leaf_size: 1, traversal: BvhTraversal::LeftFirst, split: BvhSplit::Sah, quantized: false };
+}
+
+
+impl Default for BvhOptions {
+    fn default() -> Self { Self::DEFAULT }
+}
+
+
+///
+/// Cuts a sphere down to the part between `y_min` and `y_max`
+/// (relative to its centre) and with a `phi` of at most `phi_max`.
+/// `phi` is measured the same way as the `u` texture coordinate,
+/// so `phi_max = 2π * u_max`
+///
+#[derive(Clone, Copy)]
+pub struct SphereClip {
+    pub y_min: f32,
+    pub y_max: f32,
+    pub phi_max: f32,
+}
+
+
+impl SphereClip {
+    fn contains(self, local: Vec3, radius: f32) -> bool {
+        if local.y < self.y_min || local.y > self.y_max { return false }
+        get_sphere_uv(local / radius).0 * 2.0 * PI <= self.phi_max
+    }
+}
+
+
+///
+/// The outline of a flat primitive in the plane coordinates
+/// `(alpha, beta)` along its `u` and `v` edges, a quad is
+/// everything from 0 to 1 in both
+///
+#[derive(Clone, Copy)]
+pub enum PlanarShape<'a> {
+    /// the half of the quad below its `u + v` diagonal
+    Triangle,
+    /// centred on `q` with `u` and `v` as its axes
+    Ellipse,
+    /// the corners of a convex polygon in order
+    Polygon(&'a [(f32, f32)]),
+}
+
+
+impl PlanarShape<'_> {
+    fn contains(self, alpha: f32, beta: f32) -> bool {
+        match self {
+            PlanarShape::Triangle => alpha >= 0.0 && beta >= 0.0 && alpha + beta <= 1.0,
+            PlanarShape::Ellipse => alpha*alpha + beta*beta <= 1.0,
+
+            PlanarShape::Polygon(corners) => {
+                // inside when on the same side of every edge
+                let mut sign = 0.0;
+                for (i, &(a0, b0)) in corners.iter().enumerate() {
+                    let (a1, b1) = corners[(i + 1) % corners.len()];
+                    let side = (a1 - a0) * (beta - b0) - (b1 - b0) * (alpha - a0);
+                    if side * sign < 0.0 { return false }
+                    if side != 0.0 { sign = side }
+                }
+
+                true
+            },
+        }
+    }
+
+
+    fn uv(self, alpha: f32, beta: f32) -> (f32, f32) {
+        match self {
+            PlanarShape::Ellipse => (0.5 * alpha + 0.5, 0.5 * beta + 0.5),
+            _ => (alpha, beta),
+        }
+    }
+}
+
+
+///
+/// The closest hit found while traversing a hittable.
+/// Only the distance, the primitive and whatever the primitive
+/// needs to shade the hit are tracked, the full `HitRecord` is
+/// computed once at the end of the traversal
+///
+#[derive(Clone, Copy)]
+pub struct HitCandidate<'h, 'a> {
+    pub t: f32,
+    pub hittable: &'h Hittable<'a>,
+    pub detail: HitDetail<'a>,
+    /// how to get from the primitive's space back to the
+    /// space of the ray, if there are transforms in between
+    pub placement: Option<Placement<'a>>,
+}
+
+
+///
+/// What a primitive found out about a hit while intersecting
+/// it, kept so the record doesn't have to intersect it again
+///
+#[derive(Clone, Copy)]
+pub enum HitDetail<'a> {
+    Surface,
+    Triangle(TriangleHit),
+    Curve(CurveHit),
+    Voxel(VoxelHit<'a>),
+}
+
+
+///
+/// The transforms above a hit folded into one, with the ray
+/// in the primitive's space so the record can be filled in
+/// there. The outermost material override wins
+///
+#[derive(Clone, Copy)]
+pub struct Placement<'a> {
+    ray: Ray,
+    matrix: Matrix4,
+    normal_matrix: Matrix4,
+    material: Option<Material<'a>>,
+}
+
+
+impl<'a> HitCandidate<'_, 'a> {
+    ///
+    /// Moves the candidate out through `transform`, `local`
+    /// is the ray the object of the transform was hit with
+    ///
+    fn placed(self, transform: &Transform<'a>, local: Ray) -> Self {
+        let placement = match self.placement {
+            Some(inner) => Placement {
+                ray: inner.ray,
+                matrix: transform.matrix() * inner.matrix,
+                normal_matrix: transform.normal_matrix() * inner.normal_matrix,
+                material: transform.material().or(inner.material),
+            },
+
+            None => Placement {
+                ray: local,
+                matrix: transform.matrix(),
+                normal_matrix: transform.normal_matrix(),
+                material: transform.material(),
+            },
+        };
+
+        Self { placement: Some(placement), ..self }
+    }
+
+
+    ///
+    /// Whether `ray` hits the front of the surface, worked out from
+    /// the geometric normal alone without filling in a record
+    ///
+    fn front_face(&self, ray: Ray) -> bool {
+        // transforms don't change the sign of `direction·normal`
+        let ray = self.placement.map_or(ray, |placement| placement.ray);
+        let point = ray.at(self.t);
+
+        let outward_normal = match (&self.hittable.kind, self.detail) {
+            (HittableKind::Sphere { centre, .. }, _) => point - *centre,
+
+            (HittableKind::Torus { centre, major_radius, .. }, _) => {
+                let local = point - *centre;
+                local - *major_radius * Vec3::new(local.x, 0.0, local.z).unit()
+            },
+
+            | (HittableKind::Plane { normal, .. }, _)
+            | (HittableKind::Quad { normal, .. }, _)
+            | (HittableKind::Planar { normal, .. }, _) => *normal,
+
+            (HittableKind::Box { .. }, _) => box_face(&self.hittable.aabb, point).1,
+            (HittableKind::Triangle { mesh, index, .. }, _) => mesh.geometric_normal(*index),
+            (HittableKind::CurveSegment { .. }, HitDetail::Curve(hit)) => point - hit.axis,
+            (HittableKind::Sdf { distance, .. }, _) => sdf::normal(*distance, point),
+            (HittableKind::Voxels(_), HitDetail::Voxel(hit)) => hit.outward_normal,
+
+            // media have no surface to be behind of
+            _ => return true,
+        };
+
+        ray.direction.dot(outward_normal) < 0.0
+    }
+
+
+    ///
+    /// Fills in `rec` for this hit along `ray`, which has to
+    /// be the ray `closest_hit` found it with
+    ///
+    fn fill_record(&self, ray: Ray, rec: &mut HitRecord<'a>) {
+        let Some(placement) = self.placement else { return self.fill_local_record(ray, rec) };
+
+        self.fill_local_record(placement.ray, rec);
+        rec.point = ray.at(rec.t);
+        rec.normal = placement.normal_matrix.transform_vector(rec.normal).unit();
+        rec.tangent = placement.matrix.transform_vector(rec.tangent);
+        rec.bitangent = placement.matrix.transform_vector(rec.bitangent);
+        rec.terminator_offset = placement.matrix.transform_vector(rec.terminator_offset);
+
+        if let Some(material) = placement.material {
+            rec.material = material;
+        }
+    }
+
+
+    ///
+    /// Fills in `rec` in the primitive's own space
+    ///
+    fn fill_local_record(&self, ray: Ray, rec: &mut HitRecord<'a>) {
+        rec.t = self.t;
+        rec.point = ray.at(rec.t);
+        rec.tangent = Vec3::ZERO;
+        rec.bitangent = Vec3::ZERO;
+        rec.second_uv = None;
+        rec.terminator_offset = Vec3::ZERO;
+
+        match (&self.hittable.kind, self.detail) {
+            (HittableKind::Sphere { centre, radius, mat, .. }, _) => {
+                let outward_normal = (rec.point - *centre) / *radius;
+                rec.set_face_normal(ray, outward_normal);
+                (rec.u, rec.v) = get_sphere_uv(outward_normal);
+                rec.tangent = Vec3::new(outward_normal.z, 0.0, -outward_normal.x);
+                rec.material = *mat;
+            },
+
+
+            (HittableKind::Torus { centre, major_radius, minor_radius, mat }, _) => {
+                // the normal points away from the closest point on the tube's centre ring
+                let local = rec.point - *centre;
+                let ring_dir = Vec3::new(local.x, 0.0, local.z).unit();
+                let outward_normal = (local - *major_radius * ring_dir) / *minor_radius;
+                rec.set_face_normal(ray, outward_normal);
+
+                let phi = (-local.z).atan2(local.x) + PI;
+                let theta = outward_normal.y.atan2(outward_normal.dot(ring_dir)) + PI;
+                (rec.u, rec.v) = (phi/(2.0*PI), theta/(2.0*PI));
+                rec.tangent = Vec3::new(local.z, 0.0, -local.x);
+                rec.material = *mat;
+            },
+
+
+            (HittableKind::Plane { point, normal, mat }, _) => {
+                rec.set_face_normal(ray, *normal);
+
+                let (tangent, bitangent) = plane_basis(*normal);
+                let local = rec.point - *point;
+                (rec.u, rec.v) = (local.dot(tangent), local.dot(bitangent));
+                rec.tangent = tangent;
+                rec.material = *mat;
+            },
+
+
+            (HittableKind::Box { mat }, _) => {
+                let (axis, outward_normal) = box_face(&self.hittable.aabb, rec.point);
+                rec.set_face_normal(ray, outward_normal);
+
+                let (u_axis, v_axis) = (self.hittable.aabb.axis_interval((axis + 1) % 3), self.hittable.aabb.axis_interval((axis + 2) % 3));
+                rec.u = (rec.point[(axis + 1) % 3] - u_axis.min) / u_axis.size();
+                rec.v = (rec.point[(axis + 2) % 3] - v_axis.min) / v_axis.size();
+                rec.tangent[(axis + 1) % 3] = 1.0;
+                rec.material = *mat;
+            },
+
+
+            (HittableKind::Quad { q, u, v, w, normal, mat, .. }, _) => {
+                rec.set_face_normal(ray, *normal);
+                (rec.u, rec.v) = quad_coordinates(rec.point, *q, *u, *v, *w);
+                rec.tangent = *u;
+                rec.material = *mat;
+            },
+
+
+            (HittableKind::Planar { q, u, v, w, normal, shape, mat, .. }, _) => {
+                rec.set_face_normal(ray, *normal);
+                let (alpha, beta) = quad_coordinates(rec.point, *q, *u, *v, *w);
+                (rec.u, rec.v) = shape.uv(alpha, beta);
+                rec.tangent = *u;
+                rec.material = *mat;
+            },
+
+
+            (HittableKind::Triangle { mesh, index, mat }, HitDetail::Triangle(hit)) => {
+                // the face comes from the real surface and the
+                // smooth normal is flipped to match it
+                let geometric = mesh.geometric_normal(*index);
+                let shading = mesh.shading_normal(*index, hit.b1, hit.b2);
+                rec.front_face = ray.direction.dot(geometric) < 0.0;
+                let outward = if shading.dot(geometric) < 0.0 { -shading } else { shading };
+                rec.normal = if rec.front_face { outward } else { -outward };
+
+                (rec.u, rec.v) = mesh.uv(*index, hit.b1, hit.b2);
+                rec.second_uv = mesh.second_uv(*index, hit.b1, hit.b2);
+                (rec.tangent, rec.bitangent) = mesh.tangent_frame(*index, hit.b1, hit.b2);
+                rec.material = *mat;
+
+                // only on the side the vertex normals are on, from
+                // the other one the smooth surface is behind the
+                // triangles and rays can leave right off of them
+                if rec.front_face && outward.dot(shading) > 0.0 {
+                    rec.terminator_offset = mesh.terminator_point(*index, hit.b1, hit.b2) - rec.point;
+                }
+            },
+
+
+            (HittableKind::CurveSegment { curve, index, mat }, HitDetail::Curve(hit)) => {
+                // round across the strand
+                rec.set_face_normal(ray, (rec.point - hit.axis).unit());
+                rec.u = curve.u(*index, hit.s);
+                rec.v = 0.5;
+                rec.material = *mat;
+            },
+
+
+            (HittableKind::Sdf { distance, mat }, _) => {
+                let outward_normal = sdf::normal(*distance, rec.point);
+                rec.set_face_normal(ray, outward_normal);
+                (rec.u, rec.v) = get_sphere_uv(outward_normal);
+                rec.material = *mat;
+            },
+
+
+            (HittableKind::Voxels(_), HitDetail::Voxel(hit)) => {
+                rec.set_face_normal(ray, hit.outward_normal);
+                (rec.u, rec.v) = (hit.u, hit.v);
+                rec.material = hit.material;
+            },
+
+
+            (HittableKind::Medium(medium), _) => {
+                // there's no surface, so the normal is arbitrary
+                rec.front_face = true;
+                rec.normal = Vec3::new(1.0, 0.0, 0.0);
+                (rec.u, rec.v) = (0.0, 0.0);
+                rec.material = medium.material_at(rec.point);
+            },
+
+
+            // the candidate always pairs a primitive with its own detail
+            _ => unreachable!("only primitives can be the closest hit"),
+        }
+    }
 }
 
 
@@ -51,187 +530,1651 @@ impl<'a> Hittable<'a> {
         let aabb = AABB::from_points(centre - rvec, centre + rvec);
         Hittable {
             aabb,
-            kind: HittableKind::Sphere { centre, radius, mat },
+            kind: HittableKind::Sphere { centre, radius, mat, clip: None },
         }
     }
 
 
-    pub fn moving_sphere(centre1: Point, centre2: Point, radius: f32, mat: Material<'a>) -> Hittable<'a> {
-        let centre = Ray::new(centre1, centre2 - centre1, 0.0);
-
-        let rvec = Vec3::new(radius, radius, radius);
-        let box1 = AABB::from_points(centre.at(0.0) - rvec, centre.at(0.0) + rvec);
-        let box2 = AABB::from_points(centre.at(1.0) - rvec, centre.at(1.0) + rvec);
-        let aabb = AABB::from_aabbs(&box1, &box2);
-
+    ///
+    /// A sphere with only the part inside of `clip` being solid.
+    /// Useful for domes, bowls and hemispherical lights
+    ///
+    pub fn partial_sphere(centre: Point, radius: f32, clip: SphereClip, mat: Material<'a>) -> Hittable<'a> {
+        let y_min = clip.y_min.max(-radius);
+        let y_max = clip.y_max.min(radius);
+        let aabb = AABB::from_points(centre + Vec3::new(-radius, y_min, -radius),
+                                     centre + Vec3::new(radius, y_max, radius));
         Hittable {
             aabb,
-            kind: HittableKind::MovingSphere { centre, radius, mat },
+            kind: HittableKind::Sphere { centre, radius, mat, clip: Some(clip) },
         }
     }
 
 
-    pub fn list(list: &'a [Hittable<'a>]) -> Hittable<'a> {
-        let mut aabb = AABB::new(Interval::EMPTY, Interval::EMPTY, Interval::EMPTY);
+    pub fn moving_sphere(arena: &'a Arena, centre1: Point, centre2: Point, radius: f32, mat: Material<'a>) -> Hittable<'a> {
+        Hittable::sphere(Point::ZERO, radius, mat)
+            .moving(arena, Matrix4::translation(centre1), Matrix4::translation(centre2))
+    }
 
-        for l in list {
-            aabb = AABB::from_aabbs(&aabb, l.bounding_box());
-        }
 
+    ///
+    /// A torus lying in the XZ plane around `centre`.
+    /// `major_radius` is the distance from the centre to the middle of
+    /// the tube and `minor_radius` is the radius of the tube itself
+    ///
+    pub fn torus(centre: Point, major_radius: f32, minor_radius: f32, mat: Material<'a>) -> Hittable<'a> {
+        let extent = Vec3::new(major_radius + minor_radius, minor_radius, major_radius + minor_radius);
+        let aabb = AABB::from_points(centre - extent, centre + extent);
         Hittable {
             aabb,
-            kind: HittableKind::List(list),
+            kind: HittableKind::Torus { centre, major_radius, minor_radius, mat },
         }
     }
 
-    pub fn bvh(arena: &'a Arena, list: &'a [Hittable<'a>]) -> Hittable<'a> {
-        fn box_comp(a: &Hittable, b: &Hittable, axis: usize) -> bool {
-            let a_axis_interval = a.bounding_box().axis_interval(axis);
-            let b_axis_interval = b.bounding_box().axis_interval(axis);
 
-            a_axis_interval.min < b_axis_interval.min
+    ///
+    /// An infinite plane going through `point`.
+    /// The side `normal` points towards is considered the outside
+    ///
+    pub fn plane(point: Point, normal: Vec3, mat: Material<'a>) -> Hittable<'a> {
+        Hittable {
+            aabb: AABB::new(Interval::UNIVERSE, Interval::UNIVERSE, Interval::UNIVERSE),
+            kind: HittableKind::Plane { point, normal: normal.unit(), mat },
         }
+    }
 
-        let mut aabb = AABB::new(Interval::EMPTY, Interval::EMPTY, Interval::EMPTY);
-        for l in list {
-            aabb = AABB::from_aabbs(&aabb, l.bounding_box());
+
+    ///
+    /// An axis aligned box with opposite corners `a` and `b`
+    /// The box is its own bounding box so it only stores the material
+    ///
+    pub fn axis_aligned_box(a: Point, b: Point, mat: Material<'a>) -> Hittable<'a> {
+        Hittable {
+            aabb: AABB::from_points(a, b),
+            kind: HittableKind::Box { mat },
         }
+    }
 
-        let axis = aabb.longest_axis();
 
-        let left;
-        let right;
-        if list.len() == 1 {
-            left = list[0].clone();
-            right = left.clone();
-        } else if list.len() == 2 {
-            left = list[0].clone();
-            right = list[1].clone();
-        } else {
-            let mut list = sti::vec::Vec::from_slice_in(arena, list);
-            list.sort_by(|a, b| if box_comp(a, b, axis) { Ordering::Less } else { Ordering::Greater });
+    ///
+    /// A shape given by a signed distance function which is ray marched.
+    /// The distance function is only evaluated inside of `bounds`
+    ///
+    pub fn sdf(bounds: AABB, distance: DistanceFn<'a>, mat: Material<'a>) -> Hittable<'a> {
+        Hittable {
+            aabb: bounds,
+            kind: HittableKind::Sdf { distance, mat },
+        }
+    }
+
 
-            let middle = list.len() / 2;
-            let list = list.leak().split_at(middle);
+    ///
+    /// A parallelogram with one corner at `q` and
+    /// the two edges `u` and `v` going out of it
+    ///
+    pub fn quad(q: Point, u: Vec3, v: Vec3, mat: Material<'a>) -> Hittable<'a> {
+        let diagonal1 = AABB::from_points(q, q + u + v);
+        let diagonal2 = AABB::from_points(q + u, q + v);
+        let aabb = AABB::from_aabbs(&diagonal1, &diagonal2).pad_to_minimums(0.0001);
 
-            left = Hittable::bvh(arena, list.0);
-            right = Hittable::bvh(arena, list.1);
-        }
+        let n = u.cross(v);
+        let normal = n.unit();
+        let d = normal.dot(q);
+        let w = n / n.dot(n);
 
         Hittable {
             aabb,
-            kind: HittableKind::BVH { left: arena.alloc_new(left), right: arena.alloc_new(right) }
+            kind: HittableKind::Quad { q, u, v, w, normal, d, mat },
         }
     }
 
 
-    pub fn hit(&self, ray: Ray, t: Interval, rec: &mut HitRecord<'a>) -> bool {
-        match &self.kind {
-            HittableKind::List(vec) => {
-                let mut temp_rec = HitRecord::default();
-                let mut hit_anything = false;
-                let mut closest_so_far = t.max;
+    ///
+    /// Returns the corner and edges of a quad
+    ///
+    pub fn as_quad(&self) -> Option<(Point, Vec3, Vec3)> {
+        match self.kind {
+            HittableKind::Quad { q, u, v, .. } => Some((q, u, v)),
+            _ => None,
+        }
+    }
 
-                for obj in vec.iter() {
-                    if !obj.hit(ray, Interval::new(t.min, closest_so_far), &mut temp_rec) { continue }
 
-                    hit_anything = true;
-                    closest_so_far = temp_rec.t;
-                    *rec = temp_rec.clone();
-                }
+    ///
+    /// A triangle with one corner at `q` and
+    /// the two edges `u` and `v` going out of it
+    ///
+    pub fn planar_triangle(q: Point, u: Vec3, v: Vec3, mat: Material<'a>) -> Hittable<'a> {
+        let aabb = AABB::from_aabbs(&AABB::from_points(q, q + u), &AABB::from_points(q, q + v));
+        Hittable::planar(aabb, q, u, v, PlanarShape::Triangle, mat)
+    }
 
-                hit_anything
-            },
- 
-            HittableKind::Sphere { centre, radius, mat } => {
-                let oc = ray.origin - *centre;
-                let a = ray.direction.length_squared();
-                let half_b = oc.dot(ray.direction);
-                let c = oc.length_squared() - radius*radius;
 
-                let discriminant = half_b*half_b - a*c;
-                if discriminant < 0.0 { return false }
-                
-                let discriminant_sqrt = discriminant.sqrt();
+    ///
+    /// An ellipse around `centre` with the two semi-axes `u` and `v`
+    ///
+    pub fn ellipse(centre: Point, u: Vec3, v: Vec3, mat: Material<'a>) -> Hittable<'a> {
+        let aabb = AABB::from_aabbs(&AABB::from_points(centre - u - v, centre + u + v),
+                                    &AABB::from_points(centre - u + v, centre + u - v));
+        Hittable::planar(aabb, centre, u, v, PlanarShape::Ellipse, mat)
+    }
 
-                // Find the nearest root that lies in the acceptable range
-                let mut root = (-half_b - discriminant_sqrt) / a;
-                if !t.surrounds(root) {
-                    root = (-half_b + discriminant_sqrt) / a;
-                    if !t.surrounds(root) { return false }
-                }
 
-                rec.t = root;
-                rec.point = ray.at(rec.t);
-                let outward_normal = (rec.point - *centre) / *radius;
-                rec.set_face_normal(ray, outward_normal);
-                (rec.u, rec.v) = get_sphere_uv(outward_normal);
-                rec.material = *mat;
+    ///
+    /// A convex polygon through `corners` in order,
+    /// the corners must all lie in one plane
+    ///
+    pub fn polygon(arena: &'a Arena, corners: &[Point], mat: Material<'a>) -> Hittable<'a> {
+        assert!(corners.len() >= 3, "a polygon needs at least three corners");
+        let size = corners.iter().map(|&p| (p - corners[0]).length()).fold(0.0, f32::max);
 
-                true
-            },
+        let q = corners[0];
+        let u = corners[1] - q;
+        // the corner furthest from the first edge keeps the basis well conditioned
+        let v = corners[2..].iter()
+            .map(|&p| p - q)
+            .max_by(|a, b| u.cross(*a).length_squared().total_cmp(&u.cross(*b).length_squared()))
+            .unwrap();
 
+        let n = u.cross(v);
+        let w = n / n.dot(n);
 
-            HittableKind::MovingSphere { centre, radius, mat } => {
-                let current_centre = centre.at(ray.time);
-                let oc = ray.origin - current_centre;
-                let a = ray.direction.length_squared();
-                let half_b = oc.dot(ray.direction);
-                let c = oc.length_squared() - radius*radius;
+        let mut aabb = AABB::new(Interval::EMPTY, Interval::EMPTY, Interval::EMPTY);
+        let mut planar = sti::vec::Vec::with_cap_in(arena, corners.len());
+        for &p in corners {
+            let off_plane = n.unit().dot(p - q).abs();
+            assert!(off_plane <= POLYGON_TOLERANCE * size, "the corner {p} is {off_plane} off the plane of the polygon");
 
-                let discriminant = half_b*half_b - a*c;
-                if discriminant < 0.0 { return false }
-                
-                let discriminant_sqrt = discriminant.sqrt();
+            aabb = AABB::from_aabbs(&aabb, &AABB::from_points(p, p));
+            planar.push(quad_coordinates(p, q, u, v, w));
+        }
 
-                // Find the nearest root that lies in the acceptable range
-                let mut root = (-half_b - discriminant_sqrt) / a;
-                if !t.surrounds(root) {
-                    root = (-half_b + discriminant_sqrt) / a;
-                    if !t.surrounds(root) { return false }
-                }
+        assert!(is_convex(&planar), "the polygon through {} corners from {q} isn't convex", corners.len());
 
-                rec.t = root;
-                rec.point = ray.at(rec.t);
-                let outward_normal = (rec.point - current_centre) / *radius;
-                rec.set_face_normal(ray, outward_normal);
-                (rec.u, rec.v) = get_sphere_uv(outward_normal);
-                rec.material = *mat;
+        Hittable::planar(aabb, q, u, v, PlanarShape::Polygon(planar.leak()), mat)
+    }
 
-                true
 
-            },
+    fn planar(aabb: AABB, q: Point, u: Vec3, v: Vec3, shape: PlanarShape<'a>, mat: Material<'a>) -> Hittable<'a> {
+        let n = u.cross(v);
+        let normal = n.unit();
+        let d = normal.dot(q);
+        let w = n / n.dot(n);
 
+        Hittable {
+            aabb: aabb.pad_to_minimums(0.0001),
+            kind: HittableKind::Planar { q, u, v, w, normal, d, shape, mat },
+        }
+    }
 
-            HittableKind::BVH { left, right } => {
-                if !self.bounding_box().hit(ray, t) {
-                    return false;
-                }
 
-                let hit_left = left.hit(ray, t, rec);
-                let hit_right = right.hit(ray, Interval::new(t.min, if hit_left { rec.t } else { t.max }), rec);
+    pub fn triangle(mesh: &'a Mesh<'a>, index: u32, mat: Material<'a>) -> Hittable<'a> {
+        let [a, b, c] = mesh.vertices(index);
+        let aabb = AABB::from_aabbs(&AABB::from_points(a, b), &AABB::from_points(a, c)).pad_to_minimums(0.0001);
 
-                hit_left || hit_right
-            }
+        Hittable {
+            aabb,
+            kind: HittableKind::Triangle { mesh, index, mat },
         }
     }
 
 
-    /*
-    pub fn hit(&self, ray: Ray, t: Interval, rec: &mut HitRecord<'a>) -> bool {
-        false
-    }*/
-
+    ///
+    /// Every triangle of `mesh` in a BVH
+    ///
+    pub fn mesh(arena: &'a Arena, mesh: &'a Mesh<'a>, mat: Material<'a>) -> Hittable<'a> {
+        let mut triangles = sti::vec::Vec::with_cap_in(arena, mesh.triangle_count());
+        for index in 0..mesh.triangle_count() {
+            triangles.push(Hittable::triangle(mesh, index as u32, mat));
+        }
 
-    pub fn bounding_box(&self) -> &AABB {
-        &self.aabb
+        Hittable::bvh(arena, triangles.leak())
     }
-}
 
 
-fn get_sphere_uv(p: Point) -> (f32, f32) {
-    // p: a given point on the sphere of radius one, centered at the origin.
-    // u: returned value [0,1] of angle around the Y axis from X=-1.
+    pub fn curve_segment(curve: &'a Curve<'a>, index: u32, mat: Material<'a>) -> Hittable<'a> {
+        Hittable {
+            aabb: curve.segment_bounds(index),
+            kind: HittableKind::CurveSegment { curve, index, mat },
+        }
+    }
+
+
+    ///
+    /// Every segment of `curve` in a BVH, the segments' boxes
+    /// follow the strand tightly even where it bends
+    ///
+    pub fn curve(arena: &'a Arena, curve: &'a Curve<'a>, mat: Material<'a>) -> Hittable<'a> {
+        let mut segments = sti::vec::Vec::with_cap_in(arena, curve.segment_count());
+        for index in 0..curve.segment_count() {
+            segments.push(Hittable::curve_segment(curve, index as u32, mat));
+        }
+
+        Hittable::bvh(arena, segments.leak())
+    }
+
+
+    pub fn voxels(octree: &'a VoxelOctree<'a>) -> Hittable<'a> {
+        Hittable {
+            aabb: octree.bounding_box(),
+            kind: HittableKind::Voxels(octree),
+        }
+    }
+
+
+    ///
+    /// Places `self` in the world with `matrix`.
+    /// Transforming a transform composes the
+    /// matrices instead of nesting them
+    ///
+    pub fn transformed(self, arena: &'a Arena, matrix: Matrix4) -> Hittable<'a> {
+        // `instance` does the composing
+        Hittable::instance(arena, arena.alloc_new(self), matrix)
+    }
+
+
+    ///
+    /// Places `base` in the world with `matrix` without copying it,
+    /// so e.g. a whole BVH can be placed many times while its
+    /// geometry is only stored once
+    ///
+    pub fn instance(arena: &'a Arena, base: &'a Hittable<'a>, matrix: Matrix4) -> Hittable<'a> {
+        let transform = match &base.kind {
+            HittableKind::Transform(inner) => Transform::new(inner.object(), matrix * inner.matrix())
+                                                .with_material(inner.material()),
+            _ => Transform::new(base, matrix),
+        };
+
+        Hittable {
+            aabb: transform.bounding_box(),
+            kind: HittableKind::Transform(arena.alloc_new(transform)),
+        }
+    }
+
+
+    ///
+    /// Renders everything in `self` with `mat` instead of its own
+    /// materials, so instances of the same geometry can each look
+    /// different. The outermost override wins
+    ///
+    pub fn with_material(self, arena: &'a Arena, mat: Material<'a>) -> Hittable<'a> {
+        let transform = match &self.kind {
+            HittableKind::Transform(inner) => Transform::new(inner.object(), inner.matrix()),
+            _ => Transform::new(arena.alloc_new(self), Matrix4::identity()),
+        };
+
+        let transform = transform.with_material(Some(mat));
+        Hittable {
+            aabb: transform.bounding_box(),
+            kind: HittableKind::Transform(arena.alloc_new(transform)),
+        }
+    }
+
+
+    ///
+    /// Moves `self` from `start` at time 0 to `end` at time 1,
+    /// anything can be motion blurred this way
+    ///
+    pub fn moving(self, arena: &'a Arena, start: Matrix4, end: Matrix4) -> Hittable<'a> {
+        let motion = MotionTransform::new(arena.alloc_new(self), start, end);
+
+        Hittable {
+            aabb: motion.bounding_box(),
+            kind: HittableKind::MotionTransform(arena.alloc_new(motion)),
+        }
+    }
+
+
+    ///
+    /// Makes `self` ignore any hit on a back face, e.g. for
+    /// open boxes or panels that should only be seen from one side
+    ///
+    pub fn one_sided(self, arena: &'a Arena) -> Hittable<'a> {
+        Hittable {
+            aabb: self.aabb.clone(),
+            kind: HittableKind::OneSided(arena.alloc_new(self)),
+        }
+    }
+
+
+    ///
+    /// Picks one of `levels` for each ray by how far it starts from
+    /// the centre of the first level, see `Lod`
+    ///
+    pub fn lod(arena: &'a Arena, levels: &'a [Hittable<'a>], distances: &'a [f32]) -> Hittable<'a> {
+        let aabb = Hittable::list(levels).aabb;
+        let centre = 0.5 * (levels[0].aabb.min_corner() + levels[0].aabb.max_corner());
+
+        Hittable {
+            aabb,
+            kind: HittableKind::Lod(arena.alloc_new(Lod::new(centre, levels, distances))),
+        }
+    }
+
+
+    ///
+    /// Rotates `self` by `angle` degrees around `axis` through the origin
+    ///
+    pub fn rotate_axis_by(self, arena: &'a Arena, axis: Vec3, angle: f32) -> Hittable<'a> {
+        self.transformed(arena, Matrix4::rotation(axis, angle.to_radians()))
+    }
+
+
+    pub fn rotate_x_by(self, arena: &'a Arena, angle: f32) -> Hittable<'a> {
+        self.rotate_axis_by(arena, Vec3::new(1.0, 0.0, 0.0), angle)
+    }
+
+
+    pub fn rotate_y_by(self, arena: &'a Arena, angle: f32) -> Hittable<'a> {
+        self.rotate_axis_by(arena, Vec3::new(0.0, 1.0, 0.0), angle)
+    }
+
+
+    pub fn rotate_z_by(self, arena: &'a Arena, angle: f32) -> Hittable<'a> {
+        self.rotate_axis_by(arena, Vec3::new(0.0, 0.0, 1.0), angle)
+    }
+
+
+    ///
+    /// Scales `self` along each axis away from the origin,
+    /// e.g. to turn a sphere into an ellipsoid
+    ///
+    pub fn scale_by(self, arena: &'a Arena, factor: Vec3) -> Hittable<'a> {
+        self.transformed(arena, Matrix4::scaling(factor))
+    }
+
+
+    pub fn translate_by(self, arena: &'a Arena, offset: Vec3) -> Hittable<'a> {
+        self.transformed(arena, Matrix4::translation(offset))
+    }
+
+
+    pub fn medium(medium: &'a Medium<'a>) -> Hittable<'a> {
+        Hittable {
+            aabb: medium.boundary().bounding_box().clone(),
+            kind: HittableKind::Medium(medium),
+        }
+    }
+
+
+    pub fn list(list: &'a [Hittable<'a>]) -> Hittable<'a> {
+        let mut aabb = AABB::new(Interval::EMPTY, Interval::EMPTY, Interval::EMPTY);
+
+        for l in list {
+            aabb = AABB::from_aabbs(&aabb, l.bounding_box());
+        }
+
+        Hittable {
+            aabb,
+            kind: HittableKind::List(list),
+        }
+    }
+
+    pub fn kind(&self) -> &HittableKind<'a> {
+        &self.kind
+    }
+
+
+    pub fn bvh(arena: &'a Arena, list: &'a [Hittable<'a>]) -> Hittable<'a> {
+        Hittable::bvh_with(arena, list, BvhOptions::DEFAULT)
+    }
+
+
+    pub fn bvh_with(arena: &'a Arena, list: &'a [Hittable<'a>], options: BvhOptions) -> Hittable<'a> {
+        let bvh = Hittable::bvh_with_depth(arena, list, options, MAX_BVH_DEPTH);
+        if options.quantized { bvh.quantize(arena) } else { bvh }
+    }
+
+
+    ///
+    /// Swaps every subtree of this BVH with a finite box for a
+    /// `QuantizedBvh`. The nodes above unbounded hittables like
+    /// planes stay as they are, their boxes can't be cut into steps
+    ///
+    pub fn quantize(&self, arena: &'a Arena) -> Hittable<'a> {
+        let HittableKind::BVH { left, right, axis, ordered } = &self.kind
+        else { return self.clone() };
+
+        let is_finite = (0..3).all(|axis| {
+            let interval = self.aabb.axis_interval(axis);
+            interval.min.is_finite() && interval.max.is_finite()
+        });
+
+        if is_finite {
+            let source = arena.alloc_new(self.clone());
+            return Hittable {
+                aabb: self.aabb.clone(),
+                kind: HittableKind::QuantizedBvh(arena.alloc_new(QuantizedBvh::new(arena, source))),
+            };
+        }
+
+        Hittable {
+            aabb: self.aabb.clone(),
+            kind: HittableKind::BVH {
+                left: arena.alloc_new(left.quantize(arena)),
+                right: arena.alloc_new(right.quantize(arena)),
+                axis: *axis,
+                ordered: *ordered,
+            },
+        }
+    }
+
+
+    fn bvh_with_depth(arena: &'a Arena, list: &'a [Hittable<'a>], options: BvhOptions, depth: usize) -> Hittable<'a> {
+        // the tree is planned first, which can be done in parallel
+        // since it only reorders the hittables. Every leaf of the
+        // plan is then a slice of them and the nodes are allocated
+        if options.split == BvhSplit::Spatial {
+            let refs = Vec::from_iter(list.iter().enumerate().map(|(index, h)| SbvhRef { index, aabb: h.aabb.clone() }));
+            let root_area = Hittable::list(list).aabb.surface_area();
+
+            let mut order = Vec::with_capacity(list.len());
+            let plan = plan_sbvh(&SbvhBuild { list, options, root_area }, refs, depth, &mut order);
+
+            let mut leaves = sti::vec::Vec::with_cap_in(arena, order.len());
+            for index in order { leaves.push(list[index].clone()) }
+            return Hittable::bvh_from_plan(arena, leaves.leak(), &plan, options);
+        }
+
+        let list = sti::vec::Vec::from_slice_in(arena, list).leak();
+        let plan = plan_bvh(list, options, depth);
+        Hittable::bvh_from_plan(arena, list, &plan, options)
+    }
+
+
+    fn bvh_from_plan(arena: &'a Arena, list: &'a [Hittable<'a>], plan: &BvhPlan, options: BvhOptions) -> Hittable<'a> {
+        match plan {
+            // a single hittable doesn't need a node unless a spatial
+            // split cut its box down, which only a list can carry
+            BvhPlan::Leaf { clip: None } if list.len() == 1 => list[0].clone(),
+
+            BvhPlan::Leaf { clip } => {
+                let leaf = Hittable::list(list);
+                Hittable { aabb: clip.clone().unwrap_or(leaf.aabb), kind: leaf.kind }
+            },
+
+            BvhPlan::QuadBatch => {
+                let batch = QuadBatch::new(list).expect("the plan only batches quads");
+                Hittable { aabb: Hittable::list(list).aabb, kind: HittableKind::QuadBatch(arena.alloc_new(batch)) }
+            },
+
+            BvhPlan::Node { axis, middle, left, right, clip } => {
+                let (left_list, right_list) = list.split_at(*middle);
+                let left = Hittable::bvh_from_plan(arena, left_list, left, options);
+                let right = Hittable::bvh_from_plan(arena, right_list, right, options);
+
+                Hittable {
+                    aabb: clip.clone().unwrap_or_else(|| AABB::from_aabbs(&left.aabb, &right.aabb)),
+                    kind: HittableKind::BVH {
+                        left: arena.alloc_new(left),
+                        right: arena.alloc_new(right),
+                        axis: *axis,
+                        ordered: options.traversal == BvhTraversal::NearestFirst,
+                    }
+                }
+            },
+        }
+    }
+
+
+    ///
+    /// Rebuilds a BVH around hittables which have moved, keeping
+    /// the tree as it is and only recomputing the boxes bottom-up.
+    /// `update` is called with every hittable in the leaves, left
+    /// to right, and returns where it is now. This is much cheaper
+    /// than building a new BVH but the tree gets worse the further
+    /// things move from where it was built. Every refit allocates
+    /// new nodes in `arena`
+    ///
+    pub fn refit(&self, arena: &'a Arena, update: &mut impl FnMut(&Hittable<'a>) -> Hittable<'a>) -> Hittable<'a> {
+        match &self.kind {
+            HittableKind::BVH { left, right, axis, ordered } => {
+                let left = left.refit(arena, update);
+                let right = right.refit(arena, update);
+
+                Hittable {
+                    aabb: AABB::from_aabbs(&left.aabb, &right.aabb),
+                    kind: HittableKind::BVH {
+                        left: arena.alloc_new(left),
+                        right: arena.alloc_new(right),
+                        axis: *axis,
+                        ordered: *ordered,
+                    },
+                }
+            },
+
+            HittableKind::QuantizedBvh(bvh) => bvh.source().refit(arena, update).quantize(arena),
+
+            HittableKind::List(list) => Hittable::list(refit_all(arena, list, update)),
+
+            HittableKind::QuadBatch(batch) => {
+                let quads = refit_all(arena, batch.quads(), update);
+                match QuadBatch::new(quads) {
+                    Some(batch) => Hittable { aabb: Hittable::list(quads).aabb, kind: HittableKind::QuadBatch(arena.alloc_new(batch)) },
+                    None => Hittable::list(quads),
+                }
+            },
+
+            _ => update(self),
+        }
+    }
+
+
+    ///
+    /// Swaps every level of detail for the level seen from `eye`,
+    /// so rays don't pick one each and the boxes around them
+    /// shrink to the level picked. Instances of a level of detail
+    /// measure from `eye` in their own space, so scaled up copies
+    /// switch further away like their size on screen would. Only
+    /// reaches through what `refit` does
+    ///
+    pub fn select_lods(&self, arena: &'a Arena, eye: Point) -> Hittable<'a> {
+        self.refit(arena, &mut |hittable| match &hittable.kind {
+            HittableKind::Lod(lod) => lod.select(eye).clone(),
+
+            HittableKind::Transform(transform) => {
+                let HittableKind::Lod(lod) = &transform.object().kind else { return hittable.clone() };
+
+                let local_eye = transform.to_object(Ray::new(eye, Vec3::ZERO, 0.0)).origin;
+                let transform = Transform::new(lod.select(local_eye), transform.matrix())
+                    .with_material(transform.material());
+
+                Hittable {
+                    aabb: transform.bounding_box(),
+                    kind: HittableKind::Transform(arena.alloc_new(transform)),
+                }
+            },
+
+            _ => hittable.clone(),
+        })
+    }
+
+
+    pub fn kd_tree(arena: &'a Arena, list: &'a [Hittable<'a>]) -> Hittable<'a> {
+        let aabb = Hittable::list(list).aabb;
+        let tree = KdTree::new(arena, list, &aabb);
+
+        Hittable {
+            aabb,
+            kind: HittableKind::KdTree(arena.alloc_new(tree)),
+        }
+    }
+
+
+    pub fn grid(arena: &'a Arena, list: &'a [Hittable<'a>]) -> Hittable<'a> {
+        let aabb = Hittable::list(list).aabb;
+
+        Hittable {
+            aabb,
+            kind: HittableKind::Grid(arena.alloc_new(UniformGrid::new(arena, list))),
+        }
+    }
+
+
+    pub fn accelerate(arena: &'a Arena, list: &'a [Hittable<'a>], accelerator: Accelerator) -> Hittable<'a> {
+        match accelerator {
+            Accelerator::BVH(options) => Hittable::bvh_with(arena, list, options),
+            Accelerator::KdTree => Hittable::kd_tree(arena, list),
+            Accelerator::Grid => Hittable::grid(arena, list),
+        }
+    }
+
+
+    pub fn hit(&self, ray: Ray, t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let Some(candidate) = self.closest_hit(ray, t) else { return false };
+        candidate.fill_record(ray, rec);
+        true
+    }
+
+
+    ///
+    /// Finds the closest primitive along the ray without
+    /// computing any of the shading data for it
+    ///
+    pub fn closest_hit<'h>(&'h self, ray: Ray, t: Interval) -> Option<HitCandidate<'h, 'a>> {
+        let root = match &self.kind {
+            HittableKind::List(vec) => {
+                let mut closest = None;
+                let mut closest_so_far = t.max;
+
+                for obj in vec.iter() {
+                    let Some(candidate) = obj.closest_hit(ray, Interval::new(t.min, closest_so_far)) else { continue };
+
+                    closest_so_far = candidate.t;
+                    closest = Some(candidate);
+                }
+
+                return closest;
+            },
+ 
+            HittableKind::Sphere { centre, radius, clip, .. } => {
+                let (entry, exit) = sphere_roots(ray, *centre, *radius)?;
+
+                // Find the nearest root that lies in the acceptable range
+                // and isn't clipped away
+                let accept = |root: f32| t.surrounds(root)
+                    && clip.is_none_or(|clip| clip.contains(ray.at(root) - *centre, *radius));
+                if accept(entry) { entry }
+                else if accept(exit) { exit }
+                else { return None }
+            },
+
+
+            HittableKind::Torus { centre, major_radius, minor_radius, .. } => {
+                let (roots, count) = torus_roots(ray, *centre, *major_radius, *minor_radius);
+                *roots[..count].iter().find(|&&root| t.surrounds(root))?
+            },
+
+
+            HittableKind::Plane { point, normal, .. } => {
+                let denom = normal.dot(ray.direction);
+                if denom.abs() < 1e-8 { return None }
+
+                let root = normal.dot(*point - ray.origin) / denom;
+                if !t.surrounds(root) { return None }
+                root
+            },
+
+
+            HittableKind::Box { .. } => {
+                let slab = self.aabb.slab(ray);
+                if slab.exit <= slab.entry { return None }
+
+                if t.surrounds(slab.entry) { slab.entry }
+                else if t.surrounds(slab.exit) { slab.exit }
+                else { return None }
+            },
+
+
+            HittableKind::Quad { q, u, v, w, normal, d, .. } => {
+                let root = plane_root(ray, t, *normal, *d)?;
+                let (alpha, beta) = quad_coordinates(ray.at(root), *q, *u, *v, *w);
+                if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) { return None }
+                root
+            },
+
+
+            HittableKind::Planar { q, u, v, w, normal, d, shape, .. } => {
+                let root = plane_root(ray, t, *normal, *d)?;
+                let (alpha, beta) = quad_coordinates(ray.at(root), *q, *u, *v, *w);
+                if !shape.contains(alpha, beta) { return None }
+                root
+            },
+
+
+            HittableKind::QuadBatch(batch) => return batch.closest_hit(ray, t),
+
+
+            HittableKind::Triangle { mesh, index, .. } => {
+                let hit = mesh.hit_triangle(*index, ray, t)?;
+                return Some(HitCandidate { t: hit.t, hittable: self, detail: HitDetail::Triangle(hit), placement: None });
+            },
+
+
+            HittableKind::CurveSegment { curve, index, .. } => {
+                let hit = curve.hit_segment(*index, ray, t)?;
+                return Some(HitCandidate { t: hit.t, hittable: self, detail: HitDetail::Curve(hit), placement: None });
+            },
+
+
+            HittableKind::Sdf { distance, .. } => {
+                let span = self.aabb.hit_interval(ray, t)?;
+                let root = sdf::march(*distance, ray, span)?;
+                if !t.surrounds(root) { return None }
+                root
+            },
+
+
+            HittableKind::Voxels(octree) => {
+                let hit = octree.hit(ray, t)?;
+                return Some(HitCandidate { t: hit.t, hittable: self, detail: HitDetail::Voxel(hit), placement: None });
+            },
+
+
+            HittableKind::Medium(medium) => {
+                if !self.aabb.hit(ray, t) { return None }
+                medium.sample_collision(ray, t)?
+            },
+
+
+            HittableKind::Transform(transform) => {
+                let local = transform.to_object(ray);
+                return Some(transform.object().closest_hit(local, t)?.placed(transform, local));
+            },
+
+
+            HittableKind::MotionTransform(motion) => {
+                let transform = motion.at(ray.time);
+                let local = transform.to_object(ray);
+                return Some(transform.object().closest_hit(local, t)?.placed(&transform, local));
+            },
+
+
+            HittableKind::BVH { .. } => return self.closest_hit_bvh(ray, t),
+
+
+            HittableKind::OneSided(inner) => {
+                // back faces are skipped one by one
+                let mut t = t;
+                loop {
+                    let candidate = inner.closest_hit(ray, t)?;
+                    if candidate.front_face(ray) { return Some(candidate) }
+
+                    t.min = candidate.t;
+                }
+            },
+
+
+            HittableKind::Lod(lod) => return lod.select(ray.origin).closest_hit(ray, t),
+
+
+            HittableKind::QuantizedBvh(bvh) => return bvh.closest_hit(&self.aabb, ray, t),
+
+            HittableKind::KdTree(tree) => return tree.closest_hit(&self.aabb, ray, t),
+
+
+            HittableKind::Grid(grid) => return grid.closest_hit(ray, t),
+        };
+
+        Some(HitCandidate { t: root, hittable: self, detail: HitDetail::Surface, placement: None })
+    }
+
+
+    ///
+    /// Traverses a BVH with an explicit stack instead of recursing.
+    /// The stack lives in a fixed size array and only spills
+    /// onto the heap if the tree is deeper than `BVH_STACK_SIZE`
+    ///
+    fn closest_hit_bvh<'h>(&'h self, ray: Ray, t: Interval) -> Option<HitCandidate<'h, 'a>> {
+        let inverse = InverseRay::new(ray);
+        let entry = self.bounding_box().entry_inverse(&inverse, t)?;
+
+        // nodes are pushed with where the ray enters their box
+        // so they can be skipped once a closer hit turns up
+        let mut stack = Stack::<_, BVH_STACK_SIZE>::new((self, entry));
+        stack.push((self, entry));
+
+        let mut closest = None;
+        let mut closest_so_far = t.max;
+
+        while let Some((node, entry)) = stack.pop() {
+            if entry >= closest_so_far { continue }
+
+            let HittableKind::BVH { left, right, ordered, .. } = &node.kind
+            else {
+                if let Some(candidate) = node.closest_hit(ray, Interval::new(t.min, closest_so_far)) {
+                    closest_so_far = candidate.t;
+                    closest = Some(candidate);
+                }
+                continue;
+            };
+
+            let t = Interval::new(t.min, closest_so_far);
+            let left_entry = left.bounding_box().entry_inverse(&inverse, t);
+            let right_entry = right.bounding_box().entry_inverse(&inverse, t);
+
+            // push the side to visit first last
+            let right_first = match (left_entry, right_entry) {
+                (Some(l), Some(r)) => *ordered && r < l,
+                _ => false,
+            };
+
+            let order = if right_first { [(*left, left_entry), (*right, right_entry)] }
+                        else { [(*right, right_entry), (*left, left_entry)] };
+            for (child, entry) in order {
+                if let Some(entry) = entry { stack.push((child, entry)) }
+            }
+        }
+
+        closest
+    }
+
+
+    ///
+    /// Whether `ray` hits anything in `t` at all. Returns on the
+    /// first hit found instead of looking for the closest one.
+    /// While there are `cutouts` left each hit is tested against
+    /// the alpha of its material, and going through a cutout uses
+    /// one up. They're used up in whatever order the hits are
+    /// found in, with none left every surface counts
+    ///
+    pub fn any_hit(&self, ray: Ray, t: Interval, units: Units, cutouts: &mut usize) -> bool {
+        match &self.kind {
+            HittableKind::List(list) => list.iter().any(|h| h.any_hit(ray, t, units, cutouts)),
+
+            HittableKind::QuadBatch(batch) => batch.quads().iter().any(|h| h.any_hit(ray, t, units, cutouts)),
+
+            HittableKind::Transform(transform) => transform.object().any_hit(transform.to_object(ray), t, units, cutouts),
+
+            HittableKind::MotionTransform(motion) => {
+                let transform = motion.at(ray.time);
+                transform.object().any_hit(transform.to_object(ray), t, units, cutouts)
+            },
+
+            HittableKind::Lod(lod) => lod.select(ray.origin).any_hit(ray, t, units, cutouts),
+
+            HittableKind::BVH { .. } => self.any_hit_bvh(ray, t, units, cutouts),
+
+            _ => {
+                let mut t = t;
+                while let Some(candidate) = self.closest_hit(ray, t) {
+                    if *cutouts == 0 { return true }
+
+                    let mut rec = HitRecord::default();
+                    candidate.fill_record(ray, &mut rec);
+                    if rec.material.is_opaque_at(&rec) { return true }
+
+                    // through a cutout, the same primitive can be hit again past it
+                    *cutouts -= 1;
+                    t.min = rec.t + units.hit_epsilon();
+                }
+
+                false
+            },
+        }
+    }
+
+
+    fn any_hit_bvh(&self, ray: Ray, t: Interval, units: Units, cutouts: &mut usize) -> bool {
+        let mut stack = Stack::<_, BVH_STACK_SIZE>::new(self);
+        stack.push(self);
+        let inverse = InverseRay::new(ray);
+
+        while let Some(node) = stack.pop() {
+
+            let HittableKind::BVH { left, right, .. } = &node.kind
+            else {
+                if node.any_hit(ray, t, units, cutouts) { return true }
+                continue;
+            };
+
+            if !node.bounding_box().hit_inverse(&inverse, t) { continue }
+
+            for child in [*right, *left] { stack.push(child) }
+        }
+
+        false
+    }
+
+
+    ///
+    /// Finds the closest hit like `closest_hit` while counting the
+    /// bounding boxes and primitives tested on the way. It's slower
+    /// and only meant to show where the accelerators struggle.
+    /// KD trees, grids and quad batches count as one primitive
+    /// and quantized BVHs show the BVH they were made from
+    ///
+    pub fn traversal_cost(&self, ray: Ray, t: Interval, cost: &mut TraversalCost) -> Option<f32> {
+        match &self.kind {
+            HittableKind::List(list) => {
+                let mut closest = None;
+                let mut t = t;
+                for h in list.iter() {
+                    let Some(hit) = h.traversal_cost(ray, t, cost) else { continue };
+                    t.max = hit;
+                    closest = Some(hit);
+                }
+
+                closest
+            },
+
+            HittableKind::Transform(transform) => transform.object().traversal_cost(transform.to_object(ray), t, cost),
+
+            HittableKind::MotionTransform(motion) => {
+                let transform = motion.at(ray.time);
+                transform.object().traversal_cost(transform.to_object(ray), t, cost)
+            },
+
+            HittableKind::Lod(lod) => lod.select(ray.origin).traversal_cost(ray, t, cost),
+
+            HittableKind::QuantizedBvh(bvh) => bvh.source().traversal_cost(ray, t, cost),
+
+            HittableKind::BVH { .. } => {
+                let mut stack = Stack::<_, BVH_STACK_SIZE>::new(self);
+                stack.push(self);
+                let mut closest = None;
+                let mut t = t;
+
+                while let Some(node) = stack.pop() {
+                    let HittableKind::BVH { left, right, .. } = &node.kind
+                    else {
+                        if let Some(hit) = node.traversal_cost(ray, t, cost) {
+                            t.max = hit;
+                            closest = Some(hit);
+                        }
+                        continue;
+                    };
+
+                    cost.box_tests += 1;
+                    if node.bounding_box().hit(ray, t) { stack.push(*right); stack.push(*left) }
+                }
+
+                closest
+            },
+
+            _ => {
+                cost.primitive_tests += 1;
+                self.closest_hit(ray, t).map(|candidate| candidate.t)
+            },
+        }
+    }
+
+
+    ///
+    /// Pushes every `[entry, exit]` span in which `ray` is inside of
+    /// this hittable onto `spans`, clipped to `t`.
+    /// Spans are pushed in no particular order and may overlap
+    ///
+    pub fn hit_all(&self, ray: Ray, t: Interval, spans: &mut Vec<Interval>) {
+        let mut push = |entry: f32, exit: f32| {
+            if exit < t.min || entry > t.max { return }
+            spans.push(Interval::new(entry.max(t.min), exit.min(t.max)));
+        };
+
+        match &self.kind {
+            HittableKind::List(vec) => {
+                for obj in vec.iter() {
+                    obj.hit_all(ray, t, spans);
+                }
+            },
+
+
+            HittableKind::Sphere { centre, radius, clip, .. } => {
+                let Some((mut entry, mut exit)) = sphere_roots(ray, *centre, *radius) else { return };
+
+                let Some(clip) = clip else { return push(entry, exit) };
+
+                let dir = ray.direction.y;
+                let origin = ray.origin.y - centre.y;
+                if dir == 0.0 {
+                    if origin < clip.y_min || origin > clip.y_max { return }
+                } else {
+                    let t0 = (clip.y_min - origin) / dir;
+                    let t1 = (clip.y_max - origin) / dir;
+                    entry = entry.max(t0.min(t1));
+                    exit = exit.min(t0.max(t1));
+                }
+
+                if exit <= entry { return }
+                if clip.phi_max >= 2.0 * PI { return push(entry, exit) }
+
+                // the phi wedge isn't convex once it's wider than a half
+                // turn, so the span is cut where it crosses the lines of
+                // the wedge's two sides and only the parts in it are kept
+                let origin = ray.origin - *centre;
+                let mut cuts = [entry, exit, 0.0, 0.0];
+                let mut count = 2;
+                for phi in [0.0, clip.phi_max] {
+                    let (side_x, side_z) = (-phi.cos(), phi.sin());
+                    let across = ray.direction.x * side_z - ray.direction.z * side_x;
+                    if across == 0.0 { continue }
+
+                    let at = (origin.z * side_x - origin.x * side_z) / across;
+                    if at > entry && at < exit { cuts[count] = at; count += 1 }
+                }
+
+                cuts[..count].sort_by(f32::total_cmp);
+                for span in cuts[..count].windows(2) {
+                    if clip.contains(ray.at(0.5 * (span[0] + span[1])) - *centre, *radius) {
+                        push(span[0], span[1]);
+                    }
+                }
+            },
+
+
+            HittableKind::Torus { centre, major_radius, minor_radius, .. } => {
+                let (roots, count) = torus_roots(ray, *centre, *major_radius, *minor_radius);
+                for pair in roots[..count].chunks_exact(2) {
+                    push(pair[0], pair[1]);
+                }
+            },
+
+
+            HittableKind::Plane { point, normal, .. } => {
+                // the inside of a plane is the half-space behind it
+                let denom = normal.dot(ray.direction);
+                let distance = normal.dot(ray.origin - *point);
+                if denom == 0.0 {
+                    if distance < 0.0 { push(-f32::INFINITY, f32::INFINITY) }
+                    return;
+                }
+
+                let root = -distance / denom;
+                if denom < 0.0 { push(root, f32::INFINITY) }
+                else { push(-f32::INFINITY, root) }
+            },
+
+
+            HittableKind::Box { .. } => {
+                let slab = self.aabb.slab(ray);
+                if slab.exit > slab.entry { push(slab.entry, slab.exit) }
+            },
+
+
+            // flat shapes, triangles and curves are only surfaces without an inside
+            | HittableKind::Quad { .. }
+            | HittableKind::QuadBatch(_)
+            | HittableKind::Planar { .. }
+            | HittableKind::Triangle { .. }
+            | HittableKind::CurveSegment { .. } => (),
+
+
+            HittableKind::Sdf { distance, .. } => {
+                let Some(span) = self.aabb.hit_interval(ray, t) else { return };
+                sdf::spans(*distance, ray, span, &mut push);
+            },
+
+
+            HittableKind::Voxels(octree) => octree.spans(ray, t, &mut push),
+
+
+            HittableKind::Medium(medium) => medium.boundary().hit_all(ray, t, spans),
+
+
+            HittableKind::Transform(transform) => transform.object().hit_all(transform.to_object(ray), t, spans),
+
+
+            HittableKind::MotionTransform(motion) => {
+                let transform = motion.at(ray.time);
+                transform.object().hit_all(transform.to_object(ray), t, spans);
+            },
+
+
+            HittableKind::OneSided(inner) => inner.hit_all(ray, t, spans),
+
+
+            HittableKind::Lod(lod) => lod.select(ray.origin).hit_all(ray, t, spans),
+
+
+            HittableKind::BVH { .. } => {
+                // spatial splits put a hittable in every leaf it
+                // crosses, each of them pushes the same spans
+                let start = spans.len();
+                self.hit_all_nodes(ray, t, spans);
+                spans[start..].sort_unstable_by(|a, b| a.min.total_cmp(&b.min).then(a.max.total_cmp(&b.max)));
+
+                let mut kept = start;
+                for i in start..spans.len() {
+                    let span = spans[i];
+                    if kept > start && spans[kept - 1].min == span.min && spans[kept - 1].max == span.max { continue }
+                    spans[kept] = span;
+                    kept += 1;
+                }
+                spans.truncate(kept);
+            },
+
+
+            HittableKind::QuantizedBvh(bvh) => bvh.source().hit_all(ray, t, spans),
+
+
+            HittableKind::KdTree(tree) => {
+                if !self.bounding_box().hit(ray, t) {
+                    return;
+                }
+
+                // the leaves can share hittables, so go through the list
+                // instead to avoid reporting the same span twice
+                for obj in tree.hittables() {
+                    obj.hit_all(ray, t, spans);
+                }
+            },
+
+
+            HittableKind::Grid(grid) => {
+                if !self.bounding_box().hit(ray, t) {
+                    return;
+                }
+
+                // same as the kd-tree, cells can share hittables
+                for obj in grid.hittables() {
+                    obj.hit_all(ray, t, spans);
+                }
+            },
+        }
+    }
+
+
+    ///
+    /// `hit_all` through the nodes of a BVH, down to its leaves
+    ///
+    fn hit_all_nodes(&self, ray: Ray, t: Interval, spans: &mut Vec<Interval>) {
+        let HittableKind::BVH { left, right, .. } = &self.kind
+        else { return self.hit_all(ray, t, spans) };
+
+        if !self.bounding_box().hit(ray, t) {
+            return;
+        }
+
+        left.hit_all_nodes(ray, t, spans);
+        right.hit_all_nodes(ray, t, spans);
+    }
+
+
+    /*
+    pub fn hit(&self, ray: Ray, t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        false
+    }*/
+
+
+    pub fn bounding_box(&self) -> &AABB {
+        &self.aabb
+    }
+
+
+    ///
+    /// The box around everything which has finite bounds,
+    /// infinite planes and the like are left out.
+    /// Returns `None` if nothing is bounded
+    ///
+    pub fn bounds(&self) -> Option<AABB> {
+        let is_finite = |aabb: &AABB| (0..3).all(|axis| {
+            let interval = aabb.axis_interval(axis);
+            interval.min.is_finite() && interval.max.is_finite()
+        });
+
+        if is_finite(&self.aabb) { return Some(self.aabb.clone()) }
+
+        let combine = |children: &mut dyn Iterator<Item = &Hittable<'a>>| {
+            children.filter_map(|child| child.bounds())
+                .reduce(|a, b| AABB::from_aabbs(&a, &b))
+        };
+
+        match &self.kind {
+            HittableKind::List(list) => combine(&mut list.iter()),
+            HittableKind::BVH { left, right, .. } => combine(&mut [*left, *right].into_iter()),
+            HittableKind::QuantizedBvh(bvh) => bvh.source().bounds(),
+            HittableKind::KdTree(tree) => combine(&mut tree.hittables().iter()),
+            HittableKind::Grid(grid) => combine(&mut grid.hittables().iter()),
+            HittableKind::OneSided(inner) => inner.bounds(),
+            HittableKind::Lod(lod) => combine(&mut lod.levels().iter()),
+            _ => None,
+        }
+    }
+}
+
+
+///
+/// What `Hittable::traversal_cost` counted
+///
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TraversalCost {
+    pub box_tests: usize,
+    pub primitive_tests: usize,
+}
+
+
+///
+/// The shape of a BVH before any of its nodes are allocated.
+/// A node's hittables are split into the first `middle` on
+/// the left and the rest on the right. Spatial splits `clip`
+/// boxes to less than what's inside of them
+///
+enum BvhPlan {
+    Leaf { clip: Option<AABB> },
+    /// small groups of quads are intersected all at once
+    QuadBatch,
+    Node { axis: u8, middle: usize, left: Box<BvhPlan>, right: Box<BvhPlan>, clip: Option<AABB> },
+}
+
+
+///
+/// Sorts `list` into the order the BVH's leaves will be in. Large
+/// lists are sorted, bounded and split on every thread
+///
+fn plan_bvh(list: &mut [Hittable], options: BvhOptions, depth: usize) -> BvhPlan {
+    if list.len() <= 1 || depth == 0 { return BvhPlan::Leaf { clip: None } }
+
+    if list.len() <= quad_batch::LANES && list.iter().all(|h| h.as_quad().is_some()) {
+        return BvhPlan::QuadBatch;
+    }
+
+    if list.len() <= options.leaf_size { return BvhPlan::Leaf { clip: None } }
+
+    let parallel = list.len() >= PARALLEL_BVH_BUILD;
+    let aabb = if parallel {
+        list.par_iter().map(|h| h.aabb.clone()).reduce(empty_aabb, |a, b| AABB::from_aabbs(&a, &b))
+    } else {
+        list.iter().fold(empty_aabb(), |a, h| AABB::from_aabbs(&a, &h.aabb))
+    };
+
+    let mut axis = aabb.longest_axis();
+
+    let middle = if list.len() == 2 { 1 } else {
+        let sah = if options.split == BvhSplit::Sah { sah_split(list, |h| &h.aabb) } else { None };
+
+        let median = |a: &Hittable, b: &Hittable| {
+            a.aabb.axis_interval(axis).min.partial_cmp(&b.aabb.axis_interval(axis).min).unwrap_or(Ordering::Greater)
+        };
+
+        match sah {
+            Some((sah_axis, left_count, _)) => {
+                axis = sah_axis;
+                let by_centroid = |a: &Hittable, b: &Hittable| centroid(&a.aabb, axis).total_cmp(&centroid(&b.aabb, axis));
+                if parallel { list.par_sort_by(by_centroid) } else { list.sort_by(by_centroid) }
+                left_count
+            },
+
+            None => {
+                if parallel { list.par_sort_by(median) } else { list.sort_by(median) }
+                list.len() / 2
+            },
+        }
+    };
+
+    let (left, right) = list.split_at_mut(middle);
+    let (left, right) = if parallel {
+        rayon::join(|| plan_bvh(left, options, depth - 1), || plan_bvh(right, options, depth - 1))
+    } else {
+        (plan_bvh(left, options, depth - 1), plan_bvh(right, options, depth - 1))
+    };
+
+    BvhPlan::Node { axis: axis as u8, middle, left: Box::new(left), right: Box::new(right), clip: None }
+}
+
+
+fn refit_all<'a>(arena: &'a Arena, list: &[Hittable<'a>], update: &mut impl FnMut(&Hittable<'a>) -> Hittable<'a>) -> &'a [Hittable<'a>] {
+    let mut refitted = sti::vec::Vec::with_cap_in(arena, list.len());
+    for h in list {
+        refitted.push(h.refit(arena, update));
+    }
+
+    refitted.leak()
+}
+
+
+fn centroid(aabb: &AABB, axis: usize) -> f32 {
+    let interval = aabb.axis_interval(axis);
+    0.5 * (interval.min + interval.max)
+}
+
+
+///
+/// Finds the axis and the number of boxes (sorted by centroid)
+/// to the left of the cheapest binned SAH split, and its cost.
+/// Returns `None` if any of the boxes is unbounded or if no
+/// split separates them
+///
+fn sah_split<T>(list: &[T], aabb: impl Fn(&T) -> &AABB) -> Option<(usize, usize, f32)> {
+    let mut best = None;
+    let mut best_cost = f32::INFINITY;
+
+    for axis in 0..3 {
+        let mut min = f32::INFINITY;
+        let mut max = -f32::INFINITY;
+        for h in list {
+            let c = centroid(aabb(h), axis);
+            if !c.is_finite() { return None }
+            min = min.min(c);
+            max = max.max(c);
+        }
+
+        if max <= min { continue }
+
+        let bin_of = |h: &T| (((centroid(aabb(h), axis) - min) / (max - min) * SAH_BINS as f32) as usize).min(SAH_BINS - 1);
+
+        let mut bins = [(); SAH_BINS].map(|_| (empty_aabb(), 0));
+        for h in list {
+            let bin = &mut bins[bin_of(h)];
+            bin.0 = AABB::from_aabbs(&bin.0, aabb(h));
+            bin.1 += 1;
+        }
+
+        // the cost of everything right of each split, swept from the right
+        let mut right_cost = [0.0; SAH_BINS];
+        let mut right = (empty_aabb(), 0);
+        for split in (1..SAH_BINS).rev() {
+            right.0 = AABB::from_aabbs(&right.0, &bins[split].0);
+            right.1 += bins[split].1;
+            right_cost[split] = if right.1 == 0 { 0.0 } else { right.0.surface_area() * right.1 as f32 };
+        }
+
+        let mut left = (empty_aabb(), 0);
+        for split in 1..SAH_BINS {
+            left.0 = AABB::from_aabbs(&left.0, &bins[split - 1].0);
+            left.1 += bins[split - 1].1;
+            if left.1 == 0 || left.1 == list.len() { continue }
+
+            let cost = left.0.surface_area() * left.1 as f32 + right_cost[split];
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, left.1, cost));
+            }
+        }
+    }
+
+    best
+}
+
+
+///
+/// A hittable in a spatial split BVH, which may be in several
+/// leaves. Its box is cut down to the part inside of its node
+///
+struct SbvhRef {
+    index: usize,
+    aabb: AABB,
+}
+
+
+///
+/// What stays the same while planning every node of an SBVH
+///
+struct SbvhBuild<'l, 'a> {
+    list: &'l [Hittable<'a>],
+    options: BvhOptions,
+    root_area: f32,
+}
+
+
+///
+/// Plans a BVH which can also split space instead of the
+/// hittables, putting whatever crosses the split in both
+/// children. The leaves' hittables are pushed onto `order`,
+/// so `middle` counts the references on the left
+///
+fn plan_sbvh(build: &SbvhBuild, mut refs: Vec<SbvhRef>, depth: usize, order: &mut Vec<usize>) -> BvhPlan {
+    let SbvhBuild { list, options, root_area } = *build;
+    let bounds = refs.iter().fold(empty_aabb(), |a, r| AABB::from_aabbs(&a, &r.aabb));
+    let leaf = |refs: Vec<SbvhRef>, order: &mut Vec<usize>, bounds: AABB| {
+        order.extend(refs.iter().map(|r| r.index));
+        BvhPlan::Leaf { clip: Some(bounds) }
+    };
+
+    if refs.len() <= 1 || depth == 0 { return leaf(refs, order, bounds) }
+
+    if refs.len() <= quad_batch::LANES && refs.iter().all(|r| list[r.index].as_quad().is_some()) {
+        order.extend(refs.iter().map(|r| r.index));
+        return BvhPlan::QuadBatch;
+    }
+
+    if refs.len() <= options.leaf_size { return leaf(refs, order, bounds) }
+
+    // planes and other unbounded hittables can't be split spatially
+    let Some((axis, left_count, object_cost)) = sah_split(&refs, |r| &r.aabb)
+    else {
+        let axis = bounds.longest_axis();
+        refs.sort_by(|a, b| a.aabb.axis_interval(axis).min.partial_cmp(&b.aabb.axis_interval(axis).min).unwrap_or(Ordering::Greater));
+        let right = refs.split_off(refs.len() / 2);
+        return sbvh_node(build, refs, right, axis, bounds, depth, order);
+    };
+
+    refs.sort_by(|a, b| centroid(&a.aabb, axis).total_cmp(&centroid(&b.aabb, axis)));
+    let object_right = refs.split_off(left_count);
+    let object_left = refs;
+
+    // spatial splits only pay off where the object split's
+    // children overlap a lot compared to the whole tree
+    let left_bounds = object_left.iter().fold(empty_aabb(), |a, r| AABB::from_aabbs(&a, &r.aabb));
+    let right_bounds = object_right.iter().fold(empty_aabb(), |a, r| AABB::from_aabbs(&a, &r.aabb));
+    if overlap_area(&left_bounds, &right_bounds) <= SBVH_OVERLAP * root_area {
+        return sbvh_node(build, object_left, object_right, axis, bounds, depth, order);
+    }
+
+    let mut refs = object_left;
+    refs.extend(object_right);
+
+    match spatial_split(&refs, &bounds) {
+        Some((spatial_axis, at, cost)) if cost < object_cost => {
+            let mut left = Vec::new();
+            let mut right = Vec::new();
+            for r in refs {
+                let interval = r.aabb.axis_interval(spatial_axis);
+                if interval.max <= at { left.push(r) }
+                else if interval.min >= at { right.push(r) }
+                else {
+                    let (below, above) = r.aabb.split(spatial_axis, at);
+                    left.push(SbvhRef { index: r.index, aabb: below });
+                    right.push(SbvhRef { index: r.index, aabb: above });
+                }
+            }
+
+            sbvh_node(build, left, right, spatial_axis, bounds, depth, order)
+        },
+
+        _ => {
+            let right = refs.split_off(left_count);
+            sbvh_node(build, refs, right, axis, bounds, depth, order)
+        },
+    }
+}
+
+
+fn sbvh_node(build: &SbvhBuild, left: Vec<SbvhRef>, right: Vec<SbvhRef>, axis: usize, bounds: AABB,
+             depth: usize, order: &mut Vec<usize>) -> BvhPlan {
+    let start = order.len();
+    let left = plan_sbvh(build, left, depth - 1, order);
+    let middle = order.len() - start;
+    let right = plan_sbvh(build, right, depth - 1, order);
+
+    BvhPlan::Node { axis: axis as u8, middle, left: Box::new(left), right: Box::new(right), clip: Some(bounds) }
+}
+
+
+///
+/// Finds the cheapest plane to cut `bounds` at, with the SAH
+/// cost of the references on either side of it. References
+/// crossing the plane count on both sides but only the part
+/// of their box on each side adds to that side's area
+///
+fn spatial_split(refs: &[SbvhRef], bounds: &AABB) -> Option<(usize, f32, f32)> {
+    let mut best = None;
+    let mut best_cost = f32::INFINITY;
+
+    for axis in 0..3 {
+        let extent = bounds.axis_interval(axis);
+        if extent.size().is_nan() || extent.size() <= 0.0 { continue }
+
+        let width = extent.size() / SAH_BINS as f32;
+        let plane = |bin: usize| extent.min + width * bin as f32;
+        let bin_of = |x: f32| (((x - extent.min) / width) as usize).min(SAH_BINS - 1);
+
+        // every reference enters the bin of its start and exits
+        // the bin of its end, it's clipped into each one between
+        let mut bins = [(); SAH_BINS].map(|_| (empty_aabb(), 0, 0));
+        for r in refs {
+            let interval = r.aabb.axis_interval(axis);
+            let (first, last) = (bin_of(interval.min), bin_of(interval.max));
+            bins[first].1 += 1;
+            bins[last].2 += 1;
+
+            for (bin, (bin_box, _, _)) in bins.iter_mut().enumerate().take(last + 1).skip(first) {
+                let lo = plane(bin).max(interval.min);
+                let hi = if bin == SAH_BINS - 1 { interval.max } else { plane(bin + 1).min(interval.max) };
+                let (_, above) = r.aabb.split(axis, lo);
+                let (clipped, _) = above.split(axis, hi);
+                *bin_box = AABB::from_aabbs(bin_box, &clipped);
+            }
+        }
+
+        let mut right_cost = [0.0; SAH_BINS];
+        let mut right_count = [0; SAH_BINS];
+        let mut right = (empty_aabb(), 0);
+        for split in (1..SAH_BINS).rev() {
+            right.0 = AABB::from_aabbs(&right.0, &bins[split].0);
+            right.1 += bins[split].2;
+            right_count[split] = right.1;
+            right_cost[split] = if right.1 == 0 { 0.0 } else { right.0.surface_area() * right.1 as f32 };
+        }
+
+        let mut left = (empty_aabb(), 0);
+        for split in 1..SAH_BINS {
+            left.0 = AABB::from_aabbs(&left.0, &bins[split - 1].0);
+            left.1 += bins[split - 1].1;
+
+            // a split which keeps everything on one side never ends
+            if left.1 == 0 || right_count[split] == 0 || left.1 == refs.len() && right_count[split] == refs.len() { continue }
+
+            let cost = left.0.surface_area() * left.1 as f32 + right_cost[split];
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, plane(split), cost));
+            }
+        }
+    }
+
+    best
+}
+
+
+fn overlap_area(a: &AABB, b: &AABB) -> f32 {
+    let mut size = [0.0; 3];
+    for (axis, size) in size.iter_mut().enumerate() {
+        let (a, b) = (a.axis_interval(axis), b.axis_interval(axis));
+        *size = a.max.min(b.max) - a.min.max(b.min);
+        if size.is_nan() || *size <= 0.0 { return 0.0 }
+    }
+
+    2.0 * (size[0]*size[1] + size[1]*size[2] + size[2]*size[0])
+}
+
+
+fn empty_aabb() -> AABB {
+    AABB::new(Interval::EMPTY, Interval::EMPTY, Interval::EMPTY)
+}
+
+
+///
+/// The face of `aabb` that `point` lies on, as the axis it's
+/// across and its outward normal. It's the face it's closest to
+///
+fn box_face(aabb: &AABB, point: Point) -> (usize, Vec3) {
+    let mut axis = 0;
+    let mut sign = -1.0;
+    let mut closest = f32::INFINITY;
+    for a in 0..3 {
+        let interval = aabb.axis_interval(a);
+        let to_min = (point[a] - interval.min).abs();
+        let to_max = (point[a] - interval.max).abs();
+        if to_min < closest { closest = to_min; axis = a; sign = -1.0; }
+        if to_max < closest { closest = to_max; axis = a; sign = 1.0; }
+    }
+
+    let mut outward_normal = Vec3::ZERO;
+    outward_normal[axis] = sign;
+    (axis, outward_normal)
+}
+
+
+///
+/// Returns both roots of the ray-sphere intersection
+/// as `(entry, exit)` or `None` if the ray misses
+///
+fn sphere_roots(ray: Ray, centre: Point, radius: f32) -> Option<(f32, f32)> {
+    let oc = ray.origin - centre;
+    let a = ray.direction.length_squared();
+    let half_b = oc.dot(ray.direction);
+    let c = oc.length_squared() - radius*radius;
+
+    let discriminant = half_b*half_b - a*c;
+    if discriminant < 0.0 { return None }
+
+    let discriminant_sqrt = discriminant.sqrt();
+    Some(((-half_b - discriminant_sqrt) / a, (-half_b + discriminant_sqrt) / a))
+}
+
+
+///
+/// Returns the roots of the ray-torus intersection in ascending order
+/// The torus lies in the XZ plane around `centre`
+///
+fn torus_roots(ray: Ray, centre: Point, major_radius: f32, minor_radius: f32) -> ([f32; 4], usize) {
+    // solve along a unit direction for better conditioning
+    let length = ray.direction.length();
+    let d = ray.direction / length;
+    let o = ray.origin - centre;
+
+    let (ox, oy, oz) = (o.x as f64, o.y as f64, o.z as f64);
+    let (dx, dy, dz) = (d.x as f64, d.y as f64, d.z as f64);
+    let rr = (major_radius * major_radius) as f64;
+
+    // (|p|^2 + R^2 - r^2)^2 = 4R^2 (p.x^2 + p.z^2)
+    let n = ox*dx + oy*dy + oz*dz;
+    let k = ox*ox + oy*oy + oz*oz + rr - (minor_radius * minor_radius) as f64;
+    let a = dx*dx + dz*dz;
+    let b = 2.0 * (ox*dx + oz*dz);
+    let c = ox*ox + oz*oz;
+
+    let (roots, count) = solve_quartic(
+        1.0,
+        4.0 * n,
+        4.0*n*n + 2.0*k - 4.0*rr*a,
+        4.0*n*k - 4.0*rr*b,
+        k*k - 4.0*rr*c,
+    );
+
+    let mut out = [0.0; 4];
+    for i in 0..count {
+        out[i] = roots[i] as f32 / length;
+    }
+
+    (out, count)
+}
+
+
+///
+/// Where `ray` goes through the plane of a quad or flat
+/// shape with the `normal` and `d`, if it does within `t`
+///
+fn plane_root(ray: Ray, t: Interval, normal: Vec3, d: f32) -> Option<f32> {
+    let denom = normal.dot(ray.direction);
+    if denom.abs() < 1e-8 { return None }
+
+    let root = (d - normal.dot(ray.origin)) / denom;
+    t.surrounds(root).then_some(root)
+}
+
+
+///
+/// Whether the polygon through `corners` in order turns the same
+/// way at every corner and only goes around once, straight
+/// corners are fine
+///
+fn is_convex(corners: &[(f32, f32)]) -> bool {
+    let edge = |i: usize| {
+        let (a0, b0) = corners[i % corners.len()];
+        let (a1, b1) = corners[(i + 1) % corners.len()];
+        (a1 - a0, b1 - b0)
+    };
+
+    let mut sign = 0.0;
+    let mut turned = 0.0;
+    for i in 0..corners.len() {
+        let (a0, b0) = edge(i);
+        let (a1, b1) = edge(i + 1);
+        let cross = a0 * b1 - b0 * a1;
+        if cross * sign < 0.0 { return false }
+        if cross != 0.0 { sign = cross }
+        turned += cross.atan2(a0 * a1 + b0 * b1);
+    }
+
+    (turned.abs() - 2.0 * PI).abs() < 1e-3
+}
+
+
+///
+/// Returns the coordinates of `p` along the edges
+/// of a quad, `p` is inside of it if both are in `[0, 1]`
+///
+fn quad_coordinates(p: Point, q: Point, u: Vec3, v: Vec3, w: Vec3) -> (f32, f32) {
+    let planar_hit = p - q;
+    (w.dot(planar_hit.cross(v)), w.dot(u.cross(planar_hit)))
+}
+
+
+///
+/// Returns two unit vectors which are perpendicular
+/// to `normal` and to each other
+///
+fn plane_basis(normal: Vec3) -> (Vec3, Vec3) {
+    let helper = if normal.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let tangent = helper.cross(normal).unit();
+    (tangent, normal.cross(tangent))
+}
+
+
+pub(crate) fn get_sphere_uv(p: Point) -> (f32, f32) {
+    // p: a given point on the sphere of radius one, centered at the origin.
+    // u: returned value [0,1] of angle around the Y axis from X=-1.
     // v: returned value [0,1] of angle from Y=-1 to Y=+1.
     //     <1 0 0> yields <0.50 0.50>       <-1  0  0> yields <0.00 0.50>
     //     <0 1 0> yields <0.50 1.00>       < 0 -1  0> yields <0.50 0.00>
@@ -241,3 +2184,254 @@ fn get_sphere_uv(p: Point) -> (f32, f32) {
     let phi = (-p.z).atan2(p.x) + PI;
     (phi/(2.0*PI), theta/PI)
 }
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{math::vec3::Colour, rng::next_f32, rt::texture::Texture};
+
+    use super::*;
+
+    ///
+    /// Small spheres with a few long boxes through them,
+    /// the boxes make the object split's children overlap
+    ///
+    fn overlapping_scene(arena: &Arena) -> &[Hittable<'_>] {
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+        let mut list = sti::vec::Vec::new_in(arena);
+        for _ in 0..200 {
+            list.push(Hittable::sphere(Vec3::random_range(Interval::new(-5.0, 5.0)), 0.1 + 0.2 * next_f32(), mat));
+        }
+
+        for axis in 0..3 {
+            let mut min = Vec3::random_range(Interval::new(-4.0, 4.0));
+            let mut max = min + Vec3::new(0.3, 0.3, 0.3);
+            (min[axis], max[axis]) = (-5.0, 5.0);
+            list.push(Hittable::axis_aligned_box(min, max, mat));
+        }
+
+        list.leak()
+    }
+
+
+    fn random_ray() -> Ray {
+        let origin = 10.0 * Vec3::random_unit();
+        Ray::new(origin, Vec3::random_range(Interval::new(-5.0, 5.0)) - origin, 0.0)
+    }
+
+
+    #[test]
+    fn polygons_are_hit_inside_of_their_corners() {
+        let arena = Arena::new();
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+        let t = Interval::new(0.001, f32::INFINITY);
+
+        // a hexagon at z = 1 with its corners on the unit circle
+        let corners = Vec::from_iter((0..6).map(|i| {
+            let angle = i as f32 * PI / 3.0;
+            Point::new(angle.cos(), angle.sin(), 1.0)
+        }));
+        let hexagon = Hittable::polygon(&arena, &corners, mat);
+
+        let towards = |x: f32, y: f32| Ray::new(Point::new(x, y, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let mut rec = HitRecord::default();
+        assert!(hexagon.hit(towards(0.0, 0.0), t, &mut rec));
+        assert!((rec.t - 1.0).abs() < 1e-5 && !rec.front_face);
+
+        // the edges are sqrt(3)/2 from the centre, the corners 1
+        assert!(hexagon.hit(towards(0.0, 0.85), t, &mut rec));
+        assert!(!hexagon.hit(towards(0.0, 0.88), t, &mut rec));
+        assert!(hexagon.hit(towards(0.98, 0.0), t, &mut rec));
+        assert!(!hexagon.hit(towards(0.9, 0.5), t, &mut rec));
+    }
+
+
+    #[test]
+    #[should_panic(expected = "isn't convex")]
+    fn polygons_have_to_be_convex() {
+        let arena = Arena::new();
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+        // a five pointed star, every corner turns the same way
+        let star = Vec::from_iter((0..5).map(|i| {
+            let angle = i as f32 * 4.0 * PI / 5.0;
+            Point::new(angle.cos(), angle.sin(), 0.0)
+        }));
+        Hittable::polygon(&arena, &star, mat);
+    }
+
+
+    #[test]
+    #[should_panic(expected = "off the plane")]
+    fn polygons_have_to_be_flat() {
+        let arena = Arena::new();
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+        let corners = [Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.1), Point::new(0.0, 1.0, 0.0)];
+        Hittable::polygon(&arena, &corners, mat);
+    }
+
+
+    #[test]
+    fn clipped_spheres_are_only_inside_of_their_wedge() {
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+        let t = Interval::new(0.0, 3.0);
+
+        for phi_max in [0.6 * PI, 1.5 * PI] {
+            let clip = SphereClip { y_min: -3.0, y_max: 4.0, phi_max };
+            let sphere = Hittable::partial_sphere(Point::ZERO, 5.0, clip, mat);
+
+            for _ in 0..200 {
+                let ray = random_ray();
+                let mut spans = Vec::new();
+                sphere.hit_all(ray, t, &mut spans);
+
+                for step in 0..400 {
+                    let at = t.max * step as f32 / 400.0;
+                    let local = ray.at(at);
+                    let inside = local.length() < 5.0 && clip.contains(local, 5.0);
+                    let in_span = spans.iter().any(|s| s.contains(at));
+
+                    // right by the edges it could go either way
+                    let near_edge = spans.iter().any(|s| (s.min - at).abs() < 1e-3 || (s.max - at).abs() < 1e-3);
+                    assert!(inside == in_span || near_edge, "{phi_max} {at} {inside}");
+                }
+            }
+        }
+    }
+
+
+    #[test]
+    fn spatial_splits_find_the_same_hits() {
+        let arena = Arena::new();
+        let list = overlapping_scene(&arena);
+        let plain = Hittable::bvh(&arena, list);
+        let spatial = Hittable::bvh_with(&arena, list, BvhOptions { split: BvhSplit::Spatial, ..BvhOptions::DEFAULT });
+        let t = Interval::new(0.001, f32::INFINITY);
+
+        for _ in 0..2000 {
+            let ray = random_ray();
+
+            let mut expected = HitRecord::default();
+            let mut found = HitRecord::default();
+            assert_eq!(plain.hit(ray, t, &mut expected), spatial.hit(ray, t, &mut found));
+            assert_eq!(expected.t, found.t);
+
+            // the same spans, each only once
+            let (mut expected, mut found) = (Vec::new(), Vec::new());
+            plain.hit_all(ray, t, &mut expected);
+            spatial.hit_all(ray, t, &mut found);
+            expected.sort_unstable_by(|a, b| a.min.total_cmp(&b.min));
+            assert_eq!(expected.len(), found.len());
+            assert!(expected.iter().zip(&found).all(|(a, b)| a.min == b.min && a.max == b.max));
+        }
+    }
+
+
+    #[test]
+    fn other_accelerators_find_the_same_hits() {
+        let arena = Arena::new();
+        let list = overlapping_scene(&arena);
+        let plain = Hittable::bvh(&arena, list);
+        let others = [
+            Hittable::bvh_with(&arena, list, BvhOptions { quantized: true, ..BvhOptions::DEFAULT }),
+            Hittable::kd_tree(&arena, list),
+            Hittable::grid(&arena, list),
+        ];
+        let t = Interval::new(0.001, f32::INFINITY);
+
+        for _ in 0..2000 {
+            let ray = random_ray();
+
+            let mut expected = HitRecord::default();
+            let hit = plain.hit(ray, t, &mut expected);
+            let mut expected_spans = Vec::new();
+            plain.hit_all(ray, t, &mut expected_spans);
+            expected_spans.sort_unstable_by(|a, b| a.min.total_cmp(&b.min));
+
+            for other in &others {
+                let mut found = HitRecord::default();
+                assert_eq!(hit, other.hit(ray, t, &mut found));
+                assert_eq!(expected.t, found.t);
+                assert_eq!(hit, other.any_hit(ray, t, Units::Metres, &mut 0));
+
+                let mut found = Vec::new();
+                other.hit_all(ray, t, &mut found);
+                found.sort_unstable_by(|a, b| a.min.total_cmp(&b.min));
+                assert_eq!(expected_spans.len(), found.len());
+                assert!(expected_spans.iter().zip(&found).all(|(a, b)| a.min == b.min && a.max == b.max));
+            }
+        }
+    }
+
+
+    #[test]
+    fn quad_batches_find_the_same_hits() {
+        let arena = Arena::new();
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+        let t = Interval::new(0.001, f32::INFINITY);
+
+        for _ in 0..200 {
+            let quads = arena.alloc_new(std::array::from_fn::<_, { quad_batch::LANES }, _>(|_| {
+                let side = || Vec3::random_range(Interval::new(-4.0, 4.0));
+                Hittable::quad(Vec3::random_range(Interval::new(-3.0, 3.0)), side(), side(), mat)
+            }));
+            let list = Hittable::list(quads);
+            let batch = Hittable { aabb: list.aabb.clone(), kind: HittableKind::QuadBatch(arena.alloc_new(QuadBatch::new(quads).unwrap())) };
+
+            for _ in 0..20 {
+                let ray = random_ray();
+
+                let mut expected = HitRecord::default();
+                let mut found = HitRecord::default();
+                assert_eq!(list.hit(ray, t, &mut expected), batch.hit(ray, t, &mut found));
+                assert!((expected.t - found.t).abs() < 1e-4 || expected.t == found.t, "{} {}", expected.t, found.t);
+                assert_eq!(expected.normal, found.normal);
+            }
+        }
+    }
+
+
+    #[test]
+    fn one_sided_hittables_skip_back_faces() {
+        let arena = Arena::new();
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+        // facing +z, with a wall behind it
+        let panel = Hittable::quad(Point::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), mat).one_sided(&arena);
+        let wall = Hittable::quad(Point::new(-5.0, -5.0, -2.0), Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 10.0, 0.0), mat);
+        let t = Interval::new(0.001, f32::INFINITY);
+        let from_front = Ray::new(Point::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let from_behind = Ray::new(Point::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        let world = Hittable::list(arena.alloc_new([panel.clone(), wall.clone()]));
+        let mut rec = HitRecord::default();
+        assert!(world.hit(from_front, t, &mut rec) && rec.front_face);
+        assert!((rec.t - 1.0).abs() < 1e-4, "{}", rec.t);
+        assert!(!world.hit(from_behind, t, &mut rec));
+
+        // turned around the panel faces the wall, which is
+        // then seen right through the back of the panel
+        let world = Hittable::list(arena.alloc_new([panel.rotate_y_by(&arena, 180.0), wall]));
+        assert!(world.hit(from_behind, t, &mut rec) && rec.front_face);
+        assert!((rec.t - 1.0).abs() < 1e-4, "{}", rec.t);
+        assert!(world.hit(from_front, t, &mut rec));
+        assert!((rec.t - 3.0).abs() < 1e-4, "{}", rec.t);
+    }
+
+
+    #[test]
+    fn median_splits_find_the_same_hits() {
+        let arena = Arena::new();
+        let list = overlapping_scene(&arena);
+        let sah = Hittable::bvh(&arena, list);
+        let median = Hittable::bvh_with(&arena, list, BvhOptions { split: BvhSplit::Median, ..BvhOptions::DEFAULT });
+        let t = Interval::new(0.001, f32::INFINITY);
+
+        for _ in 0..2000 {
+            let ray = random_ray();
+
+            let mut expected = HitRecord::default();
+            let mut found = HitRecord::default();
+            assert_eq!(sah.hit(ray, t, &mut expected), median.hit(ray, t, &mut found));
+            assert_eq!(expected.t, found.t);
+        }
+    }
+}
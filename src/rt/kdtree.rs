@@ -0,0 +1,219 @@
+use sti::arena::Arena;
+
+use crate::math::{aabb::AABB, interval::Interval, ray::Ray};
+
+use super::hittable::{HitCandidate, Hittable};
+
+const LEAF_SIZE : usize = 4;
+
+
+#[derive(Clone, Copy)]
+enum KdNode {
+    // `first` is the node below the split and `first + 1` the one above it
+    Interior { axis: u8, split: f32, first: u32 },
+    Leaf { start: u32, len: u32 },
+}
+
+
+///
+/// How a `KdTree` came out, see `KdTree::stats`
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KdTreeStats {
+    pub interior_nodes: usize,
+    pub leaves: usize,
+    pub empty_leaves: usize,
+    pub depth: usize,
+    /// how many times hittables are referenced from leaves,
+    /// more than there are hittables when some straddle splits
+    pub references: usize,
+}
+
+
+///
+/// A kd-tree over a list of hittables.
+/// Hittables which straddle a split plane are referenced
+/// from both sides instead of being split themselves
+///
+pub struct KdTree<'a> {
+    nodes: &'a [KdNode],
+    root: KdNode,
+    indices: &'a [u32],
+    hittables: &'a [Hittable<'a>],
+}
+
+
+impl<'a> KdTree<'a> {
+    pub fn new(arena: &'a Arena, hittables: &'a [Hittable<'a>], bounds: &AABB) -> Self {
+        let mut nodes = sti::vec::Vec::new_in(arena);
+        let mut indices = sti::vec::Vec::new_in(arena);
+
+        let items = Vec::from_iter(0..hittables.len() as u32);
+        let max_depth = 8 + (1.3 * (hittables.len().max(1) as f32).log2()) as usize;
+        let root = build(&mut nodes, &mut indices, hittables, &items, bounds.clone(), max_depth);
+
+        Self {
+            nodes: nodes.leak(),
+            root,
+            indices: indices.leak(),
+            hittables,
+        }
+    }
+
+
+    pub fn hittables(&self) -> &'a [Hittable<'a>] {
+        self.hittables
+    }
+
+
+    pub fn stats(&self) -> KdTreeStats {
+        let mut stats = KdTreeStats::default();
+        let mut stack = vec![(self.root, 1)];
+        while let Some((node, depth)) = stack.pop() {
+            stats.depth = stats.depth.max(depth);
+            match node {
+                KdNode::Interior { first, .. } => {
+                    stats.interior_nodes += 1;
+                    stack.push((self.nodes[first as usize], depth + 1));
+                    stack.push((self.nodes[first as usize + 1], depth + 1));
+                },
+
+                KdNode::Leaf { len, .. } => {
+                    stats.leaves += 1;
+                    if len == 0 { stats.empty_leaves += 1 }
+                    stats.references += len as usize;
+                },
+            }
+        }
+
+        stats
+    }
+
+
+    ///
+    /// `bounds` must be the bounds the tree was built with
+    ///
+    pub fn closest_hit(&self, bounds: &AABB, ray: Ray, t: Interval) -> Option<HitCandidate<'a, 'a>> {
+        let span = bounds.hit_interval(ray, t)?;
+        let mut t = t;
+        let mut closest = None;
+        self.hit_node(self.root, ray, span, &mut t, &mut closest);
+        closest
+    }
+
+
+    ///
+    /// `span` is the part of the ray inside of `node` and
+    /// `t.max` is shrunk to the closest hit found so far
+    ///
+    fn hit_node(&self, node: KdNode, ray: Ray, span: Interval, t: &mut Interval,
+                closest: &mut Option<HitCandidate<'a, 'a>>) -> bool {
+        if span.min > t.max { return false }
+
+        match node {
+            KdNode::Leaf { start, len } => {
+                let mut hit_anything = false;
+                for &index in &self.indices[start as usize..(start + len) as usize] {
+                    let Some(candidate) = self.hittables[index as usize].closest_hit(ray, *t) else { continue };
+
+                    hit_anything = true;
+                    t.max = candidate.t;
+                    *closest = Some(candidate);
+                }
+
+                hit_anything
+            },
+
+            KdNode::Interior { axis, split, first } => {
+                let axis = axis as usize;
+                let origin = ray.origin[axis];
+                let dir = ray.direction[axis];
+
+                let below_first = origin < split || (origin == split && dir <= 0.0);
+                let (near, far) = if below_first { (first, first + 1) } else { (first + 1, first) };
+                let near = self.nodes[near as usize];
+                let far = self.nodes[far as usize];
+
+                if dir == 0.0 {
+                    return self.hit_node(near, ray, span, t, closest);
+                }
+
+                let t_split = (split - origin) / dir;
+                if t_split > span.max || t_split <= 0.0 {
+                    return self.hit_node(near, ray, span, t, closest);
+                }
+
+                if t_split < span.min {
+                    return self.hit_node(far, ray, span, t, closest);
+                }
+
+                let hit_near = self.hit_node(near, ray, Interval::new(span.min, t_split), t, closest);
+                // a hit past the split can come from a hittable
+                // straddling it, so the far side might still be closer
+                if hit_near && t.max <= t_split { return true }
+
+                let hit_far = self.hit_node(far, ray, Interval::new(t_split, span.max), t, closest);
+                hit_near || hit_far
+            },
+        }
+    }
+}
+
+
+fn build<'a>(nodes: &mut sti::vec::Vec<KdNode, &Arena>, indices: &mut sti::vec::Vec<u32, &Arena>,
+             hittables: &'a [Hittable<'a>], items: &[u32], bounds: AABB, depth: usize) -> KdNode {
+    let leaf = |indices: &mut sti::vec::Vec<u32, &Arena>| {
+        let start = indices.len() as u32;
+        for &item in items {
+            indices.push(item);
+        }
+
+        KdNode::Leaf { start, len: items.len() as u32 }
+    };
+
+    if items.len() <= LEAF_SIZE || depth == 0 {
+        return leaf(indices);
+    }
+
+    // split at the median centroid along the longest axis,
+    // unbounded hittables like planes don't have one
+    let axis = bounds.longest_axis();
+    let mut centroids = Vec::from_iter(items.iter()
+        .map(|&i| {
+            let interval = hittables[i as usize].bounding_box().axis_interval(axis);
+            0.5 * (interval.min + interval.max)
+        })
+        .filter(|c| c.is_finite()));
+
+    if centroids.is_empty() {
+        return leaf(indices);
+    }
+
+    let middle = centroids.len() / 2;
+    let (_, &mut split, _) = centroids.select_nth_unstable_by(middle, |a, b| a.total_cmp(b));
+
+    let mut below = Vec::new();
+    let mut above = Vec::new();
+    for &item in items {
+        let interval = hittables[item as usize].bounding_box().axis_interval(axis);
+        if interval.min <= split { below.push(item) }
+        if interval.max > split { above.push(item) }
+    }
+
+    // no progress, every hittable straddles the split
+    if below.len() == items.len() && above.len() == items.len() {
+        return leaf(indices);
+    }
+
+    let first = nodes.len();
+    nodes.push(KdNode::Leaf { start: 0, len: 0 });
+    nodes.push(KdNode::Leaf { start: 0, len: 0 });
+
+    let (below_bounds, above_bounds) = bounds.split(axis, split);
+    let below = build(nodes, indices, hittables, &below, below_bounds, depth - 1);
+    let above = build(nodes, indices, hittables, &above, above_bounds, depth - 1);
+    nodes[first] = below;
+    nodes[first + 1] = above;
+
+    KdNode::Interior { axis: axis as u8, split, first: first as u32 }
+}
@@ -0,0 +1,45 @@
+use crate::math::vec3::Point;
+
+use super::hittable::Hittable;
+
+
+///
+/// Several versions of one object from most to least detailed.
+/// Each ray only sees the version picked by how far its origin is
+/// from the object, so far away copies can be much cheaper.
+/// Bounces off of nearby surfaces still see the detailed version.
+/// Distances are measured in the space of the levels, so inside
+/// of an instance they're scaled along with it
+///
+pub struct Lod<'a> {
+    centre: Point,
+    /// version `i` is used while closer than `distances[i]`,
+    /// the last version is used beyond that
+    distances: &'a [f32],
+    levels: &'a [Hittable<'a>],
+}
+
+
+impl<'a> Lod<'a> {
+    ///
+    /// Panics unless there's exactly one distance less than levels
+    ///
+    pub fn new(centre: Point, levels: &'a [Hittable<'a>], distances: &'a [f32]) -> Self {
+        assert!(!levels.is_empty() && distances.len() == levels.len() - 1,
+                "every level but the last needs a switch distance");
+
+        Self { centre, distances, levels }
+    }
+
+
+    pub fn levels(&self) -> &'a [Hittable<'a>] {
+        self.levels
+    }
+
+
+    pub fn select(&self, origin: Point) -> &'a Hittable<'a> {
+        let distance = (origin - self.centre).length();
+        let level = self.distances.iter().take_while(|&&d| distance >= d).count();
+        &self.levels[level]
+    }
+}
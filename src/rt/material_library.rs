@@ -0,0 +1,346 @@
+use std::{cell::RefCell, collections::{HashMap, HashSet}, fs, io};
+
+use sti::arena::Arena;
+
+use crate::{math::vec3::Colour, perlin_noise::PerlinNoise};
+
+use super::{materials::Material, texture::Texture, units::LightPower};
+
+const NOISE_POINT_COUNT : usize = 256;
+
+
+///
+/// Named textures and materials which can be shared between scenes.
+/// Libraries are loaded from text files with one definition per line:
+///
+/// ```text
+/// # a comment
+/// texture <name> solid <r> <g> <b>
+/// texture <name> checker <inv_scale> <even texture> <odd texture>
+/// texture <name> noise <scale>
+/// texture <name> image <path>
+/// texture <name> second_uv_set <texture>
+/// material <name> lambertian <texture>
+/// material <name> metal <texture> <fuzz_radius>
+/// material <name> ggx_metal <texture> <roughness>
+/// material <name> anisotropic_metal <texture> <roughness_u> <roughness_v>
+/// material <name> principled <texture> <metallic> <roughness> <specular> <sheen> <clearcoat> <transmission> [emission <texture> [<amount> lumens|watts <area>] [two_sided]]
+/// material <name> dielectric <texture> <refraction_index> [thin | absorption <r> <g> <b>]
+/// material <name> rough_dielectric <texture> <refraction_index> <reflection_roughness> <transmission_roughness> [absorption <r> <g> <b>]
+/// material <name> water <absorption r> <absorption g> <absorption b> <wave_height>
+/// material <name> normal_mapped <material> <normal map texture>
+/// material <name> bumped <material> <height map texture> <strength>
+/// material <name> alpha_masked <material> <alpha texture> [<cutoff>]
+/// material <name> thin_film <material> <thickness in nm> <refraction_index>
+/// material <name> subsurface <texture> <radius r> <radius g> <radius b> <refraction_index>
+/// ```
+///
+/// `metallic` and `roughness` of a principled material are either
+/// a number or the name of a texture they change over the surface by.
+/// Emission can be given as the total light of a surface of `area`
+/// square metres, which the texture should be white at full strength for.
+/// Only the front gives off light unless it's `two_sided`.
+/// A texture or material has to be defined before it's used,
+/// loading another file on top may override names
+///
+#[derive(Default)]
+pub struct MaterialLibrary<'a> {
+    textures: HashMap<String, Texture<'a>>,
+    materials: HashMap<String, Material<'a>>,
+    /// every material name which was looked up
+    used: RefCell<HashSet<String>>,
+}
+
+
+impl<'a> MaterialLibrary<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+
+    pub fn load(&mut self, arena: &'a Arena, path: &str) -> io::Result<()> {
+        let source = fs::read_to_string(path)?;
+        self.parse(arena, &source)
+    }
+
+
+    pub fn parse(&mut self, arena: &'a Arena, source: &str) -> io::Result<()> {
+        for (i, line) in source.lines().enumerate() {
+            let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{message} on line {}", i + 1));
+
+            let line = line.split('#').next().unwrap_or("");
+            let parts = Vec::from_iter(line.split_whitespace());
+            let float = |s: &str| s.parse::<f32>().map_err(|_| invalid("invalid number"));
+            let texture = |name: &str| self.texture(name).ok_or_else(|| invalid(&format!("unknown texture '{name}'")));
+            // a number for the same value everywhere or a map
+            let property = |s: &str| -> io::Result<&'a Texture<'a>> {
+                let value = match s.parse::<f32>() {
+                    Ok(value) => Texture::SolidColour(Colour::new(value, value, value)),
+                    Err(_) => texture(s)?,
+                };
+
+                Ok(arena.alloc_new(value))
+            };
+            let material = |name: &str| self.materials.get(name).copied().ok_or_else(|| invalid(&format!("unknown material '{name}'")));
+
+            match parts.as_slice() {
+                [] => (),
+
+                ["texture", name, definition @ ..] => {
+                    let value = match definition {
+                        ["solid", r, g, b] => Texture::SolidColour(Colour::new(float(r)?, float(g)?, float(b)?)),
+
+                        ["checker", inv_scale, even, odd] => Texture::Checkerboard {
+                            inv_scale: float(inv_scale)?,
+                            even: arena.alloc_new(texture(even)?),
+                            odd: arena.alloc_new(texture(odd)?),
+                        },
+
+                        ["noise", scale] => Texture::NoiseTexture(PerlinNoise::new(arena, NOISE_POINT_COUNT), float(scale)?),
+
+                        ["image", path] => {
+                            let mut image = image::ImageReader::open(path)?;
+                            image.no_limits();
+                            let image = image.decode().map_err(|e| invalid(&e.to_string()))?.into_rgb32f();
+                            Texture::Image { image: arena.alloc_new(image) }
+                        },
+
+                        ["second_uv_set", base] => Texture::SecondUvSet(arena.alloc_new(texture(base)?)),
+
+                        _ => return Err(invalid("invalid texture")),
+                    };
+
+                    self.textures.insert(name.to_string(), value);
+                },
+
+                ["material", name, definition @ ..] => {
+                    let value = match definition {
+                        ["lambertian", tex] => Material::Lambertian { texture: texture(tex)? },
+
+                        ["metal", tex, fuzz_radius] => Material::Metal { texture: texture(tex)?, fuzz_radius: float(fuzz_radius)? },
+
+                        ["ggx_metal", tex, roughness] => Material::GgxMetal { texture: texture(tex)?, roughness: float(roughness)? },
+
+                        ["anisotropic_metal", tex, roughness_u, roughness_v] => Material::AnisotropicMetal {
+                            texture: texture(tex)?,
+                            roughness_u: float(roughness_u)?,
+                            roughness_v: float(roughness_v)?,
+                        },
+
+                        ["principled", tex, metallic, roughness, specular, sheen, clearcoat, transmission, rest @ ..] => {
+                            let (rest, two_sided) = match rest {
+                                [rest @ .., "two_sided"] if rest.starts_with(&["emission"]) => (rest, true),
+                                _ => (rest, false),
+                            };
+
+                            let (emission, emission_strength) = match rest {
+                                [] => (Texture::SolidColour(Colour::ZERO), 1.0),
+                                ["emission", emission] => (texture(emission)?, 1.0),
+                                ["emission", emission, amount, unit, area] => {
+                                    let power = LightPower::parse(amount, unit).ok_or_else(|| invalid("invalid light power"))?;
+                                    (texture(emission)?, power.radiance(float(area)?))
+                                },
+                                _ => return Err(invalid("invalid material")),
+                            };
+
+                            Material::Principled {
+                                texture: texture(tex)?,
+                                metallic: property(metallic)?,
+                                roughness: property(roughness)?,
+                                emission: arena.alloc_new(emission),
+                                emission_strength,
+                                two_sided,
+                                specular: float(specular)?,
+                                sheen: float(sheen)?,
+                                clearcoat: float(clearcoat)?,
+                                transmission: float(transmission)?,
+                            }
+                        },
+
+                        ["dielectric", tex, refraction_index, rest @ ..] => {
+                            let (thin_walled, absorption) = match rest {
+                                [] => (false, Colour::ZERO),
+                                ["thin"] => (true, Colour::ZERO),
+                                ["absorption", r, g, b] => (false, Colour::new(float(r)?, float(g)?, float(b)?)),
+                                _ => return Err(invalid("invalid material")),
+                            };
+
+                            Material::Dielectric { refraction_index: float(refraction_index)?, thin_walled, absorption, texture: texture(tex)? }
+                        },
+
+                        ["rough_dielectric", tex, refraction_index, reflection_roughness, transmission_roughness, rest @ ..] => Material::RoughDielectric {
+                            refraction_index: float(refraction_index)?,
+                            reflection_roughness: float(reflection_roughness)?,
+                            transmission_roughness: float(transmission_roughness)?,
+                            absorption: match rest {
+                                [] => Colour::ZERO,
+                                ["absorption", r, g, b] => Colour::new(float(r)?, float(g)?, float(b)?),
+                                _ => return Err(invalid("invalid material")),
+                            },
+                            texture: texture(tex)?,
+                        },
+
+                        ["water", r, g, b, wave_height] => Material::Water {
+                            absorption: Colour::new(float(r)?, float(g)?, float(b)?),
+                            wave_height: float(wave_height)?,
+                            time: 0.0,
+                        },
+
+                        ["normal_mapped", base, normal_map] => Material::NormalMapped {
+                            material: arena.alloc_new(material(base)?),
+                            normal_map: texture(normal_map)?,
+                        },
+
+                        ["bumped", base, height_map, strength] => Material::Bumped {
+                            material: arena.alloc_new(material(base)?),
+                            height_map: texture(height_map)?,
+                            strength: float(strength)?,
+                        },
+
+                        ["thin_film", base, thickness, refraction_index] => Material::ThinFilm {
+                            material: arena.alloc_new(material(base)?),
+                            thickness: float(thickness)?,
+                            refraction_index: float(refraction_index)?,
+                        },
+
+                        ["subsurface", tex, r, g, b, refraction_index] => Material::Subsurface {
+                            texture: texture(tex)?,
+                            radius: Colour::new(float(r)?, float(g)?, float(b)?),
+                            refraction_index: float(refraction_index)?,
+                        },
+
+                        ["alpha_masked", base, alpha, rest @ ..] => Material::AlphaMasked {
+                            material: arena.alloc_new(material(base)?),
+                            alpha: arena.alloc_new(texture(alpha)?),
+                            cutoff: match rest {
+                                [] => None,
+                                [cutoff] => Some(float(cutoff)?),
+                                _ => return Err(invalid("invalid material")),
+                            },
+                        },
+
+                        _ => return Err(invalid("invalid material")),
+                    };
+
+                    self.materials.insert(name.to_string(), value);
+                },
+
+                _ => return Err(invalid("expected a texture or a material")),
+            }
+        }
+
+        Ok(())
+    }
+
+
+    pub fn insert_texture(&mut self, name: &str, texture: Texture<'a>) {
+        self.textures.insert(name.to_string(), texture);
+    }
+
+
+    pub fn insert_material(&mut self, name: &str, material: Material<'a>) {
+        self.materials.insert(name.to_string(), material);
+    }
+
+
+    pub fn texture(&self, name: &str) -> Option<Texture<'a>> {
+        self.textures.get(name).copied()
+    }
+
+
+    pub fn material(&self, name: &str) -> Option<Material<'a>> {
+        self.used.borrow_mut().insert(name.to_string());
+        self.materials.get(name).copied()
+    }
+
+
+    ///
+    /// The material called `name` or `fallback` if there's none,
+    /// so scenes can be restyled from a library file without
+    /// the file having to define everything in them
+    ///
+    pub fn material_or(&self, name: &str, fallback: Material<'a>) -> Material<'a> {
+        self.material(name).unwrap_or(fallback)
+    }
+
+
+    ///
+    /// The names of the materials which were never looked up
+    ///
+    pub fn unreferenced_materials(&self) -> Vec<&str> {
+        let used = self.used.borrow();
+        let mut names = Vec::from_iter(self.materials.keys()
+            .filter(|name| !used.contains(*name))
+            .map(|name| name.as_str()));
+
+        names.sort_unstable();
+        names
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_definitions() {
+        let arena = Arena::new();
+        let source = "
+            # shared lookdev
+            texture white solid 1 1 1
+            texture black solid 0 0 0
+            texture floor checker 0.5 black white
+            material ground lambertian floor
+            material pane dielectric white 1.5 thin
+            material frosted rough_dielectric white 1.5 0.05 0.4
+            material bottle dielectric white 1.5 absorption 2 0.2 1.5
+            material brushed ggx_metal white 0.3
+            material aluminium anisotropic_metal white 0.1 0.5
+            texture bumps noise 4
+            texture detail second_uv_set bumps
+            material bumpy_ground normal_mapped ground bumps
+            material car_paint principled floor 0.2 0.4 0.5 0 1 0
+            material scuffed_panel principled white floor bumps 0.5 0 0 0 emission black
+            material softbox principled white 0 1 0.5 0 0 0 emission white 1000 lumens 0.25
+            material lantern principled white 0 1 0.5 0 0 0 emission white two_sided
+            material dented_ground bumped ground bumps 0.05
+            material fence alpha_masked ground floor 0.5
+            material bubble thin_film pane 380 1.33
+            material leaf alpha_masked ground bumps
+            material wax subsurface white 0.01 0.005 0.002 1.4
+        ";
+
+        let mut library = MaterialLibrary::new();
+        library.parse(&arena, source).unwrap();
+        assert!(matches!(library.material("ground"), Some(Material::Lambertian { texture: Texture::Checkerboard { .. } })));
+        assert!(matches!(library.material("pane"), Some(Material::Dielectric { thin_walled: true, .. })));
+        assert!(library.material("frosted").is_some());
+        assert!(matches!(library.material("bottle"), Some(Material::Dielectric { thin_walled: false, absorption, .. }) if absorption == Colour::new(2.0, 0.2, 1.5)));
+        assert!(matches!(library.material("brushed"), Some(Material::GgxMetal { .. })));
+        assert!(matches!(library.material("aluminium"), Some(Material::AnisotropicMetal { .. })));
+        assert!(matches!(library.material("bumpy_ground"), Some(Material::NormalMapped { material: Material::Lambertian { .. }, .. })));
+        assert!(matches!(library.texture("detail"), Some(Texture::SecondUvSet(Texture::NoiseTexture(..)))));
+        assert!(matches!(library.material("bubble"), Some(Material::ThinFilm { material: Material::Dielectric { thin_walled: true, .. }, .. })));
+        assert!(matches!(library.material("fence"), Some(Material::AlphaMasked { cutoff: Some(0.5), .. })));
+        assert!(matches!(library.material("wax"), Some(Material::Subsurface { radius, refraction_index: 1.4, .. }) if radius == Colour::new(0.01, 0.005, 0.002)));
+        assert!(matches!(library.material("leaf"), Some(Material::AlphaMasked { cutoff: None, .. })));
+        assert!(matches!(library.material("dented_ground"), Some(Material::Bumped { strength: 0.05, .. })));
+        assert!(matches!(library.material("softbox"), Some(Material::Principled { emission_strength, two_sided: false, .. }) if emission_strength == LightPower::Lumens(1000.0).radiance(0.25)));
+        assert!(matches!(library.material("lantern"), Some(Material::Principled { emission_strength: 1.0, two_sided: true, .. })));
+        assert!(matches!(library.material("car_paint"), Some(Material::Principled { clearcoat: 1.0, metallic: &Texture::SolidColour(metallic), .. }) if metallic == Colour::new(0.2, 0.2, 0.2)));
+        assert!(matches!(library.material("scuffed_panel"), Some(Material::Principled { metallic: Texture::Checkerboard { .. }, roughness: Texture::NoiseTexture(..), .. })));
+        assert!(library.material("missing").is_none());
+        assert_eq!(library.unreferenced_materials(), Vec::<&str>::new());
+
+        // later files can build on what's already loaded
+        library.parse(&arena, "material chrome metal white 0.0").unwrap();
+        assert_eq!(library.unreferenced_materials(), ["chrome"]);
+        assert!(matches!(library.material("chrome"), Some(Material::Metal { .. })));
+
+        assert!(library.parse(&arena, "material a lambertian missing").is_err());
+        assert!(library.parse(&arena, "material a normal_mapped missing white").is_err());
+        assert!(library.parse(&arena, "texture a solid 1 1").is_err());
+        assert!(library.parse(&arena, "shader a").is_err());
+    }
+}
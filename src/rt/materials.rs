@@ -1,7 +1,50 @@
-use crate::{math::{ray::Ray, vec3::{Colour, Vec3}}, rt::hittable::HitRecord, rng::next_f32};
+use std::{f32::consts::PI, sync::OnceLock};
+
+use crate::{math::{ray::Ray, vec3::{Colour, Point, Vec3}}, rt::hittable::HitRecord, rng::next_f32};
 
 use super::texture::Texture;
 
+const WATER_REFRACTION_INDEX : f32 = 1.33;
+/// the colour of `Material::Missing`
+const MISSING_COLOUR : Colour = Colour::new(1.0, 0.0, 1.0);
+/// below this the GGX distribution gets too sharp for floats
+const MIN_GGX_ALPHA : f32 = 1e-4;
+/// how many cosines and roughnesses the single bounce
+/// albedo of GGX is tabled at, see `GgxAlbedo`
+const GGX_ALBEDO_SIZE : usize = 32;
+/// each entry of `GgxAlbedo` is integrated over this
+/// many by this many visible normals
+const GGX_ALBEDO_SAMPLES : usize = 32;
+/// seen from straight along the surface the visible
+/// normals are undefined, the table starts a little above
+const GGX_ALBEDO_MIN_COS : f32 = 1e-3;
+/// the clearcoat of `Material::Principled` is a sharp
+/// varnish with the reflectance of polyurethane
+const CLEARCOAT_F0 : f32 = 0.04;
+const CLEARCOAT_ALPHA : (f32, f32) = (0.01, 0.01);
+/// how far apart the height map of `Material::Bumped` is sampled
+const BUMP_STEP : f32 = 1e-3;
+/// the wavelengths in nanometres `Material::ThinFilm`
+/// works the red, green and blue channels out at
+const FILM_WAVELENGTHS : [f32; 3] = [650.0, 532.0, 450.0];
+/// what's under a thin film on anything which isn't a
+/// dielectric, metals are taken as a dielectric this dense
+const FILM_OPAQUE_SUBSTRATE_INDEX : f32 = 3.0;
+/// the shortest scattering radius of `Material::Subsurface`,
+/// in metres, so the walk always gets somewhere
+const MIN_SCATTERING_RADIUS : f32 = 1e-6;
+/// anisotropies closer to 0 are sampled as uniform scattering,
+/// the inverted distribution divides by it
+const MIN_ANISOTROPY : f32 = 1e-3;
+
+/// direction x, direction z, frequency and speed of each wave
+const WAVES : [(f32, f32, f32, f32); 4] = [
+    ( 0.8,  0.6, 1.3, 1.1),
+    (-0.4,  0.9, 2.1, 1.7),
+    ( 0.9, -0.4, 3.7, 2.3),
+    (-0.7, -0.7, 6.1, 3.1),
+];
+
 #[derive(Default, Clone, Copy)]
 pub enum Material<'a> {
     Lambertian {
@@ -13,18 +56,234 @@ pub enum Material<'a> {
         fuzz_radius: f32,
     },
 
+    /// A metal with a GGX (Trowbridge-Reitz) distribution of
+    /// microfacet normals, `roughness` goes from a mirror at 0
+    /// to fully rough at 1. The texture is the reflectance
+    /// head-on and goes to white at grazing angles
+    GgxMetal {
+        texture: Texture<'a>,
+        roughness: f32,
+    },
+
+    /// Disney's principled BSDF, which mixes the other materials
+    /// by sliders going from 0 to 1. `metallic` blends from a
+    /// dielectric to a `GgxMetal` and `transmission` turns the
+    /// dielectric's diffuse part into rough glass, both tinted by
+    /// the texture. `specular` scales the dielectric's reflectance
+    /// with 0.5 being the 4% of most plastics, `sheen` brightens
+    /// grazing angles like cloth and `clearcoat` adds a varnish.
+    /// `metallic` and `roughness` are maps so they can change over
+    /// the surface, taken as the mean of their channels, and the
+    /// surface gives off the light of the `emission` map times
+    /// `emission_strength`, see `LightPower` for physical lights.
+    /// Only the front face gives off light unless it's
+    /// `two_sided`, so closed light boxes don't light themselves
+    Principled {
+        texture: Texture<'a>,
+        metallic: &'a Texture<'a>,
+        roughness: &'a Texture<'a>,
+        emission: &'a Texture<'a>,
+        emission_strength: f32,
+        two_sided: bool,
+        specular: f32,
+        sheen: f32,
+        clearcoat: f32,
+        transmission: f32,
+    },
+
+    /// A `GgxMetal` which is rougher along one direction of
+    /// the surface than the other, like brushed aluminium.
+    /// `roughness_u` is along the way `u` grows and
+    /// `roughness_v` across it
+    AnisotropicMetal {
+        texture: Texture<'a>,
+        roughness_u: f32,
+        roughness_v: f32,
+    },
+
+    /// A thin walled dielectric is treated as an infinitely
+    /// thin sheet of glass. Rays going through aren't bent
+    /// so single quads and bubbles don't darken everything.
+    /// Light is absorbed by `absorption` per metre travelled
+    /// inside, which thin walled ones don't have
     Dielectric {
         refraction_index: f32,
+        thin_walled: bool,
+        absorption: Colour,
+        texture: Texture<'a>,
+    },
+
+    /// A dielectric whose reflected and transmitted rays are
+    /// fuzzed like a metal's, each by its own roughness.
+    /// Frosted glass is a rough transmission behind a
+    /// mostly sharp reflection. Absorbs like a `Dielectric`
+    RoughDielectric {
+        refraction_index: f32,
+        reflection_roughness: f32,
+        transmission_roughness: f32,
+        absorption: Colour,
+        texture: Texture<'a>,
+    },
+
+    /// A dielectric with the refraction index of water whose
+    /// horizontal surfaces are perturbed by waves. `time` moves
+    /// the waves along so frame sequences can animate them and
+    /// light is absorbed by `absorption` per unit travelled inside
+    Water {
+        absorption: Colour,
+        wave_height: f32,
+        time: f32,
+    },
+
+    /// A point inside of a participating medium. Light scatters
+    /// off of it by the Henyey-Greenstein phase function, in a
+    /// uniformly random direction with an `anisotropy` of 0 and
+    /// more and more forwards as it goes towards 1 like in fog
+    /// or backwards as it goes towards -1
+    Isotropic {
+        albedo: Colour,
+        emission: Colour,
+        anisotropy: f32,
+    },
+
+    /// `material` with its shading normal tilted by a tangent
+    /// space normal map. Red goes along the tangent, green along
+    /// the bitangent and blue out of the surface, each mapped
+    /// from 0 to 1 onto -1 to 1 so a flat map is (0.5, 0.5, 1)
+    NormalMapped {
+        material: &'a Material<'a>,
+        normal_map: Texture<'a>,
+    },
+
+    /// `material` with its shading normal tilted by the slope of
+    /// a greyscale height map, found by finite differences. The
+    /// height is the mean of the channels, `strength` scales the
+    /// slope and 0 turns the bumps off
+    Bumped {
+        material: &'a Material<'a>,
+        height_map: Texture<'a>,
+        strength: f32,
+    },
+
+    /// `material` with holes where `alpha` is below one, the ray
+    /// carries on through them as if nothing was hit. With a
+    /// `cutoff` the surface is there where the alpha reaches it,
+    /// without one it's there with a chance of the alpha so
+    /// soft edges like those of leaves blend over the samples
+    AlphaMasked {
+        material: &'a Material<'a>,
+        alpha: &'a Texture<'a>,
+        cutoff: Option<f32>,
+    },
+
+    /// `material` under a film `thickness` nanometres thick,
+    /// whose reflections interfere like on soap bubbles and oil.
+    /// What's reflected is tinted by the film's reflectance at
+    /// each channel over its mean, what goes through by the rest,
+    /// so the film only moves the light between the colours
+    ThinFilm {
+        material: &'a Material<'a>,
+        thickness: f32,
+        refraction_index: f32,
+    },
+
+    /// A smooth dielectric boundary over an inside which scatters
+    /// the light that gets in around in a random walk, like skin,
+    /// wax and marble. `radius` is how far each channel goes on
+    /// average between scatterings, in metres, and the texture is
+    /// the colour the surface ends up when it's thick enough
+    Subsurface {
         texture: Texture<'a>,
+        radius: Colour,
+        refraction_index: f32,
     },
 
+    /// What a hittable without a material gets, it scatters
+    /// like a magenta Lambertian so it stands out instead of
+    /// bringing the render down. `validate` warns about it
     #[default]
-    Unknown,
+    Missing,
 }
 
 
 impl<'a> Material<'a> {
-    pub fn scatter(self, ray_in: Ray, rec: &HitRecord) -> Option<(Ray, Colour)> {
+    ///
+    /// Picks the direction the light coming in along `ray_in` goes
+    /// on in, or `None` if it's absorbed. The density of the pick
+    /// is only worked out if `BsdfSample::pdf` asks for it, for
+    /// integrators which weigh it against directions picked some
+    /// other way, like towards lights
+    ///
+    pub fn sample<'r>(self, ray_in: Ray, rec: &'r HitRecord<'a>) -> Option<BsdfSample<'r>> where 'a: 'r {
+        let (ray, weight) = self.scatter(ray_in, rec)?;
+        Some(BsdfSample { ray, weight, material: self, ray_in, rec })
+    }
+
+
+    ///
+    /// The density over solid angle of `sample` going on in
+    /// `direction`. `None` for materials which can't tell, like
+    /// perfect mirrors and glass whose directions are certain
+    /// or principled ones which pick between their layers
+    ///
+    pub fn pdf(self, ray_in: Ray, rec: &HitRecord, direction: Vec3) -> Option<f32> {
+        match self {
+            Material::Lambertian { .. } | Material::Missing => Some(direction.unit().dot(rec.normal).max(0.0) / PI),
+
+            Material::Isotropic { anisotropy, .. } => Some(henyey_greenstein(ray_in.direction.unit().dot(direction.unit()), anisotropy)),
+
+            Material::GgxMetal { texture, roughness } => Material::AnisotropicMetal { texture, roughness_u: roughness, roughness_v: roughness }.pdf(ray_in, rec, direction),
+
+            Material::AnisotropicMetal { roughness_u, roughness_v, .. } => {
+                let (outgoing, incoming) = ggx_directions(ray_in, rec, direction);
+                Some(ggx_metal_pdf(outgoing, incoming, (ggx_alpha(roughness_u), ggx_alpha(roughness_v))))
+            },
+
+            Material::NormalMapped { material, .. } | Material::Bumped { material, .. } => material.pdf(ray_in, &self.shading_record(ray_in, rec), direction),
+
+            Material::AlphaMasked { material, .. } | Material::ThinFilm { material, .. } => material.pdf(ray_in, rec, direction),
+
+            _ => None,
+        }
+    }
+
+
+    ///
+    /// How much of the light coming from `direction` goes out
+    /// along `ray_in` backwards, times the cosine of its angle
+    /// to the normal. For every direction `sample` picks its
+    /// weight is this over the `pdf`. `None` where `pdf` is
+    ///
+    pub fn eval(self, ray_in: Ray, rec: &HitRecord, direction: Vec3) -> Option<Colour> {
+        match self {
+            Material::Lambertian { texture } => Some(direction.unit().dot(rec.normal).max(0.0) / PI * texture.at(rec)),
+
+            Material::Missing => Material::Lambertian { texture: Texture::SolidColour(MISSING_COLOUR) }.eval(ray_in, rec, direction),
+
+            Material::Isotropic { albedo, anisotropy, .. } => Some(henyey_greenstein(ray_in.direction.unit().dot(direction.unit()), anisotropy) * albedo),
+
+            Material::GgxMetal { texture, roughness } => Material::AnisotropicMetal { texture, roughness_u: roughness, roughness_v: roughness }.eval(ray_in, rec, direction),
+
+            Material::AnisotropicMetal { texture, roughness_u, roughness_v } => {
+                let (outgoing, incoming) = ggx_directions(ray_in, rec, direction);
+                Some(ggx_metal_eval(outgoing, incoming, (ggx_alpha(roughness_u), ggx_alpha(roughness_v)), texture.at(rec)))
+            },
+
+            Material::NormalMapped { material, .. } | Material::Bumped { material, .. } => material.eval(ray_in, &self.shading_record(ray_in, rec), direction),
+
+            Material::AlphaMasked { material, .. } => material.eval(ray_in, rec, direction),
+
+            Material::ThinFilm { material, .. } => {
+                let eval = material.eval(ray_in, rec, direction)?;
+                Some(eval * self.film_tint(ray_in, rec, direction.dot(rec.normal) > 0.0).unwrap_or(Colour::ONE))
+            },
+
+            _ => None,
+        }
+    }
+
+
+    fn scatter(self, ray_in: Ray, rec: &HitRecord) -> Option<(Ray, Colour)> {
         match self {
             Material::Lambertian { texture } => {
                 let mut scatter_dir = rec.normal + Vec3::random_unit();
@@ -33,7 +292,7 @@ impl<'a> Material<'a> {
 
                 let scatter_dir = scatter_dir;
                 let scattered = Ray::new(rec.point, scatter_dir, ray_in.time);
-                Some((scattered, texture.value(rec.u, rec.v, rec.point)))
+                Some((scattered, texture.at(rec)))
             },
 
             Material::Metal { texture, fuzz_radius } => {
@@ -42,32 +301,799 @@ impl<'a> Material<'a> {
                 let scattered = Ray::new(rec.point, reflected + fuzz_radius * Vec3::random_unit(), ray_in.time);
 
                 if scattered.direction.dot(rec.normal) > 0.0 {
-                    Some((scattered, texture.value(rec.u, rec.v, rec.point)))
+                    Some((scattered, texture.at(rec)))
                 } else { None }
             },
 
-            Material::Dielectric { texture, refraction_index } => {
-                let attenuation = texture.value(rec.u, rec.v, rec.point);
-                let refraction_ratio = if rec.front_face { 1.0 / refraction_index }
-                                       else { refraction_index };
+            Material::GgxMetal { texture, roughness } => {
+                Material::AnisotropicMetal { texture, roughness_u: roughness, roughness_v: roughness }.scatter(ray_in, rec)
+            },
 
-                let unit_dir = ray_in.direction.unit();
-                let cos_theta = (-unit_dir).dot(rec.normal).min(1.0);
-                let sin_theta = (1.0 - cos_theta*cos_theta).sqrt();
+            Material::AnisotropicMetal { texture, roughness_u, roughness_v } => {
+                let (tangent, bitangent) = shading_frame(rec);
+                let to_local = |v: Vec3| Vec3::new(v.dot(tangent), v.dot(bitangent), v.dot(rec.normal));
+
+                let outgoing = to_local(-ray_in.direction.unit());
+                if outgoing.z <= 0.0 { return None }
 
-                let cannot_refract = refraction_ratio * sin_theta > 1.0;
-                let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > next_f32() {
-                    unit_dir.reflect(rec.normal)
+                // a single reflection off of a visible normal as often
+                // as one gets out, otherwise one of the reflections
+                // between the facets which goes out evenly
+                let alpha = (ggx_alpha(roughness_u), ggx_alpha(roughness_v));
+                let incoming = if next_f32() < ggx_albedo().directional(outgoing.z, alpha) {
+                    reflect_on_facet(outgoing, sample_ggx_visible_normal(outgoing, alpha))
                 } else {
-                    unit_dir.refract(rec.normal, refraction_ratio)
+                    let direction = Vec3::new(0.0, 0.0, 1.0) + Vec3::random_unit();
+                    if direction.near_zero() { Vec3::new(0.0, 0.0, 1.0) } else { direction.unit() }
                 };
 
+                // reflected into the surface, the light goes on to
+                // bounce between the facets which the other lobe has
+                if incoming.z <= 0.0 { return None }
+
+                let pdf = ggx_metal_pdf(outgoing, incoming, alpha);
+                let attenuation = ggx_metal_eval(outgoing, incoming, alpha, texture.at(rec)) / pdf;
+
+                let direction = incoming.x * tangent + incoming.y * bitangent + incoming.z * rec.normal;
                 Some((Ray::new(rec.point, direction, ray_in.time), attenuation))
             },
 
-            Material::Unknown => unimplemented!(),
+            Material::Principled { texture, metallic, roughness, specular, sheen, clearcoat, transmission, .. } => {
+                let metallic = metallic.scalar_at(rec);
+                let roughness = roughness.scalar_at(rec);
+
+                let (tangent, bitangent) = tangent_frame(rec.normal);
+                let to_local = |v: Vec3| Vec3::new(v.dot(tangent), v.dot(bitangent), v.dot(rec.normal));
+                let to_world = |v: Vec3| v.x * tangent + v.y * bitangent + v.z * rec.normal;
+
+                let unit_dir = ray_in.direction.unit();
+                let outgoing = to_local(-unit_dir);
+                if outgoing.z <= 0.0 { return None }
+
+                // every layer either reflects the light or passes it
+                // on to the one below by a coin toss with the odds of
+                // its fresnel term, which leaves nothing to weigh by
+                let glossy = |alpha: (f32, f32), facet: Vec3| {
+                    let incoming = reflect_on_facet(outgoing, facet);
+                    if incoming.z <= 0.0 { return None }
+                    Some((Ray::new(rec.point, to_world(incoming), ray_in.time), smith_g1(incoming, alpha) * Colour::ONE))
+                };
+
+                let facet = sample_ggx_visible_normal(outgoing, CLEARCOAT_ALPHA);
+                if clearcoat * schlick(CLEARCOAT_F0, outgoing.dot(facet)) > next_f32() {
+                    return glossy(CLEARCOAT_ALPHA, facet);
+                }
+
+                if metallic > next_f32() {
+                    return Material::GgxMetal { texture, roughness }.scatter(ray_in, rec);
+                }
+
+                let base = texture.at(rec);
+                let alpha = (ggx_alpha(roughness), ggx_alpha(roughness));
+                let facet = sample_ggx_visible_normal(outgoing, alpha);
+                let cos_theta = outgoing.dot(facet);
+
+                // specular 0.5 is a reflectance of 4% head-on, which
+                // is also what sets the refraction index of the glass
+                let f0 = 0.08 * specular.clamp(0.0, 1.0);
+
+                if transmission > next_f32() {
+                    let refraction_index = (1.0 + f0.sqrt()) / (1.0 - f0.sqrt());
+                    let refraction_ratio = if rec.front_face { 1.0 / refraction_index }
+                                           else { refraction_index };
+
+                    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                    let cannot_refract = refraction_ratio * sin_theta > 1.0;
+                    if cannot_refract || reflectance(cos_theta, refraction_ratio) > next_f32() {
+                        return glossy(alpha, facet);
+                    }
+
+                    let direction = unit_dir.refract(to_world(facet), refraction_ratio);
+                    if direction.dot(rec.normal) >= 0.0 { return None }
+                    return Some((Ray::new(rec.point, direction, ray_in.time), base));
+                }
+
+                if schlick(f0, cos_theta) > next_f32() {
+                    return glossy(alpha, facet);
+                }
+
+                let mut scatter_dir = rec.normal + Vec3::random_unit();
+                if scatter_dir.near_zero() { scatter_dir = rec.normal };
+
+                // the sheen grows with the angle between the light
+                // and the half vector like a fresnel term
+                let half = (scatter_dir.unit() - unit_dir).unit();
+                let sheen = sheen * schlick(0.0, scatter_dir.unit().dot(half));
+                Some((Ray::new(rec.point, scatter_dir, ray_in.time), base + sheen * Colour::ONE))
+            },
+
+            Material::Dielectric { texture, refraction_index, thin_walled: false, .. } => {
+                let attenuation = texture.at(rec);
+                Some((refract_or_reflect(ray_in, rec, rec.normal, refraction_index), attenuation))
+            },
+
+            Material::Dielectric { texture, refraction_index, thin_walled: true, .. } => {
+                let attenuation = texture.at(rec);
+                let unit_dir = ray_in.direction.unit();
+                let cos_theta = (-unit_dir).dot(rec.normal).min(1.0);
+
+                // the light bouncing around between both sides of the
+                // sheet adds up to 2R / (1 + R) being reflected
+                let r = reflectance(cos_theta, 1.0 / refraction_index);
+                let direction = if 2.0 * r / (1.0 + r) > next_f32() { unit_dir.reflect(rec.normal) }
+                                else { unit_dir };
+
+                Some((Ray::new(rec.point, direction, ray_in.time), attenuation))
+            },
+
+            Material::RoughDielectric { texture, refraction_index, reflection_roughness, transmission_roughness, .. } => {
+                let (direction, reflected) = choose_lobe(ray_in.direction.unit(), rec.front_face, rec.normal, refraction_index);
+                let roughness = if reflected { reflection_roughness } else { transmission_roughness };
+                let direction = direction + roughness.min(1.0) * Vec3::random_unit();
+
+                // the fuzz can push the ray to the wrong side of the surface
+                if (direction.dot(rec.normal) > 0.0) != reflected { return None }
+
+                let attenuation = texture.at(rec);
+                Some((Ray::new(rec.point, direction, ray_in.time), attenuation))
+            },
+
+            Material::Water { wave_height, time, .. } => {
+                let normal = wave_normal(rec.normal, rec.point, wave_height, time);
+                Some((refract_or_reflect(ray_in, rec, normal, WATER_REFRACTION_INDEX), Colour::ONE))
+            },
+
+            Material::Isotropic { albedo, anisotropy, .. } => {
+                let direction = sample_henyey_greenstein(ray_in.direction.unit(), anisotropy);
+                Some((Ray::new(rec.point, direction, ray_in.time), albedo))
+            },
+
+            Material::NormalMapped { material, .. } | Material::Bumped { material, .. } => material.scatter(ray_in, &self.shading_record(ray_in, rec)),
+
+            Material::AlphaMasked { material, .. } => material.scatter(ray_in, rec),
+
+            Material::ThinFilm { material, .. } => {
+                let (scattered, attenuation) = material.scatter(ray_in, rec)?;
+                let tint = self.film_tint(ray_in, rec, scattered.direction.dot(rec.normal) > 0.0).unwrap_or(Colour::ONE);
+                Some((scattered, attenuation * tint))
+            },
+
+            Material::Subsurface { refraction_index, .. } => {
+                Some((refract_or_reflect(ray_in, rec, rec.normal, refraction_index), Colour::ONE))
+            },
+
+            Material::Missing => Material::Lambertian { texture: Texture::SolidColour(MISSING_COLOUR) }.scatter(ray_in, rec),
+        }
+    }
+
+
+    ///
+    /// The absorption coefficient of the inside of the
+    /// material, if light is absorbed when going through it
+    ///
+    pub fn absorption(self) -> Option<Colour> {
+        match self {
+            Material::Water { absorption, .. } => Some(absorption),
+            Material::NormalMapped { material, .. } => material.absorption(),
+            Material::Bumped { material, .. } => material.absorption(),
+            Material::AlphaMasked { material, .. } => material.absorption(),
+            Material::ThinFilm { material, .. } => material.absorption(),
+            Material::Dielectric { absorption, thin_walled: false, .. } => Some(absorption),
+            Material::RoughDielectric { absorption, .. } => Some(absorption),
+            _ => None,
         }
     }
+
+
+    ///
+    /// What the random walk inside of the material scatters off,
+    /// for the material at `rec`
+    ///
+    pub fn interior(self, rec: &HitRecord) -> Option<Interior> {
+        match self {
+            Material::Subsurface { texture, radius, .. } => {
+                let colour = texture.at(rec);
+                let radius = Colour::new(radius.x.max(MIN_SCATTERING_RADIUS),
+                                         radius.y.max(MIN_SCATTERING_RADIUS),
+                                         radius.z.max(MIN_SCATTERING_RADIUS));
+                Some(Interior {
+                    albedo: Colour::new(single_scattering_albedo(colour.x),
+                                        single_scattering_albedo(colour.y),
+                                        single_scattering_albedo(colour.z)),
+                    extinction: Colour::new(1.0 / radius.x, 1.0 / radius.y, 1.0 / radius.z),
+                })
+            },
+            Material::NormalMapped { material, .. } => material.interior(rec),
+            Material::Bumped { material, .. } => material.interior(rec),
+            Material::AlphaMasked { material, .. } => material.interior(rec),
+            Material::ThinFilm { material, .. } => material.interior(rec),
+            _ => None,
+        }
+    }
+
+
+    pub fn emitted(self, rec: &HitRecord) -> Colour {
+        match self {
+            Material::Isotropic { emission, .. } => emission,
+            Material::Principled { two_sided: false, .. } if !rec.front_face => Colour::ZERO,
+            Material::Principled { emission, emission_strength, .. } => emission_strength * emission.at(rec),
+            Material::NormalMapped { material, .. } => material.emitted(rec),
+            Material::Bumped { material, .. } => material.emitted(rec),
+            Material::AlphaMasked { material, .. } => material.emitted(rec),
+            Material::ThinFilm { material, .. } => material.emitted(rec),
+            _ => Colour::ZERO,
+        }
+    }
+
+
+    ///
+    /// Whether `rec` is on the surface rather than in one of its
+    /// cutouts, only alpha masked materials have any
+    ///
+    pub fn is_opaque_at(self, rec: &HitRecord) -> bool {
+        match self {
+            Material::AlphaMasked { alpha, cutoff, .. } => {
+                let alpha = alpha.scalar_at(rec);
+                alpha >= cutoff.unwrap_or_else(next_f32)
+            },
+            Material::NormalMapped { material, .. } => material.is_opaque_at(rec),
+            Material::Bumped { material, .. } => material.is_opaque_at(rec),
+            Material::ThinFilm { material, .. } => material.is_opaque_at(rec),
+            _ => true,
+        }
+    }
+
+
+    ///
+    /// `rec` with the normal a normal mapped or bumped material
+    /// tilts it to. It's left as it is where the tilted normal
+    /// would send the light through the surface, or there is none
+    ///
+    fn shading_record(self, ray_in: Ray, rec: &HitRecord<'a>) -> HitRecord<'a> {
+        let normal = match self {
+            Material::NormalMapped { normal_map, .. } => {
+                let (tangent, bitangent) = shading_frame(rec);
+                let texel = 2.0 * normal_map.at(rec) - Colour::ONE;
+                (texel.x * tangent + texel.y * bitangent + texel.z * rec.normal).unit()
+            },
+
+            Material::Bumped { height_map, strength, .. } if strength != 0.0 => {
+                let (tangent, bitangent) = shading_frame(rec);
+                let height = |u, v, point| height_map.scalar(u, v, point);
+
+                // step along both the uvs and the surface so height
+                // maps sampled by position get a slope as well
+                let (u, v) = height_map.uv(rec);
+                let here = height(u, v, rec.point);
+                let along_u = height(u + BUMP_STEP, v, rec.point + BUMP_STEP * tangent) - here;
+                let along_v = height(u, v + BUMP_STEP, rec.point + BUMP_STEP * bitangent) - here;
+
+                let slope = strength / BUMP_STEP;
+                (rec.normal - slope * along_u * tangent - slope * along_v * bitangent).unit()
+            },
+
+            _ => return rec.clone(),
+        };
+
+        // tilted away from the ray it would send the light
+        // through the surface, and a black texel has no normal
+        if normal.x.is_nan() || normal.dot(ray_in.direction) >= 0.0 { return rec.clone() }
+
+        let mut rec = rec.clone();
+        rec.normal = normal;
+        rec
+    }
+
+
+    ///
+    /// What a thin film tints the light by which it `reflected`
+    /// or let through, `None` if it's not a thin film or the
+    /// film reflects nothing or everything
+    ///
+    fn film_tint(self, ray_in: Ray, rec: &HitRecord, reflected: bool) -> Option<Colour> {
+        let Material::ThinFilm { material, thickness, refraction_index } = self else { return None };
+
+        let cos_theta = (-ray_in.direction.unit()).dot(rec.normal).clamp(0.0, 1.0);
+        let (outside, behind) = if rec.front_face { (1.0, material.substrate_index()) }
+                                else { (material.substrate_index(), 1.0) };
+        let film = FILM_WAVELENGTHS.map(|wavelength| film_reflectance(cos_theta, outside, refraction_index, behind, thickness, wavelength));
+        let mean = film.iter().sum::<f32>() / 3.0;
+
+        let tint = if reflected { film.map(|r| r / mean) }
+                   else { film.map(|r| (1.0 - r) / (1.0 - mean)) };
+        if tint.iter().any(|t| !t.is_finite()) { return None }
+
+        Some(Colour::new(tint[0], tint[1], tint[2]))
+    }
+
+
+    ///
+    /// The refraction index of what's under a thin film on the
+    /// material. A thin walled dielectric is itself the film
+    /// of a bubble so there's air on the other side
+    ///
+    fn substrate_index(self) -> f32 {
+        match self {
+            Material::Dielectric { thin_walled: true, .. } => 1.0,
+            Material::Dielectric { refraction_index, .. } => refraction_index,
+            Material::RoughDielectric { refraction_index, .. } => refraction_index,
+            Material::Water { .. } => WATER_REFRACTION_INDEX,
+            Material::Subsurface { refraction_index, .. } => refraction_index,
+            Material::NormalMapped { material, .. } => material.substrate_index(),
+            Material::Bumped { material, .. } => material.substrate_index(),
+            Material::AlphaMasked { material, .. } => material.substrate_index(),
+            _ => FILM_OPAQUE_SUBSTRATE_INDEX,
+        }
+    }
+}
+
+
+///
+/// A direction picked by `Material::sample`
+///
+#[derive(Clone, Copy)]
+pub struct BsdfSample<'r> {
+    pub ray: Ray,
+    /// what the light coming back along `ray` is multiplied by
+    pub weight: Colour,
+    material: Material<'r>,
+    ray_in: Ray,
+    rec: &'r HitRecord<'r>,
+}
+
+
+impl BsdfSample<'_> {
+    ///
+    /// The density of having picked `ray`, see `Material::pdf`
+    ///
+    pub fn pdf(&self) -> Option<f32> {
+        self.material.pdf(self.ray_in, self.rec, self.ray.direction)
+    }
+}
+
+
+///
+/// The inside of a `Material::Subsurface` a ray is walking
+/// through, with the albedo and extinction per metre of
+/// every channel
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interior {
+    pub albedo: Colour,
+    pub extinction: Colour,
+}
+
+
+impl Interior {
+    ///
+    /// How far a ray goes, in metres, before it scatters or
+    /// reaches the surface `limit` metres away, and how much
+    /// what it sees next is weighted by. The distance is drawn
+    /// for a random channel and weighted by the mean chance of
+    /// all three so every channel gets its own falloff
+    ///
+    pub fn step(self, limit: f32) -> (f32, Colour) {
+        let extinction = [self.extinction.x, self.extinction.y, self.extinction.z];
+        let channel = ((next_f32() * 3.0) as usize).min(2);
+        let distance = -(1.0 - next_f32()).ln() / extinction[channel];
+
+        let transmittance = |d: f32| Colour::new((-extinction[0] * d).exp(), (-extinction[1] * d).exp(), (-extinction[2] * d).exp());
+        let mean = |c: Colour| (c.x + c.y + c.z) / 3.0;
+
+        if distance < limit {
+            let density = self.extinction * transmittance(distance);
+            let weight = self.albedo * density / mean(density);
+            return (distance, weight)
+        }
+
+        let survived = transmittance(limit);
+        (limit, survived / mean(survived))
+    }
+}
+
+
+///
+/// The albedo of a single scattering which a random walk
+/// needs for the surface to end up `colour` after many
+/// of them, from Chiang et al. 2016
+///
+fn single_scattering_albedo(colour: f32) -> f32 {
+    let colour = colour.clamp(0.0, 1.0);
+    1.0 - (4.09712 + 4.20863 * colour - (9.59217 + 41.6808 * colour + 17.7126 * colour * colour).sqrt()).powi(2)
+}
+
+
+///
+/// Refracts or reflects `ray_in` on a dielectric surface
+/// with the given (possibly perturbed) `normal`
+///
+fn refract_or_reflect(ray_in: Ray, rec: &HitRecord, normal: Vec3, refraction_index: f32) -> Ray {
+    let (direction, _) = choose_lobe(ray_in.direction.unit(), rec.front_face, normal, refraction_index);
+    Ray::new(rec.point, direction, ray_in.time)
+}
+
+
+///
+/// Picks between the reflected and refracted direction of `unit_dir`
+/// by the Fresnel reflectance, returns whether it reflected
+///
+fn choose_lobe(unit_dir: Vec3, front_face: bool, normal: Vec3, refraction_index: f32) -> (Vec3, bool) {
+    let refraction_ratio = if front_face { 1.0 / refraction_index }
+                           else { refraction_index };
+
+    let cos_theta = (-unit_dir).dot(normal).min(1.0);
+    let sin_theta = (1.0 - cos_theta*cos_theta).sqrt();
+
+    let cannot_refract = refraction_ratio * sin_theta > 1.0;
+    if cannot_refract || reflectance(cos_theta, refraction_ratio) > next_f32() {
+        (unit_dir.reflect(normal), true)
+    } else {
+        (unit_dir.refract(normal, refraction_ratio), false)
+    }
+}
+
+
+///
+/// Tilts `normal` by the slope of a sum of sine waves
+/// running over the xz plane. Only faces pointing up
+/// or down are affected
+///
+fn wave_normal(normal: Vec3, p: Point, height: f32, time: f32) -> Vec3 {
+    let mut slope_x = 0.0;
+    let mut slope_z = 0.0;
+    for (dir_x, dir_z, frequency, speed) in WAVES {
+        let phase = frequency * (dir_x * p.x + dir_z * p.z) + speed * time;
+        let slope = height * frequency * phase.cos();
+        slope_x += slope * dir_x;
+        slope_z += slope * dir_z;
+    }
+
+    (normal - normal.y * Vec3::new(slope_x, 0.0, slope_z)).unit()
+}
+
+
+///
+/// The density over solid angle of the Henyey-Greenstein phase
+/// function at an angle with a cosine of `cos_theta` from the
+/// way the light was going
+///
+fn henyey_greenstein(cos_theta: f32, anisotropy: f32) -> f32 {
+    let g = anisotropy;
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.max(0.0).sqrt())
+}
+
+
+///
+/// A direction drawn from the Henyey-Greenstein phase function
+/// around `forward`, which should have unit length
+///
+fn sample_henyey_greenstein(forward: Vec3, anisotropy: f32) -> Vec3 {
+    let g = anisotropy;
+    let cos_theta = if g.abs() < MIN_ANISOTROPY { 1.0 - 2.0 * next_f32() }
+                    else {
+                        let square = (1.0 - g * g) / (1.0 - g + 2.0 * g * next_f32());
+                        ((1.0 + g * g - square * square) / (2.0 * g)).clamp(-1.0, 1.0)
+                    };
+
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * next_f32();
+    let (tangent, bitangent) = tangent_frame(forward);
+    sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * forward
+}
+
+
+///
+/// Two unit vectors which make an orthonormal basis with `normal`,
+/// from "Building an Orthonormal Basis, Revisited" (Duff et al.)
+///
+fn tangent_frame(normal: Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(normal.z);
+    let a = -1.0 / (sign + normal.z);
+    let b = normal.x * normal.y * a;
+
+    let tangent = Vec3::new(1.0 + sign * normal.x * normal.x * a, sign * b, -sign * normal.x);
+    let bitangent = Vec3::new(b, sign + normal.y * normal.y * a, -normal.y);
+    (tangent, bitangent)
+}
+
+
+///
+/// The tangent frame of `rec` with the tangent turned to be at a
+/// right angle to the normal, or any frame if it has no tangent.
+/// The bitangent goes the way of `rec.bitangent` if it has one
+///
+fn shading_frame(rec: &HitRecord) -> (Vec3, Vec3) {
+    let tangent = rec.tangent - rec.tangent.dot(rec.normal) * rec.normal;
+    if tangent.near_zero() { return tangent_frame(rec.normal) }
+
+    let tangent = tangent.unit();
+    let bitangent = rec.normal.cross(tangent);
+    if rec.bitangent.dot(bitangent) < 0.0 { (tangent, -bitangent) } else { (tangent, bitangent) }
+}
+
+
+///
+/// Picks a microfacet normal as seen from `outgoing`, both in the
+/// space where the surface normal is z. `alpha` is the roughness
+/// along x and y. From "Sampling the GGX Distribution of Visible
+/// Normals" (Heitz 2018)
+///
+fn sample_ggx_visible_normal(outgoing: Vec3, alpha: (f32, f32)) -> Vec3 {
+    ggx_visible_normal(outgoing, alpha, next_f32(), next_f32())
+}
+
+
+///
+/// The visible normal `sample_ggx_visible_normal` picks
+/// for the random numbers `u` and `v` in 0 to 1
+///
+fn ggx_visible_normal(outgoing: Vec3, (alpha_x, alpha_y): (f32, f32), u: f32, v: f32) -> Vec3 {
+    // stretch the view so the distribution becomes a hemisphere
+    let view = Vec3::new(alpha_x * outgoing.x, alpha_y * outgoing.y, outgoing.z).unit();
+
+    let length_squared = view.x * view.x + view.y * view.y;
+    let t1 = if length_squared > 0.0 { Vec3::new(-view.y, view.x, 0.0) / length_squared.sqrt() }
+             else { Vec3::new(1.0, 0.0, 0.0) };
+    let t2 = view.cross(t1);
+
+    // a point on the disk, squashed onto the visible half
+    let r = u.sqrt();
+    let phi = 2.0 * std::f32::consts::PI * v;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + view.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+    let normal = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * view;
+
+    // and unstretch it
+    Vec3::new(alpha_x * normal.x, alpha_y * normal.y, normal.z.max(0.0)).unit()
+}
+
+
+///
+/// The density of a GGX metal's `scatter` going out along `incoming`,
+/// both in the space where the surface normal is z
+///
+fn ggx_metal_pdf(outgoing: Vec3, incoming: Vec3, alpha: (f32, f32)) -> f32 {
+    if outgoing.z <= 0.0 || incoming.z <= 0.0 { return 0.0 }
+
+    // the visible normals are sampled and each reflects into
+    // a direction with a quarter of its density over the angle
+    let facet = (outgoing + incoming).unit();
+    let single = smith_g1(outgoing, alpha) * ggx_distribution(facet, alpha) / (4.0 * outgoing.z);
+
+    let p = ggx_albedo().directional(outgoing.z, alpha);
+    p * single + (1.0 - p) * incoming.z / PI
+}
+
+
+///
+/// The light a GGX metal which reflects `f0` head-on sends from
+/// `incoming` to `outgoing` times the cosine of `incoming`, both
+/// in the space where the surface normal is z
+///
+fn ggx_metal_eval(outgoing: Vec3, incoming: Vec3, alpha: (f32, f32), f0: Colour) -> Colour {
+    if outgoing.z <= 0.0 || incoming.z <= 0.0 { return Colour::ZERO }
+
+    let facet = (outgoing + incoming).unit();
+    let fresnel = f0 + (1.0 - outgoing.dot(facet)).powi(5) * (Colour::ONE - f0);
+
+    // the cosine of the incoming light cancels out
+    let masking = smith_g1(outgoing, alpha) * smith_g1(incoming, alpha);
+    let single = ggx_distribution(facet, alpha) * masking / (4.0 * outgoing.z) * fresnel;
+
+    single + ggx_multiple_scattering(outgoing, incoming, alpha, f0)
+}
+
+
+///
+/// The light a GGX surface reflects after bouncing between its
+/// facets more than once, times the cosine of `incoming`. The
+/// microfacet model only has the first bounce, which loses more
+/// of the light the rougher the surface is, this gives it back
+/// as a diffuse lobe with the energy compensation of "Revisiting
+/// Physically Based Shading at Imageworks" (Kulla and Conty 2017)
+///
+fn ggx_multiple_scattering(outgoing: Vec3, incoming: Vec3, alpha: (f32, f32), f0: Colour) -> Colour {
+    let table = ggx_albedo();
+    let average = table.average(alpha);
+    if average >= 1.0 { return Colour::ZERO }
+
+    let lost = (1.0 - table.directional(outgoing.z, alpha)) * (1.0 - table.directional(incoming.z, alpha));
+    let lobe = lost / (PI * (1.0 - average)) * incoming.z;
+
+    // every bounce is tinted by the average fresnel term again
+    let fresnel = |f0: f32| {
+        let mean = f0 + (1.0 - f0) / 21.0;
+        mean * mean * average / (1.0 - mean * (1.0 - average))
+    };
+
+    lobe * Colour::new(fresnel(f0.x), fresnel(f0.y), fresnel(f0.z))
+}
+
+
+///
+/// How much light a GGX surface with a fresnel term of 1 reflects
+/// with a single bounce, tabled over the cosine of the direction
+/// it's seen from and the roughness, which is its alpha's root
+///
+struct GgxAlbedo {
+    /// `[roughness][cosine]`
+    directional: [[f32; GGX_ALBEDO_SIZE]; GGX_ALBEDO_SIZE],
+    /// over every direction it's seen from, weighted by the cosine
+    average: [f32; GGX_ALBEDO_SIZE],
+}
+
+
+impl GgxAlbedo {
+    fn new() -> Self {
+        let step = 1.0 / (GGX_ALBEDO_SIZE - 1) as f32;
+        let mut directional = [[0.0; GGX_ALBEDO_SIZE]; GGX_ALBEDO_SIZE];
+        let mut average = [0.0; GGX_ALBEDO_SIZE];
+
+        for (j, row) in directional.iter_mut().enumerate() {
+            let alpha = ggx_alpha(j as f32 * step);
+            let alpha = (alpha, alpha);
+
+            for (i, albedo) in row.iter_mut().enumerate() {
+                let cos = (i as f32 * step).max(GGX_ALBEDO_MIN_COS);
+                let outgoing = Vec3::new((1.0 - cos * cos).sqrt(), 0.0, cos);
+
+                // sampling the visible normals leaves only the
+                // masking of the reflection, over a regular grid
+                let mut total = 0.0;
+                for a in 0..GGX_ALBEDO_SAMPLES {
+                    for b in 0..GGX_ALBEDO_SAMPLES {
+                        let u = (a as f32 + 0.5) / GGX_ALBEDO_SAMPLES as f32;
+                        let v = (b as f32 + 0.5) / GGX_ALBEDO_SAMPLES as f32;
+                        let incoming = reflect_on_facet(outgoing, ggx_visible_normal(outgoing, alpha, u, v));
+                        if incoming.z > 0.0 { total += smith_g1(incoming, alpha) }
+                    }
+                }
+
+                *albedo = total / (GGX_ALBEDO_SAMPLES * GGX_ALBEDO_SAMPLES) as f32;
+            }
+
+            // 2 times the integral of the albedo times the cosine
+            average[j] = (1..GGX_ALBEDO_SIZE).map(|i| {
+                let (a, b) = ((i - 1) as f32 * step, i as f32 * step);
+                (row[i - 1] * a + row[i] * b) * step
+            }).sum::<f32>().min(1.0);
+        }
+
+        Self { directional, average }
+    }
+
+
+    fn directional(&self, cos: f32, alpha: (f32, f32)) -> f32 {
+        let (j, s) = Self::cell(Self::roughness(alpha));
+        let (i, t) = Self::cell(cos);
+        let row = |j: usize| (1.0 - t) * self.directional[j][i] + t * self.directional[j][i + 1];
+        (1.0 - s) * row(j) + s * row(j + 1)
+    }
+
+
+    fn average(&self, alpha: (f32, f32)) -> f32 {
+        let (j, s) = Self::cell(Self::roughness(alpha));
+        (1.0 - s) * self.average[j] + s * self.average[j + 1]
+    }
+
+
+    ///
+    /// Anisotropic surfaces are looked up
+    /// by the mean of their two roughnesses
+    ///
+    fn roughness((alpha_x, alpha_y): (f32, f32)) -> f32 {
+        (alpha_x * alpha_y).sqrt().sqrt()
+    }
+
+
+    ///
+    /// The index of the entry at or below `x` in 0 to
+    /// 1 and how far it is on towards the next one
+    ///
+    fn cell(x: f32) -> (usize, f32) {
+        let x = x.clamp(0.0, 1.0) * (GGX_ALBEDO_SIZE - 1) as f32;
+        let i = (x as usize).min(GGX_ALBEDO_SIZE - 2);
+        (i, x - i as f32)
+    }
+}
+
+
+///
+/// The table is worked out the first time it's needed
+///
+fn ggx_albedo() -> &'static GgxAlbedo {
+    static ALBEDO : OnceLock<GgxAlbedo> = OnceLock::new();
+    ALBEDO.get_or_init(GgxAlbedo::new)
+}
+
+
+///
+/// Mirrors `outgoing` on a microfacet with the normal `facet`
+///
+fn reflect_on_facet(outgoing: Vec3, facet: Vec3) -> Vec3 {
+    2.0 * outgoing.dot(facet) * facet - outgoing
+}
+
+
+///
+/// How much of the microfacets is visible from `direction`
+/// with Smith's masking function for GGX
+///
+fn smith_g1(direction: Vec3, (alpha_x, alpha_y): (f32, f32)) -> f32 {
+    let x = alpha_x * direction.x;
+    let y = alpha_y * direction.y;
+    let lambda = 0.5 * (-1.0 + (1.0 + (x * x + y * y) / (direction.z * direction.z)).sqrt());
+    1.0 / (1.0 + lambda)
+}
+
+
+///
+/// The density of microfacet normals pointing along `facet`,
+/// in the space where the surface normal is z
+///
+fn ggx_distribution(facet: Vec3, (alpha_x, alpha_y): (f32, f32)) -> f32 {
+    let x = facet.x / alpha_x;
+    let y = facet.y / alpha_y;
+    let d = x * x + y * y + facet.z * facet.z;
+    1.0 / (PI * alpha_x * alpha_y * d * d)
+}
+
+
+///
+/// Roughness is squared so it looks about linear
+///
+fn ggx_alpha(roughness: f32) -> f32 {
+    (roughness * roughness).clamp(MIN_GGX_ALPHA, 1.0)
+}
+
+
+///
+/// The directions away from the surface towards where `ray_in`
+/// came from and towards `direction`, in the space of the
+/// shading frame where the normal is z
+///
+fn ggx_directions(ray_in: Ray, rec: &HitRecord, direction: Vec3) -> (Vec3, Vec3) {
+    let (tangent, bitangent) = shading_frame(rec);
+    let to_local = |v: Vec3| Vec3::new(v.dot(tangent), v.dot(bitangent), v.dot(rec.normal));
+    (to_local(-ray_in.direction.unit()), to_local(direction.unit()))
+}
+
+
+///
+/// Schlick's approximation of the reflectance
+/// at `cos` of a surface which reflects `f0` head-on
+///
+fn schlick(f0: f32, cos: f32) -> f32 {
+    f0 + (1.0 - f0) * (1.0 - cos).clamp(0.0, 1.0).powi(5)
+}
+
+
+///
+/// How much light of `wavelength` a film `thickness` thick
+/// reflects, with the Airy sum of the light bouncing between
+/// its faces. `cos_theta` is of the angle the light comes in at
+/// through the medium of index `outside`, `film` and `behind`
+/// are the indices of the film and what's under it. Only the
+/// s polarised part is worked out to keep it cheap
+///
+fn film_reflectance(cos_theta: f32, outside: f32, film: f32, behind: f32, thickness: f32, wavelength: f32) -> f32 {
+    let sin_squared = 1.0 - cos_theta * cos_theta;
+    let cos_film = (1.0 - sin_squared * (outside / film).powi(2)).max(0.0).sqrt();
+    let cos_behind_squared = 1.0 - sin_squared * (outside / behind).powi(2);
+
+    let r12 = (outside * cos_theta - film * cos_film) / (outside * cos_theta + film * cos_film);
+    // past the critical angle everything is reflected back into the film
+    let r23 = if cos_behind_squared <= 0.0 { 1.0 } else {
+        let cos_behind = cos_behind_squared.sqrt();
+        (film * cos_film - behind * cos_behind) / (film * cos_film + behind * cos_behind)
+    };
+
+    let phase = 4.0 * std::f32::consts::PI * film * thickness * cos_film / wavelength;
+    let interference = 2.0 * r12 * r23 * phase.cos();
+    ((r12 * r12 + r23 * r23 + interference) / (1.0 + r12 * r12 * r23 * r23 + interference)).clamp(0.0, 1.0)
 }
 
 
@@ -77,3 +1103,122 @@ fn reflectance(cos: f32, rr: f32) -> f32 {
     let r0 = r0*r0;
     r0 + (1.0-r0)*(1.0-cos).powi(5)
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thin_film_reflectance() {
+        // without a film it's the fresnel term of glass
+        let bare = film_reflectance(1.0, 1.0, 1.38, 1.5, 0.0, 550.0);
+        assert!((bare - 0.04).abs() < 1e-4, "{bare}");
+
+        // a quarter wave coating cancels most of it out
+        let quarter_wave = 550.0 / (4.0 * 1.38);
+        let coated = film_reflectance(1.0, 1.0, 1.38, 1.5, quarter_wave, 550.0);
+        let expected = ((1.5f32 - 1.38 * 1.38) / (1.5 + 1.38 * 1.38)).powi(2);
+        assert!((coated - expected).abs() < 1e-4, "{coated} {expected}");
+
+        // a soap film changes colour with its thickness
+        let bubble = |thickness| FILM_WAVELENGTHS.map(|wavelength| film_reflectance(1.0, 1.0, 1.33, 1.0, thickness, wavelength));
+        assert_ne!(bubble(300.0), bubble(400.0));
+    }
+
+
+    #[test]
+    fn subsurface_walk() {
+        assert!(single_scattering_albedo(0.0).abs() < 1e-4);
+        assert!((single_scattering_albedo(1.0) - 1.0).abs() < 1e-4);
+
+        // on average each channel scatters as often as its own
+        // extinction says even though distances come from all three
+        let interior = Interior { albedo: Colour::new(1.0, 0.5, 0.25), extinction: Colour::new(1.0, 4.0, 16.0) };
+        let limit = 0.5;
+        let steps = 100_000;
+        let mut scattered = Colour::ZERO;
+        for _ in 0..steps {
+            let (distance, weight) = interior.step(limit);
+            if distance < limit { scattered += weight }
+        }
+
+        let scattered = scattered / steps as f32;
+        let expected = interior.albedo * Colour::new(1.0 - (-0.5f32).exp(), 1.0 - (-2.0f32).exp(), 1.0 - (-8.0f32).exp());
+        assert!((scattered - expected).length() < 0.02, "{scattered:?} {expected:?}");
+    }
+
+
+    #[test]
+    fn samples_match_their_density() {
+        let rec = HitRecord { normal: Vec3::new(0.0, 1.0, 0.0), tangent: Vec3::new(1.0, 0.0, 0.0), front_face: true, ..HitRecord::default() };
+        let ray_in = Ray::new(Point::new(-1.0, 1.0, 0.3), Vec3::new(1.0, -1.0, -0.3), 0.0);
+
+        let red = Texture::SolidColour(Colour::new(0.9, 0.2, 0.1));
+        let lambertian = Material::Lambertian { texture: red };
+        let materials = [
+            lambertian,
+            Material::GgxMetal { texture: red, roughness: 0.5 },
+            Material::AnisotropicMetal { texture: red, roughness_u: 0.2, roughness_v: 0.7 },
+            Material::Isotropic { albedo: Colour::new(0.5, 0.5, 0.5), emission: Colour::ZERO, anisotropy: 0.0 },
+            Material::Isotropic { albedo: Colour::new(0.5, 0.5, 0.5), emission: Colour::ZERO, anisotropy: 0.7 },
+            Material::AlphaMasked { material: &lambertian, alpha: &red, cutoff: Some(0.0) },
+        ];
+
+        for material in materials {
+            for _ in 0..100 {
+                let Some(sample) = material.sample(ray_in, &rec) else { continue };
+                let pdf = sample.pdf().unwrap();
+                let eval = material.eval(ray_in, &rec, sample.ray.direction).unwrap();
+                assert!((sample.weight - eval / pdf).length() < 1e-3, "{:?} {:?}", sample.weight, eval / pdf);
+            }
+
+            // over every direction it adds up to at most one, less
+            // where reflections into the surface are thrown away
+            let directions = 20_000;
+            let total = (0..directions).map(|_| material.pdf(ray_in, &rec, Vec3::random_unit()).unwrap()).sum::<f32>()
+                        * 4.0 * PI / directions as f32;
+            assert!((0.5..1.05).contains(&total), "{total}");
+        }
+
+        let glass = Material::Dielectric { refraction_index: 1.5, thin_walled: false, absorption: Colour::ZERO, texture: red };
+        assert!(glass.sample(ray_in, &rec).unwrap().pdf().is_none());
+    }
+
+
+    #[test]
+    fn rough_white_metal_passes_the_furnace() {
+        // a metal which reflects everything head-on keeps all of the
+        // light however rough it is, once the bounces between the
+        // facets are counted as well
+        let rec = HitRecord { normal: Vec3::new(0.0, 0.0, 1.0), tangent: Vec3::new(1.0, 0.0, 0.0), front_face: true, ..HitRecord::default() };
+        let white = Texture::SolidColour(Colour::ONE);
+
+        for roughness in [0.2, 0.6, 1.0] {
+            for cos in [0.2, 0.6, 1.0f32] {
+                let ray_in = Ray::new(Point::ZERO, Vec3::new((1.0 - cos * cos).sqrt(), 0.0, -cos), 0.0);
+                let metal = Material::GgxMetal { texture: white, roughness };
+
+                let samples = 20_000;
+                let total = (0..samples).filter_map(|_| metal.sample(ray_in, &rec)).map(|s| s.weight.x).sum::<f32>();
+                let albedo = total / samples as f32;
+                assert!((albedo - 1.0).abs() < 0.03, "{roughness} {cos} {albedo}");
+            }
+        }
+    }
+
+
+    #[test]
+    fn media_scatter_by_their_anisotropy() {
+        let rec = HitRecord::default();
+        let ray_in = Ray::new(Point::ZERO, Vec3::new(0.0, 0.0, 2.0), 0.0);
+
+        // the mean cosine of the Henyey-Greenstein phase function is g
+        for anisotropy in [-0.5, 0.0, 0.8] {
+            let fog = Material::Isotropic { albedo: Colour::ONE, emission: Colour::ZERO, anisotropy };
+            let samples = 20_000;
+            let mean = (0..samples).map(|_| fog.sample(ray_in, &rec).unwrap().ray.direction.unit().z).sum::<f32>() / samples as f32;
+            assert!((mean - anisotropy).abs() < 0.02, "{anisotropy} {mean}");
+        }
+    }
+}
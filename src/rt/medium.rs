@@ -0,0 +1,272 @@
+use std::cell::RefCell;
+
+use sti::arena::Arena;
+
+use crate::{math::{aabb::AABB, interval::Interval, ray::Ray, vec3::{Colour, Point}}, rng::next_f32};
+
+use super::{blackbody::blackbody, hittable::Hittable, materials::Material, texture::Texture};
+
+///
+/// The density of a medium at a point,
+/// it must never go over the medium's `max_density`
+///
+pub type DensityFn<'a> = &'a (dyn Fn(Point) -> f32 + Sync);
+
+///
+/// The radiance a medium adds at each collision at a point
+///
+pub type EmissionFn<'a> = &'a (dyn Fn(Point) -> Colour + Sync);
+
+const FIRE_IGNITION : f32 = 800.0;
+const FIRE_PEAK : f32 = 1800.0;
+const FIRE_DENSITY : f32 = 4.0;
+const FIRE_ALBEDO : Colour = Colour::new(0.2, 0.2, 0.2);
+const MAX_ANISOTROPY : f32 = 0.99;
+
+
+///
+/// A heterogeneous participating medium filling the inside of
+/// `boundary`. Collisions are sampled with delta tracking so
+/// every hit lands at a random distance into the medium.
+/// That also means a medium can't be put inside of a transform,
+/// as the transform has to find its hit a second time,
+/// transform the boundary instead
+///
+pub struct Medium<'a> {
+    boundary: Hittable<'a>,
+    density: DensityFn<'a>,
+    max_density: f32,
+    albedo: Colour,
+    emission: Option<EmissionFn<'a>>,
+    /// the Henyey-Greenstein `g` of every collision, 0 scatters
+    /// evenly and towards 1 mostly keeps going the same way
+    anisotropy: f32,
+}
+
+
+impl<'a> Medium<'a> {
+    pub fn new(boundary: Hittable<'a>, density: DensityFn<'a>, max_density: f32,
+               albedo: Colour, emission: Option<EmissionFn<'a>>) -> Self {
+        Self { boundary, density, max_density, albedo, emission, anisotropy: 0.0 }
+    }
+
+
+    ///
+    /// Makes the medium glow with `texture` looked up at every
+    /// point in it, times `strength`. Like the fire the light is
+    /// given off at each collision, so over the many collisions
+    /// of a ray the whole stretch it goes through adds up. Dense
+    /// parts glow through more collisions than thin ones, so the
+    /// glow follows the density as well
+    ///
+    pub fn with_emission(mut self, arena: &'a Arena, texture: Texture<'a>, strength: f32) -> Self {
+        self.emission = Some(arena.alloc_new(move |p: Point| strength * texture.value(0.0, 0.0, p)));
+        self
+    }
+
+
+    ///
+    /// Makes the medium scatter forwards for an `anisotropy`
+    /// above 0 or backwards below it, it's kept within -1 and 1
+    /// which would only ever scatter one way
+    ///
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = anisotropy.clamp(-MAX_ANISOTROPY, MAX_ANISOTROPY);
+        self
+    }
+
+
+    ///
+    /// A fire whose density and blackbody emission follow `temperatures`.
+    /// Anything colder than `FIRE_IGNITION` kelvin is empty and
+    /// `intensity` scales how bright the hottest parts are
+    ///
+    pub fn fire(arena: &'a Arena, temperatures: &'a TemperatureGrid<'a>, intensity: f32) -> Self {
+        let heat = |kelvin: f32| Interval::new(0.0, 1.0).clamp((kelvin - FIRE_IGNITION) / (FIRE_PEAK - FIRE_IGNITION));
+
+        let density = arena.alloc_new(move |p: Point| FIRE_DENSITY * heat(temperatures.sample(p)));
+        let emission = arena.alloc_new(move |p: Point| {
+            let kelvin = temperatures.sample(p);
+            if kelvin <= FIRE_IGNITION { return Colour::ZERO }
+
+            // radiance goes up with the fourth power of the temperature
+            let brightness = intensity * (kelvin / FIRE_PEAK).powi(4);
+            brightness * blackbody(kelvin)
+        });
+
+        let boundary = Hittable::axis_aligned_box(temperatures.bounds.min_corner(), temperatures.bounds.max_corner(), Material::default());
+        Self::new(boundary, density, FIRE_DENSITY, FIRE_ALBEDO, Some(emission))
+    }
+
+
+    pub fn boundary(&self) -> &Hittable<'a> {
+        &self.boundary
+    }
+
+
+    pub fn max_density(&self) -> f32 {
+        self.max_density
+    }
+
+
+    pub fn emission(&self) -> Option<EmissionFn<'a>> {
+        self.emission
+    }
+
+
+    ///
+    /// Returns the `t` of the next collision along the ray, if any
+    ///
+    pub fn sample_collision(&self, ray: Ray, t: Interval) -> Option<f32> {
+        thread_local! {
+            // reused between rays so the boundary's spans don't allocate
+            static SPANS : RefCell<Vec<Interval>> = const { RefCell::new(Vec::new()) };
+        }
+
+        SPANS.with_borrow_mut(|spans| {
+            spans.clear();
+            self.boundary.hit_all(ray, t, spans);
+            spans.sort_unstable_by(|a, b| a.min.total_cmp(&b.min));
+            self.collide_in(ray, t, spans)
+        })
+    }
+
+
+    fn collide_in(&self, ray: Ray, t: Interval, spans: &[Interval]) -> Option<f32> {
+        let inv_step = 1.0 / (self.max_density * ray.direction.length());
+        let mut covered = t.min;
+        for span in spans {
+            let mut t = span.min.max(covered);
+
+            loop {
+                t -= (1.0 - next_f32()).ln() * inv_step;
+                if t >= span.max { break }
+
+                // a null collision unless the density says otherwise
+                if next_f32() * self.max_density < (self.density)(ray.at(t)) {
+                    return Some(t);
+                }
+            }
+
+            covered = covered.max(span.max);
+        }
+
+        None
+    }
+
+
+    pub fn material_at(&self, p: Point) -> Material<'a> {
+        let emission = match self.emission {
+            Some(emission) => emission(p),
+            None => Colour::ZERO,
+        };
+
+        Material::Isotropic { albedo: self.albedo, emission, anisotropy: self.anisotropy }
+    }
+}
+
+
+///
+/// Temperatures in kelvin sampled on a regular grid over `bounds`
+///
+pub struct TemperatureGrid<'a> {
+    bounds: AABB,
+    resolution: [usize; 3],
+    values: &'a [f32],
+}
+
+
+impl<'a> TemperatureGrid<'a> {
+    ///
+    /// Fills the grid by calling `temperature` at the centre of every cell
+    ///
+    pub fn new<F: Fn(Point) -> f32>(arena: &'a Arena, bounds: AABB, resolution: [usize; 3], temperature: F) -> Self {
+        let min = bounds.min_corner();
+        let size = bounds.max_corner() - min;
+
+        let mut values = sti::vec::Vec::with_cap_in(arena, resolution[0] * resolution[1] * resolution[2]);
+        for z in 0..resolution[2] {
+            for y in 0..resolution[1] {
+                for x in 0..resolution[0] {
+                    let cell = Point::new((x as f32 + 0.5) / resolution[0] as f32,
+                                          (y as f32 + 0.5) / resolution[1] as f32,
+                                          (z as f32 + 0.5) / resolution[2] as f32);
+                    values.push(temperature(min + size * cell));
+                }
+            }
+        }
+
+        Self { bounds, resolution, values: values.leak() }
+    }
+
+
+    pub fn bounds(&self) -> &AABB {
+        &self.bounds
+    }
+
+
+    ///
+    /// Trilinearly interpolates the temperature at `p`,
+    /// everything outside of the grid is at zero kelvin
+    ///
+    pub fn sample(&self, p: Point) -> f32 {
+        let min = self.bounds.min_corner();
+        let size = self.bounds.max_corner() - min;
+
+        let mut base = [0; 3];
+        let mut frac = [0.0; 3];
+        for axis in 0..3 {
+            let cell = (p[axis] - min[axis]) / size[axis] * self.resolution[axis] as f32 - 0.5;
+            if cell < -0.5 || cell > self.resolution[axis] as f32 - 0.5 { return 0.0 }
+
+            let cell = cell.clamp(0.0, (self.resolution[axis] - 1) as f32);
+            base[axis] = (cell as usize).min(self.resolution[axis].saturating_sub(2));
+            frac[axis] = cell - base[axis] as f32;
+        }
+
+        let value = |x: usize, y: usize, z: usize| {
+            let x = (base[0] + x).min(self.resolution[0] - 1);
+            let y = (base[1] + y).min(self.resolution[1] - 1);
+            let z = (base[2] + z).min(self.resolution[2] - 1);
+            self.values[(z * self.resolution[1] + y) * self.resolution[0] + x]
+        };
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let mut result = [[0.0; 2]; 2];
+        for (z, row) in result.iter_mut().enumerate() {
+            for (y, along_x) in row.iter_mut().enumerate() {
+                *along_x = lerp(value(0, y, z), value(1, y, z), frac[0]);
+            }
+        }
+
+        let near = lerp(result[0][0], result[0][1], frac[1]);
+        let far = lerp(result[1][0], result[1][1], frac[1]);
+        lerp(near, far, frac[2])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{math::vec3::Vec3, rng::Stream, rt::{sky::Sky, units::Units}};
+
+    use super::*;
+
+    #[test]
+    fn glowing_media_add_up_along_the_ray() {
+        let arena = Arena::new();
+        let boundary = Hittable::axis_aligned_box(Point::new(-0.5, -0.5, 0.0), Point::new(0.5, 0.5, 1.0), Material::default());
+        let density = arena.alloc_new(|_| 1.0);
+        let glow = Medium::new(boundary, density, 1.0, Colour::ZERO, None)
+            .with_emission(&arena, Texture::SolidColour(Colour::ONE), 2.0);
+        let world = Hittable::medium(arena.alloc_new(glow));
+
+        // a ray through one unit of it collides with a chance of 1 - 1/e
+        let ray = Ray::new(Point::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let samples = 20_000;
+        let total = (0..samples).map(|i| ray.colour(&world, &Sky::Solid(Colour::ZERO), Units::Metres, 4, 0, Stream::new(i)).x)
+            .sum::<f32>();
+        let expected = 2.0 * (1.0 - (-1.0f32).exp());
+        assert!((total / samples as f32 - expected).abs() < 0.05, "{}", total / samples as f32);
+    }
+}
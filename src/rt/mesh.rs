@@ -0,0 +1,822 @@
+use std::{collections::HashMap, fs, io};
+
+use sti::arena::Arena;
+
+use crate::math::{interval::Interval, ray::Ray, vec3::{Point, Vec3}};
+
+
+///
+/// An indexed triangle mesh. The vertex arrays are all indexed
+/// by the same vertex index, `normals`, `uvs` and `second_uvs`
+/// are either empty or as long as `positions`. `tangents` are
+/// there if both normals and uvs are, see `vertex_tangents`.
+/// The second uv set is for lightmaps and detail maps, textures
+/// use it through `Texture::SecondUvSet`
+///
+pub struct Mesh<'a> {
+    positions: &'a [Point],
+    normals: &'a [Vec3],
+    uvs: &'a [(f32, f32)],
+    second_uvs: &'a [(f32, f32)],
+    tangents: &'a [Tangent],
+    triangles: &'a [[u32; 3]],
+}
+
+
+///
+/// Which way `u` grows at a vertex at a right angle to its
+/// normal, and whether `v` grows along `normal × direction`
+/// (1) or against it (-1) like with mirrored uvs
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tangent {
+    pub direction: Vec3,
+    pub sign: f32,
+}
+
+
+///
+/// Where a ray hits a triangle, `(b1, b2)` are the barycentric
+/// weights of its second and third vertex
+///
+#[derive(Clone, Copy)]
+pub struct TriangleHit {
+    pub t: f32,
+    pub b1: f32,
+    pub b2: f32,
+}
+
+
+impl<'a> Mesh<'a> {
+    ///
+    /// With `smooth_normals` every vertex gets the area
+    /// weighted average normal of the triangles around it,
+    /// otherwise the triangles are shaded flat
+    ///
+    pub fn new(arena: &'a Arena, positions: &[Point], uvs: &[(f32, f32)],
+               triangles: &[[u32; 3]], smooth_normals: bool) -> Self {
+        let normals = if smooth_normals { smooth_vertex_normals(positions, triangles) }
+                      else { Vec::new() };
+
+        let tangents = vertex_tangents(positions, &normals, uvs, triangles);
+        Self {
+            positions: arena_slice(arena, positions),
+            normals: arena_slice(arena, &normals),
+            uvs: arena_slice(arena, uvs),
+            second_uvs: &[],
+            tangents: arena_slice(arena, &tangents),
+            triangles: arena_slice(arena, triangles),
+        }
+    }
+
+
+    pub fn load_obj(arena: &'a Arena, path: &str, smooth_normals: bool) -> io::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        Self::parse_obj(arena, &source, smooth_normals)
+    }
+
+
+    ///
+    /// Parses the `v`, `vt`, `vn` and `f` lines of a Wavefront OBJ,
+    /// polygons are split into fans of triangles. Normals in the
+    /// file are used as they are and `smooth_normals` only
+    /// applies to files without any
+    ///
+    pub fn parse_obj(arena: &'a Arena, source: &str, smooth_normals: bool) -> io::Result<Self> {
+        let mut file_positions = Vec::new();
+        let mut file_uvs = Vec::new();
+        let mut file_normals = Vec::new();
+
+        // every distinct position/uv/normal triple becomes a vertex
+        let mut vertex_of = HashMap::new();
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+        let mut triangles = Vec::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let error = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {message}", i + 1));
+            let mut parts = line.split_whitespace();
+
+            match parts.next() {
+                Some("v") => {
+                    let [x, y, z] = parse_floats(&mut parts).ok_or_else(|| error("invalid vertex position"))?;
+                    file_positions.push(Point::new(x, y, z));
+                },
+
+                Some("vt") => {
+                    let [u, v] = parse_floats(&mut parts).ok_or_else(|| error("invalid texture coordinate"))?;
+                    file_uvs.push((u, v));
+                },
+
+                Some("vn") => {
+                    let [x, y, z] = parse_floats(&mut parts).ok_or_else(|| error("invalid vertex normal"))?;
+                    // it'd turn into NaN once normalised
+                    let normal = Vec3::new(x, y, z);
+                    let length = normal.length();
+                    if !(length > 0.0 && length.is_finite()) { return Err(error("invalid vertex normal")) }
+                    file_normals.push(normal.unit());
+                },
+
+                Some("f") => {
+                    let mut face = Vec::new();
+                    for corner in parts {
+                        let mut indices = corner.split('/');
+                        let position = resolve_index(indices.next(), file_positions.len())
+                            .ok_or_else(|| error("invalid position index"))?;
+                        let uv = resolve_optional_index(indices.next(), file_uvs.len())
+                            .ok_or_else(|| error("invalid texture coordinate index"))?;
+                        let normal = resolve_optional_index(indices.next(), file_normals.len())
+                            .ok_or_else(|| error("invalid normal index"))?;
+
+                        let vertex = *vertex_of.entry((position, uv, normal)).or_insert_with(|| {
+                            positions.push(file_positions[position]);
+                            uvs.push(uv.map(|uv| file_uvs[uv]));
+                            normals.push(normal.map(|normal| file_normals[normal]));
+                            positions.len() as u32 - 1
+                        });
+
+                        face.push(vertex);
+                    }
+
+                    if face.len() < 3 { return Err(error("a face needs at least 3 vertices")) }
+                    for j in 1..face.len() - 1 {
+                        triangles.push([face[0], face[j], face[j + 1]]);
+                    }
+                },
+
+                _ => (),
+            }
+        }
+
+        // only keep the optional arrays if every vertex has them
+        let uvs = Vec::from_iter(uvs.iter().map_while(|uv| *uv));
+        let uvs = if uvs.len() == positions.len() { uvs } else { Vec::new() };
+
+        let normals = Vec::from_iter(normals.iter().map_while(|normal| *normal));
+        let normals = if normals.len() == positions.len() { normals }
+                      else if smooth_normals { smooth_vertex_normals(&positions, &triangles) }
+                      else { Vec::new() };
+
+        let tangents = vertex_tangents(&positions, &normals, &uvs, &triangles);
+        Ok(Self {
+            positions: arena_slice(arena, &positions),
+            normals: arena_slice(arena, &normals),
+            uvs: arena_slice(arena, &uvs),
+            second_uvs: &[],
+            tangents: arena_slice(arena, &tangents),
+            triangles: arena_slice(arena, &triangles),
+        })
+    }
+
+
+    ///
+    /// A unit sphere around the origin made of `segments` slices
+    /// around the y axis and `rings` stacked along it
+    ///
+    pub fn uv_sphere(arena: &'a Arena, segments: u32, rings: u32, smooth_normals: bool) -> Self {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        for ring in 0..=rings {
+            let theta = std::f32::consts::PI * ring as f32 / rings as f32;
+            // the repeated positions along the seam and at the
+            // poles have to be exactly the same for `subdivide`
+            let sin_theta = if ring == 0 || ring == rings { 0.0 } else { theta.sin() };
+            for segment in 0..=segments {
+                let phi = 2.0 * std::f32::consts::PI * (segment % segments) as f32 / segments as f32;
+                positions.push(Point::new(-sin_theta * phi.cos(), -theta.cos(), sin_theta * phi.sin()));
+                uvs.push((segment as f32 / segments as f32, 1.0 - ring as f32 / rings as f32));
+            }
+        }
+
+        let mut triangles = Vec::new();
+        let row = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = ring * row + segment;
+                let b = a + row;
+                // the poles only need one triangle per segment
+                if ring != 0 { triangles.push([a, a + 1, b]) }
+                if ring != rings - 1 { triangles.push([a + 1, b + 1, b]) }
+            }
+        }
+
+        // the seam and the poles repeat positions, weld them
+        // for the normals so the shading doesn't crease there
+        let mut mesh = Self::new(arena, &positions, &uvs, &triangles, false);
+        if smooth_normals {
+            let normals = Vec::from_iter(positions.iter().map(|p| p.unit()));
+            mesh.normals = arena_slice(arena, &normals);
+            mesh.tangents = arena_slice(arena, &vertex_tangents(&positions, &normals, &uvs, &triangles));
+        }
+
+        mesh
+    }
+
+
+    ///
+    /// Smooths the mesh with `levels` rounds of Loop subdivision,
+    /// every round splits each triangle into four. Vertices which
+    /// only differ by their uvs or normals are moved together so
+    /// seams stay closed, edges with only one triangle are kept
+    /// as creases. Meshes with normals get smooth normals again
+    ///
+    pub fn subdivide(&self, arena: &'a Arena, levels: u32) -> Self {
+        let mut positions = self.positions.to_vec();
+        let mut uvs = self.uvs.to_vec();
+        let mut second_uvs = self.second_uvs.to_vec();
+        let mut triangles = self.triangles.to_vec();
+
+        for _ in 0..levels {
+            // the new vertices come out in the same order
+            // so the second set can be split on its own
+            if !second_uvs.is_empty() { second_uvs = loop_subdivide(&positions, &second_uvs, &triangles).1 }
+            (positions, uvs, triangles) = loop_subdivide(&positions, &uvs, &triangles);
+        }
+
+        let normals = if self.normals.is_empty() { Vec::new() }
+                      else { welded_smooth_normals(&positions, &triangles) };
+        let tangents = vertex_tangents(&positions, &normals, &uvs, &triangles);
+
+        Self {
+            positions: arena_slice(arena, &positions),
+            normals: arena_slice(arena, &normals),
+            uvs: arena_slice(arena, &uvs),
+            second_uvs: arena_slice(arena, &second_uvs),
+            tangents: arena_slice(arena, &tangents),
+            triangles: arena_slice(arena, &triangles),
+        }
+    }
+
+
+    ///
+    /// Gives every vertex a second set of texture coordinates,
+    /// `uvs` has to be as long as the vertex arrays
+    ///
+    pub fn with_second_uvs(mut self, arena: &'a Arena, uvs: &[(f32, f32)]) -> Self {
+        assert_eq!(uvs.len(), self.positions.len(), "every vertex needs a second uv");
+        self.second_uvs = arena_slice(arena, uvs);
+        self
+    }
+
+
+    pub fn has_uvs(&self) -> bool {
+        !self.uvs.is_empty()
+    }
+
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+
+    pub fn vertices(&self, triangle: u32) -> [Point; 3] {
+        self.triangles[triangle as usize].map(|i| self.positions[i as usize])
+    }
+
+
+    ///
+    /// Möller-Trumbore ray/triangle intersection
+    ///
+    pub fn hit_triangle(&self, triangle: u32, ray: Ray, t: Interval) -> Option<TriangleHit> {
+        let [p0, p1, p2] = self.vertices(triangle);
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+
+        let p = ray.direction.cross(edge2);
+        let det = edge1.dot(p);
+        if det.abs() < 1e-12 { return None }
+
+        let inv_det = 1.0 / det;
+        let s = ray.origin - p0;
+        let b1 = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&b1) { return None }
+
+        let q = s.cross(edge1);
+        let b2 = ray.direction.dot(q) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 { return None }
+
+        let root = edge2.dot(q) * inv_det;
+        if !t.surrounds(root) { return None }
+
+        Some(TriangleHit { t: root, b1, b2 })
+    }
+
+
+    pub fn geometric_normal(&self, triangle: u32) -> Vec3 {
+        let [p0, p1, p2] = self.vertices(triangle);
+        (p1 - p0).cross(p2 - p0).unit()
+    }
+
+
+    ///
+    /// The interpolated vertex normal, or the
+    /// geometric one if the mesh is flat shaded
+    ///
+    pub fn shading_normal(&self, triangle: u32, b1: f32, b2: f32) -> Vec3 {
+        if self.normals.is_empty() { return self.geometric_normal(triangle) }
+
+        let [n0, n1, n2] = self.triangles[triangle as usize].map(|i| self.normals[i as usize]);
+        ((1.0 - b1 - b2) * n0 + b1 * n1 + b2 * n2).unit()
+    }
+
+
+    ///
+    /// The point at `(b1, b2)` pushed out to where the smooth
+    /// surface the vertex normals describe would roughly be, from
+    /// "Hacking the Shadow Terminator" (Hanika 2021). Rays leaving
+    /// from there don't get shadowed by the neighbouring triangles
+    /// like they do off of the flat triangle at the terminator of
+    /// smooth shaded low poly meshes. The point is only pushed
+    /// towards the side the normals are on and stays where it is
+    /// on flat shaded meshes
+    ///
+    pub fn terminator_point(&self, triangle: u32, b1: f32, b2: f32) -> Point {
+        let [p0, p1, p2] = self.vertices(triangle);
+        let point = (1.0 - b1 - b2) * p0 + b1 * p1 + b2 * p2;
+        if self.normals.is_empty() { return point }
+
+        // how far below the plane of each vertex normal the point is
+        let [n0, n1, n2] = self.triangles[triangle as usize].map(|i| self.normals[i as usize]);
+        let below = |vertex: Point, normal: Vec3| (point - vertex).dot(normal).min(0.0) * normal;
+        point - (1.0 - b1 - b2) * below(p0, n0) - b1 * below(p1, n1) - b2 * below(p2, n2)
+    }
+
+
+    ///
+    /// The interpolated texture coordinates, or the
+    /// barycentric weights if the mesh has none
+    ///
+    pub fn uv(&self, triangle: u32, b1: f32, b2: f32) -> (f32, f32) {
+        if self.uvs.is_empty() { return (b1, b2) }
+        self.interpolate_uv(self.uvs, triangle, b1, b2)
+    }
+
+
+    ///
+    /// The interpolated coordinates of the second
+    /// uv set, if the mesh has one
+    ///
+    pub fn second_uv(&self, triangle: u32, b1: f32, b2: f32) -> Option<(f32, f32)> {
+        if self.second_uvs.is_empty() { return None }
+        Some(self.interpolate_uv(self.second_uvs, triangle, b1, b2))
+    }
+
+
+    fn interpolate_uv(&self, uvs: &[(f32, f32)], triangle: u32, b1: f32, b2: f32) -> (f32, f32) {
+        let [uv0, uv1, uv2] = self.triangles[triangle as usize].map(|i| uvs[i as usize]);
+        let b0 = 1.0 - b1 - b2;
+        (b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0, b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1)
+    }
+
+
+    ///
+    /// Which way `u` of `uv` grows along the triangle,
+    /// the first edge if the mapping is degenerate
+    ///
+    pub fn tangent(&self, triangle: u32) -> Vec3 {
+        let vertices = self.vertices(triangle);
+        match uv_gradients(vertices, self.triangle_uvs(triangle)) {
+            Some((tangent, _)) => tangent,
+            None => vertices[1] - vertices[0],
+        }
+    }
+
+
+    ///
+    /// The interpolated vertex tangent and which way `v` grows,
+    /// or the triangle's tangent and no bitangent if the mesh
+    /// has no vertex tangents
+    ///
+    pub fn tangent_frame(&self, triangle: u32, b1: f32, b2: f32) -> (Vec3, Vec3) {
+        if self.tangents.is_empty() { return (self.tangent(triangle), Vec3::ZERO) }
+
+        let [t0, t1, t2] = self.triangles[triangle as usize].map(|i| self.tangents[i as usize]);
+        let tangent = (1.0 - b1 - b2) * t0.direction + b1 * t1.direction + b2 * t2.direction;
+        if tangent.near_zero() { return (self.tangent(triangle), Vec3::ZERO) }
+
+        // the sign is the same all over a triangle unless its
+        // vertices are shared with mirrored ones
+        let sign = if t0.sign + t1.sign + t2.sign < 0.0 { -1.0 } else { 1.0 };
+        let bitangent = sign * self.shading_normal(triangle, b1, b2).cross(tangent);
+        (tangent, bitangent)
+    }
+
+
+    fn triangle_uvs(&self, triangle: u32) -> Option<[(f32, f32); 3]> {
+        if self.uvs.is_empty() { return None }
+        Some(self.triangles[triangle as usize].map(|i| self.uvs[i as usize]))
+    }
+}
+
+
+///
+/// Which ways `u` and `v` grow along a triangle per unit of
+/// each, `None` without uvs or if they're degenerate
+///
+fn uv_gradients([p0, p1, p2]: [Point; 3], uvs: Option<[(f32, f32); 3]>) -> Option<(Vec3, Vec3)> {
+    let [uv0, uv1, uv2] = uvs?;
+    let (e1, e2) = (p1 - p0, p2 - p0);
+    let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+    let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+
+    let det = du1 * dv2 - du2 * dv1;
+    if det.abs() < f32::EPSILON { return None }
+    Some(((dv2 * e1 - dv1 * e2) / det, (du1 * e2 - du2 * e1) / det))
+}
+
+
+///
+/// Per vertex tangents like MikkTSpace makes them, so normal maps
+/// baked elsewhere line up. Each triangle adds its unit `u` and
+/// `v` directions to its vertices weighted by the angle at them,
+/// then the tangent is made a right angle to the vertex normal
+/// and the sign says which side the `v` directions ended up on.
+/// Empty unless there are both normals and uvs
+///
+fn vertex_tangents(positions: &[Point], normals: &[Vec3], uvs: &[(f32, f32)], triangles: &[[u32; 3]]) -> Vec<Tangent> {
+    if normals.is_empty() || uvs.is_empty() { return Vec::new() }
+
+    let mut sums = vec![(Vec3::ZERO, Vec3::ZERO); positions.len()];
+    for &[a, b, c] in triangles {
+        let corners = [a, b, c].map(|i| positions[i as usize]);
+        let Some((tangent, bitangent)) = uv_gradients(corners, Some([a, b, c].map(|i| uvs[i as usize])))
+        else { continue };
+        let (tangent, bitangent) = (tangent.unit(), bitangent.unit());
+
+        for (corner, i) in [a, b, c].into_iter().enumerate() {
+            let p = corners[corner];
+            let to_next = corners[(corner + 1) % 3] - p;
+            let to_prev = corners[(corner + 2) % 3] - p;
+            let cos = to_next.unit().dot(to_prev.unit()).clamp(-1.0, 1.0);
+            if cos.is_nan() { continue }
+
+            let angle = cos.acos();
+            sums[i as usize].0 += angle * tangent;
+            sums[i as usize].1 += angle * bitangent;
+        }
+    }
+
+    Vec::from_iter(sums.iter().zip(normals).map(|(&(tangent, bitangent), &normal)| {
+        let direction = tangent - tangent.dot(normal) * normal;
+        if direction.near_zero() { return Tangent { direction: Vec3::ZERO, sign: 1.0 } }
+
+        let direction = direction.unit();
+        let sign = if normal.cross(direction).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+        Tangent { direction, sign }
+    }))
+}
+
+
+///
+/// Sums the normals of the triangles around each vertex.
+/// The unnormalised cross product is twice the triangle's
+/// area, so bigger triangles weigh more
+///
+fn smooth_vertex_normals(positions: &[Point], triangles: &[[u32; 3]]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for &[a, b, c] in triangles {
+        let [p0, p1, p2] = [a, b, c].map(|i| positions[i as usize]);
+        let weighted = (p1 - p0).cross(p2 - p0);
+
+        for i in [a, b, c] {
+            normals[i as usize] += weighted;
+        }
+    }
+
+    for normal in &mut normals {
+        if *normal != Vec3::ZERO { *normal = normal.unit() }
+    }
+
+    normals
+}
+
+
+/// the positions, uvs and triangles of a mesh
+type Geometry = (Vec<Point>, Vec<(f32, f32)>, Vec<[u32; 3]>);
+/// what's kept for every edge, by its two vertices with the lower one first
+type EdgeMap<T> = HashMap<(u32, u32), T>;
+
+
+///
+/// One round of Loop subdivision, see `Mesh::subdivide`
+///
+fn loop_subdivide(positions: &[Point], uvs: &[(f32, f32)], triangles: &[[u32; 3]]) -> Geometry {
+    let (weld, welded_count) = weld_positions(positions);
+    let edge = |a: u32, b: u32| {
+        let (a, b) = (weld[a as usize], weld[b as usize]);
+        (a.min(b), a.max(b))
+    };
+
+    // the corners opposite of every welded edge
+    let mut opposite = EdgeMap::<Vec<u32>>::new();
+    for &[a, b, c] in triangles {
+        for (from, to, other) in [(a, b, c), (b, c, a), (c, a, b)] {
+            opposite.entry(edge(from, to)).or_default().push(other);
+        }
+    }
+
+    let mut welded = vec![Point::ZERO; welded_count];
+    for (i, &p) in positions.iter().enumerate() {
+        welded[weld[i] as usize] = p;
+    }
+
+    // the neighbours of every welded vertex, creases
+    // only count the neighbours along the crease
+    let mut neighbours = vec![Vec::new(); welded_count];
+    let mut crease_neighbours = vec![Vec::new(); welded_count];
+    for (&(a, b), others) in &opposite {
+        neighbours[a as usize].push(b);
+        neighbours[b as usize].push(a);
+        if others.len() != 2 {
+            crease_neighbours[a as usize].push(b);
+            crease_neighbours[b as usize].push(a);
+        }
+    }
+
+    let moved = Vec::from_iter((0..welded_count).map(|i| {
+        let p = welded[i];
+        if crease_neighbours[i].len() >= 2 {
+            let sum = crease_neighbours[i].iter().fold(Vec3::ZERO, |sum, &n| sum + welded[n as usize]);
+            return 0.75 * p + (0.25 / crease_neighbours[i].len() as f32) * sum;
+        }
+
+        let n = neighbours[i].len();
+        if n == 0 { return p }
+
+        let beta = if n == 3 { 3.0 / 16.0 } else { 3.0 / (8.0 * n as f32) };
+        let sum = neighbours[i].iter().fold(Vec3::ZERO, |sum, &n| sum + welded[n as usize]);
+        (1.0 - n as f32 * beta) * p + beta * sum
+    }));
+
+    let mut new_positions = Vec::from_iter((0..positions.len()).map(|i| moved[weld[i] as usize]));
+    let mut new_uvs = uvs.to_vec();
+    let mut new_triangles = Vec::with_capacity(triangles.len() * 4);
+
+    // every edge gets a new vertex, edges across a uv
+    // seam get one for each side at the same position
+    let mut midpoints = EdgeMap::new();
+    let mut midpoint = |a: u32, b: u32| *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+        let key = edge(a, b);
+        let ends = welded[key.0 as usize] + welded[key.1 as usize];
+        let others = &opposite[&key];
+        let p = if others.len() == 2 {
+            let wings = welded[weld[others[0] as usize] as usize] + welded[weld[others[1] as usize] as usize];
+            0.375 * ends + 0.125 * wings
+        } else { 0.5 * ends };
+
+        new_positions.push(p);
+        if !uvs.is_empty() {
+            let (ua, va) = uvs[a as usize];
+            let (ub, vb) = uvs[b as usize];
+            new_uvs.push((0.5 * (ua + ub), 0.5 * (va + vb)));
+        }
+
+        new_positions.len() as u32 - 1
+    });
+
+    for &[a, b, c] in triangles {
+        let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+        new_triangles.extend([[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+    }
+
+    (new_positions, new_uvs, new_triangles)
+}
+
+
+///
+/// Gives every vertex the index of the first
+/// vertex with exactly the same position
+///
+fn weld_positions(positions: &[Point]) -> (Vec<u32>, usize) {
+    let mut first = HashMap::new();
+    let weld = Vec::from_iter(positions.iter().map(|p| {
+        let count = first.len() as u32;
+        // adding zero turns -0 into 0
+        let bits = [p.x, p.y, p.z].map(|axis| (axis + 0.0).to_bits());
+        *first.entry(bits).or_insert(count)
+    }));
+
+    (weld, first.len())
+}
+
+
+///
+/// Like `smooth_vertex_normals` but vertices at
+/// the same position all get the same normal
+///
+fn welded_smooth_normals(positions: &[Point], triangles: &[[u32; 3]]) -> Vec<Vec3> {
+    let (weld, welded_count) = weld_positions(positions);
+    let mut welded = vec![Point::ZERO; welded_count];
+    for (i, &p) in positions.iter().enumerate() {
+        welded[weld[i] as usize] = p;
+    }
+
+    let welded_triangles = Vec::from_iter(triangles.iter().map(|t| t.map(|i| weld[i as usize])));
+    let normals = smooth_vertex_normals(&welded, &welded_triangles);
+    Vec::from_iter(weld.iter().map(|&i| normals[i as usize]))
+}
+
+
+fn arena_slice<'a, T: Copy>(arena: &'a Arena, items: &[T]) -> &'a [T] {
+    let mut vec = sti::vec::Vec::with_cap_in(arena, items.len());
+    for &item in items {
+        vec.push(item);
+    }
+
+    vec.leak()
+}
+
+
+fn parse_floats<'s, const N: usize>(parts: &mut impl Iterator<Item = &'s str>) -> Option<[f32; N]> {
+    let mut values = [0.0f32; N];
+    for value in &mut values {
+        *value = parts.next()?.parse().ok()?;
+        if !value.is_finite() { return None }
+    }
+
+    Some(values)
+}
+
+
+///
+/// Turns a one based (or negative, relative to
+/// the end) OBJ index into a zero based one
+///
+fn resolve_index(index: Option<&str>, len: usize) -> Option<usize> {
+    let index = index?.parse::<isize>().ok()?;
+    let index = if index < 0 { len as isize + index } else { index - 1 };
+    if index < 0 || index as usize >= len { return None }
+    Some(index as usize)
+}
+
+
+///
+/// Same as `resolve_index` but a missing or
+/// empty index isn't an error
+///
+fn resolve_optional_index(index: Option<&str>, len: usize) -> Option<Option<usize>> {
+    match index {
+        None | Some("") => Some(None),
+        index => resolve_index(index, len).map(Some),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obj_faces() {
+        let arena = Arena::new();
+        let source = "
+            v 0 0 0
+            v 1 0 0
+            v 1 0 1
+            v 0 0 1
+            vt 0 0
+            f 1/1 2/1 3/1 4/1
+            f -4 -2 -1
+        ";
+
+        let mesh = Mesh::parse_obj(&arena, source, true).unwrap();
+        assert_eq!(mesh.triangle_count(), 3);
+        // the uvs are dropped since the last face has none
+        assert!(mesh.uvs.is_empty());
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+
+        assert!(Mesh::parse_obj(&arena, "v 0 0 0\nf 1 2 3", false).is_err());
+        assert!(Mesh::parse_obj(&arena, "v 0 0\n", false).is_err());
+        assert!(Mesh::parse_obj(&arena, "vn 0 0 0\n", false).is_err());
+    }
+
+
+    #[test]
+    fn tangents_follow_u() {
+        let arena = Arena::new();
+        let source = "
+            v 0 0 0
+            v 0 0 2
+            v 1 0 0
+            vt 0 0
+            vt 1 0
+            vt 0 1
+            f 1/1 2/2 3/3
+        ";
+
+        let mesh = Mesh::parse_obj(&arena, source, false).unwrap();
+        assert_eq!(mesh.tangent(0), Vec3::new(0.0, 0.0, 2.0));
+    }
+
+
+    #[test]
+    fn vertex_tangents_keep_mirrored_uvs() {
+        let arena = Arena::new();
+        let positions = [Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0),
+                         Point::new(1.0, 0.0, 1.0), Point::new(0.0, 0.0, 1.0)];
+        let triangles = [[0, 2, 1], [0, 3, 2]];
+
+        // v grows along z, which is against up × x
+        let uvs = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let mesh = Mesh::new(&arena, &positions, &uvs, &triangles, true);
+        assert!(mesh.tangents.iter().all(|t| *t == Tangent { direction: Vec3::new(1.0, 0.0, 0.0), sign: -1.0 }));
+
+        let (tangent, bitangent) = mesh.tangent_frame(1, 0.25, 0.25);
+        assert_eq!((tangent, bitangent), (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)));
+
+        // mirrored along v it flips
+        let uvs = uvs.map(|(u, v)| (u, 1.0 - v));
+        let mesh = Mesh::new(&arena, &positions, &uvs, &triangles, true);
+        assert_eq!(mesh.tangent_frame(1, 0.25, 0.25).1, Vec3::new(0.0, 0.0, -1.0));
+
+        // flat shaded meshes keep the tangent of each triangle
+        let mesh = Mesh::new(&arena, &positions, &uvs, &triangles, false);
+        assert!(mesh.tangents.is_empty());
+    }
+
+
+    #[test]
+    fn second_uv_set() {
+        let arena = Arena::new();
+        let positions = [Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(0.0, 0.0, 1.0)];
+        let uvs = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+        let mesh = Mesh::new(&arena, &positions, &uvs, &[[0, 2, 1]], false);
+        assert_eq!(mesh.second_uv(0, 0.5, 0.5), None);
+
+        // a detail map tiled four times
+        let mesh = mesh.with_second_uvs(&arena, &uvs.map(|(u, v)| (4.0 * u, 4.0 * v)));
+        assert_eq!(mesh.uv(0, 0.5, 0.0), (0.0, 0.5));
+        assert_eq!(mesh.second_uv(0, 0.5, 0.0), Some((0.0, 2.0)));
+
+        let mesh = mesh.subdivide(&arena, 1);
+        assert_eq!(mesh.second_uvs.len(), mesh.uvs.len());
+        assert!(mesh.uvs.iter().zip(mesh.second_uvs).all(|(&(u, v), &second)| second == (4.0 * u, 4.0 * v)));
+    }
+
+
+    #[test]
+    fn smooth_normals_are_area_weighted() {
+        // a big triangle facing up and a small one facing
+        // along z share the vertex at the origin
+        let positions = [Point::new(0.0, 0.0, 0.0), Point::new(0.0, 0.0, 4.0), Point::new(4.0, 0.0, 0.0),
+                         Point::new(1.0, 0.0, 0.0), Point::new(0.0, 1.0, 0.0)];
+        let normals = smooth_vertex_normals(&positions, &[[0, 1, 2], [0, 3, 4]]);
+
+        assert_eq!(normals[1], Vec3::new(0.0, 1.0, 0.0));
+        assert!(normals[0].y > 0.99 && normals[0].z > 0.0);
+    }
+
+
+    #[test]
+    fn loop_subdivision() {
+        let arena = Arena::new();
+        let octahedron = [
+            Point::new( 1.0, 0.0, 0.0), Point::new(-1.0, 0.0, 0.0),
+            Point::new(0.0,  1.0, 0.0), Point::new(0.0, -1.0, 0.0),
+            Point::new(0.0, 0.0,  1.0), Point::new(0.0, 0.0, -1.0),
+        ];
+        let triangles = [[0, 2, 4], [2, 1, 4], [1, 3, 4], [3, 0, 4],
+                         [2, 0, 5], [1, 2, 5], [3, 1, 5], [0, 3, 5]];
+
+        let mesh = Mesh::new(&arena, &octahedron, &[], &triangles, false).subdivide(&arena, 1);
+        assert_eq!(mesh.triangle_count(), 32);
+        assert_eq!(mesh.positions.len(), 18);
+        assert!((mesh.positions[0] - Point::new(0.625, 0.0, 0.0)).length() < 1e-6);
+        assert!(mesh.positions.iter().any(|&p| (p - Point::new(0.375, 0.375, 0.0)).length() < 1e-6));
+
+        // the uv sphere's seam and poles have to stay closed
+        let mesh = Mesh::uv_sphere(&arena, 8, 4, true).subdivide(&arena, 2);
+        let (weld, _) = weld_positions(mesh.positions);
+        let mut edges = EdgeMap::<usize>::new();
+        for &[a, b, c] in mesh.triangles {
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                let (from, to) = (weld[from as usize], weld[to as usize]);
+                *edges.entry((from.min(to), from.max(to))).or_default() += 1;
+            }
+        }
+
+        assert!(edges.values().all(|&count| count == 2));
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+    }
+
+
+    #[test]
+    fn terminator_points_lie_on_the_smooth_surface() {
+        let arena = Arena::new();
+        let smooth = Mesh::uv_sphere(&arena, 8, 4, true);
+        let flat = Mesh::uv_sphere(&arena, 8, 4, false);
+
+        let third = 1.0 / 3.0;
+        for triangle in 0..smooth.triangle_count() as u32 {
+            let on_triangle = flat.terminator_point(triangle, third, third);
+            let pushed = smooth.terminator_point(triangle, third, third);
+
+            // pushed out to around the unit sphere, coarse triangles
+            // can overshoot it a little since the tangent planes do
+            assert!(pushed.length() >= on_triangle.length() - 1e-5);
+            assert!(pushed.length() < 1.1, "{}", pushed.length());
+            if on_triangle.length() < 0.9 { assert!(pushed.length() > on_triangle.length() + 0.01) }
+        }
+    }
+}
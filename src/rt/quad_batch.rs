@@ -0,0 +1,123 @@
+use crate::math::{interval::Interval, ray::Ray, vec3::Vec3};
+
+use super::hittable::{HitCandidate, HitDetail, Hittable};
+
+pub const LANES : usize = 4;
+
+type Lanes = [f32; LANES];
+type Lanes3 = [Lanes; 3];
+
+
+///
+/// Up to `LANES` quads stored as a structure of arrays so that
+/// all of them can be intersected at once. The loops are written
+/// over whole lanes so the compiler turns them into SIMD.
+/// Unused lanes have a zero normal which never hits
+///
+pub struct QuadBatch<'a> {
+    quads: &'a [Hittable<'a>],
+    q: Lanes3,
+    u: Lanes3,
+    v: Lanes3,
+    normal: Lanes3,
+    d: Lanes,
+    w: Lanes3,
+}
+
+
+impl<'a> QuadBatch<'a> {
+    ///
+    /// Returns `None` if there are more than `LANES`
+    /// hittables or if any of them isn't a quad
+    ///
+    pub fn new(quads: &'a [Hittable<'a>]) -> Option<Self> {
+        if quads.len() > LANES { return None }
+
+        let mut batch = Self {
+            quads,
+            q: [[0.0; LANES]; 3],
+            u: [[0.0; LANES]; 3],
+            v: [[0.0; LANES]; 3],
+            normal: [[0.0; LANES]; 3],
+            d: [0.0; LANES],
+            w: [[0.0; LANES]; 3],
+        };
+
+        for (lane, quad) in quads.iter().enumerate() {
+            let (q, u, v) = quad.as_quad()?;
+            let n = u.cross(v);
+            let normal = n.unit();
+            let w = n / n.dot(n);
+
+            for axis in 0..3 {
+                batch.q[axis][lane] = q[axis];
+                batch.u[axis][lane] = u[axis];
+                batch.v[axis][lane] = v[axis];
+                batch.normal[axis][lane] = normal[axis];
+                batch.w[axis][lane] = w[axis];
+            }
+
+            batch.d[lane] = normal.dot(q);
+        }
+
+        Some(batch)
+    }
+
+
+    pub fn quads(&self) -> &'a [Hittable<'a>] {
+        self.quads
+    }
+
+
+    pub fn closest_hit(&self, ray: Ray, t: Interval) -> Option<HitCandidate<'a, 'a>> {
+        let o = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let dir = [ray.direction.x, ray.direction.y, ray.direction.z];
+
+        let mut roots = [f32::INFINITY; LANES];
+        for (lane, lane_root) in roots.iter_mut().enumerate() {
+            let denom = dot(&self.normal, lane, dir);
+            let root = (self.d[lane] - dot(&self.normal, lane, o)) / denom;
+
+            // the planar coordinates of the hit inside of the quad
+            let hp = [o[0] + root * dir[0] - self.q[0][lane],
+                      o[1] + root * dir[1] - self.q[1][lane],
+                      o[2] + root * dir[2] - self.q[2][lane]];
+            let alpha = dot(&self.w, lane, cross(hp, column(&self.v, lane)));
+            let beta = dot(&self.w, lane, cross(column(&self.u, lane), hp));
+
+            let valid = denom.abs() >= 1e-8 && t.surrounds(root)
+                        && (0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta);
+            *lane_root = if valid { root } else { f32::INFINITY };
+        }
+
+        let mut closest = None;
+        let mut closest_so_far = t.max;
+        for (lane, &root) in roots.iter().enumerate().take(self.quads.len()) {
+            if root < closest_so_far {
+                closest_so_far = root;
+                closest = Some(HitCandidate { t: root, hittable: &self.quads[lane], detail: HitDetail::Surface, placement: None });
+            }
+        }
+
+        closest
+    }
+}
+
+
+#[inline(always)]
+fn column(lanes: &Lanes3, lane: usize) -> [f32; 3] {
+    [lanes[0][lane], lanes[1][lane], lanes[2][lane]]
+}
+
+
+#[inline(always)]
+fn dot(lanes: &Lanes3, lane: usize, v: [f32; 3]) -> f32 {
+    lanes[0][lane] * v[0] + lanes[1][lane] * v[1] + lanes[2][lane] * v[2]
+}
+
+
+#[inline(always)]
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    let v = Vec3::new(a[0], a[1], a[2]).cross(Vec3::new(b[0], b[1], b[2]));
+    [v.x, v.y, v.z]
+}
@@ -0,0 +1,198 @@
+use sti::arena::Arena;
+
+use crate::{math::{aabb::{InverseRay, AABB}, interval::Interval, ray::Ray}, utils::Stack};
+
+use super::hittable::{HitCandidate, Hittable, HittableKind, BVH_STACK_SIZE};
+
+/// marks a child as an index into `leaves` instead of `nodes`
+const LEAF : u32 = 1 << 31;
+/// a box is cut into this many steps on every axis, the last
+/// of the 256 values only leaves room for rounding outwards
+const STEPS : f32 = 254.0;
+
+
+#[derive(Clone, Copy)]
+struct QuantizedNode {
+    // the boxes of both children in steps of this node's box,
+    // rounded outwards so they're never smaller than the real ones
+    min: [[u8; 3]; 2],
+    max: [[u8; 3]; 2],
+    children: [u32; 2],
+}
+
+
+///
+/// A BVH whose nodes store the boxes of their children in 8 bits
+/// per side relative to their own box, which makes a node 20 bytes
+/// instead of a whole `Hittable`. Boxes are decompressed on the way
+/// down and only grow from it, so it finds the same hits as the BVH
+/// it was made from.
+/// Only built over finite boxes, see `Hittable::quantize`
+///
+pub struct QuantizedBvh<'a> {
+    nodes: &'a [QuantizedNode],
+    leaves: &'a [&'a Hittable<'a>],
+    ordered: bool,
+    source: &'a Hittable<'a>,
+}
+
+
+impl<'a> QuantizedBvh<'a> {
+    ///
+    /// `source` has to be a BVH node with a finite box
+    ///
+    pub fn new(arena: &'a Arena, source: &'a Hittable<'a>) -> Self {
+        let HittableKind::BVH { ordered, .. } = source.kind()
+        else { unreachable!("only BVHs can be quantized") };
+
+        let mut nodes = sti::vec::Vec::new_in(arena);
+        let mut leaves = sti::vec::Vec::new_in(arena);
+        build(&mut nodes, &mut leaves, source, frame_of(source.bounding_box()));
+
+        Self {
+            nodes: nodes.leak(),
+            leaves: leaves.leak(),
+            ordered: *ordered,
+            source,
+        }
+    }
+
+
+    ///
+    /// The BVH this was made from, for
+    /// everything but finding hits
+    ///
+    pub fn source(&self) -> &'a Hittable<'a> {
+        self.source
+    }
+
+
+    ///
+    /// `bounds` must be the box of `source`
+    ///
+    pub fn closest_hit(&self, bounds: &AABB, ray: Ray, t: Interval) -> Option<HitCandidate<'a, 'a>> {
+        let inverse = InverseRay::new(ray);
+        let entry = bounds.entry_inverse(&inverse, t)?;
+
+        let root = frame_of(bounds);
+        let mut stack = Stack::<_, BVH_STACK_SIZE>::new((0, root, entry));
+        stack.push((0, root, entry));
+
+        let mut closest = None;
+        let mut closest_so_far = t.max;
+
+        while let Some((index, frame, entry)) = stack.pop() {
+            if entry >= closest_so_far { continue }
+
+            if index & LEAF != 0 {
+                let leaf = self.leaves[(index & !LEAF) as usize];
+                if let Some(candidate) = leaf.closest_hit(ray, Interval::new(t.min, closest_so_far)) {
+                    closest_so_far = candidate.t;
+                    closest = Some(candidate);
+                }
+                continue;
+            }
+
+            let node = &self.nodes[index as usize];
+            let t = Interval::new(t.min, closest_so_far);
+
+            let children = [0, 1].map(|child| {
+                let child_frame = decode(&frame, node.min[child], node.max[child]);
+                let [x, y, z] = child_frame;
+                let entry = AABB::new(x, y, z).entry_inverse(&inverse, t);
+                (node.children[child], child_frame, entry)
+            });
+
+            // push the side to visit first last
+            let right_first = match (children[0].2, children[1].2) {
+                (Some(l), Some(r)) => self.ordered && r < l,
+                _ => false,
+            };
+
+            let order = if right_first { [0, 1] } else { [1, 0] };
+            for (index, frame, entry) in order.map(|i| children[i]) {
+                if let Some(entry) = entry { stack.push((index, frame, entry)) }
+            }
+        }
+
+        closest
+    }
+}
+
+
+type Frame = [Interval; 3];
+
+
+fn frame_of(aabb: &AABB) -> Frame {
+    [aabb.axis_interval(0), aabb.axis_interval(1), aabb.axis_interval(2)]
+}
+
+
+#[inline(always)]
+fn step_of(frame: Interval) -> f32 {
+    (frame.max - frame.min) / STEPS
+}
+
+
+#[inline(always)]
+fn decode(frame: &Frame, min: [u8; 3], max: [u8; 3]) -> Frame {
+    let axis = |i: usize| {
+        let step = step_of(frame[i]);
+        Interval::new(frame[i].min + min[i] as f32 * step, frame[i].min + max[i] as f32 * step)
+    };
+
+    [axis(0), axis(1), axis(2)]
+}
+
+
+///
+/// The steps of `frame` which cover `aabb`
+///
+fn encode(frame: &Frame, aabb: &AABB) -> ([u8; 3], [u8; 3]) {
+    let mut min = [0; 3];
+    let mut max = [0; 3];
+
+    for axis in 0..3 {
+        let f = frame[axis];
+        let b = aabb.axis_interval(axis);
+        let step = step_of(f);
+        let at = |q: u8| f.min + q as f32 * step;
+
+        // the estimate can be off by one either way after rounding,
+        // checking with `at` makes sure decoding gives the same box
+        let mut lo = ((b.min - f.min) / step).floor().clamp(0.0, 255.0) as u8;
+        while lo > 0 && at(lo) > b.min { lo -= 1 }
+
+        let mut hi = ((b.max - f.min) / step).ceil().clamp(0.0, 255.0) as u8;
+        while hi < 255 && at(hi) < b.max { hi += 1 }
+
+        min[axis] = lo;
+        max[axis] = hi;
+    }
+
+    (min, max)
+}
+
+
+fn build<'a>(nodes: &mut sti::vec::Vec<QuantizedNode, &Arena>, leaves: &mut sti::vec::Vec<&'a Hittable<'a>, &Arena>,
+             node: &'a Hittable<'a>, frame: Frame) -> u32 {
+    let HittableKind::BVH { left, right, .. } = node.kind()
+    else {
+        leaves.push(node);
+        return (leaves.len() - 1) as u32 | LEAF;
+    };
+
+    let index = nodes.len();
+    nodes.push(QuantizedNode { min: [[0; 3]; 2], max: [[0; 3]; 2], children: [0; 2] });
+
+    let mut quantized = nodes[index];
+    for (i, child) in [*left, *right].into_iter().enumerate() {
+        let (min, max) = encode(&frame, child.bounding_box());
+        quantized.min[i] = min;
+        quantized.max[i] = max;
+        quantized.children[i] = build(nodes, leaves, child, decode(&frame, min, max));
+    }
+
+    nodes[index] = quantized;
+    index as u32
+}
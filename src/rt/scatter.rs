@@ -0,0 +1,148 @@
+use sti::arena::Arena;
+
+use crate::{math::{matrix::Matrix4, vec3::Vec3}, rng::next_f32};
+
+use super::{hittable::Hittable, mesh::Mesh, texture::Texture};
+
+
+///
+/// How `scatter` spreads copies of an object over a surface
+///
+#[derive(Clone, Copy, Debug)]
+pub struct ScatterOptions {
+    /// how many places are tried, each one is kept with
+    /// the chance of the density there
+    pub attempts: usize,
+    /// the smallest and largest a copy is scaled by
+    pub scale: (f32, f32),
+    /// the most a copy leans away from standing up, in radians
+    pub tilt: f32,
+    /// whether copies stand along the normal of the surface,
+    /// like rocks on a slope, or straight up like grass
+    pub follow_normal: bool,
+}
+
+
+impl ScatterOptions {
+    pub const DEFAULT : ScatterOptions = ScatterOptions {
+        attempts: 1000,
+        scale: (0.8, 1.2),
+        tilt: 0.0,
+        follow_normal: false,
+    };
+}
+
+
+///
+/// Copies of `object` spread over `surface` as a BVH of
+/// instances, so the object is only stored once however many
+/// copies there are. Where they go is decided by `placements`
+///
+pub fn scatter<'a>(arena: &'a Arena, object: &'a Hittable<'a>, surface: &Mesh,
+                   density: &Texture, options: ScatterOptions) -> Hittable<'a> {
+    let mut copies = sti::vec::Vec::new_in(arena);
+    for matrix in placements(surface, density, options) {
+        copies.push(Hittable::instance(arena, object, matrix));
+    }
+
+    Hittable::bvh(arena, copies.leak())
+}
+
+
+///
+/// Where each copy of `scatter` goes. The places are picked
+/// evenly by area and kept by the mean of `density` at their
+/// uvs, then every copy gets a random turn about its up,
+/// lean and scale. An object's up is y and it stands on y = 0
+///
+pub fn placements(surface: &Mesh, density: &Texture, options: ScatterOptions) -> Vec<Matrix4> {
+    // running sum of the triangle areas to pick them by
+    let mut areas = Vec::with_capacity(surface.triangle_count());
+    let mut total = 0.0;
+    for triangle in 0..surface.triangle_count() {
+        let [p0, p1, p2] = surface.vertices(triangle as u32);
+        total += 0.5 * (p1 - p0).cross(p2 - p0).length();
+        areas.push(total);
+    }
+
+    if total <= 0.0 { return Vec::new() }
+
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let mut matrices = Vec::new();
+    for _ in 0..options.attempts {
+        let target = next_f32() * total;
+        let triangle = areas.partition_point(|&sum| sum <= target).min(areas.len() - 1) as u32;
+
+        // folding the square in half keeps the point uniform
+        let (mut b1, mut b2) = (next_f32(), next_f32());
+        if b1 + b2 > 1.0 { (b1, b2) = (1.0 - b1, 1.0 - b2) }
+
+        let [p0, p1, p2] = surface.vertices(triangle);
+        let position = p0 + b1 * (p1 - p0) + b2 * (p2 - p0);
+        let (u, v) = surface.uv(triangle, b1, b2);
+        if density.scalar(u, v, position) <= next_f32() { continue }
+
+        let stand = if options.follow_normal { align_up(up, surface.shading_normal(triangle, b1, b2)) }
+                    else { Matrix4::identity() };
+
+        let lean_direction = 2.0 * std::f32::consts::PI * next_f32();
+        let lean = Matrix4::rotation(Vec3::new(lean_direction.cos(), 0.0, lean_direction.sin()), options.tilt * next_f32());
+        let turn = Matrix4::rotation(up, 2.0 * std::f32::consts::PI * next_f32());
+
+        let (min_scale, max_scale) = options.scale;
+        let scale = min_scale + (max_scale - min_scale) * next_f32();
+
+        matrices.push(Matrix4::translation(position) * stand * lean * turn * Matrix4::scaling(scale * Vec3::ONE));
+    }
+
+    matrices
+}
+
+
+///
+/// The rotation which turns `from` onto `to`, both unit vectors
+///
+fn align_up(from: Vec3, to: Vec3) -> Matrix4 {
+    let axis = from.cross(to);
+    let cos = from.dot(to).clamp(-1.0, 1.0);
+    if axis.near_zero() {
+        // facing the same way or exactly opposite
+        return if cos > 0.0 { Matrix4::identity() } else { Matrix4::rotation(Vec3::new(1.0, 0.0, 0.0), std::f32::consts::PI) };
+    }
+
+    Matrix4::rotation(axis.unit(), cos.acos())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::math::vec3::{Colour, Point};
+
+    use super::*;
+
+    #[test]
+    fn density_decides_where_copies_go() {
+        let arena = Arena::new();
+        // two unit squares along x, the checkerboard is
+        // white over the first and black over the second
+        let positions = [Point::new(0.0, 0.0, 0.0), Point::new(2.0, 0.0, 0.0),
+                         Point::new(2.0, 0.0, 1.0), Point::new(0.0, 0.0, 1.0)];
+        let surface = Mesh::new(&arena, &positions, &[], &[[0, 2, 1], [0, 3, 2]], false);
+        let white = Texture::SolidColour(Colour::ONE);
+        let black = Texture::SolidColour(Colour::ZERO);
+        let density = Texture::Checkerboard { inv_scale: 1.0, even: &white, odd: &black };
+
+        let options = ScatterOptions { attempts: 2000, scale: (0.5, 2.0), ..ScatterOptions::DEFAULT };
+        let matrices = placements(&surface, &density, options);
+        assert!((800..1200).contains(&matrices.len()), "{}", matrices.len());
+
+        for matrix in matrices {
+            let base = matrix.transform_point(Point::ZERO);
+            assert!(base.x < 1.0 && base.y.abs() < 1e-6);
+
+            // only turned about y and scaled so up stays up
+            let up = matrix.transform_vector(Vec3::new(0.0, 1.0, 0.0));
+            assert!(up.x.abs() < 1e-5 && up.z.abs() < 1e-5 && (0.5 - 1e-5..=2.0 + 1e-5).contains(&up.y));
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::math::{interval::Interval, ray::Ray, vec3::{Point, Vec3}};
+
+///
+/// A signed distance function, negative inside of the shape.
+/// It must never overestimate the distance to the surface
+///
+pub type DistanceFn<'a> = &'a (dyn Fn(Point) -> f32 + Sync);
+
+const MAX_STEPS : usize = 256;
+const SURFACE_EPSILON : f32 = 1e-4;
+
+
+///
+/// Sphere traces `distance` along the ray inside of `span`
+/// and returns the `t` of the first surface it reaches
+///
+pub fn march(distance: DistanceFn, ray: Ray, span: Interval) -> Option<f32> {
+    let inv_length = 1.0 / ray.direction.length();
+    let mut t = span.min;
+
+    for _ in 0..MAX_STEPS {
+        if t >= span.max { return None }
+
+        let d = distance(ray.at(t)).abs();
+        if d < SURFACE_EPSILON { return Some(t) }
+
+        t += d * inv_length;
+    }
+
+    None
+}
+
+
+///
+/// Calls `push` with every `[entry, exit]` span
+/// inside of the shape along the ray
+///
+pub fn spans<F: FnMut(f32, f32)>(distance: DistanceFn, ray: Ray, span: Interval, push: &mut F) {
+    let inv_length = 1.0 / ray.direction.length();
+    let mut t = span.min;
+    let mut inside = distance(ray.at(t)) < 0.0;
+    let mut entry = t;
+
+    for _ in 0..MAX_STEPS {
+        if t >= span.max { break }
+
+        let d = distance(ray.at(t)).abs();
+        if d >= SURFACE_EPSILON {
+            t += d * inv_length;
+            continue;
+        }
+
+        // step over the surface and see which side we ended up on
+        let next = t + 2.0 * SURFACE_EPSILON * inv_length;
+        let now_inside = distance(ray.at(next)) < 0.0;
+        if now_inside != inside {
+            if inside { push(entry, t) }
+            else { entry = t }
+            inside = now_inside;
+        }
+
+        t = next;
+    }
+
+    if inside { push(entry, span.max) }
+}
+
+
+///
+/// Estimates the surface normal at `p` by central differences
+///
+pub fn normal(distance: DistanceFn, p: Point) -> Vec3 {
+    const H : f32 = 1e-4;
+    let dx = Vec3::new(H, 0.0, 0.0);
+    let dy = Vec3::new(0.0, H, 0.0);
+    let dz = Vec3::new(0.0, 0.0, H);
+
+    Vec3::new(
+        distance(p + dx) - distance(p - dx),
+        distance(p + dy) - distance(p - dy),
+        distance(p + dz) - distance(p - dz),
+    ).unit()
+}
+
+
+pub fn sphere(p: Point, radius: f32) -> f32 {
+    p.length() - radius
+}
+
+
+pub fn round_box(p: Point, half_extent: Vec3, radius: f32) -> f32 {
+    let q = Vec3::new(p.x.abs() - half_extent.x, p.y.abs() - half_extent.y, p.z.abs() - half_extent.z);
+    let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+    outside + q.x.max(q.y).max(q.z).min(0.0) - radius
+}
+
+
+///
+/// A union of two distances which blends them within `k` of each other
+///
+pub fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+
+///
+/// Distance estimator for the mandelbulb fractal around the origin
+///
+pub fn mandelbulb(p: Point, power: f32, iterations: usize) -> f32 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+
+    for _ in 0..iterations {
+        r = z.length();
+        if r > 2.0 { break }
+
+        let theta = (z.z / r).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+
+        let zr = r.powf(power);
+        z = zr * Vec3::new(theta.sin() * phi.cos(), phi.sin() * theta.sin(), theta.cos()) + p;
+    }
+
+    0.5 * r.ln() * r / dr
+}
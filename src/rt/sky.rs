@@ -0,0 +1,93 @@
+use image::Rgb32FImage;
+
+use crate::{math::{interval::Interval, vec3::{Colour, Vec3}}, rt::hittable::get_sphere_uv};
+
+const SUN_ANGULAR_RADIUS : f32 = 0.03;
+const SUN_COLOUR : Colour = Colour::new(8.0, 7.0, 5.5);
+const NIGHT_COLOUR : Colour = Colour::new(0.01, 0.01, 0.03);
+const SUNSET_COLOUR : Colour = Colour::new(1.0, 0.45, 0.15);
+
+
+///
+/// The colour of the rays which escape the scene
+///
+#[derive(Clone, Copy, Default)]
+pub enum Sky<'a> {
+    /// A white to light blue gradient going up
+    #[default]
+    Gradient,
+    /// The same colour in every direction
+    Solid(Colour),
+    /// Blends from `bottom` straight down to `top` straight up
+    VerticalGradient { bottom: Colour, top: Colour },
+    /// A sky lit by a sun in `sun_direction`,
+    /// which is a unit vector pointing towards it
+    Daylight { sun_direction: Vec3 },
+    /// An equirectangular image around the scene, mapped like
+    /// the uvs of a sphere. The pixels are taken as linear so
+    /// HDR maps keep their brightness
+    Environment { image: &'a Rgb32FImage },
+}
+
+
+impl Sky<'_> {
+    ///
+    /// A daylight sky with the sun where it is at `hours` (0..24,
+    /// solar time) on an equinox at `latitude` degrees north.
+    /// `+x` is east, `+y` is up and `+z` is north
+    ///
+    pub fn at_time_of_day(hours: f32, latitude: f32) -> Self {
+        let hour_angle = ((hours - 12.0) * 15.0).to_radians();
+        let (sin_lat, cos_lat) = latitude.to_radians().sin_cos();
+        let (sin_h, cos_h) = hour_angle.sin_cos();
+
+        // the sun's declination is zero on an equinox
+        let east = -sin_h;
+        let north = -cos_h * sin_lat;
+        let up = cos_h * cos_lat;
+
+        Sky::Daylight { sun_direction: Vec3::new(east, up, north).unit() }
+    }
+
+
+    pub fn colour(&self, direction: Vec3) -> Colour {
+        let unit_dir = direction.unit();
+        let a = 0.5 * (unit_dir.y + 1.0);
+        let gradient = (1.0 - a) * Colour::new(1.0, 1.0, 1.0) + a * Colour::new(0.5, 0.7, 1.0);
+
+        let sun_direction = match *self {
+            Sky::Gradient => return gradient,
+            Sky::Solid(colour) => return colour,
+            Sky::VerticalGradient { bottom, top } => return (1.0 - a) * bottom + a * top,
+            Sky::Environment { image } => return environment(image, unit_dir),
+            Sky::Daylight { sun_direction } => sun_direction,
+        };
+
+        // fade to night once the sun is below the horizon and
+        // redden the horizon while it's close to it
+        let unit = Interval::new(0.0, 1.0);
+        let day = unit.clamp((sun_direction.y + 0.1) / 0.3);
+        let sunset = unit.clamp(1.0 - sun_direction.y.abs() / 0.25) * unit.clamp(1.0 - unit_dir.y.abs() / 0.5);
+
+        let sky = day * gradient + (1.0 - day) * NIGHT_COLOUR;
+        let sky = (1.0 - 0.6 * sunset) * sky + 0.6 * sunset * SUNSET_COLOUR;
+
+        if sun_direction.y > -SUN_ANGULAR_RADIUS && unit_dir.dot(sun_direction) > SUN_ANGULAR_RADIUS.cos() {
+            return sky + SUN_COLOUR;
+        }
+
+        sky
+    }
+}
+
+
+fn environment(image: &Rgb32FImage, direction: Vec3) -> Colour {
+    let (u, v) = get_sphere_uv(direction);
+    // the first row of the image is straight up
+    let unit = Interval::new(0.0, 1.0);
+    let x = (unit.clamp(u) * (image.width() - 1) as f32) as u32;
+    let y = ((1.0 - unit.clamp(v)) * (image.height() - 1) as f32) as u32;
+
+    let pixel = image.get_pixel(x, y);
+    Colour::new(pixel[0], pixel[1], pixel[2])
+}
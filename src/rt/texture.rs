@@ -1,6 +1,6 @@
 use image::Rgb32FImage;
 
-use crate::{math::{interval::Interval, vec3::{Colour, Point}}, perlin_noise::PerlinNoise};
+use crate::{math::{interval::Interval, vec3::{Colour, Point}}, perlin_noise::PerlinNoise, rt::hittable::HitRecord};
 
 #[derive(Clone, Copy)]
 pub enum Texture<'a> {
@@ -20,6 +20,12 @@ pub enum Texture<'a> {
 
     
     NoiseTexture(PerlinNoise<'a>, f32),
+
+
+    /// The texture looked up with the second uv set of meshes
+    /// which have one, for lightmaps and detail maps. Anything
+    /// else uses its only uvs
+    SecondUvSet(&'a Texture<'a>),
 }
 
 
@@ -56,7 +62,49 @@ impl<'a> Texture<'a> {
             Texture::NoiseTexture(noise, scale) => {
                 (1.0 + (scale * p.z + 10.0 * noise.turbulance(p, 7)).sin()) * Colour::new(0.5, 0.5, 0.5)
             },
+
+
+            Texture::SecondUvSet(texture) => texture.value(u, v, p),
+        }
+    }
+
+
+    ///
+    /// The value where `rec` hit, with whichever
+    /// uv set the texture is looked up with
+    ///
+    pub fn at(&self, rec: &HitRecord) -> Colour {
+        let (u, v) = self.uv(rec);
+        self.value(u, v, rec.point)
+    }
+
+
+    ///
+    /// The texture coordinates of `rec` the texture is looked up with
+    ///
+    pub fn uv(&self, rec: &HitRecord) -> (f32, f32) {
+        match self {
+            Texture::SecondUvSet(_) => rec.second_uv.unwrap_or((rec.u, rec.v)),
+            _ => (rec.u, rec.v),
         }
     }
 
+
+    ///
+    /// The mean of the channels, for textures which
+    /// map a single property like roughness
+    ///
+    pub fn scalar(&self, u: f32, v: f32, p: Point) -> f32 {
+        mean(self.value(u, v, p))
+    }
+
+
+    pub fn scalar_at(&self, rec: &HitRecord) -> f32 {
+        mean(self.at(rec))
+    }
+}
+
+
+fn mean(colour: Colour) -> f32 {
+    (colour.x + colour.y + colour.z) / 3.0
 }
@@ -0,0 +1,399 @@
+use crate::math::{aabb::AABB, interval::Interval, matrix::Matrix4, quaternion::Quaternion, ray::Ray, vec3::{Point, Vec3}};
+
+use super::{hittable::Hittable, materials::Material};
+
+
+///
+/// A hittable placed in the world by an affine matrix.
+/// Rays are moved into object space on the way in, since the
+/// direction isn't normalised their `t` stays the same.
+/// Normals are moved back out with the inverse-transpose.
+/// The object is only referenced so many transforms
+/// can share the same geometry, each optionally
+/// with its own material
+///
+pub struct Transform<'a> {
+    /// object space to world space
+    matrix: Matrix4,
+    /// world space to object space
+    inverse: Matrix4,
+    normal_matrix: Matrix4,
+    object: &'a Hittable<'a>,
+    /// replaces the material of everything in `object`
+    material: Option<Material<'a>>,
+}
+
+
+impl<'a> Transform<'a> {
+    ///
+    /// Panics if `matrix` isn't invertible
+    ///
+    pub fn new(object: &'a Hittable<'a>, matrix: Matrix4) -> Self {
+        let inverse = matrix.inverse().expect("a transform must be invertible");
+
+        Self {
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            object,
+            material: None,
+        }
+    }
+
+
+    ///
+    /// A transform whose `inverse` was already worked out
+    ///
+    fn from_parts(object: &'a Hittable<'a>, matrix: Matrix4, inverse: Matrix4) -> Self {
+        Self {
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            object,
+            material: None,
+        }
+    }
+
+
+    pub fn with_material(self, material: Option<Material<'a>>) -> Self {
+        Self { material, ..self }
+    }
+
+
+    pub fn object(&self) -> &'a Hittable<'a> {
+        self.object
+    }
+
+
+    pub fn matrix(&self) -> Matrix4 {
+        self.matrix
+    }
+
+
+    pub fn normal_matrix(&self) -> Matrix4 {
+        self.normal_matrix
+    }
+
+
+    pub fn material(&self) -> Option<Material<'a>> {
+        self.material
+    }
+
+
+    #[inline(always)]
+    pub fn to_object(&self, ray: Ray) -> Ray {
+        Ray::new(self.inverse.transform_point(ray.origin), self.inverse.transform_vector(ray.direction), ray.time)
+    }
+
+
+    #[inline(always)]
+    pub fn normal_to_world(&self, normal: Vec3) -> Vec3 {
+        self.normal_matrix.transform_vector(normal).unit()
+    }
+
+
+    #[inline(always)]
+    pub fn vector_to_world(&self, vector: Vec3) -> Vec3 {
+        self.matrix.transform_vector(vector)
+    }
+
+
+    ///
+    /// The world space box around the transformed corners
+    /// of the object's box, unbounded objects stay unbounded
+    ///
+    pub fn bounding_box(&self) -> AABB {
+        let aabb = self.object.bounding_box();
+        let axes = [aabb.axis_interval(0), aabb.axis_interval(1), aabb.axis_interval(2)];
+        if axes.iter().any(|i| !i.min.is_finite() || !i.max.is_finite()) {
+            return AABB::new(Interval::UNIVERSE, Interval::UNIVERSE, Interval::UNIVERSE);
+        }
+
+        let mut min = Point::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max = -min;
+        for corner in 0..8 {
+            let pick = |axis: usize| if corner & (1 << axis) == 0 { axes[axis].min } else { axes[axis].max };
+            let p = self.matrix.transform_point(Point::new(pick(0), pick(1), pick(2)));
+
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+
+        AABB::from_points(min, max)
+    }
+}
+
+
+///
+/// A transform which moves from `start` at time 0 to `end` at
+/// time 1 for motion blur. Each end is split into a scale, a
+/// rotation and a translation which are interpolated on their
+/// own, so a spin keeps the object's shape the whole way
+/// around instead of squashing it through a singular matrix
+///
+pub struct MotionTransform<'a> {
+    start: Decomposed,
+    end: Decomposed,
+    object: &'a Hittable<'a>,
+}
+
+
+///
+/// A matrix taken apart into a scale, then a rotation, then a
+/// translation. A shear can't be taken apart like this and is lost
+///
+#[derive(Clone, Copy)]
+struct Decomposed {
+    translation: Vec3,
+    rotation: Quaternion,
+    scale: Vec3,
+}
+
+
+impl Decomposed {
+    fn new(matrix: Matrix4) -> Self {
+        let translation = Vec3::new(matrix[0][3], matrix[1][3], matrix[2][3]);
+        let column = |j: usize| Vec3::new(matrix[0][j], matrix[1][j], matrix[2][j]);
+        let columns = [column(0), column(1), column(2)];
+
+        // a mirror is a negative scale along x
+        let mut scale = Vec3::new(columns[0].length(), columns[1].length(), columns[2].length());
+        if columns[0].cross(columns[1]).dot(columns[2]) < 0.0 { scale.x = -scale.x }
+
+        // a flattened axis says nothing about the
+        // rotation, the quaternion fills it back in
+        let mut rotation = Matrix4::identity();
+        for (j, column) in columns.into_iter().enumerate() {
+            let axis = if scale[j] == 0.0 { Vec3::ZERO } else { column / scale[j] };
+            for i in 0..3 { rotation[i][j] = axis[i] }
+        }
+
+        Self { translation, rotation: Quaternion::from_matrix(rotation), scale }
+    }
+
+
+    fn lerp(self, end: Decomposed, time: f32) -> Self {
+        Self {
+            translation: (1.0 - time) * self.translation + time * end.translation,
+            rotation: self.rotation.slerp(end.rotation, time),
+            scale: (1.0 - time) * self.scale + time * end.scale,
+        }
+    }
+}
+
+
+impl<'a> MotionTransform<'a> {
+    ///
+    /// How many times in the shutter the box is taken at
+    ///
+    const BOUNDING_STEPS : usize = 16;
+
+
+    pub fn new(object: &'a Hittable<'a>, start: Matrix4, end: Matrix4) -> Self {
+        Self { start: Decomposed::new(start), end: Decomposed::new(end), object }
+    }
+
+
+    pub fn object(&self) -> &'a Hittable<'a> {
+        self.object
+    }
+
+
+    ///
+    /// The transform at `time`. The inverse is put together from
+    /// the parts, a scale of 0 maps onto a very thin slab instead
+    /// of dividing by 0
+    ///
+    pub fn at(&self, time: f32) -> Transform<'a> {
+        let parts = self.start.lerp(self.end, time);
+        let rotation = parts.rotation.to_matrix();
+
+        let inverse_scale = |s: f32| 1.0 / if s.abs() < 1e-12 { 1e-12f32.copysign(s) } else { s };
+        let matrix = Matrix4::translation(parts.translation) * rotation * Matrix4::scaling(parts.scale);
+        let inverse = Matrix4::scaling(Vec3::new(inverse_scale(parts.scale.x), inverse_scale(parts.scale.y), inverse_scale(parts.scale.z)))
+            * rotation.transpose()
+            * Matrix4::translation(-parts.translation);
+
+        Transform::from_parts(self.object, matrix, inverse)
+    }
+
+
+    ///
+    /// The boxes at a few times in the shutter put together.
+    /// Between two of them a point turns on an arc instead of a
+    /// straight line, so they're grown by how far the arc can
+    /// bulge out of the line
+    ///
+    pub fn bounding_box(&self) -> AABB {
+        let mut aabb = self.at(0.0).bounding_box();
+        for step in 1..=Self::BOUNDING_STEPS {
+            aabb = AABB::from_aabbs(&aabb, &self.at(step as f32 / Self::BOUNDING_STEPS as f32).bounding_box());
+        }
+
+        let object = self.object.bounding_box();
+        let reach = object.min_corner().length().max(object.max_corner().length())
+            * [self.start.scale, self.end.scale].iter().map(|s| s.x.abs().max(s.y.abs()).max(s.z.abs())).fold(0.0, f32::max);
+        let step = self.start.rotation.angle_to(self.end.rotation) / Self::BOUNDING_STEPS as f32;
+        let bulge = if step > 0.0 { reach * (1.0 - (0.5 * step).cos()) } else { 0.0 };
+
+        let grow = |i: Interval| i.expand(2.0 * bulge);
+        AABB::new(grow(aabb.axis_interval(0)), grow(aabb.axis_interval(1)), grow(aabb.axis_interval(2)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sti::arena::Arena;
+
+    use crate::{math::vec3::Colour, rng::next_f32, rt::{hittable::HitRecord, medium::Medium, texture::Texture}};
+
+    use super::*;
+
+    ///
+    /// Wraps `object` in a random translation, rotation or
+    /// scale and returns the matrix it should've been placed by
+    ///
+    fn random_step<'a>(arena: &'a Arena, object: Hittable<'a>) -> (Hittable<'a>, Matrix4) {
+        let random = Vec3::new(next_f32(), next_f32(), next_f32());
+        match (next_f32() * 4.0) as usize {
+            0 => {
+                let offset = 4.0 * random - Vec3::new(2.0, 2.0, 2.0);
+                (object.translate_by(arena, offset), Matrix4::translation(offset))
+            },
+
+            1 => {
+                let angle = 360.0 * next_f32();
+                (object.rotate_y_by(arena, angle), Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), angle.to_radians()))
+            },
+
+            2 => {
+                let (axis, angle) = (Vec3::random_unit(), 360.0 * next_f32());
+                (object.rotate_axis_by(arena, axis, angle), Matrix4::rotation(axis, angle.to_radians()))
+            },
+
+            _ => {
+                let factor = Vec3::new(0.5, 0.5, 0.5) + random;
+                (object.scale_by(arena, factor), Matrix4::scaling(factor))
+            },
+        }
+    }
+
+
+    #[test]
+    fn composed_transforms_match_the_analytic_ray() {
+        let arena = Arena::new();
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+        let bases = [
+            Hittable::sphere(Point::ZERO, 1.0, mat),
+            Hittable::axis_aligned_box(Point::new(-1.0, -0.5, -0.25), Point::new(1.0, 0.5, 0.25), mat),
+        ];
+
+        let mut hits = 0;
+        for i in 0..200 {
+            let base = &bases[i % bases.len()];
+
+            // up to 6 steps, sometimes behind a list so the
+            // transforms are nested instead of composed
+            let mut object = base.clone();
+            let mut matrix = Matrix4::identity();
+            for _ in 0..1 + (next_f32() * 6.0) as usize {
+                if next_f32() < 0.3 { object = Hittable::list(arena.alloc_new([object])) }
+
+                let (stepped, step) = random_step(&arena, object);
+                object = stepped;
+                matrix = step * matrix;
+            }
+
+            let to_object = matrix.inverse().unwrap();
+            let aabb = object.bounding_box();
+            let centre = 0.5 * (aabb.min_corner() + aabb.max_corner());
+
+            for _ in 0..100 {
+                let origin = centre + 10.0 * Vec3::random_unit();
+                let target = centre + Vec3::random_in_unit_sphere();
+                let ray = Ray::new(origin, target - origin, 0.0);
+                let t = Interval::new(0.001, f32::INFINITY);
+
+                let mut found = HitRecord::default();
+                let mut expected = HitRecord::default();
+                let hit = object.hit(ray, t, &mut found);
+                let object_ray = Ray::new(to_object.transform_point(ray.origin), to_object.transform_vector(ray.direction), 0.0);
+                assert_eq!(hit, base.hit(object_ray, t, &mut expected));
+                if !hit { continue }
+
+                hits += 1;
+                let normal = to_object.transpose().transform_vector(expected.normal).unit();
+                assert!((found.t - expected.t).abs() < 1e-3 * expected.t, "{} != {}", found.t, expected.t);
+                assert!((found.normal - normal).length() < 1e-3, "{} != {}", found.normal, normal);
+                assert_eq!(found.front_face, expected.front_face);
+            }
+        }
+
+        assert!(hits > 5000, "{hits}");
+    }
+
+
+    #[test]
+    fn motion_through_almost_a_half_turn_keeps_its_shape() {
+        // blending the two matrices element by element would
+        // flatten the box to almost nothing halfway through,
+        // and to nothing at all for a whole half turn
+        let arena = Arena::new();
+        let mat = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+        let cube = Hittable::axis_aligned_box(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0), mat);
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let spinning = cube.moving(&arena, Matrix4::identity(), Matrix4::rotation(axis, 178f32.to_radians()));
+        let aabb = spinning.bounding_box();
+
+        for _ in 0..1000 {
+            let time = next_f32();
+            let origin = 10.0 * Vec3::random_unit();
+            let ray = Ray::new(origin, Vec3::random_in_unit_sphere() - origin, time);
+
+            // the same cube turned by the slerped angle
+            let turned = Hittable::axis_aligned_box(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0), mat)
+                .rotate_y_by(&arena, 178.0 * time);
+
+            let mut found = HitRecord::default();
+            let mut expected = HitRecord::default();
+            let t = Interval::new(0.001, f32::INFINITY);
+            assert!(turned.hit(ray, t, &mut expected));
+            assert!(spinning.hit(ray, t, &mut found), "missed at {time}");
+            assert!((found.t - expected.t).abs() < 1e-3 * expected.t, "{} != {}", found.t, expected.t);
+            assert!((0..3).all(|axis| aabb.axis_interval(axis).contains(found.point[axis])), "{}", found.point);
+        }
+    }
+
+
+    #[test]
+    fn nested_transforms_of_media_fill_their_records() {
+        // a medium's collisions are random, so its hit can't be
+        // found a second time to fill in the record
+        let arena = Arena::new();
+        let boundary = Hittable::axis_aligned_box(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0), Material::default());
+        let medium = Medium::new(boundary, arena.alloc_new(|_| 50.0), 50.0, Colour::ONE, None);
+        let outer = Material::Lambertian { texture: Texture::SolidColour(Colour::ONE) };
+
+        let mut object = Hittable::medium(arena.alloc_new(medium));
+        for step in 0..3 {
+            object = Hittable::list(arena.alloc_new([object]))
+                .transformed(&arena, Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), 0.5) * Matrix4::translation(Vec3::new(step as f32, 0.0, 0.0)));
+        }
+        let object = object.with_material(&arena, outer);
+        let aabb = object.bounding_box();
+        let centre = 0.5 * (aabb.min_corner() + aabb.max_corner());
+
+        for _ in 0..1000 {
+            let origin = centre + 20.0 * Vec3::random_unit();
+            let ray = Ray::new(origin, centre - origin, 0.0);
+
+            let mut rec = HitRecord::default();
+            assert!(object.hit(ray, Interval::new(0.001, f32::INFINITY), &mut rec));
+            assert!((0..3).all(|axis| aabb.axis_interval(axis).contains(rec.point[axis])), "{}", rec.point);
+            assert!(matches!(rec.material, Material::Lambertian { .. }));
+        }
+    }
+}
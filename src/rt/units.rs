@@ -0,0 +1,119 @@
+use std::f32::consts::PI;
+
+///
+/// The length one unit of a scene's coordinates stands for.
+/// Anything with a physical size, like how fast the camera moves
+/// or how quickly light is absorbed, is given in metres and
+/// converted with the scene's units
+///
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub enum Units {
+    #[default]
+    Metres,
+    Centimetres,
+    Millimetres,
+}
+
+/// How far a bounced ray has to travel before it can hit anything
+const HIT_EPSILON_METRES : f32 = 0.001;
+/// The luminance in nits one unit of radiance stands for. It's
+/// what just turns white at an EV100 of 0, so an exposure of
+/// minus the EV100 matches a camera set to it
+const NITS_PER_RADIANCE : f32 = 1.2;
+/// The efficacy of 555nm light, the most any light can have
+const LUMENS_PER_WATT : f32 = 683.0;
+
+
+///
+/// How much light a light gives off in total
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightPower {
+    Lumens(f32),
+    /// radiant watts, taken as light with the highest efficacy
+    /// there is, which is brighter than the electrical watts
+    /// bulbs are sold by
+    Watts(f32),
+}
+
+
+impl Units {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "metres" => Some(Units::Metres),
+            "centimetres" => Some(Units::Centimetres),
+            "millimetres" => Some(Units::Millimetres),
+            _ => None,
+        }
+    }
+
+
+    ///
+    /// How many metres one unit is
+    ///
+    pub fn metres(self) -> f32 {
+        match self {
+            Units::Metres => 1.0,
+            Units::Centimetres => 0.01,
+            Units::Millimetres => 0.001,
+        }
+    }
+
+
+    pub fn from_metres(self, metres: f32) -> f32 {
+        metres / self.metres()
+    }
+
+
+    pub fn hit_epsilon(self) -> f32 {
+        self.from_metres(HIT_EPSILON_METRES)
+    }
+}
+
+
+impl LightPower {
+    pub fn parse(amount: &str, unit: &str) -> Option<Self> {
+        let amount = amount.parse::<f32>().ok().filter(|amount| *amount >= 0.0)?;
+        match unit {
+            "lumens" => Some(LightPower::Lumens(amount)),
+            "watts" => Some(LightPower::Watts(amount)),
+            _ => None,
+        }
+    }
+
+
+    pub fn lumens(self) -> f32 {
+        match self {
+            LightPower::Lumens(lumens) => lumens,
+            LightPower::Watts(watts) => watts * LUMENS_PER_WATT,
+        }
+    }
+
+
+    ///
+    /// The radiance a surface of `area` square metres which gives
+    /// off the light evenly in every direction over its front has
+    ///
+    pub fn radiance(self, area: f32) -> f32 {
+        let nits = self.lumens() / (PI * area);
+        nits / NITS_PER_RADIANCE
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_hit_epsilon_scales_with_the_units() {
+        let metres = Units::parse("metres").unwrap().hit_epsilon();
+        let centimetres = Units::parse("centimetres").unwrap().hit_epsilon();
+        let millimetres = Units::parse("millimetres").unwrap().hit_epsilon();
+
+        // the same physical distance in every unit
+        assert!((centimetres - 100.0 * metres).abs() < 1e-6, "{centimetres}");
+        assert!((millimetres - 1000.0 * metres).abs() < 1e-6, "{millimetres}");
+        assert_eq!(Units::parse("feet"), None);
+    }
+}
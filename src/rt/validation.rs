@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+
+use crate::math::vec3::{Colour, Point, Vec3};
+
+use super::{hittable::{Hittable, HittableKind}, material_library::MaterialLibrary, materials::Material};
+
+/// BVH leaves with more hittables than this are reported
+const OVERSIZED_LEAF : usize = 16;
+/// How many points along each axis an emitting medium is sampled at
+const EMISSION_SAMPLES : usize = 8;
+
+
+///
+/// Looks through a built scene for mistakes which would otherwise
+/// only show up in the finished render, e.g. quads without an
+/// area or positions which are NaN. Materials in `library` which
+/// the scene never looked up are reported as well
+///
+pub fn validate(world: &Hittable, library: Option<&MaterialLibrary>) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut visited = HashSet::new();
+    validate_hittable(world, false, &mut visited, &mut warnings);
+
+    if let Some(library) = library {
+        for name in library.unreferenced_materials() {
+            warnings.push(format!("material '{name}' is never used"));
+        }
+    }
+
+    warnings
+}
+
+
+fn validate_hittable(hittable: &Hittable, in_bvh: bool, visited: &mut HashSet<usize>, warnings: &mut Vec<String>) {
+    match hittable.kind() {
+        HittableKind::List(list) => {
+            if in_bvh && list.len() > OVERSIZED_LEAF {
+                warnings.push(format!("a BVH leaf holds {} hittables, the BVH may have hit its depth limit", list.len()));
+            }
+
+            for h in list.iter() { validate_hittable(h, in_bvh, visited, warnings) }
+        },
+
+        HittableKind::Sphere { centre, radius, mat, .. } => {
+            check_point("a sphere", *centre, warnings);
+            if not_positive(*radius) { warnings.push(format!("the sphere at {centre} has a radius of {radius}")) }
+            check_material("the sphere", *centre, mat, warnings);
+        },
+
+        HittableKind::Torus { centre, major_radius, minor_radius, mat } => {
+            check_point("a torus", *centre, warnings);
+            if not_positive(*major_radius) || not_positive(*minor_radius) {
+                warnings.push(format!("the torus at {centre} has radii of {major_radius} and {minor_radius}"));
+            }
+            check_material("the torus", *centre, mat, warnings);
+        },
+
+        HittableKind::Plane { point, normal, mat } => {
+            check_point("a plane", *point, warnings);
+            if !finite(*normal) || normal.near_zero() { warnings.push(format!("the plane through {point} has a normal of {normal}")) }
+            check_material("the plane", *point, mat, warnings);
+        },
+
+        HittableKind::Box { mat } => {
+            let min = hittable.bounding_box().min_corner();
+            check_point("a box", min, warnings);
+            check_point("a box", hittable.bounding_box().max_corner(), warnings);
+            check_material("the box", min, mat, warnings);
+        },
+
+        HittableKind::Quad { q, u, v, mat, .. } => {
+            check_point("a quad", *q, warnings);
+            if not_positive(u.cross(*v).length_squared()) {
+                warnings.push(format!("the quad at {q} has no area, its edges {u} and {v} are parallel or zero"));
+            }
+            check_material("the quad", *q, mat, warnings);
+        },
+
+        HittableKind::Planar { q, u, v, mat, .. } => {
+            check_point("a flat shape", *q, warnings);
+            if not_positive(u.cross(*v).length_squared()) {
+                warnings.push(format!("the flat shape at {q} has no area, its edges {u} and {v} are parallel or zero"));
+            }
+            check_material("the flat shape", *q, mat, warnings);
+        },
+
+        HittableKind::QuadBatch(batch) => {
+            for h in batch.quads() { validate_hittable(h, in_bvh, visited, warnings) }
+        },
+
+        HittableKind::Triangle { mesh, index, mat } => {
+            let [a, b, c] = mesh.vertices(*index);
+            for p in [a, b, c] { check_point("a triangle", p, warnings) }
+            if not_positive((b - a).cross(c - a).length_squared()) {
+                warnings.push(format!("triangle {index} at {a} has no area"));
+            }
+            check_material("the triangle", a, mat, warnings);
+        },
+
+        HittableKind::CurveSegment { curve, index, mat } => {
+            let start = curve.points()[*index as usize];
+            check_point("a curve", start, warnings);
+            check_point("a curve", curve.points()[*index as usize + 1], warnings);
+            check_material("the curve", start, mat, warnings);
+        },
+
+        HittableKind::Sdf { mat, .. } => check_material("the SDF", hittable.bounding_box().min_corner(), mat, warnings),
+
+        HittableKind::Voxels(_) => (),
+
+        HittableKind::Medium(medium) => {
+            let min = medium.boundary().bounding_box().min_corner();
+            let max = medium.boundary().bounding_box().max_corner();
+            if not_positive(medium.max_density()) {
+                warnings.push(format!("the medium at {min} has a density of {}, it won't be visible", medium.max_density()));
+            }
+
+            if let Some(emission) = medium.emission() {
+                let cell = |i: usize| (i as f32 + 0.5) / EMISSION_SAMPLES as f32;
+                let emits = (0..EMISSION_SAMPLES.pow(3)).any(|i| {
+                    let at = Vec3::new(cell(i % EMISSION_SAMPLES), cell(i / EMISSION_SAMPLES % EMISSION_SAMPLES), cell(i / EMISSION_SAMPLES.pow(2)));
+                    let p = min + (max - min) * at;
+                    emission(p) != Colour::ZERO
+                });
+
+                if !emits { warnings.push(format!("the emitting medium at {min} has an emission of zero everywhere")) }
+            }
+
+            check_point("a medium", min, warnings);
+            check_point("a medium", max, warnings);
+        },
+
+        // instances share their object so it's only checked
+        // once, objects are told apart by their address
+        HittableKind::Transform(transform) => {
+            if visited.insert(transform.object() as *const _ as usize) {
+                validate_hittable(transform.object(), false, visited, warnings);
+            }
+        },
+
+        HittableKind::MotionTransform(motion) => {
+            if visited.insert(motion.object() as *const _ as usize) {
+                validate_hittable(motion.object(), false, visited, warnings);
+            }
+        },
+
+        HittableKind::OneSided(inner) => {
+            validate_hittable(inner, in_bvh, visited, warnings);
+        },
+
+        HittableKind::Lod(lod) => {
+            for h in lod.levels() { validate_hittable(h, in_bvh, visited, warnings) }
+        },
+
+        HittableKind::BVH { left, right, .. } => {
+            validate_hittable(left, true, visited, warnings);
+            validate_hittable(right, true, visited, warnings);
+        },
+
+        HittableKind::QuantizedBvh(bvh) => validate_hittable(bvh.source(), in_bvh, visited, warnings),
+
+        HittableKind::KdTree(tree) => {
+            for h in tree.hittables() { validate_hittable(h, false, visited, warnings) }
+        },
+
+        HittableKind::Grid(grid) => {
+            for h in grid.hittables() { validate_hittable(h, false, visited, warnings) }
+        },
+    }
+}
+
+
+fn check_point(what: &str, p: Point, warnings: &mut Vec<String>) {
+    if !finite(p) { warnings.push(format!("{what} has a position of {p}")) }
+}
+
+
+fn check_material(what: &str, at: Point, mat: &Material, warnings: &mut Vec<String>) {
+    if let Material::Missing = mat {
+        warnings.push(format!("{what} at {at} has no material"));
+    }
+}
+
+
+fn finite(p: Vec3) -> bool {
+    p.x.is_finite() && p.y.is_finite() && p.z.is_finite()
+}
+
+
+///
+/// Whether `x` is 0 or below, NaN counts as well
+///
+fn not_positive(x: f32) -> bool {
+    x.is_nan() || x <= 0.0
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sti::arena::Arena;
+
+    use crate::rt::{hittable::{BvhOptions, BvhSplit}, medium::Medium, mesh::Mesh, texture::Texture};
+
+    use super::*;
+
+    const GREY : Material = Material::Lambertian { texture: Texture::SolidColour(Colour::new(0.5, 0.5, 0.5)) };
+
+
+    fn warnings_of(hittable: Hittable) -> Vec<String> {
+        validate(&hittable, None)
+    }
+
+
+    fn assert_warns(warnings: &[String], contains: &str) {
+        assert!(warnings.len() == 1 && warnings[0].contains(contains), "expected one '{contains}' in {warnings:?}");
+    }
+
+
+    #[test]
+    fn a_sound_scene_has_no_warnings() {
+        let arena = Arena::new();
+        let list = arena.alloc_new([
+            Hittable::sphere(Point::new(0.0, 0.0, 0.0), 1.0, GREY),
+            Hittable::quad(Point::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), GREY),
+        ]);
+        assert_eq!(warnings_of(Hittable::bvh(&arena, list)), Vec::<String>::new());
+    }
+
+
+    #[test]
+    fn oversized_bvh_leaves() {
+        let arena = Arena::new();
+        // split once in the middle, into two leaves just over the limit
+        let spheres = Vec::from_iter((0..2 * OVERSIZED_LEAF + 2).map(|i| Hittable::sphere(Point::new(i as f32, 0.0, 0.0), 0.5, GREY)));
+        let list = arena.alloc_new(spheres);
+        let options = BvhOptions { leaf_size: 2 * OVERSIZED_LEAF, split: BvhSplit::Median, ..BvhOptions::DEFAULT };
+        let bvh = Hittable::bvh_with(&arena, list, options);
+        assert!(warnings_of(bvh).iter().any(|w| w.contains("BVH leaf")));
+    }
+
+
+    #[test]
+    fn spheres_without_a_radius() {
+        assert_warns(&warnings_of(Hittable::sphere(Point::ZERO, 0.0, GREY)), "radius of 0");
+        assert_warns(&warnings_of(Hittable::sphere(Point::ZERO, f32::NAN, GREY)), "radius of NaN");
+    }
+
+
+    #[test]
+    fn tori_without_a_radius() {
+        assert_warns(&warnings_of(Hittable::torus(Point::ZERO, 1.0, f32::NAN, GREY)), "radii of 1 and NaN");
+        assert_warns(&warnings_of(Hittable::torus(Point::ZERO, -1.0, 0.5, GREY)), "radii of -1 and 0.5");
+    }
+
+
+    #[test]
+    fn planes_without_a_normal() {
+        assert_warns(&warnings_of(Hittable::plane(Point::ZERO, Vec3::ZERO, GREY)), "has a normal of");
+    }
+
+
+    #[test]
+    fn quads_without_an_area() {
+        let u = Vec3::new(1.0, 0.0, 0.0);
+        assert_warns(&warnings_of(Hittable::quad(Point::ZERO, u, 2.0 * u, GREY)), "the quad");
+        assert_warns(&warnings_of(Hittable::quad(Point::ZERO, u, Vec3::new(f32::NAN, 0.0, 0.0), GREY)), "no area");
+    }
+
+
+    #[test]
+    fn flat_shapes_without_an_area() {
+        let u = Vec3::new(1.0, 0.0, 0.0);
+        assert_warns(&warnings_of(Hittable::planar_triangle(Point::ZERO, u, Vec3::ZERO, GREY)), "the flat shape");
+    }
+
+
+    #[test]
+    fn triangles_without_an_area() {
+        let arena = Arena::new();
+        let positions = [Point::ZERO, Point::new(1.0, 0.0, 0.0), Point::new(2.0, 0.0, 0.0)];
+        let mesh = arena.alloc_new(Mesh::new(&arena, &positions, &[], &[[0, 1, 2]], false));
+        assert_warns(&warnings_of(Hittable::triangle(mesh, 0, GREY)), "triangle 0");
+    }
+
+
+    #[test]
+    fn positions_which_are_not_finite() {
+        assert_warns(&warnings_of(Hittable::sphere(Point::new(f32::INFINITY, 0.0, 0.0), 1.0, GREY)), "a sphere has a position");
+    }
+
+
+    #[test]
+    fn hittables_without_a_material() {
+        assert_warns(&warnings_of(Hittable::sphere(Point::ZERO, 1.0, Material::Missing)), "has no material");
+    }
+
+
+    #[test]
+    fn media_without_a_density() {
+        let arena = Arena::new();
+        let boundary = Hittable::axis_aligned_box(Point::ZERO, Point::new(1.0, 1.0, 1.0), GREY);
+        let medium = arena.alloc_new(Medium::new(boundary, arena.alloc_new(|_| 0.0), 0.0, Colour::ONE, None));
+        assert_warns(&warnings_of(Hittable::medium(medium)), "density of 0");
+    }
+
+
+    #[test]
+    fn media_which_emit_nothing() {
+        let arena = Arena::new();
+        let boundary = Hittable::axis_aligned_box(Point::ZERO, Point::new(1.0, 1.0, 1.0), GREY);
+        let medium = Medium::new(boundary, arena.alloc_new(|_| 1.0), 1.0, Colour::ONE, None)
+            .with_emission(&arena, Texture::SolidColour(Colour::ZERO), 1.0);
+        assert_warns(&warnings_of(Hittable::medium(arena.alloc_new(medium))), "emission of zero");
+    }
+
+
+    #[test]
+    fn materials_which_are_never_used() {
+        let arena = Arena::new();
+        let mut library = MaterialLibrary::new();
+        library.parse(&arena, "
+            texture white solid 1 1 1
+            material used lambertian white
+            material unused lambertian white
+        ").unwrap();
+
+        let world = Hittable::sphere(Point::ZERO, 1.0, library.material("used").unwrap());
+        assert_eq!(validate(&world, Some(&library)), vec!["material 'unused' is never used".to_string()]);
+    }
+}
@@ -0,0 +1,196 @@
+use sti::arena::Arena;
+
+use crate::{math::{aabb::{Slab, AABB}, interval::Interval, ray::Ray, vec3::{Point, Vec3}}, rt::materials::Material};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Node {
+    Empty,
+    Solid(u32),
+    // index of the first of 8 consecutive children
+    Branch(u32),
+}
+
+
+///
+/// A sparse voxel octree.
+/// Uniform regions are collapsed into a single node so
+/// large empty or solid areas only cost a single slab test
+///
+pub struct VoxelOctree<'a> {
+    nodes: &'a [Node],
+    root: Node,
+    materials: &'a [Material<'a>],
+    origin: Point,
+    size: f32,
+}
+
+
+#[derive(Clone, Copy)]
+pub struct VoxelHit<'a> {
+    pub t: f32,
+    pub outward_normal: Vec3,
+    pub material: Material<'a>,
+    pub u: f32,
+    pub v: f32,
+}
+
+
+impl<'a> VoxelOctree<'a> {
+    ///
+    /// Builds an octree over a grid of `2^depth` voxels per axis
+    /// with its minimum corner at `origin`.
+    /// `voxel(x, y, z)` returns the index into `materials`
+    /// of each voxel or `None` if it's empty
+    ///
+    pub fn new<F>(arena: &'a Arena, origin: Point, voxel_size: f32, depth: u32,
+                  materials: &'a [Material<'a>], voxel: F) -> Self
+    where F: Fn(usize, usize, usize) -> Option<u32> {
+        fn build<F: Fn(usize, usize, usize) -> Option<u32>>(
+            nodes: &mut sti::vec::Vec<Node, &Arena>, voxel: &F,
+            min: (usize, usize, usize), size: usize) -> Node {
+
+            if size == 1 {
+                return match voxel(min.0, min.1, min.2) {
+                    Some(material) => Node::Solid(material),
+                    None => Node::Empty,
+                };
+            }
+
+            let half = size / 2;
+            let children : [Node; 8] = std::array::from_fn(|i| {
+                let min = (min.0 + (i & 1) * half, min.1 + ((i >> 1) & 1) * half, min.2 + ((i >> 2) & 1) * half);
+                build(nodes, voxel, min, half)
+            });
+
+            let first = children[0];
+            if !matches!(first, Node::Branch(_)) && children.iter().all(|&c| c == first) {
+                return first;
+            }
+
+            let index = nodes.len() as u32;
+            for child in children {
+                nodes.push(child);
+            }
+
+            Node::Branch(index)
+        }
+
+
+        let mut nodes = sti::vec::Vec::new_in(arena);
+        let root = build(&mut nodes, &voxel, (0, 0, 0), 1 << depth);
+
+        Self {
+            nodes: nodes.leak(),
+            root,
+            materials,
+            origin,
+            size: voxel_size * (1 << depth) as f32,
+        }
+    }
+
+
+    pub fn bounding_box(&self) -> AABB {
+        AABB::from_points(self.origin, self.origin + Vec3::new(self.size, self.size, self.size))
+    }
+
+
+    pub fn hit(&self, ray: Ray, t: Interval) -> Option<VoxelHit<'a>> {
+        self.hit_node(self.root, self.origin, self.size, ray, t)
+    }
+
+
+    ///
+    /// Calls `push` with the `[entry, exit]` span of
+    /// every solid node along the ray in order
+    ///
+    pub fn spans<F: FnMut(f32, f32)>(&self, ray: Ray, t: Interval, push: &mut F) {
+        self.spans_node(self.root, self.origin, self.size, ray, t, push)
+    }
+
+
+    fn hit_node(&self, node: Node, min: Point, size: f32, ray: Ray, t: Interval) -> Option<VoxelHit<'a>> {
+        let slab = cube_slab(ray, min, size, t)?;
+
+        match node {
+            Node::Empty => None,
+
+            Node::Solid(material) => {
+                let (root, axis, sign) = if t.surrounds(slab.entry) { (slab.entry, slab.entry_axis, -1.0) }
+                                         else if t.surrounds(slab.exit) { (slab.exit, slab.exit_axis, 1.0) }
+                                         else { return None };
+
+                let mut outward_normal = Vec3::ZERO;
+                outward_normal[axis] = sign * ray.direction[axis].signum();
+
+                // face local coordinates of the hit inside of this node
+                let local = ray.at(root) - min;
+                let (u, v) = ((local[(axis + 1) % 3] / size).fract(), (local[(axis + 2) % 3] / size).fract());
+
+                Some(VoxelHit { t: root, outward_normal, material: self.materials[material as usize], u, v })
+            },
+
+            Node::Branch(index) => {
+                // children don't overlap so the first hit in entry order is the closest
+                for (child, child_min) in self.children(index, min, size, ray) {
+                    if let Some(hit) = self.hit_node(child, child_min, size / 2.0, ray, t) {
+                        return Some(hit);
+                    }
+                }
+
+                None
+            },
+        }
+    }
+
+
+    fn spans_node<F: FnMut(f32, f32)>(&self, node: Node, min: Point, size: f32, ray: Ray, t: Interval, push: &mut F) {
+        let Some(slab) = cube_slab(ray, min, size, t)
+        else { return };
+
+        match node {
+            Node::Empty => (),
+            Node::Solid(_) => push(slab.entry, slab.exit),
+            Node::Branch(index) => {
+                for (child, child_min) in self.children(index, min, size, ray) {
+                    self.spans_node(child, child_min, size / 2.0, ray, t, push);
+                }
+            },
+        }
+    }
+
+
+    ///
+    /// Returns the children of a branch with their minimum corners,
+    /// ordered front to back along the ray
+    ///
+    fn children(&self, index: u32, min: Point, size: f32, ray: Ray) -> [(Node, Point); 8] {
+        let half = size / 2.0;
+        let mut children : [(Node, Point); 8] = std::array::from_fn(|i| {
+            let offset = Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32);
+            (self.nodes[index as usize + i], min + half * offset)
+        });
+
+        // ordering by the projection onto the ray direction
+        // guarantees that a child comes before any child behind it
+        let centre = min + Vec3::new(half, half, half);
+        children.sort_by(|a, b| {
+            let da = (a.1 - centre).dot(ray.direction);
+            let db = (b.1 - centre).dot(ray.direction);
+            da.total_cmp(&db)
+        });
+
+        children
+    }
+}
+
+
+///
+/// Intersects the ray with the cube at `min` with side length `size`.
+/// Returns `None` if the ray misses it inside of `t`
+///
+#[inline(always)]
+fn cube_slab(ray: Ray, min: Point, size: f32, t: Interval) -> Option<Slab> {
+    let slab = AABB::from_points(min, min + Vec3::new(size, size, size)).slab(ray);
+    if slab.exit <= slab.entry || slab.exit <= t.min || slab.entry >= t.max { return None }
+    Some(slab)
+}
@@ -0,0 +1,541 @@
+use std::{collections::HashMap, fmt::Write, fs, io};
+
+use crate::{camera::{Bookmark, Camera}, math::vec3::{Colour, Point}, rt::{fog::Fog, sky::Sky}, CAMERA_SPEED, RENDER_RESOLUTION, RENDER_RESOLUTION_X};
+
+
+///
+/// The input of one interactive frame.
+/// Replaying the same inputs in order moves the
+/// camera along exactly the same path
+///
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct FrameInput {
+    /// the index of the scene it switched to, the scenes
+    /// are up to whoever replays it, see `Recording::render_offline`
+    pub switch_scene: Option<usize>,
+    /// a bookmark it jumped to before moving on
+    pub go_to: Option<Bookmark>,
+    pub dt: f32,
+    pub forward: bool,
+    pub backward: bool,
+    pub left: bool,
+    pub right: bool,
+    pub speedboost: bool,
+    pub delta_pitch: f32,
+    pub delta_yaw: f32,
+    pub toggle_fog: bool,
+    pub toggle_collide: bool,
+    pub toggle_walk: bool,
+    /// stops the lights and the exposure are brightened by,
+    /// see `Camera::adjust_lighting`
+    pub light_stops: f32,
+    pub exposure_stops: f32,
+    pub linked_lighting: bool,
+}
+
+
+impl FrameInput {
+    pub fn apply(&self, camera: &mut Camera, fog: Fog) {
+        if let Some(bookmark) = self.go_to { camera.go_to(bookmark) }
+
+        if self.light_stops != 0.0 || self.exposure_stops != 0.0 {
+            camera.adjust_lighting(self.light_stops, self.exposure_stops, self.linked_lighting);
+        }
+
+        if self.toggle_fog {
+            camera.fog = if camera.fog.is_some() { None } else { Some(fog) };
+        }
+
+        if self.toggle_collide { camera.collide = !camera.collide }
+        if self.toggle_walk { camera.walk = !camera.walk }
+
+        camera.change_pitch_yaw_by(self.delta_pitch, self.delta_yaw);
+
+        let mut cam_speed = camera.units.from_metres(CAMERA_SPEED) * self.dt;
+
+        if self.speedboost { cam_speed *= 5.0 }
+        if self.forward { camera.move_by(cam_speed * camera.forward()) }
+        if self.backward { camera.move_by(cam_speed * camera.backward()) }
+        if self.left { camera.move_by(cam_speed * camera.left()) }
+        if self.right { camera.move_by(cam_speed * camera.right()) }
+        camera.fall(self.dt);
+    }
+}
+
+
+///
+/// Presets for offline renders, so the samples, the noise they
+/// stop at and the bounce depth don't have to be picked one by
+/// one. Samples and a target noise given on the command line
+/// still override the preset's
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quality {
+    /// A quick and noisy look at the framing and lighting
+    Draft,
+    Medium,
+    /// Slow, for images which are meant to be kept
+    Final,
+}
+
+
+impl Quality {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "draft" => Some(Quality::Draft),
+            "medium" => Some(Quality::Medium),
+            "final" => Some(Quality::Final),
+            _ => None,
+        }
+    }
+
+
+    pub fn samples(self) -> usize {
+        match self {
+            Quality::Draft => 8,
+            Quality::Medium => 100,
+            Quality::Final => 1000,
+        }
+    }
+
+
+    ///
+    /// The noise a render stops at before running out of samples,
+    /// see `Camera::noise`. The final one is below what a step of
+    /// 8 bit colour shows
+    ///
+    pub fn target_noise(self) -> f32 {
+        match self {
+            Quality::Draft => 0.05,
+            Quality::Medium => 0.01,
+            Quality::Final => 0.002,
+        }
+    }
+
+
+    pub fn max_depth(self) -> usize {
+        match self {
+            Quality::Draft => 6,
+            Quality::Medium => 25,
+            Quality::Final => 50,
+        }
+    }
+
+
+    pub fn apply(self, camera: &mut Camera) {
+        camera.rt_cam.max_depth = self.max_depth();
+    }
+}
+
+
+///
+/// A timeline of frame inputs, saved as one line of text per
+/// frame after a `scene <name> <x> <y> <z> <pitch> <yaw> <vfov>
+/// <focus_dist>` line with where it starts. Each frame's line has
+/// its 11 inputs, followed by `scene <index>`, `lights <light stops>
+/// <exposure stops> <linked>` or `go_to` and a bookmark like the
+/// start's for the frames which did those
+///
+pub struct Recording {
+    scene: String,
+    start: Bookmark,
+    frames: Vec<FrameInput>,
+}
+
+
+impl Recording {
+    pub fn new(scene: &str, start: Bookmark) -> Self {
+        Self { scene: scene.to_string(), start, frames: Vec::new() }
+    }
+
+
+    ///
+    /// The name of the scene the recording starts in
+    ///
+    pub fn scene(&self) -> &str {
+        &self.scene
+    }
+
+
+    pub fn start(&self) -> Bookmark {
+        self.start
+    }
+
+
+    pub fn push(&mut self, frame: FrameInput) {
+        self.frames.push(frame);
+    }
+
+
+    pub fn frames(&self) -> &[FrameInput] {
+        &self.frames
+    }
+
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let pose = |b: Bookmark| format!("{} {} {} {} {} {} {}", b.position.x, b.position.y, b.position.z, b.pitch, b.yaw, b.vfov, b.focus_dist);
+
+        let mut string = String::new();
+        let _ = writeln!(string, "scene {} {}", self.scene, pose(self.start));
+        for f in &self.frames {
+            let _ = write!(string, "{} {} {} {} {} {} {} {} {} {} {}",
+                           f.dt, f.forward as u8, f.backward as u8, f.left as u8, f.right as u8,
+                           f.speedboost as u8, f.delta_pitch, f.delta_yaw, f.toggle_fog as u8,
+                           f.toggle_collide as u8, f.toggle_walk as u8);
+
+            if let Some(scene) = f.switch_scene { let _ = write!(string, " scene {scene}"); }
+            if f.light_stops != 0.0 || f.exposure_stops != 0.0 {
+                let _ = write!(string, " lights {} {} {}", f.light_stops, f.exposure_stops, f.linked_lighting as u8);
+            }
+            if let Some(bookmark) = f.go_to { let _ = write!(string, " go_to {}", pose(bookmark)); }
+            string.push('\n');
+        }
+
+        fs::write(path, string)
+    }
+
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let invalid = |line: usize| io::Error::new(io::ErrorKind::InvalidData, format!("invalid frame on line {}", line + 1));
+        let pose = |parts: &[&str], i: usize| {
+            let &[x, y, z, pitch, yaw, vfov, focus_dist] = parts else { return Err(invalid(i)) };
+            let float = |s: &str| s.parse::<f32>().map_err(|_| invalid(i));
+            Ok(Bookmark {
+                position: Point::new(float(x)?, float(y)?, float(z)?),
+                pitch: float(pitch)?,
+                yaw: float(yaw)?,
+                vfov: float(vfov)?,
+                focus_dist: float(focus_dist)?,
+            })
+        };
+
+        let source = fs::read_to_string(path)?;
+        let mut lines = source.lines().enumerate();
+        let header = Vec::from_iter(lines.next().map_or("", |(_, line)| line).split_whitespace());
+        let ["scene", scene, start @ ..] = header.as_slice() else { return Err(invalid(0)) };
+        let start = pose(start, 0)?;
+
+        let mut frames = Vec::new();
+        for (i, line) in lines {
+            let parts = Vec::from_iter(line.split_whitespace());
+            if parts.len() < 11 { return Err(invalid(i)) }
+            let (inputs, mut events) = parts.split_at(11);
+
+            let &[dt, forward, backward, left, right, speedboost, delta_pitch, delta_yaw,
+                  toggle_fog, toggle_collide, toggle_walk] = inputs
+            else { return Err(invalid(i)) };
+
+            let float = |s: &str| s.parse::<f32>().map_err(|_| invalid(i));
+            let flag = |s: &str| match s { "0" => Ok(false), "1" => Ok(true), _ => Err(invalid(i)) };
+
+            let mut frame = FrameInput {
+                dt: float(dt)?,
+                forward: flag(forward)?,
+                backward: flag(backward)?,
+                left: flag(left)?,
+                right: flag(right)?,
+                speedboost: flag(speedboost)?,
+                delta_pitch: float(delta_pitch)?,
+                delta_yaw: float(delta_yaw)?,
+                toggle_fog: flag(toggle_fog)?,
+                toggle_collide: flag(toggle_collide)?,
+                toggle_walk: flag(toggle_walk)?,
+                ..FrameInput::default()
+            };
+
+            while let [event, rest @ ..] = events {
+                events = match (*event, rest) {
+                    ("scene", [scene, rest @ ..]) => {
+                        frame.switch_scene = Some(scene.parse().map_err(|_| invalid(i))?);
+                        rest
+                    },
+                    ("lights", [light_stops, exposure_stops, linked, rest @ ..]) => {
+                        (frame.light_stops, frame.exposure_stops) = (float(light_stops)?, float(exposure_stops)?);
+                        frame.linked_lighting = flag(linked)?;
+                        rest
+                    },
+                    ("go_to", rest) if rest.len() >= 7 => {
+                        frame.go_to = Some(pose(&rest[..7], i)?);
+                        &rest[7..]
+                    },
+                    _ => return Err(invalid(i)),
+                };
+            }
+
+            frames.push(frame);
+        }
+
+        Ok(Self { scene: scene.to_string(), start, frames })
+    }
+
+
+    ///
+    /// Replays the recording without a window and renders every
+    /// frame with `samples` samples into `out_dir`. `camera` has
+    /// to be in the recording's `scene` already, it's moved to the
+    /// start. `switch_scene` switches it to the scene of an index
+    ///
+    pub fn render_offline<'a>(&self, mut camera: Camera<'a>, fog: Fog, samples: usize, target_noise: Option<f32>, out_dir: &str,
+                              mut switch_scene: impl FnMut(&mut Camera<'a>, usize)) -> io::Result<()> {
+        fs::create_dir_all(out_dir)?;
+        let mut buff = vec![0; RENDER_RESOLUTION * RENDER_RESOLUTION_X];
+
+        camera.go_to(self.start);
+        for (i, frame) in self.frames.iter().enumerate() {
+            if let Some(scene) = frame.switch_scene { switch_scene(&mut camera, scene) }
+            frame.apply(&mut camera, fog);
+
+            // every frame starts accumulating from scratch
+            camera.samples = 0;
+            accumulate(&mut camera, &mut buff, samples, target_noise);
+
+            write_ppm(&format!("{out_dir}/frame_{i:05}.ppm"), &buff)?;
+            println!("Rendered frame {}/{}", i + 1, self.frames.len());
+        }
+
+        Ok(())
+    }
+}
+
+
+///
+/// Numbered camera bookmarks kept in a text file next to
+/// the scene, one `<slot> <x> <y> <z> <pitch> <yaw> <vfov>
+/// <focus_dist>` line per bookmark
+///
+#[derive(Default)]
+pub struct Bookmarks {
+    slots: HashMap<u8, Bookmark>,
+}
+
+
+impl Bookmarks {
+    ///
+    /// A file which doesn't exist yet has no bookmarks
+    ///
+    pub fn load(path: &str) -> io::Result<Self> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        let mut slots = HashMap::new();
+        for (i, line) in source.lines().enumerate() {
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid bookmark on line {}", i + 1));
+
+            let parts = Vec::from_iter(line.split_whitespace());
+            let &[slot, x, y, z, pitch, yaw, vfov, focus_dist] = parts.as_slice()
+            else { return Err(invalid()) };
+
+            let float = |s: &str| s.parse::<f32>().map_err(|_| invalid());
+            let slot = slot.parse::<u8>().map_err(|_| invalid())?;
+            slots.insert(slot, Bookmark {
+                position: Point::new(float(x)?, float(y)?, float(z)?),
+                pitch: float(pitch)?,
+                yaw: float(yaw)?,
+                vfov: float(vfov)?,
+                focus_dist: float(focus_dist)?,
+            });
+        }
+
+        Ok(Self { slots })
+    }
+
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut slots = Vec::from_iter(&self.slots);
+        slots.sort_unstable_by_key(|(slot, _)| **slot);
+
+        let mut string = String::new();
+        for (slot, b) in slots {
+            let _ = writeln!(string, "{slot} {} {} {} {} {} {} {}",
+                             b.position.x, b.position.y, b.position.z, b.pitch, b.yaw, b.vfov, b.focus_dist);
+        }
+
+        fs::write(path, string)
+    }
+
+
+    pub fn get(&self, slot: u8) -> Option<Bookmark> {
+        self.slots.get(&slot).copied()
+    }
+
+
+    pub fn set(&mut self, slot: u8, bookmark: Bookmark) {
+        self.slots.insert(slot, bookmark);
+    }
+}
+
+
+///
+/// A converged image to measure renders against, see
+/// `Camera::mean_image`. Saved as a little endian portable
+/// float map, which keeps the colours over 1
+///
+pub struct Reference {
+    pub size: (usize, usize),
+    pub pixels: Vec<Colour>,
+}
+
+
+impl Reference {
+    pub fn load(path: &str) -> io::Result<Self> {
+        Self::decode(&fs::read(path)?)
+    }
+
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.encode())
+    }
+
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = format!("PF\n{} {}\n-1.0\n", self.size.0, self.size.1).into_bytes();
+
+        // the rows go from the bottom up
+        for row in self.pixels.chunks_exact(self.size.0).rev() {
+            for pixel in row {
+                for channel in [pixel.x, pixel.y, pixel.z] { bytes.extend(channel.to_le_bytes()) }
+            }
+        }
+
+        bytes
+    }
+
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        // the header is three lines of text
+        let mut header = bytes.splitn(4, |&b| b == b'\n');
+        let mut line = || header.next().and_then(|line| std::str::from_utf8(line).ok())
+            .ok_or_else(|| invalid("truncated header"));
+
+        if line()?.trim() != "PF" { return Err(invalid("not a colour float map")) }
+
+        let size = line()?;
+        let mut size = size.split_whitespace().map(|s| s.parse::<usize>().ok());
+        let (Some(Some(width)), Some(Some(height)), None) = (size.next(), size.next(), size.next())
+        else { return Err(invalid("invalid size")) };
+
+        let scale = line()?.trim().parse::<f32>().map_err(|_| invalid("invalid scale"))?;
+        if scale >= 0.0 { return Err(invalid("only little endian float maps are supported")) }
+
+        let data = header.next().unwrap_or(&[]);
+        if Some(data.len()) != width.checked_mul(height).and_then(|pixels| pixels.checked_mul(12)) {
+            return Err(invalid("the size doesn't match the data"));
+        }
+
+        let channel = |i: usize| f32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let i = 12 * (y * width + x);
+                pixels.push(Colour::new(channel(i), channel(i + 4), channel(i + 8)));
+            }
+        }
+
+        Ok(Self { size: (width, height), pixels })
+    }
+}
+
+
+///
+/// Renders up to `samples` samples into `buff`, printing the
+/// estimated noise after each one. Stops early once the noise
+/// is at or below `target_noise`, if there is one
+///
+pub fn accumulate(camera: &mut Camera, buff: &mut [u32], samples: usize, target_noise: Option<f32>) {
+    for _ in 0..samples {
+        camera.render(buff);
+
+        let noise = camera.noise();
+        println!("Sample {}: noise {noise}", camera.samples);
+        if target_noise.is_some_and(|target| noise <= target) { break }
+    }
+}
+
+
+///
+/// Renders `frames` frames from a fixed camera while the sun
+/// goes around once over a whole day at `latitude` degrees
+///
+pub fn render_timelapse(mut camera: Camera, latitude: f32, frames: usize, samples: usize, target_noise: Option<f32>, out_dir: &str) -> io::Result<()> {
+    fs::create_dir_all(out_dir)?;
+    let mut buff = vec![0; RENDER_RESOLUTION * RENDER_RESOLUTION_X];
+
+    for i in 0..frames {
+        let hours = 24.0 * i as f32 / frames as f32;
+        camera.sky = Sky::at_time_of_day(hours, latitude);
+
+        camera.samples = 0;
+        accumulate(&mut camera, &mut buff, samples, target_noise);
+
+        write_ppm(&format!("{out_dir}/frame_{i:05}.ppm"), &buff)?;
+        println!("Rendered frame {}/{frames} at {hours:.2}h", i + 1);
+    }
+
+    Ok(())
+}
+
+
+pub fn write_ppm(path: &str, buff: &[u32]) -> io::Result<()> {
+    let mut string = String::new();
+    let _ = writeln!(string, "P3\n{} {}\n255", RENDER_RESOLUTION_X, RENDER_RESOLUTION);
+
+    for &pixel in buff {
+        let _ = write!(string, "{} {} {} ", (pixel >> 16) & 0xFF, (pixel >> 8) & 0xFF, pixel & 0xFF);
+    }
+
+    fs::write(path, string)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_round_trip() {
+        let reference = Reference {
+            size: (2, 3),
+            pixels: Vec::from_iter((0..6).map(|i| Colour::new(i as f32, 0.5, 12.25))),
+        };
+
+        let decoded = Reference::decode(&reference.encode()).unwrap();
+        assert_eq!(decoded.size, (2, 3));
+        assert!(decoded.pixels.iter().zip(&reference.pixels).all(|(a, b)| a == b));
+
+        assert!(Reference::decode(b"PF\n2 3\n-1.0\n").is_err());
+        assert!(Reference::decode(b"Pf\n1 1\n-1.0\n000000000000").is_err());
+        assert!(Reference::decode(b"PF\n1 1\n1.0\n000000000000").is_err());
+    }
+
+
+    #[test]
+    fn recording_round_trip() {
+        let start = Bookmark { position: Point::new(1.0, 2.5, -3.0), pitch: -10.0, yaw: 90.5, vfov: 40.0, focus_dist: 3.25 };
+        let mut recording = Recording::new("cornell", start);
+        recording.push(FrameInput { dt: 0.016, forward: true, delta_yaw: 1.5, ..FrameInput::default() });
+        recording.push(FrameInput { dt: 0.02, switch_scene: Some(3), go_to: Some(start), light_stops: -0.5, exposure_stops: 0.5, linked_lighting: true, ..FrameInput::default() });
+
+        let path = std::env::temp_dir().join(format!("recording_round_trip_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        recording.save(path).unwrap();
+        let loaded = Recording::load(path);
+
+        fs::write(path, "0.016 0 0 0 0 0 0 0 0 0 0\n").unwrap();
+        let headless = Recording::load(path);
+        fs::write(path, "scene cornell 0 0 0 0 0 40 1\n0.016 0 0 0 0 0 0 0 0 0 0 lights 1\n").unwrap();
+        let cut_short = Recording::load(path);
+        let _ = fs::remove_file(path);
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.scene(), "cornell");
+        assert_eq!(loaded.start(), start);
+        assert_eq!(loaded.frames(), recording.frames());
+        assert!(headless.is_err());
+        assert!(cut_short.is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use std::{fs, time::Duration};
+
+use crate::rt::hittable::{Accelerator, BvhOptions, BvhTraversal};
+
+const CACHE_PATH : &str = "bvh_tuning.txt";
+const LEAF_SIZES : [usize; 4] = [1, 2, 4, 8];
+const TRAVERSALS : [BvhTraversal; 2] = [BvhTraversal::LeftFirst, BvhTraversal::NearestFirst];
+
+
+///
+/// Returns the fastest leaf size and traversal for `scene`
+/// built with the rest of `requested`. `probe` renders a few
+/// samples with the given options and returns how long that
+/// took. The winner is cached in `CACHE_PATH` so each scene
+/// is only probed once for each kind of BVH
+///
+pub fn tuned_bvh_options<F: FnMut(BvhOptions) -> Duration>(scene: &str, requested: BvhOptions, mut probe: F) -> BvhOptions {
+    let key = format!("{scene}:{}", Accelerator::BVH(requested).name());
+    if let Some((leaf_size, traversal)) = read_cache(&key) {
+        return BvhOptions { leaf_size, traversal, ..requested };
+    }
+
+    println!("Tuning the BVH for '{key}'..");
+
+    let mut best = (requested, Duration::MAX);
+    for leaf_size in LEAF_SIZES {
+        for traversal in TRAVERSALS {
+            let options = BvhOptions { leaf_size, traversal, ..requested };
+            let time = probe(options);
+            println!("  leaf size {leaf_size}, {traversal:?}: {}ms", time.as_millis());
+
+            if time < best.1 { best = (options, time) }
+        }
+    }
+
+    println!("Picked leaf size {}, {:?}", best.0.leaf_size, best.0.traversal);
+    write_cache(&key, best.0);
+    best.0
+}
+
+
+fn traversal_name(traversal: BvhTraversal) -> &'static str {
+    match traversal {
+        BvhTraversal::LeftFirst => "left_first",
+        BvhTraversal::NearestFirst => "nearest_first",
+    }
+}
+
+
+fn read_cache(key: &str) -> Option<(usize, BvhTraversal)> {
+    let cache = fs::read_to_string(CACHE_PATH).ok()?;
+
+    for line in cache.lines() {
+        let mut parts = line.split_whitespace();
+        if parts.next() != Some(key) { continue }
+
+        let leaf_size = parts.next()?.parse().ok()?;
+        let traversal = parts.next()?;
+        let traversal = TRAVERSALS.into_iter().find(|&t| traversal_name(t) == traversal)?;
+        return Some((leaf_size, traversal));
+    }
+
+    None
+}
+
+
+fn write_cache(key: &str, options: BvhOptions) {
+    // keep the entries of the other scenes and BVHs
+    let mut cache = String::new();
+    for line in fs::read_to_string(CACHE_PATH).unwrap_or_default().lines() {
+        if line.split_whitespace().next() == Some(key) { continue }
+        cache.push_str(line);
+        cache.push('\n');
+    }
+
+    cache.push_str(&format!("{key} {} {}\n", options.leaf_size, traversal_name(options.traversal)));
+
+    if let Err(err) = fs::write(CACHE_PATH, cache) {
+        println!("Couldn't write the tuning cache: {err}");
+    }
+}
@@ -3,3 +3,113 @@ pub struct SendPtr<T>(pub *mut T);
 unsafe impl<T> Send for SendPtr<T> {}
 unsafe impl<T> Sync for SendPtr<T> {}
 
+
+///
+/// A stack which keeps its first `N` items in a fixed size array
+/// and only goes to the heap past that, for traversals which
+/// almost never get deep enough to allocate
+///
+pub struct Stack<T: Copy, const N: usize> {
+    inline: [T; N],
+    len: usize,
+    spill: Vec<T>,
+}
+
+
+impl<T: Copy, const N: usize> Stack<T, N> {
+    ///
+    /// `fill` is only there to initialise the unused slots
+    ///
+    #[inline(always)]
+    pub fn new(fill: T) -> Self {
+        Self { inline: [fill; N], len: 0, spill: Vec::new() }
+    }
+
+
+    #[inline(always)]
+    pub fn push(&mut self, item: T) {
+        if self.len < N { self.inline[self.len] = item; self.len += 1 }
+        else { self.spill.push(item) }
+    }
+
+
+    ///
+    /// The spill is only used while the array is full,
+    /// so popping it first keeps the order
+    ///
+    #[inline(always)]
+    pub fn pop(&mut self) -> Option<T> {
+        if let Some(item) = self.spill.pop() { return Some(item) }
+        if self.len == 0 { return None }
+
+        self.len -= 1;
+        Some(self.inline[self.len])
+    }
+}
+
+
+///
+/// Builds rayon's global thread pool with one thread per core,
+/// each pinned to its own core so the OS doesn't move render
+/// threads between sockets. Only the cores the process may run
+/// on are used, e.g. under `taskset` or in a container. Has to
+/// be called before anything else uses rayon and only pins
+/// threads on Linux
+///
+pub fn pin_render_threads() -> Result<(), String> {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let allowed = allowed_cores();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(cores)
+        .start_handler(move |index| {
+            if !allowed.is_empty() { pin_current_thread(allowed[index % allowed.len()]) }
+        })
+        .build_global()
+        .map_err(|err| err.to_string())
+}
+
+
+/// a cpu_set_t, big enough for 1024 cores
+#[cfg(target_os = "linux")]
+type CpuSet = [u64; 16];
+
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn sched_getaffinity(pid: i32, size: usize, mask: *mut u64) -> i32;
+    fn sched_setaffinity(pid: i32, size: usize, mask: *const u64) -> i32;
+}
+
+
+///
+/// The cores the calling thread is allowed to run on,
+/// none if they can't be read
+///
+#[cfg(target_os = "linux")]
+fn allowed_cores() -> Vec<usize> {
+    let mut mask : CpuSet = [0; 16];
+
+    // pid 0 is the calling thread
+    if unsafe { sched_getaffinity(0, size_of_val(&mask), mask.as_mut_ptr()) } != 0 { return Vec::new() }
+    Vec::from_iter((0..mask.len() * 64).filter(|core| mask[core / 64] & (1 << (core % 64)) != 0))
+}
+
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(core: usize) {
+    let mut mask : CpuSet = [0; 16];
+    if core >= mask.len() * 64 { return }
+    mask[core / 64] |= 1 << (core % 64);
+
+    // failing just leaves it unpinned
+    unsafe { sched_setaffinity(0, size_of_val(&mask), mask.as_ptr()) };
+}
+
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cores() -> Vec<usize> { Vec::new() }
+
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_core: usize) {}